@@ -2,8 +2,8 @@ use std::hint::black_box;
 
 use criterion::{criterion_group, criterion_main, Criterion};
 
-use bwa_rust::align::{self, SwParams};
-use bwa_rust::index::{bwt, fm, sa};
+use bwa_rust::align::{self, SwMode, SwParams};
+use bwa_rust::index::{bifm, bwt, fm, sa};
 use bwa_rust::util::dna;
 
 fn make_reference(len: usize) -> Vec<u8> {
@@ -56,6 +56,13 @@ fn bench_smem_seeds(c: &mut Criterion) {
             black_box(align::find_smem_seeds(black_box(&fm_idx), black_box(&alpha), 19));
         });
     });
+
+    let bi = bifm::BiFMIndex::new(fm_idx);
+    c.bench_function("smem_seeds_bidir_100bp", |b| {
+        b.iter(|| {
+            black_box(align::find_smem_seeds_bidir(black_box(&bi), black_box(&alpha), 19));
+        });
+    });
 }
 
 fn bench_banded_sw(c: &mut Criterion) {
@@ -68,6 +75,11 @@ fn bench_banded_sw(c: &mut Criterion) {
         gap_open: 2,
         gap_extend: 1,
         band_width: 16,
+        diagonal_offset: 0,
+        score_matrix: None,
+        mode: SwMode::Local,
+        iupac: false,
+        n_penalty: 0,
     };
 
     c.bench_function("banded_sw_100bp", |b| {
@@ -75,6 +87,33 @@ fn bench_banded_sw(c: &mut Criterion) {
             black_box(align::banded_sw(black_box(&query), black_box(&ref_seq), params));
         });
     });
+
+    #[cfg(feature = "simd")]
+    c.bench_function("banded_sw_simd_100bp", |b| {
+        b.iter(|| {
+            black_box(align::banded_sw_simd(black_box(&query), black_box(&ref_seq), params));
+        });
+    });
+
+    // 宽带：150 bp read 位于 1 kb 参考窗口中部
+    let window = make_reference(1000);
+    let read = window[400..550].to_vec();
+    let wide = SwParams {
+        band_width: 500,
+        ..params
+    };
+    c.bench_function("banded_sw_150bp_wide_band", |b| {
+        b.iter(|| {
+            black_box(align::banded_sw(black_box(&read), black_box(&window), wide));
+        });
+    });
+
+    #[cfg(feature = "simd")]
+    c.bench_function("banded_sw_simd_150bp_wide_band", |b| {
+        b.iter(|| {
+            black_box(align::banded_sw_simd(black_box(&read), black_box(&window), wide));
+        });
+    });
 }
 
 fn bench_build_sa(c: &mut Criterion) {
@@ -90,6 +129,42 @@ fn bench_build_sa(c: &mut Criterion) {
             black_box(sa::build_sa(black_box(&text)));
         });
     });
+    c.bench_function("build_sa_doubling_10k", |b| {
+        b.iter(|| {
+            black_box(sa::build_sa_doubling(black_box(&text)));
+        });
+    });
+}
+
+fn bench_build_chains(c: &mut Criterion) {
+    // 高拷贝重复：同一组 query 区间在参考上有大量拷贝
+    let mut x: u32 = 42;
+    let mut rand = |m: u32| {
+        x = x.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        (x >> 16) % m
+    };
+    let seeds: Vec<align::MemSeed> = (0..5000)
+        .map(|_| {
+            let qb = rand(130) as usize;
+            let len = 19 + rand(12) as usize;
+            let rb = rand(1_000_000);
+            align::MemSeed {
+                contig: 0,
+                qb,
+                qe: qb + len,
+                rb,
+                re: rb + len as u32,
+                occ: 1,
+                is_rev: false,
+            }
+        })
+        .collect();
+
+    c.bench_function("build_chains_5000_seeds", |b| {
+        b.iter(|| {
+            black_box(align::build_chains(black_box(&seeds), 150));
+        });
+    });
 }
 
 criterion_group!(
@@ -97,6 +172,7 @@ criterion_group!(
     bench_backward_search,
     bench_smem_seeds,
     bench_banded_sw,
-    bench_build_sa
+    bench_build_sa,
+    bench_build_chains
 );
 criterion_main!(benches);
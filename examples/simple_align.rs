@@ -5,7 +5,7 @@
 //! cargo run --example simple_align
 //! ```
 
-use bwa_rust::align::{self, SwParams};
+use bwa_rust::align::{self, SwMode, SwParams};
 use bwa_rust::index::{bwt, fm, sa};
 use bwa_rust::util::dna;
 
@@ -77,6 +77,11 @@ fn main() {
         gap_open: 2,
         gap_extend: 1,
         band_width: 8,
+        diagonal_offset: 0,
+        score_matrix: None,
+        mode: SwMode::Local,
+        iupac: false,
+        n_penalty: 0,
     };
 
     let result = align::banded_sw(query, ref_seq, sw_params);
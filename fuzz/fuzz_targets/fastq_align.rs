@@ -0,0 +1,33 @@
+//! FASTQ 解析 + 单端比对的端到端 fuzz 目标。
+//!
+//! 运行：`cargo +nightly fuzz run fastq_align fuzz/corpus/fastq_align`
+#![no_main]
+
+use std::io::Cursor;
+use std::sync::OnceLock;
+
+use bwa_rust::align::{align_read, AlignOpt};
+use bwa_rust::index::builder::build_fm_index;
+use bwa_rust::index::fm::FMIndex;
+use bwa_rust::io::fastq::FastqReader;
+use libfuzzer_sys::fuzz_target;
+
+const REFERENCE: &[u8] = b">chr1\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCCTAGCAATGGTACCGTTAGCCTA\n\
+>chr2\nTCGGTTAGCCTAGCAATGGTACCGTTCAGGACTTAGCANNNNNNNNNNNNACGTACGTACGT\n";
+
+fn fm() -> &'static FMIndex {
+    static FM: OnceLock<FMIndex> = OnceLock::new();
+    FM.get_or_init(|| build_fm_index(Cursor::new(REFERENCE), 16).unwrap().fm)
+}
+
+fuzz_target!(|data: &[u8]| {
+    let fm = fm();
+    let opt = AlignOpt::default();
+    let mut reader = FastqReader::new(Cursor::new(data));
+    while let Ok(Some(rec)) = reader.next_record() {
+        for line in align_read(fm, &rec, &opt) {
+            let fields = line.split('\t').count();
+            assert!(fields >= 11, "SAM line has {} fields: {:?}", fields, line);
+        }
+    }
+});
@@ -1,14 +1,16 @@
 use std::collections::HashMap;
+use std::time::Instant;
 
 use crate::index::fm::Contig;
 use crate::index::fm::FMIndex;
+use crate::io::sam::soft_clip_cigar;
 use crate::util::dna;
 
 use super::extend::chain_to_alignment_with_buf;
-use super::seed::find_smem_seeds_with_max_occ;
+use super::seed::{find_smem_seeds_with_max_occ, seeds_from_smems, AlnReg, MemSeed, SmemInterval};
 use super::sw::{self, SwBuffer, SwParams, SwResult};
 use super::AlignOpt;
-use super::{build_chains_with_limit, filter_chains};
+use super::{build_chains_with_limit, filter_chains, retain_top_contigs};
 
 #[derive(Debug, Clone)]
 pub struct AlignCandidate {
@@ -28,6 +30,51 @@ pub struct AlignCandidate {
     pub query_start: usize,
     /// End position on the original query (0-based, exclusive, forward strand)
     pub query_end: usize,
+    /// Query bases inside the alignment covered by the chain's seeds
+    pub seed_cov: usize,
+    /// Lower bound on other reference copies of this hit (smallest seed occurrence minus one)
+    pub sub_n: usize,
+}
+
+impl AlignCandidate {
+    /// 参考上的比对区间 `[pos1, ref_end)`（1-based，右开）
+    pub fn ref_end(&self) -> u32 {
+        self.pos1 + cigar_ref_length(&self.cigar) as u32
+    }
+
+    /// 转换为 [`AlnReg`] 供 [`super::mapq::mapq`] 计算 MAPQ；
+    /// 得分取 `sort_score`（含剪切惩罚），与候选排序及主比对的选取一致
+    pub fn to_aln_reg(&self) -> AlnReg {
+        AlnReg {
+            qb: self.query_start,
+            qe: self.query_end,
+            rb: self.pos1 - 1,
+            re: self.ref_end() - 1,
+            contig: self.contig_idx,
+            score: self.sort_score,
+            sub_score: 0,
+            cigar: self.cigar.clone(),
+            nm: self.nm,
+            is_rev: self.is_rev,
+            seed_cov: self.seed_cov,
+            sub_n: self.sub_n,
+        }
+    }
+}
+
+/// 候选比对使用的 SW 工作缓冲区（链延伸与窗口精修各一份）。
+///
+/// 多线程比对时每个工作线程各持有一份，跨 read 复用以避免反复分配 DP 矩阵。
+#[derive(Default)]
+pub struct CandidateBuffers {
+    extend: SwBuffer,
+    refine: SwBuffer,
+}
+
+impl CandidateBuffers {
+    pub fn new() -> Self {
+        Self::default()
+    }
 }
 
 /// 从 FM 索引查找种子、构建链并执行 SW 对齐，将所有候选结果追加到 `candidates`。
@@ -47,52 +94,181 @@ pub fn collect_candidates(
     opt: &AlignOpt,
     candidates: &mut Vec<AlignCandidate>,
 ) {
+    collect_candidates_with_deadline(
+        fm,
+        query_norm,
+        query_alpha,
+        sw_params,
+        is_rev,
+        original_query_len,
+        opt,
+        None,
+        candidates,
+    );
+}
+
+/// 同 [`collect_candidates`]，但在每条链延伸前检查 `deadline`。
+///
+/// 超时即放弃剩余链并返回 `false`；已追加的候选保留在 `candidates` 中。
+pub fn collect_candidates_with_deadline(
+    fm: &FMIndex,
+    query_norm: &[u8],
+    query_alpha: &[u8],
+    sw_params: SwParams,
+    is_rev: bool,
+    original_query_len: usize,
+    opt: &AlignOpt,
+    deadline: Option<Instant>,
+    candidates: &mut Vec<AlignCandidate>,
+) -> bool {
+    collect_candidates_with_buf(
+        fm,
+        query_norm,
+        query_alpha,
+        sw_params,
+        is_rev,
+        original_query_len,
+        opt,
+        deadline,
+        &mut CandidateBuffers::new(),
+        candidates,
+    )
+}
+
+/// 同 [`collect_candidates_with_deadline`]，但使用调用方提供的 [`CandidateBuffers`]
+pub fn collect_candidates_with_buf(
+    fm: &FMIndex,
+    query_norm: &[u8],
+    query_alpha: &[u8],
+    sw_params: SwParams,
+    is_rev: bool,
+    original_query_len: usize,
+    opt: &AlignOpt,
+    deadline: Option<Instant>,
+    bufs: &mut CandidateBuffers,
+    candidates: &mut Vec<AlignCandidate>,
+) -> bool {
     let len = query_alpha.len();
     if len == 0 {
-        return;
+        return true;
+    }
+
+    let seeds = find_smem_seeds_with_max_occ(fm, query_alpha, seed_min_len(opt, len), opt.max_occ);
+    extend_seeds(
+        fm,
+        &seeds,
+        query_norm,
+        sw_params,
+        is_rev,
+        original_query_len,
+        opt,
+        deadline,
+        bufs,
+        candidates,
+    )
+}
+
+/// 同 [`collect_candidates_with_buf`]，但跳过种子搜索，使用预先计算的 SMEM（如 `.sai` 文件中的区间）
+pub fn collect_candidates_from_smems_with_buf(
+    fm: &FMIndex,
+    smems: &[SmemInterval],
+    query_norm: &[u8],
+    sw_params: SwParams,
+    is_rev: bool,
+    original_query_len: usize,
+    opt: &AlignOpt,
+    deadline: Option<Instant>,
+    bufs: &mut CandidateBuffers,
+    candidates: &mut Vec<AlignCandidate>,
+) -> bool {
+    if query_norm.is_empty() {
+        return true;
     }
+    let seeds = seeds_from_smems(fm, smems, opt.max_occ);
+    extend_seeds(
+        fm,
+        &seeds,
+        query_norm,
+        sw_params,
+        is_rev,
+        original_query_len,
+        opt,
+        deadline,
+        bufs,
+        candidates,
+    )
+}
+
+/// SMEM 最小长度：BWA 风格，默认 `min_seed_len`（19），但不超过 read 长度的一半
+pub(crate) fn seed_min_len(opt: &AlignOpt, read_len: usize) -> usize {
+    opt.min_seed_len.min(read_len / 2 + 1).max(1)
+}
 
-    // BWA 风格：min_seed_len 默认 19，但不超过 read 长度的一半
-    let min_mem_len = opt.min_seed_len.min(len / 2 + 1).max(1);
-    let seeds = find_smem_seeds_with_max_occ(fm, query_alpha, min_mem_len, opt.max_occ);
+/// 由种子构建链并逐链 SW 延伸；超过 `deadline` 时返回 `false`
+fn extend_seeds(
+    fm: &FMIndex,
+    seeds: &[MemSeed],
+    query_norm: &[u8],
+    sw_params: SwParams,
+    is_rev: bool,
+    original_query_len: usize,
+    opt: &AlignOpt,
+    deadline: Option<Instant>,
+    bufs: &mut CandidateBuffers,
+    candidates: &mut Vec<AlignCandidate>,
+) -> bool {
     if seeds.is_empty() {
-        return;
+        return true;
     }
 
     // 构建多条链
-    let mut chains = build_chains_with_limit(&seeds, len, opt.max_chains_per_contig);
+    let mut chains = build_chains_with_limit(seeds, query_norm.len(), opt.max_chains_per_contig);
     // 过滤弱链：保留得分 >= 最佳得分 * 0.3 的链
     // 0.3 阈值来自 BWA 经验值，平衡保留多比对和过滤噪声
     filter_chains(&mut chains, 0.3);
+    // 弱链在延伸前直接丢弃，省去 SW 计算
+    let min_chain_score = opt.min_chain_score_for(original_query_len);
+    chains.retain(|c| c.score >= min_chain_score);
+    // 只延伸种子支持最强的若干 contig
+    retain_top_contigs(&mut chains, opt.max_contigs_per_read);
 
-    let mut sw_buf = SwBuffer::new();
-    let mut refine_buf = SwBuffer::new();
     let mut ref_cache: HashMap<usize, Vec<u8>> = HashMap::new();
 
     for ch in &chains {
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            return false;
+        }
         let ci = ch.contig;
         let contig = &fm.contigs[ci];
-        let ref_seq = ref_cache.entry(ci).or_insert_with(|| {
-            let offset = contig.offset as usize;
-            let contig_len = contig.len as usize;
-            fm.text[offset..offset + contig_len]
-                .iter()
-                .map(|&code| dna::from_alphabet(code))
-                .collect()
-        });
+        let ref_seq = ref_cache.entry(ci).or_insert_with(|| contig_reference(fm, contig, opt.iupac));
         if ref_seq.is_empty() {
             continue;
         }
 
-        let approx = chain_to_alignment_with_buf(ch, query_norm, ref_seq.as_slice(), sw_params, opt.zdrop, &mut sw_buf);
-        let refined = refine_candidate_alignment(ch, query_norm, ref_seq.as_slice(), sw_params, &mut refine_buf);
-        let (ref_offset, selected) = choose_alignment(approx, refined, opt.clip_penalty);
+        let refined = refine_candidate_alignment(ch, query_norm, ref_seq.as_slice(), sw_params, &mut bufs.refine);
+        let (ref_offset, selected) = if opt.no_clip {
+            // 只接受窗口内的 query 全局比对，且参考跨度与 read 长度之差不超出带宽
+            match refined {
+                Some((offset, res)) if spans_full_read(&res, query_norm.len(), sw_params.band_width) => (offset, res),
+                _ => continue,
+            }
+        } else {
+            let approx = chain_to_alignment_with_buf(
+                ch,
+                query_norm,
+                ref_seq.as_slice(),
+                sw_params,
+                opt.zdrop,
+                &mut bufs.extend,
+            );
+            choose_alignment(approx, refined, opt.clip_penalty)
+        };
 
         if selected.score <= 0 || selected.cigar.is_empty() {
             continue;
         }
 
-        candidates.push(build_candidate(
+        let mut cand = build_candidate(
             contig,
             ci,
             is_rev,
@@ -102,7 +278,41 @@ pub fn collect_candidates(
             ref_seq.as_slice(),
             query_norm,
             original_query_len,
-        ));
+        );
+        cand.seed_cov = seed_coverage(&ch.seeds, selected.query_start, selected.query_end);
+        cand.sub_n = ch.seeds.iter().map(|s| s.occ as usize).min().unwrap_or(1).saturating_sub(1);
+        candidates.push(cand);
+    }
+    true
+}
+
+/// 种子在 query 区间 `[qb, qe)` 内覆盖的碱基数（重叠部分只计一次）
+fn seed_coverage(seeds: &[MemSeed], qb: usize, qe: usize) -> usize {
+    let mut spans: Vec<(usize, usize)> = seeds
+        .iter()
+        .map(|s| (s.qb.max(qb), s.qe.min(qe)))
+        .filter(|&(b, e)| b < e)
+        .collect();
+    spans.sort_unstable();
+    let mut covered = 0;
+    let mut end = 0;
+    for (b, e) in spans {
+        if e > end {
+            covered += e - b.max(end);
+            end = e;
+        }
+    }
+    covered
+}
+
+/// contig 的参考序列（大写 ASCII）；`iupac` 时从无损索引恢复 IUPAC 兼并碱基，
+/// 非无损索引中这些碱基已折叠为 `N`
+fn contig_reference(fm: &FMIndex, contig: &Contig, iupac: bool) -> Vec<u8> {
+    let (start, end) = (contig.offset as usize, (contig.offset + contig.len) as usize);
+    if iupac && fm.is_lossless() {
+        dna::normalize_seq_iupac(&fm.decode_text_lossless(start, end))
+    } else {
+        fm.decode_text(start, end)
     }
 }
 
@@ -119,6 +329,8 @@ fn refine_candidate_alignment(
 
     let seed_start = chain.seeds.iter().map(|s| s.rb as usize).min()?;
     let seed_end = chain.seeds.iter().map(|s| s.re as usize).max()?;
+    // `reference` 是单条 contig 的序列，窗口不会跨过 `$` 分隔符；种子区间两侧各留
+    // read 长度 + 带宽的余量后再截断到 contig 边界，contig 足够长时窗口至少覆盖 read_len + band_width
     let pad = query_norm.len() + sw_params.band_width + 16;
     let window_start = seed_start.saturating_sub(pad);
     let window_end = (seed_end + pad).min(reference.len());
@@ -165,15 +377,17 @@ fn build_candidate(
     // Extract the aligned reference segment for MD:Z tag generation
     // ref_offset is the window start, res.ref_start is the offset within the window
     let abs_ref_start = ref_offset + res.ref_start;
+    // Flanking query bases outside the aligned core are written as soft clips
+    let cigar = soft_clip_cigar(&res.cigar, res.query_start, res.query_end, query_norm.len());
     // Calculate reference length consumed by CIGAR
-    let ref_len = cigar_ref_length(&res.cigar);
+    let ref_len = cigar_ref_length(&cigar);
     let ref_segment = if abs_ref_start + ref_len <= ref_seq.len() {
         ref_seq[abs_ref_start..abs_ref_start + ref_len].to_vec()
     } else {
         Vec::new()
     };
 
-    let query_len = cigar_query_length(&res.cigar);
+    let query_len = cigar_query_length(&cigar);
     let query_segment = if query_len <= query_norm.len() {
         query_norm[..query_len].to_vec()
     } else {
@@ -195,25 +409,31 @@ fn build_candidate(
 
     AlignCandidate {
         score: res.score,
-        sort_score: effective_score(res.score, &res.cigar, clip_penalty),
+        sort_score: effective_score(res.score, &cigar, clip_penalty),
         is_rev,
         rname: contig.name.clone(),
         pos1: (ref_offset + res.ref_start) as u32 + 1,
-        cigar: res.cigar.clone(),
+        cigar,
         nm: res.nm,
         contig_idx,
         ref_seq: ref_segment,
         query_seq: query_segment,
         query_start,
         query_end,
+        seed_cov: 0,
+        sub_n: 0,
     }
 }
 
 /// Calculate the reference length consumed by a CIGAR string.
-fn cigar_ref_length(cigar: &str) -> usize {
-    sw::parse_cigar(cigar)
-        .into_iter()
-        .filter_map(|(op, len)| match op {
+pub fn cigar_ref_length(cigar: &str) -> usize {
+    cigar_ops_ref_length(&sw::parse_cigar(cigar))
+}
+
+/// Calculate the reference length consumed by parsed CIGAR operations.
+pub fn cigar_ops_ref_length(ops: &[(char, usize)]) -> usize {
+    ops.iter()
+        .filter_map(|&(op, len)| match op {
             'M' | '=' | 'X' | 'D' | 'N' => Some(len),
             _ => None,
         })
@@ -230,6 +450,14 @@ fn cigar_query_length(cigar: &str) -> usize {
         .sum()
 }
 
+/// `res` 是否为覆盖整条 read、不含剪切、且净插入/缺失不超过 `band_width` 的比对（`--no-clip`）
+fn spans_full_read(res: &SwResult, query_len: usize, band_width: usize) -> bool {
+    res.query_start == 0
+        && res.query_end == query_len
+        && soft_clipped_bases(&res.cigar) == 0
+        && cigar_ref_length(&res.cigar).abs_diff(query_len) <= band_width
+}
+
 fn effective_score(score: i32, cigar: &str, clip_penalty: i32) -> i32 {
     score - soft_clipped_bases(cigar) as i32 * clip_penalty
 }
@@ -272,7 +500,9 @@ pub fn dedup_candidates(candidates: &mut Vec<AlignCandidate>) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::testutil::build_test_fm;
+    use crate::align::sw::SwMode;
+    use crate::testutil::{build_test_fm, build_test_fm_contigs, Lcg};
+    use crate::util::dna;
 
     fn default_opt() -> AlignOpt {
         AlignOpt::default()
@@ -291,6 +521,11 @@ mod tests {
             gap_open: 2,
             gap_extend: 1,
             band_width: 16,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
         };
         let mut candidates = Vec::new();
         let opt = default_opt();
@@ -314,6 +549,11 @@ mod tests {
             gap_open: 2,
             gap_extend: 1,
             band_width: 16,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
         };
         let mut candidates = Vec::new();
         let opt = default_opt();
@@ -331,6 +571,11 @@ mod tests {
             gap_open: 2,
             gap_extend: 1,
             band_width: 16,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
         };
         let mut candidates = Vec::new();
         let opt = default_opt();
@@ -354,6 +599,8 @@ mod tests {
                 query_seq: Vec::new(),
                 query_start: 0,
                 query_end: 20,
+                seed_cov: 20,
+                sub_n: 0,
             },
             AlignCandidate {
                 score: 40,
@@ -368,6 +615,8 @@ mod tests {
                 query_seq: Vec::new(),
                 query_start: 0,
                 query_end: 20,
+                seed_cov: 20,
+                sub_n: 0,
             },
             AlignCandidate {
                 score: 45,
@@ -382,6 +631,8 @@ mod tests {
                 query_seq: Vec::new(),
                 query_start: 0,
                 query_end: 20,
+                seed_cov: 20,
+                sub_n: 0,
             },
         ];
         dedup_candidates(&mut cands);
@@ -404,6 +655,8 @@ mod tests {
                 query_seq: Vec::new(),
                 query_start: 0,
                 query_end: 20,
+                seed_cov: 20,
+                sub_n: 0,
             },
             AlignCandidate {
                 score: 45,
@@ -418,6 +671,8 @@ mod tests {
                 query_seq: Vec::new(),
                 query_start: 20,
                 query_end: 40,
+                seed_cov: 20,
+                sub_n: 0,
             },
             AlignCandidate {
                 score: 40,
@@ -432,6 +687,8 @@ mod tests {
                 query_seq: Vec::new(),
                 query_start: 0,
                 query_end: 20,
+                seed_cov: 20,
+                sub_n: 0,
             },
         ];
         dedup_candidates(&mut cands);
@@ -476,4 +733,77 @@ mod tests {
             "4"
         );
     }
+
+    #[test]
+    fn build_candidate_soft_clips_unaligned_ends() {
+        let contig = Contig {
+            name: "chr1".to_string(),
+            len: 4,
+            offset: 0,
+        };
+        // 局部比对只返回比对核心的 CIGAR
+        let res = SwResult {
+            score: 8,
+            query_start: 2,
+            query_end: 6,
+            ref_start: 0,
+            ref_end: 4,
+            cigar: "4M".to_string(),
+            nm: 0,
+        };
+
+        let cand = build_candidate(&contig, 0, false, &res, 0, 1, b"ACGT", b"TTACGTGG", 8);
+
+        assert_eq!(cand.cigar, "2S4M2S");
+        assert_eq!(cand.sort_score, 4);
+        assert_eq!(cand.query_seq, b"TTACGTGG");
+        assert_eq!(
+            crate::align::md_tag(&cand.query_seq, &cand.ref_seq, &cand.cigar, 0),
+            "4"
+        );
+    }
+
+    #[test]
+    fn max_contigs_per_read_extends_only_best_supported_contig() {
+        // 伪随机序列，避免意外的重复种子
+        let mut rng = Lcg::new(12_345);
+        let chr_a = rng.dna(120);
+        let spurious = rng.dna(24);
+        let mut chr_b = rng.dna(40);
+        chr_b.extend_from_slice(&spurious);
+        chr_b.extend(rng.dna(40));
+        let fm = build_test_fm_contigs(&[("chrA", &chr_a), ("chrB", &chr_b)]);
+
+        // chrA 提供 50bp 种子，chrB 仅有一个 24bp 的虚假种子
+        let mut read = chr_a[30..80].to_vec();
+        read.extend_from_slice(&spurious);
+        let norm = dna::normalize_seq(&read);
+        let alpha: Vec<u8> = norm.iter().map(|&b| dna::to_alphabet(b)).collect();
+        let sw = SwParams {
+            match_score: 2,
+            mismatch_penalty: 1,
+            gap_open: 2,
+            gap_extend: 1,
+            band_width: 16,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
+        };
+
+        let mut all = Vec::new();
+        collect_candidates(&fm, &norm, &alpha, sw, false, norm.len(), &default_opt(), &mut all);
+        assert!(all.iter().any(|c| c.contig_idx == 0));
+        assert!(all.iter().any(|c| c.contig_idx == 1));
+
+        let opt = AlignOpt {
+            max_contigs_per_read: 1,
+            ..default_opt()
+        };
+        let mut top = Vec::new();
+        collect_candidates(&fm, &norm, &alpha, sw, false, norm.len(), &opt, &mut top);
+        assert!(!top.is_empty());
+        assert!(top.iter().all(|c| c.contig_idx == 0));
+    }
 }
@@ -1,4 +1,4 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use super::seed::MemSeed;
 
@@ -13,12 +13,83 @@ pub struct Chain {
     pub score: u32,
 }
 
-/// 用 DP 方法从种子集合中找到得分最高的单条链。
+impl Chain {
+    /// 链的种子是否来自 read 的反向互补链（见 [`MemSeed::is_rev`]）
+    pub fn is_rev(&self) -> bool {
+        self.seeds.first().is_some_and(|s| s.is_rev)
+    }
+}
+
+/// 链间 gap 代价的默认值，以种子碱基为单位（链得分中每个种子碱基计 1）
+pub const DEFAULT_CHAIN_GAP_OPEN: u32 = 1;
+pub const DEFAULT_CHAIN_GAP_EXTEND: u32 = 1;
+pub const DEFAULT_CHAIN_MISMATCH: u32 = 1;
+
+/// 链式 DP 参数：gap 上限与相邻种子之间的 gap 代价。
+///
+/// 相邻种子的 query 间隔 `gap_q`、ref 间隔 `gap_r` 不相等时，差值部分为插入/缺失，
+/// 计 `gap_open + |gap_q - gap_r| * gap_extend`；两侧都有间隔时，对角线上未被种子覆盖的部分
+/// 至少有一个错配（否则两个 SMEM 会合并），再计 `mismatch_penalty`。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainParams {
+    /// query 侧 gap 上限（插入）
+    pub max_gap_q: usize,
+    /// ref 侧 gap 上限（缺失）
+    pub max_gap_r: usize,
+    pub gap_open: u32,
+    pub gap_extend: u32,
+    pub mismatch_penalty: u32,
+}
+
+impl ChainParams {
+    /// 两侧 gap 上限均为 `max_gap`，代价取默认值
+    pub fn new(max_gap: usize) -> Self {
+        Self::with_gaps(max_gap, max_gap)
+    }
+
+    /// query 侧与 ref 侧 gap 上限分开设置，代价取默认值
+    pub fn with_gaps(max_gap_q: usize, max_gap_r: usize) -> Self {
+        Self {
+            max_gap_q,
+            max_gap_r,
+            gap_open: DEFAULT_CHAIN_GAP_OPEN,
+            gap_extend: DEFAULT_CHAIN_GAP_EXTEND,
+            mismatch_penalty: DEFAULT_CHAIN_MISMATCH,
+        }
+    }
+
+    /// 链接 query 间隔 `gap_q`、ref 间隔 `gap_r` 的两个种子的代价
+    pub fn gap_penalty(&self, gap_q: usize, gap_r: usize) -> u32 {
+        let indel = gap_q.abs_diff(gap_r) as u32;
+        let indel_cost = if indel > 0 {
+            self.gap_open + indel * self.gap_extend
+        } else {
+            0
+        };
+        let mismatch_cost = if gap_q.min(gap_r) > 0 { self.mismatch_penalty } else { 0 };
+        indel_cost + mismatch_cost
+    }
+}
+
+/// 用 DP 方法从种子集合中找到得分最高的单条链（gap 代价取默认值，见 [`ChainParams`]）。
 ///
-/// 按 `(contig, qb, rb)` 排序后做链式 DP，不允许跨 contig 或 query/ref 上有重叠，
+/// 按 `(contig, qb, rb)` 排序后做链式 DP，不允许跨 contig、跨链方向或 query/ref 上有重叠，
 /// gap（query 侧或 ref 侧）超过 `max_gap` 的种子对不能链接。
 /// 返回 `None` 当且仅当 `seeds` 为空。
 pub fn best_chain(seeds: &[MemSeed], max_gap: usize) -> Option<Chain> {
+    best_chain_with_params(seeds, &ChainParams::new(max_gap))
+}
+
+/// 同 [`best_chain`]，但 query 侧与 ref 侧 gap 上限分开设置。
+///
+/// query 侧 gap 对应插入，ref 侧 gap 对应缺失；例如放宽 `max_gap_r` 可允许跨越较长缺失的链。
+pub fn best_chain_with_gaps(seeds: &[MemSeed], max_gap_q: usize, max_gap_r: usize) -> Option<Chain> {
+    best_chain_with_params(seeds, &ChainParams::with_gaps(max_gap_q, max_gap_r))
+}
+
+/// 以指定参数做链式 DP：链得分为种子长度之和减去相邻种子间的 gap 代价
+/// （[`ChainParams::gap_penalty`]）；链接后得分不高于从当前种子重新开始时不链接。
+pub fn best_chain_with_params(seeds: &[MemSeed], params: &ChainParams) -> Option<Chain> {
     if seeds.is_empty() {
         return None;
     }
@@ -33,34 +104,35 @@ pub fn best_chain(seeds: &[MemSeed], max_gap: usize) -> Option<Chain> {
     let mut dp: Vec<u32> = vec![0; n];
     let mut prev: Vec<Option<usize>> = vec![None; n];
     let mut best_i: Option<usize> = None;
+    // 已处理种子按 (contig, 链方向, re, 排序位置) 索引：前驱须满足 re ∈ [rb - max_gap_r, rb]，
+    // 只需做一次范围查询，而不是扫描全部已处理种子（高拷贝重复上种子数可达数千）
+    let mut by_ref_end: BTreeSet<(usize, bool, u32, usize)> = BTreeSet::new();
 
     for (t, &i) in idxs.iter().enumerate() {
         let si = &seeds[i];
         let len_i = (si.qe - si.qb) as u32;
         dp[t] = len_i;
 
-        for (u, &j) in idxs[..t].iter().enumerate() {
-            let sj = &seeds[j];
-            if sj.contig != si.contig {
-                continue;
-            }
+        let re_lo = (si.rb as usize).saturating_sub(params.max_gap_r) as u32;
+        let window = (si.contig, si.is_rev, re_lo, 0)..=(si.contig, si.is_rev, si.rb, usize::MAX);
+        for &(_, _, _, u) in by_ref_end.range(window) {
+            let sj = &seeds[idxs[u]];
             if sj.qe > si.qb {
                 continue;
             }
-            if sj.re > si.rb {
-                continue;
-            }
             let gap_q = si.qb - sj.qe;
             let gap_r = (si.rb - sj.re) as usize;
-            if gap_q > max_gap || gap_r > max_gap {
+            if gap_q > params.max_gap_q {
                 continue;
             }
-            let cand = dp[u] + len_i;
-            if cand > dp[t] {
+            let cand = (dp[u] + len_i).saturating_sub(params.gap_penalty(gap_q, gap_r));
+            // 同分时取排序位置最小的前驱，与按位置顺序扫描的结果一致
+            if cand > dp[t] || (cand == dp[t] && prev[t].is_some_and(|p| u < p)) {
                 dp[t] = cand;
                 prev[t] = Some(u);
             }
         }
+        by_ref_end.insert((si.contig, si.is_rev, si.re, t));
 
         if best_i.map(|bi| dp[t] > dp[bi]).unwrap_or(true) {
             best_i = Some(t);
@@ -88,7 +160,7 @@ pub fn best_chain(seeds: &[MemSeed], max_gap: usize) -> Option<Chain> {
 }
 
 /// 构建所有可能的链（返回多条链，按得分排序）
-/// 对种子集合按 contig 分组，每组内贪心剥离出最多 `max_chains_per_contig` 条链，
+/// 对种子集合按 `(contig, 链方向)` 分组，每组内贪心剥离出最多 `max_chains_per_contig` 条链，
 /// 全部链按得分降序、contig 升序、参考区间和 query 区间确定性排序后返回。
 pub fn build_chains(seeds: &[MemSeed], max_gap: usize) -> Vec<Chain> {
     build_chains_with_limit(seeds, max_gap, DEFAULT_MAX_CHAINS_PER_CONTIG)
@@ -96,18 +168,37 @@ pub fn build_chains(seeds: &[MemSeed], max_gap: usize) -> Vec<Chain> {
 
 /// 同 [`build_chains`]，但可指定每个 contig 的最大链数。
 pub fn build_chains_with_limit(seeds: &[MemSeed], max_gap: usize, max_chains_per_contig: usize) -> Vec<Chain> {
+    build_chains_with_gap_limits(seeds, max_gap, max_gap, max_chains_per_contig)
+}
+
+/// 同 [`build_chains_with_limit`]，但 query 侧与 ref 侧 gap 上限分开设置（见 [`best_chain_with_gaps`]）。
+pub fn build_chains_with_gap_limits(
+    seeds: &[MemSeed],
+    max_gap_q: usize,
+    max_gap_r: usize,
+    max_chains_per_contig: usize,
+) -> Vec<Chain> {
+    build_chains_with_params(
+        seeds,
+        &ChainParams::with_gaps(max_gap_q, max_gap_r),
+        max_chains_per_contig,
+    )
+}
+
+/// 同 [`build_chains_with_limit`]，但以 [`ChainParams`] 指定 gap 上限与代价。
+pub fn build_chains_with_params(seeds: &[MemSeed], params: &ChainParams, max_chains_per_contig: usize) -> Vec<Chain> {
     if seeds.is_empty() {
         return Vec::new();
     }
 
-    // 按 contig 分组
-    let mut by_contig: HashMap<usize, Vec<MemSeed>> = HashMap::new();
+    // 按 (contig, 链方向) 分组；单链种子只有一个方向，等同按 contig 分组
+    let mut by_contig: HashMap<(usize, bool), Vec<MemSeed>> = HashMap::new();
     for s in seeds {
-        by_contig.entry(s.contig).or_default().push(*s);
+        by_contig.entry((s.contig, s.is_rev)).or_default().push(*s);
     }
 
-    let mut contig_groups: Vec<(usize, Vec<MemSeed>)> = by_contig.into_iter().collect();
-    contig_groups.sort_by_key(|(contig_id, _)| *contig_id);
+    let mut contig_groups: Vec<((usize, bool), Vec<MemSeed>)> = by_contig.into_iter().collect();
+    contig_groups.sort_by_key(|(key, _)| *key);
 
     let mut chains = Vec::new();
     for (_contig_id, contig_seeds) in contig_groups {
@@ -117,7 +208,7 @@ pub fn build_chains_with_limit(seeds: &[MemSeed], max_gap: usize, max_chains_per
             if remaining.is_empty() {
                 break;
             }
-            if let Some(chain) = best_chain(&remaining, max_gap) {
+            if let Some(chain) = best_chain_with_params(&remaining, params) {
                 // 从 remaining 中移除已用种子
                 let used: HashSet<(usize, usize, u32, u32)> =
                     chain.seeds.iter().map(|s| (s.qb, s.qe, s.rb, s.re)).collect();
@@ -158,7 +249,7 @@ pub fn filter_chains(chains: &mut Vec<Chain>, min_score_ratio: f64) {
 
     let ranges: Vec<ChainRanges> = chains.iter().map(ChainRanges::from_chain).collect();
 
-    // 仅在同一 contig 且参考区间也高度重叠时，才视为冗余链
+    // 仅在同一 contig、同一链方向且参考区间也高度重叠时，才视为冗余链
     // OVERLAP_THRESHOLD = 0.8: 两条链在 query 和 ref 上重叠都超过 80% 视为冗余
     const OVERLAP_THRESHOLD: f64 = 0.8;
     let mut keep = vec![true; chains.len()];
@@ -174,7 +265,7 @@ pub fn filter_chains(chains: &mut Vec<Chain>, min_score_ratio: f64) {
                 continue;
             }
             let cj = &chains[j];
-            if ci.contig != cj.contig {
+            if ci.contig != cj.contig || ci.is_rev() != cj.is_rev() {
                 continue;
             }
             let rj = &ranges[j];
@@ -195,6 +286,30 @@ pub fn filter_chains(chains: &mut Vec<Chain>, min_score_ratio: f64) {
     });
 }
 
+/// 仅保留种子支持最强的 `max_contigs` 个 contig 上的链（`0` 表示不限制）。
+///
+/// contig 按其所有链的种子总长度降序排名，同分时 contig 编号小者优先；
+/// 用于在种子散布于大量 contig 时只对最可信的位置做 SW 延伸。
+pub fn retain_top_contigs(chains: &mut Vec<Chain>, max_contigs: usize) {
+    if max_contigs == 0 {
+        return;
+    }
+    let mut support: Vec<(usize, usize)> = Vec::new();
+    for ch in chains.iter() {
+        let len: usize = ch.seeds.iter().map(|s| s.qe - s.qb).sum();
+        match support.iter_mut().find(|(c, _)| *c == ch.contig) {
+            Some((_, total)) => *total += len,
+            None => support.push((ch.contig, len)),
+        }
+    }
+    if support.len() <= max_contigs {
+        return;
+    }
+    support.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    support.truncate(max_contigs);
+    chains.retain(|ch| support.iter().any(|&(c, _)| c == ch.contig));
+}
+
 fn chain_query_range(chain: &Chain) -> (usize, usize) {
     let min = chain.seeds.iter().map(|s| s.qb).min().unwrap_or(0);
     let max = chain.seeds.iter().map(|s| s.qe).max().unwrap_or(0);
@@ -230,6 +345,7 @@ fn sort_chains_deterministically(chains: &mut Vec<Chain>) {
         b.score
             .cmp(&a.score)
             .then(a.contig.cmp(&b.contig))
+            .then(a.is_rev().cmp(&b.is_rev()))
             .then((ra.rb, ra.re).cmp(&(rb.rb, rb.re)))
             .then((ra.qb, ra.qe).cmp(&(rb.qb, rb.qe)))
     });
@@ -254,6 +370,7 @@ fn overlap_ratio(a_start: u64, a_end: u64, b_start: u64, b_end: u64) -> f64 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testutil::Lcg;
 
     #[test]
     fn best_chain_simple_diagonal() {
@@ -264,6 +381,8 @@ mod tests {
                 qe: 4,
                 rb: 0,
                 re: 4,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 0,
@@ -271,6 +390,8 @@ mod tests {
                 qe: 8,
                 rb: 4,
                 re: 8,
+                occ: 1,
+                is_rev: false,
             },
         ];
         let chain = best_chain(&seeds, 10).expect("chain");
@@ -288,6 +409,8 @@ mod tests {
                 qe: 4,
                 rb: 0,
                 re: 4,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 0,
@@ -295,6 +418,8 @@ mod tests {
                 qe: 6,
                 rb: 3,
                 re: 6,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 0,
@@ -302,6 +427,8 @@ mod tests {
                 qe: 24,
                 rb: 20,
                 re: 24,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 0,
@@ -309,6 +436,8 @@ mod tests {
                 qe: 8,
                 rb: 4,
                 re: 8,
+                occ: 1,
+                is_rev: false,
             },
         ];
         let chain = best_chain(&seeds, 10).expect("chain");
@@ -327,6 +456,8 @@ mod tests {
                 qe: 4,
                 rb: 0,
                 re: 4,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 0,
@@ -334,6 +465,8 @@ mod tests {
                 qe: 8,
                 rb: 4,
                 re: 8,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 0,
@@ -341,6 +474,8 @@ mod tests {
                 qe: 4,
                 rb: 100,
                 re: 104,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 0,
@@ -348,6 +483,8 @@ mod tests {
                 qe: 8,
                 rb: 104,
                 re: 108,
+                occ: 1,
+                is_rev: false,
             },
         ];
         let chains = build_chains(&seeds, 10);
@@ -365,6 +502,8 @@ mod tests {
                     qe: 20,
                     rb: 0,
                     re: 20,
+                    occ: 1,
+                    is_rev: false,
                 }],
                 score: 20,
             },
@@ -376,6 +515,8 @@ mod tests {
                     qe: 3,
                     rb: 100,
                     re: 103,
+                    occ: 1,
+                    is_rev: false,
                 }],
                 score: 3,
             },
@@ -398,6 +539,8 @@ mod tests {
             qe: 10,
             rb: 100,
             re: 105,
+            occ: 1,
+            is_rev: false,
         }];
         let chain = best_chain(&seeds, 10).unwrap();
         assert_eq!(chain.seeds.len(), 1);
@@ -414,6 +557,8 @@ mod tests {
                 qe: 5,
                 rb: 0,
                 re: 5,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 0,
@@ -421,6 +566,8 @@ mod tests {
                 qe: 10,
                 rb: 5,
                 re: 10,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 0,
@@ -428,6 +575,8 @@ mod tests {
                 qe: 15,
                 rb: 10,
                 re: 15,
+                occ: 1,
+                is_rev: false,
             },
         ];
         let chain = best_chain(&seeds, 10).unwrap();
@@ -444,6 +593,8 @@ mod tests {
                 qe: 5,
                 rb: 0,
                 re: 5,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 1,
@@ -451,6 +602,8 @@ mod tests {
                 qe: 10,
                 rb: 5,
                 re: 10,
+                occ: 1,
+                is_rev: false,
             },
         ];
         let chain = best_chain(&seeds, 10).unwrap();
@@ -474,6 +627,8 @@ mod tests {
                 qe: 10,
                 rb: 0,
                 re: 10,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 0,
@@ -481,6 +636,8 @@ mod tests {
                 qe: 3,
                 rb: 100,
                 re: 103,
+                occ: 1,
+                is_rev: false,
             },
         ];
         let chains = build_chains(&seeds, 10);
@@ -508,6 +665,8 @@ mod tests {
                     qe: 10,
                     rb: 0,
                     re: 10,
+                    occ: 1,
+                    is_rev: false,
                 }],
                 score: 10,
             },
@@ -519,6 +678,8 @@ mod tests {
                     qe: 30,
                     rb: 20,
                     re: 30,
+                    occ: 1,
+                    is_rev: false,
                 }],
                 score: 10,
             },
@@ -538,6 +699,8 @@ mod tests {
                     qe: 12,
                     rb: 10,
                     re: 22,
+                    occ: 1,
+                    is_rev: false,
                 }],
                 score: 12,
             },
@@ -549,6 +712,8 @@ mod tests {
                     qe: 12,
                     rb: 110,
                     re: 122,
+                    occ: 1,
+                    is_rev: false,
                 }],
                 score: 12,
             },
@@ -566,6 +731,8 @@ mod tests {
                 qe: 4,
                 rb: 100,
                 re: 104,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 1,
@@ -573,6 +740,8 @@ mod tests {
                 qe: 8,
                 rb: 104,
                 re: 108,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 0,
@@ -580,6 +749,8 @@ mod tests {
                 qe: 4,
                 rb: 0,
                 re: 4,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 0,
@@ -587,6 +758,8 @@ mod tests {
                 qe: 8,
                 rb: 4,
                 re: 8,
+                occ: 1,
+                is_rev: false,
             },
         ];
         let chains = build_chains(&seeds, 10);
@@ -604,6 +777,8 @@ mod tests {
                 qe: 5,
                 rb: 0,
                 re: 5,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 0,
@@ -611,6 +786,8 @@ mod tests {
                 qe: 105,
                 rb: 100,
                 re: 105,
+                occ: 1,
+                is_rev: false,
             },
         ];
         // max_gap = 10, gap between seeds = 95
@@ -627,6 +804,8 @@ mod tests {
                 qe: 4,
                 rb: 0,
                 re: 4,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 0,
@@ -634,6 +813,8 @@ mod tests {
                 qe: 8,
                 rb: 4,
                 re: 8,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 0,
@@ -641,6 +822,8 @@ mod tests {
                 qe: 4,
                 rb: 100,
                 re: 104,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 0,
@@ -648,6 +831,8 @@ mod tests {
                 qe: 8,
                 rb: 104,
                 re: 108,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 0,
@@ -655,6 +840,8 @@ mod tests {
                 qe: 4,
                 rb: 200,
                 re: 204,
+                occ: 1,
+                is_rev: false,
             },
             MemSeed {
                 contig: 0,
@@ -662,6 +849,8 @@ mod tests {
                 qe: 8,
                 rb: 204,
                 re: 208,
+                occ: 1,
+                is_rev: false,
             },
         ];
         // With limit 1, only one chain per contig
@@ -676,4 +865,146 @@ mod tests {
         let chains_default = build_chains(&seeds, 10);
         assert!(chains_default.len() >= chains.len());
     }
+
+    #[test]
+    fn best_chain_with_gaps_allows_ref_gap_but_not_query_gap() {
+        let seed = |qb: usize, rb: u32| MemSeed {
+            contig: 0,
+            qb,
+            qe: qb + 40,
+            rb,
+            re: rb + 40,
+            occ: 1,
+            is_rev: false,
+        };
+        // 缺失：query 连续，ref 上间隔 30
+        let deletion = vec![seed(0, 0), seed(40, 70)];
+        // 插入：ref 连续，query 上间隔 30
+        let insertion = vec![seed(0, 0), seed(70, 40)];
+
+        let del = best_chain_with_gaps(&deletion, 5, 50).expect("chain");
+        assert_eq!(del.seeds.len(), 2);
+        // 80 个种子碱基减去 30 bp 缺失的代价
+        assert_eq!(del.score, 80 - ChainParams::with_gaps(5, 50).gap_penalty(0, 30));
+
+        let ins = best_chain_with_gaps(&insertion, 5, 50).expect("chain");
+        assert_eq!(ins.seeds.len(), 1);
+        assert_eq!(ins.score, 40);
+
+        // 两侧上限相同时与 best_chain 一致
+        assert_eq!(best_chain_with_gaps(&deletion, 20, 20), best_chain(&deletion, 20));
+        assert_eq!(
+            build_chains_with_gap_limits(&insertion, 50, 50, 5),
+            build_chains_with_limit(&insertion, 50, 5)
+        );
+    }
+
+    #[test]
+    fn gapped_chain_loses_to_tighter_one() {
+        let seed = |qb: usize, qe: usize, rb: u32| MemSeed {
+            contig: 0,
+            qb,
+            qe,
+            rb,
+            re: rb + (qe - qb) as u32,
+            occ: 1,
+            is_rev: false,
+        };
+        // 共同起点种子；一侧经一个 SNP 接在同一对角线上，另一侧跨 20 bp 缺失接上稍长的种子
+        let anchor = seed(0, 10, 0);
+        let snp = seed(12, 22, 12);
+        let deletion = seed(10, 21, 30);
+        let params = ChainParams::new(50);
+        assert_eq!(params.gap_penalty(2, 2), DEFAULT_CHAIN_MISMATCH);
+        assert_eq!(
+            params.gap_penalty(0, 20),
+            DEFAULT_CHAIN_GAP_OPEN + 20 * DEFAULT_CHAIN_GAP_EXTEND
+        );
+
+        let chain = best_chain(&[anchor, deletion, snp], 50).expect("chain");
+        assert_eq!(chain.seeds, vec![anchor, snp]);
+        assert_eq!(chain.score, 20 - DEFAULT_CHAIN_MISMATCH);
+
+        // 不计 gap 代价时种子总长更大的缺失链胜出
+        let free = ChainParams {
+            gap_open: 0,
+            gap_extend: 0,
+            mismatch_penalty: 0,
+            ..params
+        };
+        let chain = best_chain_with_params(&[anchor, deletion, snp], &free).expect("chain");
+        assert_eq!(chain.seeds, vec![anchor, deletion]);
+        assert_eq!(chain.score, 21);
+    }
+
+    /// 原 O(n²) 实现：按排序位置扫描全部已处理种子
+    fn best_chain_quadratic(seeds: &[MemSeed], params: &ChainParams) -> Option<Chain> {
+        let mut idxs: Vec<usize> = (0..seeds.len()).collect();
+        idxs.sort_by_key(|&i| (seeds[i].contig, seeds[i].qb, seeds[i].rb));
+        let mut dp = vec![0u32; idxs.len()];
+        let mut prev: Vec<Option<usize>> = vec![None; idxs.len()];
+        let mut best_t: Option<usize> = None;
+        for (t, &i) in idxs.iter().enumerate() {
+            let si = &seeds[i];
+            let len_i = (si.qe - si.qb) as u32;
+            dp[t] = len_i;
+            for (u, &j) in idxs[..t].iter().enumerate() {
+                let sj = &seeds[j];
+                if sj.contig != si.contig || sj.is_rev != si.is_rev || sj.qe > si.qb || sj.re > si.rb {
+                    continue;
+                }
+                let (gap_q, gap_r) = (si.qb - sj.qe, (si.rb - sj.re) as usize);
+                if gap_q > params.max_gap_q || gap_r > params.max_gap_r {
+                    continue;
+                }
+                let cand = (dp[u] + len_i).saturating_sub(params.gap_penalty(gap_q, gap_r));
+                if cand > dp[t] {
+                    dp[t] = cand;
+                    prev[t] = Some(u);
+                }
+            }
+            if best_t.map_or(true, |b| dp[t] > dp[b]) {
+                best_t = Some(t);
+            }
+        }
+        let mut chain_idxs = Vec::new();
+        let mut cur = best_t;
+        while let Some(t) = cur {
+            chain_idxs.push(idxs[t]);
+            cur = prev[t];
+        }
+        chain_idxs.reverse();
+        Some(Chain {
+            contig: seeds[chain_idxs[0]].contig,
+            seeds: chain_idxs.into_iter().map(|i| seeds[i]).collect(),
+            score: dp[best_t?],
+        })
+    }
+
+    #[test]
+    fn best_chain_matches_quadratic_scan_on_repeats() {
+        // 同一组 query 区间在两条 contig、两条链上以多个拷贝出现，拷贝间距有近有远
+        let mut rng = Lcg::new(7);
+        let mut rand = |m: u32| rng.below(m);
+        let mut seeds = Vec::new();
+        for _ in 0..400 {
+            let qb = rand(120) as usize;
+            let len = 5 + rand(20) as usize;
+            let rb = rand(3000);
+            seeds.push(MemSeed {
+                contig: rand(2) as usize,
+                qb,
+                qe: qb + len,
+                rb,
+                re: rb + len as u32,
+                occ: 1,
+                is_rev: rand(2) == 1,
+            });
+        }
+        for params in [ChainParams::new(30), ChainParams::with_gaps(10, 200)] {
+            let chain = best_chain_with_params(&seeds, &params);
+            assert!(chain.as_ref().is_some_and(|c| c.seeds.len() > 1));
+            assert_eq!(chain, best_chain_quadratic(&seeds, &params));
+        }
+    }
 }
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::io::sam::{cigar_ref_blocks, flags, SamRecord};
+
+/// 逐碱基覆盖度累加器，输出 BEDGRAPH 覆盖度轨道。
+///
+/// 每个 contig 只记录比对块边界处的深度增量（差分表示）：增量追加到平坦数组，
+/// 长度超过上次压缩后的两倍时排序并合并同一位置，每个不同边界只占 8 字节，
+/// 内存与不同边界数成正比、与参考长度无关，适合大参考基因组。只统计已比对的主记录与 supplementary 记录
+/// （忽略 secondary），deletion / skip 不计入覆盖度，见 [`cigar_ref_blocks`]。
+#[derive(Debug, Clone, Default)]
+pub struct CoverageTrack {
+    contigs: Vec<(String, u32)>,
+    index: HashMap<String, usize>,
+    /// 每个 contig：`(0-based 位置, 深度增量)`，压缩前的尾部未排序
+    deltas: Vec<Vec<(u32, i32)>>,
+    /// 每个 contig 上次压缩后的增量数
+    compacted: Vec<usize>,
+}
+
+/// 增量数组至少达到该长度才触发压缩，避免小数组频繁排序
+const COMPACT_MIN: usize = 4096;
+
+/// 按位置排序并合并同一位置的增量，去掉合计为 0 的项
+fn compact(deltas: &mut Vec<(u32, i32)>) {
+    deltas.sort_unstable_by_key(|&(pos, _)| pos);
+    let mut w = 0usize;
+    for r in 0..deltas.len() {
+        let (pos, d) = deltas[r];
+        if w > 0 && deltas[w - 1].0 == pos {
+            deltas[w - 1].1 += d;
+        } else {
+            deltas[w] = (pos, d);
+            w += 1;
+        }
+        if deltas[w - 1].1 == 0 {
+            w -= 1;
+        }
+    }
+    deltas.truncate(w);
+}
+
+impl CoverageTrack {
+    /// 以 contig `(名称, 长度)` 列表（输出顺序）创建空轨道
+    pub fn new(contigs: &[(&str, u32)]) -> Self {
+        Self {
+            contigs: contigs.iter().map(|&(name, len)| (name.to_string(), len)).collect(),
+            index: contigs
+                .iter()
+                .enumerate()
+                .map(|(i, &(name, _))| (name.to_string(), i))
+                .collect(),
+            deltas: vec![Vec::new(); contigs.len()],
+            compacted: vec![0; contigs.len()],
+        }
+    }
+
+    /// 累加一条 SAM 记录覆盖的参考区间
+    pub fn add(&mut self, record: &SamRecord) {
+        if record.is_unmapped() || record.flag & flags::SECONDARY != 0 {
+            return;
+        }
+        let Some(&ci) = self.index.get(&record.rname) else {
+            return;
+        };
+        let contig_len = self.contigs[ci].1;
+        let start = record.pos.saturating_sub(1);
+        let deltas = &mut self.deltas[ci];
+        for (off, len) in cigar_ref_blocks(&record.cigar) {
+            let b = (start as usize + off).min(contig_len as usize) as u32;
+            let e = (start as usize + off + len).min(contig_len as usize) as u32;
+            if b < e {
+                deltas.push((b, 1));
+                deltas.push((e, -1));
+            }
+        }
+        if deltas.len() >= COMPACT_MIN.max(2 * self.compacted[ci]) {
+            compact(deltas);
+            self.compacted[ci] = deltas.len();
+        }
+    }
+
+    /// 各 contig 的深度恒定区间 `(contig, start, end, depth)`（0-based 半开区间），
+    /// 按 contig 顺序与位置排列，省略深度为 0 的区间
+    pub fn runs(&self) -> Vec<(&str, u32, u32, u64)> {
+        let mut out = Vec::new();
+        for ((name, _), deltas) in self.contigs.iter().zip(&self.deltas) {
+            let mut deltas = deltas.clone();
+            compact(&mut deltas);
+            let mut depth = 0i64;
+            let mut prev = 0u32;
+            for (pos, d) in deltas {
+                if depth > 0 && pos > prev {
+                    out.push((name.as_str(), prev, pos, depth as u64));
+                }
+                depth += d as i64;
+                prev = pos;
+            }
+        }
+        out
+    }
+
+    /// 以 BEDGRAPH 格式写出（`contig\tstart\tend\tdepth`）
+    pub fn write_bedgraph<W: Write>(&self, mut w: W) -> Result<()> {
+        for (name, start, end, depth) in self.runs() {
+            writeln!(w, "{}\t{}\t{}\t{}", name, start, end, depth)?;
+        }
+        w.flush()?;
+        Ok(())
+    }
+
+    /// 将 BEDGRAPH 写入 `path`
+    pub fn write_bedgraph_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let file = std::fs::File::create(path)
+            .map_err(|e| anyhow::anyhow!("cannot write coverage to '{}': {}", path.display(), e))?;
+        self.write_bedgraph(BufWriter::new(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::Lcg;
+
+    fn mapped(flag: u16, pos: u32, cigar: &str) -> SamRecord {
+        SamRecord::mapped("r", flag, "chr1", pos, 60, cigar, "*", "*")
+    }
+
+    #[test]
+    fn overlapping_reads_produce_depth_runs() {
+        let mut cov = CoverageTrack::new(&[("chr1", 100), ("chr2", 50)]);
+        cov.add(&mapped(0, 11, "10M"));
+        cov.add(&mapped(16, 16, "2S10M"));
+        // deletion 不计入覆盖度
+        cov.add(&mapped(0, 21, "3M2D3M"));
+        // secondary 与未比对记录被忽略
+        cov.add(&mapped(flags::SECONDARY, 11, "10M"));
+        cov.add(&SamRecord::unmapped("u", "ACGT", "IIII"));
+
+        assert_eq!(
+            cov.runs(),
+            vec![("chr1", 10, 15, 1), ("chr1", 15, 23, 2), ("chr1", 23, 28, 1)]
+        );
+        let mut out = Vec::new();
+        cov.write_bedgraph(&mut out).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("chr1\t10\t15\t1\nchr1\t15\t23\t2\n"));
+    }
+
+    #[test]
+    fn compaction_keeps_depth_and_bounds_memory() {
+        let mut cov = CoverageTrack::new(&[("chr1", 500)]);
+        let mut naive = vec![0u64; 500];
+        let mut rng = Lcg::new(13);
+        for _ in 0..20_000 {
+            let pos = 1 + rng.below(450);
+            let len = 1 + rng.below(50);
+            cov.add(&mapped(0, pos, &format!("{}M", len)));
+            for d in &mut naive[pos as usize - 1..(pos + len - 1) as usize] {
+                *d += 1;
+            }
+        }
+        // 至多 501 个不同边界：压缩后数组不随 read 数增长
+        assert!(cov.deltas[0].len() < COMPACT_MIN * 2, "{}", cov.deltas[0].len());
+
+        let mut expected = Vec::new();
+        for (i, &d) in naive.iter().enumerate() {
+            match expected.last_mut() {
+                Some((_, _, end, depth)) if *end == i as u32 && *depth == d => *end += 1,
+                _ if d > 0 => expected.push(("chr1", i as u32, i as u32 + 1, d)),
+                _ => {}
+            }
+        }
+        assert_eq!(cov.runs(), expected);
+    }
+}
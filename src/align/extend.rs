@@ -40,7 +40,6 @@ pub fn chain_to_alignment_with_buf(
 
     let mut ops: Vec<(char, usize)> = Vec::new();
     let mut total_score: i32 = 0;
-    let mut total_nm: u32 = 0;
     let first_seed = &seeds[0];
     let last_seed = &seeds[seeds.len() - 1];
 
@@ -59,11 +58,6 @@ pub fn chain_to_alignment_with_buf(
         if left_ext.score > 0 && !left_ext.ops.is_empty() {
             push_char_ops(&mut ops, &left_ext.ops);
             total_score += left_ext.score;
-            total_nm += sw::nm_from_ops(
-                &left_ext.ops,
-                &left_q[left_q.len() - left_ext.query_len..],
-                &left_r[left_r.len() - left_ext.ref_len..],
-            );
             query_start = first_seed.qb - left_ext.query_len;
             ref_start = ref_left_end - left_ext.ref_len;
         }
@@ -93,16 +87,13 @@ pub fn chain_to_alignment_with_buf(
                             push_run(&mut ops, op_ch, num);
                         }
                         total_score += res.score;
-                        total_nm += res.nm;
                     }
                 } else if q_gap_len > 0 {
                     push_run(&mut ops, 'I', q_gap_len);
                     total_score -= p.gap_open + p.gap_extend * q_gap_len as i32;
-                    total_nm += q_gap_len as u32;
                 } else {
                     push_run(&mut ops, 'D', r_gap_len);
                     total_score -= p.gap_open + p.gap_extend * r_gap_len as i32;
-                    total_nm += r_gap_len as u32;
                 }
             }
         }
@@ -111,7 +102,12 @@ pub fn chain_to_alignment_with_buf(
         let len = s.qe - s.qb;
         if len > 0 {
             push_run(&mut ops, 'M', len);
-            total_score += (len as i32) * p.match_score;
+            let seed_ref = reference.get(s.rb as usize..).unwrap_or_default();
+            total_score += query[s.qb..s.qe]
+                .iter()
+                .zip(seed_ref)
+                .map(|(&q, &r)| p.subst(q, r))
+                .sum::<i32>();
         }
     }
 
@@ -124,11 +120,6 @@ pub fn chain_to_alignment_with_buf(
         if right_ext.score > 0 && !right_ext.ops.is_empty() {
             push_char_ops(&mut ops, &right_ext.ops);
             total_score += right_ext.score;
-            total_nm += sw::nm_from_ops(
-                &right_ext.ops,
-                &right_q[..right_ext.query_len],
-                &right_r[..right_ext.ref_len],
-            );
             query_end = last_seed.qe + right_ext.query_len;
             ref_end = ref_right_start + right_ext.ref_len;
         }
@@ -147,6 +138,8 @@ pub fn chain_to_alignment_with_buf(
         let _ = write!(&mut cigar, "{}{}", len, op);
     }
 
+    // CIGAR 以覆盖 query 起点的软剪切开头，对应参考从 `ref_start` 开始
+    let nm = sw::edit_distance(query, reference, &cigar, 0, ref_start);
     SwResult {
         score: total_score,
         query_start,
@@ -154,7 +147,7 @@ pub fn chain_to_alignment_with_buf(
         ref_start,
         ref_end,
         cigar,
-        nm: total_nm,
+        nm,
     }
 }
 
@@ -181,6 +174,7 @@ fn push_char_ops(ops: &mut Vec<(char, usize)>, chars: &[char]) {
 mod tests {
     use super::*;
     use crate::align::seed::MemSeed;
+    use crate::align::sw::SwMode;
 
     const DEFAULT_ZDROP: i32 = 100;
 
@@ -191,6 +185,11 @@ mod tests {
             gap_open: 1,
             gap_extend: 0,
             band_width: 8,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
         }
     }
 
@@ -205,6 +204,8 @@ mod tests {
                 qe: 4,
                 rb: 0,
                 re: 4,
+                occ: 1,
+                is_rev: false,
             }],
             score: 4,
         };
@@ -239,6 +240,8 @@ mod tests {
                     qe: 4,
                     rb: 0,
                     re: 4,
+                    occ: 1,
+                    is_rev: false,
                 },
                 MemSeed {
                     contig: 0,
@@ -246,6 +249,8 @@ mod tests {
                     qe: 8,
                     rb: 4,
                     re: 8,
+                    occ: 1,
+                    is_rev: false,
                 },
             ],
             score: 8,
@@ -270,6 +275,8 @@ mod tests {
                     qe: 4,
                     rb: 0,
                     re: 4,
+                    occ: 1,
+                    is_rev: false,
                 },
                 MemSeed {
                     contig: 0,
@@ -277,6 +284,8 @@ mod tests {
                     qe: 10,
                     rb: 6,
                     re: 10,
+                    occ: 1,
+                    is_rev: false,
                 },
             ],
             score: 8,
@@ -299,6 +308,8 @@ mod tests {
                 qe: 4,
                 rb: 0,
                 re: 4,
+                occ: 1,
+                is_rev: false,
             }],
             score: 4,
         };
@@ -316,6 +327,11 @@ mod tests {
             gap_open: 2,
             gap_extend: 1,
             band_width: 8,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
         };
         let chain = Chain {
             contig: 0,
@@ -325,6 +341,8 @@ mod tests {
                 qe: 4,
                 rb: 0,
                 re: 4,
+                occ: 1,
+                is_rev: false,
             }],
             score: 4,
         };
@@ -350,6 +368,8 @@ mod tests {
                     qe: 3,
                     rb: 0,
                     re: 3,
+                    occ: 1,
+                    is_rev: false,
                 },
                 MemSeed {
                     contig: 0,
@@ -357,6 +377,8 @@ mod tests {
                     qe: 6,
                     rb: 3,
                     re: 6,
+                    occ: 1,
+                    is_rev: false,
                 },
             ],
             score: 6,
@@ -377,6 +399,8 @@ mod tests {
                 qe: 6,
                 rb: 2,
                 re: 6,
+                occ: 1,
+                is_rev: false,
             }],
             score: 4,
         };
@@ -397,6 +421,11 @@ mod tests {
             gap_open: 2,
             gap_extend: 1,
             band_width: 8,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
         };
         let chain = Chain {
             contig: 0,
@@ -407,6 +436,8 @@ mod tests {
                     qe: 4,
                     rb: 0,
                     re: 4,
+                    occ: 1,
+                    is_rev: false,
                 },
                 MemSeed {
                     contig: 0,
@@ -414,6 +445,8 @@ mod tests {
                     qe: 12,
                     rb: 12,
                     re: 16,
+                    occ: 1,
+                    is_rev: false,
                 },
             ],
             score: 8,
@@ -1,5 +1,7 @@
 use std::cmp::Ordering;
 
+use super::PairingOpt;
+
 const MAX_SAMPLES: usize = 100_000;
 const UPDATE_INTERVAL: usize = 1_000;
 
@@ -95,6 +97,63 @@ impl InsertSizeStats {
     }
 }
 
+/// Insert-size distribution learned from confidently mapped proper pairs.
+///
+/// Mirrors BWA-MEM's automatic estimation: samples outside
+/// `[Q1 - 2*IQR, Q3 + 2*IQR]` are dropped as outliers before the mean and
+/// standard deviation are computed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InsertEstimate {
+    /// Pairs kept after outlier removal
+    pub pairs: usize,
+    pub mean: f64,
+    pub stddev: f64,
+    pub median: f64,
+}
+
+impl InsertEstimate {
+    /// Estimate from observed insert sizes (absolute TLEN); `None` if no
+    /// positive sample remains.
+    pub fn from_samples(samples: &[i32]) -> Option<Self> {
+        let mut sorted: Vec<i32> = samples.iter().copied().filter(|&x| x > 0).collect();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort_unstable();
+        let quantile = |q: f64| sorted[((sorted.len() - 1) as f64 * q).round() as usize] as f64;
+        let (q1, q3) = (quantile(0.25), quantile(0.75));
+        let iqr = q3 - q1;
+        let (lo, hi) = (q1 - 2.0 * iqr, q3 + 2.0 * iqr);
+        let kept: Vec<f64> = sorted.iter().map(|&x| x as f64).filter(|&x| x >= lo && x <= hi).collect();
+
+        let n = kept.len() as f64;
+        let mean = kept.iter().sum::<f64>() / n;
+        let var = kept.iter().map(|&x| (x - mean) * (x - mean)).sum::<f64>() / n;
+        let mid = kept.len() / 2;
+        let median = if kept.len() % 2 == 0 {
+            (kept[mid - 1] + kept[mid]) / 2.0
+        } else {
+            kept[mid]
+        };
+        Some(Self {
+            pairs: kept.len(),
+            mean,
+            stddev: var.sqrt(),
+            median,
+        })
+    }
+
+    /// Proper-pair window `mean ± 4 * stddev` (as in BWA-MEM), applied to `base`.
+    pub fn pairing_opt(&self, base: PairingOpt) -> PairingOpt {
+        let spread = 4.0 * self.stddev;
+        PairingOpt {
+            min_insert: (self.mean - spread).floor().max(0.0) as usize,
+            max_insert: (self.mean + spread).ceil() as usize,
+            ..base
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -194,4 +253,23 @@ mod tests {
         let stats = InsertSizeStats::default();
         assert_eq!(stats.max_insert, 500);
     }
+
+    #[test]
+    fn insert_estimate_drops_outliers() {
+        let mut samples: Vec<i32> = (0..210).map(|i| 290 + (i % 21)).collect();
+        samples.extend([5_000, 12_000, -300, 0]);
+        let est = InsertEstimate::from_samples(&samples).unwrap();
+        assert_eq!(est.pairs, 210);
+        assert!((est.mean - 300.0).abs() < 0.5, "{:?}", est);
+        assert_eq!(est.median, 300.0);
+        assert!(est.stddev > 5.0 && est.stddev < 7.0, "{:?}", est);
+
+        let pairing = est.pairing_opt(PairingOpt::default());
+        assert!(pairing.min_insert > 260 && pairing.min_insert < 280);
+        assert!(pairing.max_insert > 320 && pairing.max_insert < 340);
+        assert!(pairing.mate_rescue);
+
+        assert!(InsertEstimate::from_samples(&[]).is_none());
+        assert!(InsertEstimate::from_samples(&[0, -5]).is_none());
+    }
 }
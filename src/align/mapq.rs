@@ -1,28 +1,112 @@
+use super::seed::AlnReg;
+use super::sw::SwParams;
+
+/// MAPQ 上限（BWA `MEM_MAPQ_MAX`）
+const MAPQ_MAX: u8 = 60;
+
+/// `coef * (1 - sub/best) * log_term` 四舍五入并截断到 `[0, MAPQ_MAX]`
+fn scaled_mapq(coef: f64, best: f64, sub: f64, log_term: f64) -> u8 {
+    let q = (coef * (1.0 - sub / best) * log_term).round() as i32;
+    q.clamp(0, MAPQ_MAX as i32) as u8
+}
+
 /// BWA 风格的 MAPQ 计算
 /// 参考 BWA mem_approx_mapq_se: mapq = MEM_MAPQ_COEF * (1 - sub/best) * ln(best)
 /// MEM_MAPQ_COEF = 30, MEM_MAPQ_MAX = 60
+///
+/// 仅依据两个得分的简化模型；比对流程输出的 MAPQ 由 [`mapq`] 计算。
 #[must_use]
 pub fn compute_mapq(best_score: i32, second_best_score: i32) -> u8 {
     const MAPQ_COEF: f64 = 30.0;
-    const MAPQ_MAX: u8 = 60;
 
     if best_score <= 0 {
         return 0;
     }
+    let best = best_score as f64;
+    // 唯一比对时 sub = 0：q = coef * ln(best)
+    let sub = second_best_score.max(0) as f64;
+    scaled_mapq(MAPQ_COEF, best, sub, best.ln())
+}
+
+/// 考虑种子覆盖与比对一致性的 MAPQ（BWA-MEM `mem_approx_mapq_se` 模型）。
+///
+/// - `seed_cov`：最佳比对中被种子覆盖的 query 碱基数
+/// - `aln_len`：最佳比对长度（query 与参考跨度的较大者）
+/// - 无次优比对（`sub_score <= 0`）时以 `min_seed_len * match_score` 作为次优得分，
+///   因此短比对即使唯一也得不到满分
+///
+/// `mapq = 40 * (1 - sub/best) * ln(seed_cov)`；一致性低于 0.95 时再乘以其平方，
+/// 结果截断到 `[0, 60]`。`sub >= best` 时为 0。
+#[must_use]
+pub fn compute_mapq_ext(
+    best_score: i32,
+    sub_score: i32,
+    seed_cov: usize,
+    aln_len: usize,
+    p: &SwParams,
+    min_seed_len: usize,
+) -> u8 {
+    const MAPQ_COEF: f64 = 40.0;
 
+    let sub = if sub_score > 0 {
+        sub_score
+    } else {
+        min_seed_len as i32 * p.match_score
+    };
+    if best_score <= 0 || sub >= best_score || seed_cov == 0 || aln_len == 0 {
+        return 0;
+    }
     let best = best_score as f64;
+    let q = scaled_mapq(MAPQ_COEF, best, sub as f64, (seed_cov as f64).ln());
 
-    if second_best_score <= 0 {
-        // 唯一比对：q = coef * ln(best)，上限 MAPQ_MAX
-        let q = (MAPQ_COEF * best.ln()).round() as i32;
-        return (q.clamp(0, MAPQ_MAX as i32)) as u8;
+    // 一致性：把得分缺口折算成错配数后占比对长度的比例
+    let len = aln_len as f64;
+    let per_base = f64::from(p.match_score + p.mismatch_penalty);
+    let identity = 1.0 - (len * f64::from(p.match_score) - best) / per_base / len;
+    if identity < 0.95 {
+        (f64::from(q) * identity * identity).round().max(0.0) as u8
+    } else {
+        q
     }
+}
+
+/// 根据一组比对区域计算最佳区域的 MAPQ。
+///
+/// - 最佳区域：得分最高者（同分取靠前者）。
+/// - 次优得分：其余区域最高分与最佳区域自身 `sub_score` 的较大者。
+/// - 以最佳区域的 `seed_cov` 与比对长度按 [`compute_mapq_ext`] 计算，
+///   `min_seed_len` 为无次优比对时次优得分的下限。
+/// - 除次优区域外，其余得分与次优相同的区域数与最佳区域自身 `sub_n` 的较大者记为 `n`，
+///   再减去 `4.343 * ln(n + 1)`（BWA 的 sub_n 修正），使多个等价次优位置进一步降低置信度。
+///
+/// 比对流程的主比对 MAPQ 即由此计算。空输入返回 0。
+#[must_use]
+pub fn mapq(regs: &[AlnReg], p: &SwParams, min_seed_len: usize) -> u8 {
+    let Some((best_idx, best)) = regs
+        .iter()
+        .enumerate()
+        .max_by(|(ia, a), (ib, b)| a.score.cmp(&b.score).then(ib.cmp(ia)))
+    else {
+        return 0;
+    };
+
+    let others = regs.iter().enumerate().filter(|&(i, _)| i != best_idx).map(|(_, r)| r.score);
+    let second = others.clone().max().unwrap_or(0);
+    let sub = second.max(best.sub_score);
+    let extra_subs = others.filter(|&s| s > 0 && s == sub).count().saturating_sub(1);
+    let sub_n = extra_subs.max(best.sub_n);
 
-    let sub = second_best_score as f64;
-    let ratio = sub / best;
-    // q = coef * (1 - sub/best) * ln(best)
-    let q = (MAPQ_COEF * (1.0 - ratio) * best.ln()).round() as i32;
-    (q.clamp(0, MAPQ_MAX as i32)) as u8
+    let aln_len = (best.qe - best.qb).max((best.re - best.rb) as usize);
+    let q = i32::from(compute_mapq_ext(
+        best.score,
+        sub,
+        best.seed_cov,
+        aln_len,
+        p,
+        min_seed_len,
+    ));
+    let penalty = (4.343 * ((sub_n + 1) as f64).ln()).round() as i32;
+    (q - penalty).max(0) as u8
 }
 
 #[cfg(test)]
@@ -62,4 +146,100 @@ mod tests {
             assert_eq!(compute_mapq(score, score), 0);
         }
     }
+
+    #[test]
+    fn mapq_ext_corner_cases() {
+        let p = SwParams {
+            match_score: 1,
+            mismatch_penalty: 4,
+            ..SwParams::default()
+        };
+        // 次优与最佳同分
+        assert_eq!(compute_mapq_ext(150, 150, 150, 150, &p, 19), 0);
+        assert_eq!(compute_mapq_ext(0, 0, 0, 0, &p, 19), 0);
+        // 无次优：150 bp 完美比对满分
+        assert_eq!(compute_mapq_ext(150, 0, 150, 150, &p, 19), 60);
+        // 20 bp 完美比对即使唯一也远低于 60
+        assert!(compute_mapq_ext(20, 0, 20, 20, &p, 19) < 10);
+        // 种子覆盖越少、一致性越低，MAPQ 越低
+        let full = compute_mapq_ext(100, 80, 100, 100, &p, 19);
+        assert!(compute_mapq_ext(100, 80, 30, 100, &p, 19) < full);
+        assert!(compute_mapq_ext(100, 80, 100, 150, &p, 19) < full);
+    }
+
+    fn params() -> SwParams {
+        SwParams {
+            match_score: 1,
+            mismatch_penalty: 4,
+            ..SwParams::default()
+        }
+    }
+
+    fn reg(score: i32) -> AlnReg {
+        AlnReg {
+            qb: 0,
+            qe: 100,
+            rb: 0,
+            re: 100,
+            contig: 0,
+            score,
+            sub_score: 0,
+            cigar: "100M".to_string(),
+            nm: 0,
+            is_rev: false,
+            seed_cov: 100,
+            sub_n: 0,
+        }
+    }
+
+    #[test]
+    fn mapq_from_regions_uses_best_and_suboptimal() {
+        let p = params();
+        assert_eq!(mapq(&[], &p, 19), 0);
+        assert_eq!(mapq(&[reg(100)], &p, 19), compute_mapq_ext(100, 0, 100, 100, &p, 19));
+
+        let regs = [reg(80), reg(100), reg(50)];
+        assert_eq!(mapq(&regs, &p, 19), compute_mapq_ext(100, 80, 100, 100, &p, 19));
+        assert!(mapq(&regs, &p, 19) > 0 && mapq(&regs, &p, 19) < 60);
+    }
+
+    #[test]
+    fn mapq_from_regions_penalizes_repeated_suboptimal_hits() {
+        let p = params();
+        let single = mapq(&[reg(100), reg(80)], &p, 19);
+        let repeated = mapq(&[reg(100), reg(80), reg(80), reg(80)], &p, 19);
+        // n = 2 extra copies of the sub-optimal hit: 4.343 * ln(3) ≈ 4.77 → 5
+        assert_eq!(repeated, single - 5);
+
+        // 区域自身记录的 sub_n（如重复种子）同样扣分
+        let mut best = reg(100);
+        best.sub_n = 2;
+        assert_eq!(mapq(&[best, reg(80)], &p, 19), single - 5);
+    }
+
+    #[test]
+    fn mapq_from_regions_honors_region_sub_score() {
+        let p = params();
+        let mut best = reg(100);
+        best.sub_score = 90;
+        assert_eq!(
+            mapq(&[best, reg(40)], &p, 19),
+            compute_mapq_ext(100, 90, 100, 100, &p, 19)
+        );
+    }
+
+    #[test]
+    fn mapq_from_regions_uses_seed_coverage_and_length() {
+        let p = params();
+        let mut sparse = reg(100);
+        sparse.seed_cov = 30;
+        assert!(mapq(&[sparse, reg(80)], &p, 19) < mapq(&[reg(100), reg(80)], &p, 19));
+
+        // 唯一但很短的比对达不到满分
+        let mut short = reg(20);
+        short.qe = 20;
+        short.re = 20;
+        short.seed_cov = 20;
+        assert!(mapq(&[short], &p, 19) < 10);
+    }
 }
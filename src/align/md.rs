@@ -0,0 +1,90 @@
+use std::fmt::Write as _;
+
+use super::sw::parse_cigar;
+
+/// 按 SAM 规范生成 `MD:Z` 标签内容。
+///
+/// - `query`：CIGAR 覆盖的完整 query（含软剪切部分）
+/// - `reference_window`：参考序列窗口，比对从 `reference_window[ref_start]` 开始
+///
+/// 匹配段输出连续匹配的碱基数，错配输出参考碱基，缺失输出 `^` 加被删除的参考碱基；
+/// 参考为 `N` 的位置不算匹配，即使 query 也是 `N` 也按错配输出 `N`；
+/// `I`/`S`/`H` 不出现在 MD 中，`N` 跳过参考但不输出。结果符合
+/// `[0-9]+(([A-Z]|\^[A-Z]+)[0-9]+)*`：总以数字开头和结尾，相邻的错配/缺失之间补 `0`，
+/// 例如缺失后紧跟错配为 `4^TTT0A3`。比较不区分大小写，输出的参考碱基为大写。
+/// CIGAR 不含 `M`/`=`/`X`/`D` 时返回空串。
+pub fn md_tag(query: &[u8], reference_window: &[u8], cigar: &str, ref_start: usize) -> String {
+    let mut md = String::new();
+    let mut qi = 0usize;
+    let mut ri = ref_start;
+    let mut run = 0usize;
+    let mut aligned = false;
+
+    for (op, len) in parse_cigar(cigar) {
+        match op {
+            'M' | '=' | 'X' => {
+                aligned = true;
+                for _ in 0..len {
+                    let (Some(&q), Some(&r)) = (query.get(qi), reference_window.get(ri)) else {
+                        break;
+                    };
+                    if q.eq_ignore_ascii_case(&r) && !r.eq_ignore_ascii_case(&b'N') {
+                        run += 1;
+                    } else {
+                        let _ = write!(md, "{}", run);
+                        md.push(r.to_ascii_uppercase() as char);
+                        run = 0;
+                    }
+                    qi += 1;
+                    ri += 1;
+                }
+            }
+            'D' => {
+                aligned = true;
+                let _ = write!(md, "{}^", run);
+                run = 0;
+                for &r in reference_window.iter().skip(ri).take(len) {
+                    md.push(r.to_ascii_uppercase() as char);
+                }
+                ri += len;
+            }
+            'N' => ri += len,
+            'I' | 'S' => qi += len,
+            _ => {}
+        }
+    }
+
+    if aligned {
+        let _ = write!(md, "{}", run);
+    }
+    md
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn md_mismatches_and_deletions_follow_spec() {
+        assert_eq!(md_tag(b"ACGTACGT", b"ACGTACGT", "8M", 0), "8");
+        // 首尾错配与相邻错配之间都要有数字
+        assert_eq!(md_tag(b"TCGTACGA", b"ACGTACGT", "8M", 0), "0A6T0");
+        assert_eq!(md_tag(b"ACTTACGT", b"ACGAACGT", "8M", 0), "2G0A4");
+        // 缺失后紧跟错配
+        assert_eq!(md_tag(b"ACGTTCGT", b"ACGTTTTACGT", "4M3D4M", 0), "4^TTT0A3");
+        // 两段缺失之间只有插入
+        assert_eq!(md_tag(b"ACGTGACGT", b"ACGTAACCACGT", "4M2D1I2D4M", 0), "4^AA0^CC4");
+    }
+
+    #[test]
+    fn md_skips_clips_insertions_and_ref_skips() {
+        // 参考窗口内从 ref_start 开始，软剪切与插入只消耗 query
+        let reference = b"GGGGACGTACGTCCCC";
+        assert_eq!(md_tag(b"nnACGTTACGA", reference, "2S4M1I3M1S", 4), "7");
+        assert_eq!(md_tag(b"ACGTACGT", b"ACGTTTTTACGT", "4M4N4M", 0), "8");
+        assert_eq!(md_tag(b"acgt", b"ACGA", "4M", 0), "3A0");
+        // N 对 N 不计入匹配段
+        assert_eq!(md_tag(b"ACNNGT", b"ACNNGT", "6M", 0), "2N0N2");
+        assert_eq!(md_tag(b"", b"", "", 0), "");
+    }
+}
@@ -1,21 +1,56 @@
 pub mod candidate;
 pub mod chain;
+pub mod coverage;
 pub mod extend;
 pub mod insert_size;
 pub mod mapq;
+pub mod md;
+pub mod paired;
 pub mod pipeline;
+pub mod sai;
+pub mod score_report;
 pub mod seed;
+pub mod stats;
 pub mod supplementary;
 pub mod sw;
+#[cfg(feature = "simd")]
+pub mod sw_simd;
+pub mod verify;
 
-pub use candidate::{collect_candidates, dedup_candidates, AlignCandidate};
-pub use chain::{best_chain, build_chains, build_chains_with_limit, filter_chains, Chain};
+pub use candidate::{
+    cigar_ref_length, collect_candidates, collect_candidates_from_smems_with_buf, collect_candidates_with_buf,
+    collect_candidates_with_deadline, dedup_candidates, AlignCandidate, CandidateBuffers,
+};
+pub use chain::{
+    best_chain, best_chain_with_gaps, best_chain_with_params, build_chains, build_chains_with_gap_limits,
+    build_chains_with_limit, build_chains_with_params, filter_chains, retain_top_contigs, Chain, ChainParams,
+};
+pub use coverage::CoverageTrack;
 pub use extend::{chain_to_alignment, chain_to_alignment_with_buf};
-pub use mapq::compute_mapq;
-pub use pipeline::{align_fastq_with_fm_opt, align_fastq_with_opt};
-pub use seed::{find_smem_seeds, find_smem_seeds_with_max_occ, AlnReg, MemSeed};
-pub use supplementary::{are_non_overlapping, classify_alignments, generate_sa_tag, AlignmentType};
-pub use sw::{banded_sw, SwParams, SwResult};
+pub use insert_size::InsertEstimate;
+pub use mapq::{compute_mapq, compute_mapq_ext, mapq};
+pub use md::md_tag;
+pub use paired::{attach_comment, set_mate_fields, tag_mates};
+pub use pipeline::{
+    align_fastq_paired, align_fastq_paired_to_output, align_fastq_paired_with_fm, align_fastq_to_output,
+    align_fastq_with_fm_opt, align_fastq_with_opt, align_manifest, align_manifest_to_output, align_pair_records,
+    align_read, align_read_records, align_sai, align_sai_to_output, estimate_insert_size, header_contigs, open_output,
+};
+pub use sai::{write_sai_file, SaiReader, SaiRecord, SaiWriter};
+pub use score_report::ScoreReport;
+pub use seed::{
+    find_smem_intervals, find_smem_seeds, find_smem_seeds_bidir, find_smem_seeds_bidir_with_max_occ,
+    find_smem_seeds_both_strands, find_smem_seeds_both_strands_with_max_occ, find_smem_seeds_with_max_occ,
+    resume_smem_search, seeds_from_checkpoint, seeds_from_smems, AlnReg, MemSeed, SmemCheckpoint, SmemInterval,
+};
+pub use stats::AlignStats;
+pub use supplementary::{
+    are_non_overlapping, classify_alignments, generate_sa_tag, generate_xa_tag, ref_overlap_fraction,
+    remove_redundant_secondaries, AlignmentType,
+};
+pub use sw::{banded_sw, extend_align, global_align, left_align_indels, SwMode, SwParams, SwResult};
+#[cfg(feature = "simd")]
+pub use sw_simd::banded_sw_simd;
 
 /// Re-export DEFAULT_MAX_OCC from seed module
 pub use seed::DEFAULT_MAX_OCC;
@@ -29,6 +64,9 @@ pub const DEFAULT_MAX_ALIGNMENTS_PER_READ: usize = 5;
 /// Default Z-drop threshold for alignment extension
 pub const DEFAULT_ZDROP: i32 = 100;
 
+/// Default number of reads aligned per batch
+pub const DEFAULT_BATCH_SIZE: usize = 4096;
+
 /// Default maximum insert size for paired-end alignment
 pub const DEFAULT_MAX_INSERT: usize = 500;
 
@@ -59,13 +97,18 @@ impl Default for PairingOpt {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct AlignOpt {
     pub match_score: i32,
     pub mismatch_penalty: i32,
     pub gap_open: i32,
     pub gap_extend: i32,
     pub clip_penalty: i32,
+    /// Penalty for aligning any base against an `N` (read or reference); 0 scores N neutrally
+    pub n_penalty: i32,
+    /// Score IUPAC ambiguity codes as matches against compatible bases. Read codes are kept
+    /// instead of collapsing to N; reference codes are restored from a lossless index.
+    pub iupac: bool,
     pub band_width: usize,
     pub score_threshold: i32,
     pub min_seed_len: usize,
@@ -78,6 +121,73 @@ pub struct AlignOpt {
     pub max_occ: usize,
     /// Z-drop threshold for alignment extension termination
     pub zdrop: i32,
+    /// Gzip level (0-9) used when the output path ends in `.gz`
+    pub compress_level: u32,
+    /// Minimum chain score (seed coverage) required before SW extension.
+    /// `0` disables the filter, values in `(0, 1)` are a fraction of the read
+    /// length, and values `>= 1` are an absolute number of bases.
+    pub min_chain_score: f64,
+    /// Reads read and aligned per batch; output order is preserved within and across batches
+    pub batch_size: usize,
+    /// Per-read alignment time budget in milliseconds (`0` = unlimited); reads
+    /// exceeding it are emitted unmapped with `ZQ:Z:timeout`
+    pub per_read_timeout_ms: u64,
+    /// Skip the reverse-complement pass when the forward strand already has a
+    /// perfect full-length hit (`read_len * match_score`). Reverse-strand
+    /// secondary hits of such reads are not reported.
+    pub strand_shortcut: bool,
+    /// Print a CIGAR-op / score summary of primary alignments to stderr after aligning
+    pub score_report: bool,
+    /// Append each read's FASTQ comment to its SAM records as a `CO:Z` tag
+    pub append_comment: bool,
+    /// Attach the reference bases each mapped record aligned against as a `ZR:Z` tag
+    pub emit_ref: bool,
+    /// Attach the soft-clipped 5' and 3' read bases of mapped records as
+    /// `Z5:Z` / `Z3:Z` tags (original read orientation)
+    pub clip_tags: bool,
+    /// Trim a trailing poly-G run (NovaSeq/NextSeq dark cycles) longer than
+    /// [`POLYG_MIN_LEN`](pipeline::POLYG_MIN_LEN) from each read before aligning
+    pub trim_polyg: bool,
+    /// Serialization of alignment records (SAM, PAF or BED12)
+    pub output_format: crate::io::output::OutputMode,
+    /// Order of the `@SQ` header lines by contig name (`None` = index order).
+    /// Must list every contig exactly once; only the header is affected,
+    /// record contig indices and positions are unchanged.
+    pub sq_order: Option<Vec<String>>,
+    /// Minimum MAPQ of a read's primary alignment (`0` = no filter); reads
+    /// below it are dropped, or emitted unmapped with `low_mapq_as_unmapped`.
+    /// Pairs are filtered as a unit: a pair is dropped only when both mates
+    /// fail, otherwise the failing mate is emitted unmapped
+    pub min_mapq: u8,
+    /// Emit reads failing `min_mapq` as unmapped records instead of dropping them
+    pub low_mapq_as_unmapped: bool,
+    /// Extend only chains on the `max_contigs_per_read` contigs with the most
+    /// total seed length, per strand (`0` = no limit)
+    pub max_contigs_per_read: usize,
+    /// Write a BEDGRAPH per-base coverage track of the aligned reads to this path
+    pub coverage_path: Option<String>,
+    /// Recompute the edit distance of each mapped record against the reference
+    /// and report records whose `NM` disagrees by more than this many edits
+    /// (`None` = no verification)
+    pub verify_tolerance: Option<u32>,
+    /// Report secondary alignments in an `XA:Z` tag on the primary record
+    /// (`rname,{+|-}pos,CIGAR,NM;` per hit) instead of as separate 0x100 records
+    pub xa_tag: bool,
+    /// Input qualities are Phred+64 (Illumina 1.3–1.7); rewrite them to
+    /// Phred+33 before alignment so output QUAL is standards-compliant
+    pub phred64: bool,
+    /// Read group spec (`ID:lane1\tSM:sample1\tPL:ILLUMINA`): written as an
+    /// `@RG` header line, and its `ID` tagged as `RG:Z` on every record
+    pub read_group: Option<String>,
+    /// Align every read end to end: SW runs query-global, placements are never
+    /// soft-clipped, and reads with no full-length placement are unmapped
+    pub no_clip: bool,
+    /// Suppress the stderr progress lines and the end-of-run summary
+    pub quiet: bool,
+    /// Smallest insert size of a proper pair in paired-end alignment
+    pub min_insert: usize,
+    /// Largest insert size of a proper pair in paired-end alignment
+    pub max_insert: usize,
 }
 
 impl Default for AlignOpt {
@@ -88,6 +198,8 @@ impl Default for AlignOpt {
             gap_open: 2,
             gap_extend: 1,
             clip_penalty: 1,
+            n_penalty: 1,
+            iupac: false,
             band_width: 16,
             score_threshold: 20,
             min_seed_len: 19,
@@ -96,6 +208,30 @@ impl Default for AlignOpt {
             max_alignments_per_read: DEFAULT_MAX_ALIGNMENTS_PER_READ,
             max_occ: DEFAULT_MAX_OCC,
             zdrop: DEFAULT_ZDROP,
+            compress_level: crate::io::gzip::DEFAULT_COMPRESS_LEVEL,
+            min_chain_score: 0.0,
+            batch_size: DEFAULT_BATCH_SIZE,
+            per_read_timeout_ms: 0,
+            strand_shortcut: false,
+            score_report: false,
+            append_comment: false,
+            emit_ref: false,
+            clip_tags: false,
+            trim_polyg: false,
+            output_format: crate::io::output::OutputMode::Sam,
+            sq_order: None,
+            min_mapq: 0,
+            low_mapq_as_unmapped: false,
+            max_contigs_per_read: 0,
+            coverage_path: None,
+            verify_tolerance: None,
+            xa_tag: false,
+            phred64: false,
+            read_group: None,
+            no_clip: false,
+            quiet: false,
+            min_insert: DEFAULT_MIN_INSERT,
+            max_insert: DEFAULT_MAX_INSERT,
         }
     }
 }
@@ -121,6 +257,9 @@ impl AlignOpt {
         if self.clip_penalty < 0 {
             return Err("clip_penalty must be non-negative");
         }
+        if self.n_penalty < 0 {
+            return Err("n_penalty must be non-negative");
+        }
         if self.threads == 0 {
             return Err("threads must be greater than 0");
         }
@@ -130,8 +269,103 @@ impl AlignOpt {
         if self.max_alignments_per_read == 0 {
             return Err("max_alignments_per_read must be greater than 0");
         }
+        if self.compress_level > crate::io::gzip::MAX_COMPRESS_LEVEL {
+            return Err("compress_level must be between 0 and 9");
+        }
+        if !self.min_chain_score.is_finite() || self.min_chain_score < 0.0 {
+            return Err("min_chain_score must be a non-negative number");
+        }
+        if self.min_insert > self.max_insert {
+            return Err("min_insert must not exceed max_insert");
+        }
+        if self.batch_size == 0 {
+            return Err("batch_size must be greater than 0");
+        }
+        if let Some(spec) = &self.read_group {
+            if crate::io::manifest::parse_read_group(spec).is_err() {
+                return Err("read_group must be TAG:VALUE fields including ID");
+            }
+        }
         Ok(())
     }
+
+    /// Suspicious but valid parameter combinations, as human-readable warnings.
+    ///
+    /// These do not make the options invalid (see [`AlignOpt::validate`], which
+    /// already rejects a zero band width); they flag scoring that silently
+    /// produces nonsensical alignments.
+    pub fn parameter_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.match_score == 0 {
+            warnings.push("match score is 0: no alignment can score above 0".to_string());
+        }
+        if self.mismatch_penalty == 0 {
+            warnings.push("mismatch penalty is 0: mismatches are free".to_string());
+        }
+        if self.gap_open == 0 && self.gap_extend == 0 {
+            warnings.push("gap open and extension penalties are 0: gaps are free".to_string());
+        } else if self.gap_extend > self.gap_open {
+            warnings.push(format!(
+                "gap extension penalty ({}) exceeds gap open penalty ({}): long gaps cost more to extend than to open",
+                self.gap_extend, self.gap_open
+            ));
+        }
+        let one_base_gap = self.gap_open + self.gap_extend;
+        if self.mismatch_penalty > 0 && one_base_gap < self.mismatch_penalty {
+            warnings.push(format!(
+                "a 1-base gap (gap open + extension = {}) is cheaper than a mismatch ({}): indels will replace mismatches",
+                one_base_gap, self.mismatch_penalty
+            ));
+        }
+        if self.score_threshold <= 0 {
+            warnings.push(format!(
+                "score threshold is {}: every candidate alignment is reported",
+                self.score_threshold
+            ));
+        }
+        warnings
+    }
+
+    /// Paired-end options derived from these options: the proper-pair insert window
+    /// comes from `min_insert`/`max_insert`, everything else from [`PairingOpt::default`].
+    pub fn pairing_opt(&self) -> PairingOpt {
+        PairingOpt {
+            min_insert: self.min_insert,
+            max_insert: self.max_insert,
+            ..PairingOpt::default()
+        }
+    }
+
+    /// Smith-Waterman scoring parameters derived from these options.
+    pub fn sw_params(&self) -> SwParams {
+        SwParams {
+            match_score: self.match_score,
+            mismatch_penalty: self.mismatch_penalty,
+            gap_open: self.gap_open,
+            gap_extend: self.gap_extend,
+            band_width: self.band_width,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: if self.no_clip {
+                SwMode::QueryGlobal
+            } else {
+                SwMode::Local
+            },
+            iupac: self.iupac,
+            n_penalty: self.n_penalty,
+        }
+    }
+
+    /// Resolve `min_chain_score` to an absolute chain score for a read of `read_len` bases.
+    pub fn min_chain_score_for(&self, read_len: usize) -> u32 {
+        if self.min_chain_score <= 0.0 {
+            0
+        } else if self.min_chain_score < 1.0 {
+            (self.min_chain_score * read_len as f64).ceil() as u32
+        } else {
+            self.min_chain_score.ceil() as u32
+        }
+    }
 }
 
 #[cfg(test)]
@@ -144,6 +378,45 @@ mod tests {
         assert!(opt.validate().is_ok());
     }
 
+    #[test]
+    fn parameter_warnings_flag_degenerate_scoring() {
+        assert!(AlignOpt::default().parameter_warnings().is_empty());
+        let warns = |opt: AlignOpt| opt.parameter_warnings().join("\n");
+
+        assert!(warns(AlignOpt {
+            match_score: 0,
+            ..AlignOpt::default()
+        })
+        .contains("match score is 0"));
+        assert!(warns(AlignOpt {
+            mismatch_penalty: 0,
+            ..AlignOpt::default()
+        })
+        .contains("mismatches are free"));
+        assert!(warns(AlignOpt {
+            gap_open: 0,
+            gap_extend: 0,
+            ..AlignOpt::default()
+        })
+        .contains("gaps are free"));
+        assert!(warns(AlignOpt {
+            gap_open: 1,
+            gap_extend: 3,
+            ..AlignOpt::default()
+        })
+        .contains("exceeds gap open penalty"));
+        assert!(warns(AlignOpt {
+            mismatch_penalty: 6,
+            ..AlignOpt::default()
+        })
+        .contains("cheaper than a mismatch"));
+        assert!(warns(AlignOpt {
+            score_threshold: 0,
+            ..AlignOpt::default()
+        })
+        .contains("every candidate alignment"));
+    }
+
     #[test]
     fn align_opt_rejects_zero_band_width() {
         let opt = AlignOpt {
@@ -197,4 +470,80 @@ mod tests {
         };
         assert!(opt.validate().is_err());
     }
+
+    #[test]
+    fn align_opt_rejects_out_of_range_compress_level() {
+        let opt = AlignOpt {
+            compress_level: 10,
+            ..AlignOpt::default()
+        };
+        assert!(opt.validate().is_err());
+    }
+
+    #[test]
+    fn align_opt_rejects_negative_min_chain_score() {
+        let opt = AlignOpt {
+            min_chain_score: -1.0,
+            ..AlignOpt::default()
+        };
+        assert!(opt.validate().is_err());
+    }
+
+    #[test]
+    fn align_opt_rejects_zero_batch_size() {
+        let opt = AlignOpt {
+            batch_size: 0,
+            ..AlignOpt::default()
+        };
+        assert!(opt.validate().is_err());
+    }
+
+    #[test]
+    fn insert_window_feeds_pairing_opt() {
+        let opt = AlignOpt {
+            min_insert: 150,
+            max_insert: 450,
+            ..AlignOpt::default()
+        };
+        let pairing = opt.pairing_opt();
+        assert_eq!((pairing.min_insert, pairing.max_insert), (150, 450));
+        assert_eq!(pairing.pen_unpaired, PairingOpt::default().pen_unpaired);
+        let inverted = AlignOpt {
+            min_insert: 500,
+            max_insert: 100,
+            ..AlignOpt::default()
+        };
+        assert!(inverted.validate().is_err());
+    }
+
+    #[test]
+    fn no_clip_switches_sw_to_query_global() {
+        assert_eq!(AlignOpt::default().sw_params().mode, SwMode::Local);
+        let opt = AlignOpt {
+            no_clip: true,
+            ..AlignOpt::default()
+        };
+        assert_eq!(opt.sw_params().mode, SwMode::QueryGlobal);
+    }
+
+    #[test]
+    fn align_opt_rejects_read_group_without_id() {
+        let opt = AlignOpt {
+            read_group: Some("SM:sample1".to_string()),
+            ..AlignOpt::default()
+        };
+        assert!(opt.validate().is_err());
+    }
+
+    #[test]
+    fn min_chain_score_fraction_or_absolute() {
+        let mut opt = AlignOpt::default();
+        assert_eq!(opt.min_chain_score_for(100), 0);
+        opt.min_chain_score = 0.25;
+        assert_eq!(opt.min_chain_score_for(100), 25);
+        assert_eq!(opt.min_chain_score_for(10), 3);
+        opt.min_chain_score = 30.0;
+        assert_eq!(opt.min_chain_score_for(100), 30);
+        assert_eq!(opt.min_chain_score_for(10), 30);
+    }
 }
@@ -0,0 +1,280 @@
+//! Paired-end SAM record assembly.
+//!
+//! Each mate is aligned on its own; the helpers here turn the two record sets
+//! into a pair: READ1/READ2 flags, each mate's own FASTQ comment, and the mate
+//! fields (RNEXT, PNEXT, TLEN, 0x2/0x8/0x20).
+
+use crate::io::sam::{flags, SamRecord, TagValue};
+
+use super::candidate::cigar_ref_length;
+use super::PairingOpt;
+
+/// Append a FASTQ comment to every record as a `CO:Z` tag.
+///
+/// Tabs are replaced by spaces so the comment stays a single SAM field.
+/// Records are left untouched when `desc` is `None` or empty.
+pub fn attach_comment(records: &mut [SamRecord], desc: Option<&str>) {
+    let Some(desc) = desc.filter(|d| !d.is_empty()) else {
+        return;
+    };
+    let value = desc.replace('\t', " ");
+    for record in records {
+        record.push_tag("CO", TagValue::Str(value.clone()));
+    }
+}
+
+/// Mark the records of both mates as paired (READ1 for `r1`, READ2 for `r2`).
+///
+/// With `append_comment`, each mate's records carry that mate's own comment
+/// (`desc1` on R1, `desc2` on R2).
+pub fn tag_mates(
+    r1: &mut [SamRecord],
+    r2: &mut [SamRecord],
+    desc1: Option<&str>,
+    desc2: Option<&str>,
+    append_comment: bool,
+) {
+    for (records, read_flag, desc) in [(&mut *r1, flags::READ1, desc1), (&mut *r2, flags::READ2, desc2)] {
+        for record in records.iter_mut() {
+            record.flag |= flags::PAIRED | read_flag;
+        }
+        if append_comment {
+            attach_comment(records, desc);
+        }
+    }
+}
+
+/// Placement of a mate's primary record.
+#[derive(Debug, Clone)]
+struct MatePlacement {
+    rname: String,
+    /// 1-based leftmost reference position
+    pos: u32,
+    /// 1-based rightmost reference position
+    end: u32,
+    reverse: bool,
+}
+
+/// Primary record of one mate (first record without the secondary/supplementary flag).
+fn primary_index(records: &[SamRecord]) -> Option<usize> {
+    records
+        .iter()
+        .position(|r| r.flag & (flags::SECONDARY | flags::SUPPLEMENTARY) == 0)
+}
+
+fn placement(records: &[SamRecord]) -> Option<MatePlacement> {
+    let r = &records[primary_index(records)?];
+    if r.is_unmapped() {
+        return None;
+    }
+    let ref_len = cigar_ref_length(&r.cigar);
+    Some(MatePlacement {
+        rname: r.rname.clone(),
+        pos: r.pos,
+        end: r.pos + ref_len.max(1) as u32 - 1,
+        reverse: r.flag & flags::REVERSE != 0,
+    })
+}
+
+/// Signed outer distance between two mates on the same contig; positive for
+/// the leftmost mate (`a` on ties).
+fn outer_tlen(a: &MatePlacement, b: &MatePlacement) -> i64 {
+    let left = a.pos.min(b.pos) as i64;
+    let right = a.end.max(b.end) as i64;
+    let span = right - left + 1;
+    if a.pos <= b.pos {
+        span
+    } else {
+        -span
+    }
+}
+
+/// FR orientation within the insert-size window: the forward mate is leftmost
+/// and the outer distance lies in `[min_insert, max_insert]`.
+fn is_proper_pair(a: &MatePlacement, b: &MatePlacement, pairing: &PairingOpt) -> bool {
+    if a.rname != b.rname || a.reverse == b.reverse {
+        return false;
+    }
+    let (fwd, rev) = if a.reverse { (b, a) } else { (a, b) };
+    let insert = outer_tlen(a, b).unsigned_abs() as usize;
+    fwd.pos <= rev.pos && insert >= pairing.min_insert && insert <= pairing.max_insert
+}
+
+/// Fill each record's mate fields from the other mate's primary placement.
+///
+/// * RNEXT/PNEXT point at the mate (`=` on the same contig), and 0x20 mirrors
+///   the mate's strand; 0x8 is set when the mate is unmapped.
+/// * An unmapped mate of a mapped read takes that read's RNAME/POS.
+/// * TLEN is the signed outer distance of the two primary records on the same
+///   contig (0 otherwise); 0x2 marks FR pairs within the insert-size window.
+pub fn set_mate_fields(r1: &mut [SamRecord], r2: &mut [SamRecord], pairing: &PairingOpt) {
+    let p1 = placement(r1);
+    let p2 = placement(r2);
+
+    // Unmapped mates are placed at their mapped partner.
+    for (records, partner) in [(&mut *r1, &p2), (&mut *r2, &p1)] {
+        if let (Some(partner), Some(i)) = (partner, primary_index(records)) {
+            if records[i].is_unmapped() {
+                records[i].rname = partner.rname.clone();
+                records[i].pos = partner.pos;
+            }
+        }
+    }
+
+    let proper = match (&p1, &p2) {
+        (Some(a), Some(b)) => is_proper_pair(a, b, pairing),
+        _ => false,
+    };
+    for (records, own, mate) in [(&mut *r1, &p1, &p2), (&mut *r2, &p2, &p1)] {
+        let primary = primary_index(records);
+        for (i, record) in records.iter_mut().enumerate() {
+            record.flag |= flags::PAIRED;
+            match mate.as_ref().or(own.as_ref()) {
+                Some(target) => {
+                    record.rnext = if target.rname == record.rname {
+                        "=".to_string()
+                    } else {
+                        target.rname.clone()
+                    };
+                    record.pnext = target.pos;
+                }
+                None => {
+                    record.rnext = "*".to_string();
+                    record.pnext = 0;
+                }
+            }
+            match mate {
+                Some(m) if m.reverse => record.flag |= flags::MREVERSE,
+                Some(_) => {}
+                None => record.flag |= flags::MUNMAP,
+            }
+            if Some(i) == primary {
+                if let (Some(a), Some(b)) = (own, mate) {
+                    if a.rname == b.rname {
+                        record.tlen = outer_tlen(a, b);
+                    }
+                }
+                if proper {
+                    record.flag |= flags::PROPER_PAIR;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comments(records: &[SamRecord]) -> Vec<Option<&TagValue>> {
+        records.iter().map(|r| r.tag("CO")).collect()
+    }
+
+    #[test]
+    fn mates_keep_their_own_comments_and_read_flags() {
+        let mut r1 = vec![
+            SamRecord::mapped("p", 0, "chr1", 10, 60, "4M", "ACGT", "IIII"),
+            SamRecord::mapped("p", flags::SECONDARY, "chr1", 90, 0, "4M", "ACGT", "IIII"),
+        ];
+        let mut r2 = vec![SamRecord::mapped(
+            "p",
+            flags::REVERSE,
+            "chr1",
+            200,
+            60,
+            "4M",
+            "TTAA",
+            "JJJJ",
+        )];
+        tag_mates(&mut r1, &mut r2, Some("BC:Z:AAAA r1"), Some("BC:Z:CCCC\tr2"), true);
+
+        let c1 = TagValue::Str("BC:Z:AAAA r1".to_string());
+        let c2 = TagValue::Str("BC:Z:CCCC r2".to_string());
+        assert_eq!(comments(&r1), vec![Some(&c1), Some(&c1)]);
+        assert_eq!(comments(&r2), vec![Some(&c2)]);
+        assert!(r1
+            .iter()
+            .all(|r| r.flag & (flags::PAIRED | flags::READ1) == flags::PAIRED | flags::READ1));
+        assert!(r1.iter().all(|r| r.flag & flags::READ2 == 0));
+        assert_eq!(r2[0].flag, flags::PAIRED | flags::READ2 | flags::REVERSE);
+        assert!(r2[0].to_string().ends_with("\tCO:Z:BC:Z:CCCC r2"));
+    }
+
+    #[test]
+    fn comments_are_skipped_when_disabled_or_absent() {
+        let mut r1 = vec![SamRecord::unmapped("p", "ACGT", "IIII")];
+        let mut r2 = vec![SamRecord::unmapped("p", "ACGT", "IIII")];
+        tag_mates(&mut r1, &mut r2, Some("x"), Some("y"), false);
+        assert!(r1[0].tag("CO").is_none() && r2[0].tag("CO").is_none());
+
+        tag_mates(&mut r1, &mut r2, None, Some(""), true);
+        assert!(r1[0].tag("CO").is_none() && r2[0].tag("CO").is_none());
+    }
+
+    fn mapped(flag: u16, rname: &str, pos: u32, cigar: &str) -> SamRecord {
+        SamRecord::mapped("p", flag, rname, pos, 60, cigar, "ACGTACGTAC", "IIIIIIIIII")
+    }
+
+    #[test]
+    fn fr_pair_gets_mate_fields_and_proper_flag() {
+        let mut r1 = vec![mapped(0, "chr1", 100, "10M")];
+        let mut r2 = vec![mapped(flags::REVERSE, "chr1", 291, "5M2D5M")];
+        set_mate_fields(&mut r1, &mut r2, &PairingOpt::default());
+
+        // outer span: 100..=302
+        assert_eq!((r1[0].rnext.as_str(), r1[0].pnext, r1[0].tlen), ("=", 291, 203));
+        assert_eq!((r2[0].rnext.as_str(), r2[0].pnext, r2[0].tlen), ("=", 100, -203));
+        assert_eq!(r1[0].flag, flags::PAIRED | flags::PROPER_PAIR | flags::MREVERSE);
+        assert_eq!(r2[0].flag, flags::PAIRED | flags::PROPER_PAIR | flags::REVERSE);
+    }
+
+    #[test]
+    fn improper_orientation_distance_or_contig_is_not_proper() {
+        let pairing = PairingOpt::default();
+        // RF: reverse mate on the left
+        let mut r1 = vec![mapped(flags::REVERSE, "chr1", 100, "10M")];
+        let mut r2 = vec![mapped(0, "chr1", 200, "10M")];
+        set_mate_fields(&mut r1, &mut r2, &pairing);
+        assert_eq!(r1[0].flag & flags::PROPER_PAIR, 0);
+        assert_eq!(r1[0].tlen, 110);
+
+        // too far apart
+        let mut r1 = vec![mapped(0, "chr1", 100, "10M")];
+        let mut r2 = vec![mapped(flags::REVERSE, "chr1", 5000, "10M")];
+        set_mate_fields(&mut r1, &mut r2, &pairing);
+        assert_eq!(r2[0].flag & flags::PROPER_PAIR, 0);
+
+        // different contigs: RNEXT names the mate contig, TLEN 0
+        let mut r1 = vec![mapped(0, "chr1", 100, "10M")];
+        let mut r2 = vec![mapped(flags::REVERSE, "chr2", 50, "10M")];
+        set_mate_fields(&mut r1, &mut r2, &pairing);
+        assert_eq!((r1[0].rnext.as_str(), r1[0].pnext, r1[0].tlen), ("chr2", 50, 0));
+        assert_eq!((r2[0].rnext.as_str(), r2[0].pnext, r2[0].tlen), ("chr1", 100, 0));
+    }
+
+    #[test]
+    fn unmapped_mate_is_placed_at_its_partner() {
+        let mut r1 = vec![
+            mapped(flags::REVERSE, "chr1", 100, "10M"),
+            mapped(flags::SECONDARY, "chr2", 7, "10M"),
+        ];
+        let mut r2 = vec![SamRecord::unmapped("p", "ACGT", "IIII")];
+        set_mate_fields(&mut r1, &mut r2, &PairingOpt::default());
+
+        assert_eq!(r1[0].flag, flags::PAIRED | flags::REVERSE | flags::MUNMAP);
+        assert_eq!((r1[0].rnext.as_str(), r1[0].pnext, r1[0].tlen), ("=", 100, 0));
+        assert_eq!((r1[1].rnext.as_str(), r1[1].pnext), ("chr1", 100));
+        assert_eq!((r2[0].rname.as_str(), r2[0].pos), ("chr1", 100));
+        assert_eq!((r2[0].rnext.as_str(), r2[0].pnext), ("=", 100));
+        assert_eq!(r2[0].flag, flags::PAIRED | flags::UNMAP | flags::MREVERSE);
+
+        // both unmapped: no placement
+        let mut r1 = vec![SamRecord::unmapped("p", "ACGT", "IIII")];
+        let mut r2 = vec![SamRecord::unmapped("p", "ACGT", "IIII")];
+        set_mate_fields(&mut r1, &mut r2, &PairingOpt::default());
+        for r in r1.iter().chain(&r2) {
+            assert_eq!(r.flag, flags::PAIRED | flags::UNMAP | flags::MUNMAP);
+            assert_eq!((r.rname.as_str(), r.pos, r.rnext.as_str(), r.pnext), ("*", 0, "*", 0));
+        }
+    }
+}
@@ -1,22 +1,48 @@
 use anyhow::Result;
-use std::io::Write;
+use std::borrow::Cow;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use rayon::prelude::*;
 
-use crate::index::fm::FMIndex;
-use crate::io::fastq::{FastqReader, FastqRecord};
-use crate::io::sam;
+use crate::index::fm::{FMIndex, IndexMeta};
+use crate::io::bam::{self, BamFormat};
+use crate::io::fastq::{trim_homopolymer_tail, FastqReader, FastqRecord, PairedFastqReader, ReadPair};
+use crate::io::gzip::{open_maybe_gzip, OutputWriter};
+use crate::io::manifest::{
+    add_rg_line, format_rg_line, parse_read_group, read_manifest, rg_header_lines, ManifestEntry,
+};
+use crate::io::output::{OutputFormat, OutputMode};
+use crate::io::sam::{flags, ContigSplitWriter, SamRecord, TagValue};
 use crate::util::dna;
+use crate::util::qual::{self, PHRED33, PHRED64};
 
-use super::candidate::{collect_candidates, dedup_candidates, AlignCandidate};
-use super::mapq::compute_mapq;
-use super::supplementary::{classify_alignments, generate_sa_tag, AlignmentType};
-use super::AlignOpt;
+use super::candidate::{
+    collect_candidates_from_smems_with_buf, collect_candidates_with_buf, dedup_candidates, AlignCandidate,
+    CandidateBuffers,
+};
+use super::coverage::CoverageTrack;
+use super::insert_size::InsertEstimate;
+use super::mapq::mapq;
+use super::md::md_tag;
+use super::paired::{attach_comment, set_mate_fields, tag_mates};
+use super::sai::{next_sai_for, strand_smems, SaiReader, SaiRecord};
+use super::score_report::ScoreReport;
+use super::stats::AlignStats;
+use super::supplementary::{
+    classify_alignments, generate_sa_tag, generate_xa_tag, remove_redundant_secondaries, AlignmentType,
+};
+use super::verify::verify_nm;
 use super::SwParams;
+use super::{AlignOpt, PairingOpt};
 
-pub fn align_fastq_with_opt(index_path: &str, fastq_path: &str, out_path: Option<&str>, opt: AlignOpt) -> Result<()> {
-    let fm = Arc::new(FMIndex::load_from_file(index_path)?);
+pub fn align_fastq_with_opt(
+    index_path: &str,
+    fastq_path: &str,
+    out_path: Option<&str>,
+    opt: AlignOpt,
+) -> Result<AlignStats> {
+    let fm = Arc::new(load_index(index_path)?);
     align_fastq_with_fm_opt(fm, fastq_path, out_path, opt)
 }
 
@@ -25,81 +51,765 @@ pub fn align_fastq_with_fm_opt(
     fastq_path: &str,
     out_path: Option<&str>,
     opt: AlignOpt,
-) -> Result<()> {
-    let fq = std::fs::File::open(fastq_path)?;
-    let mut reader = FastqReader::new(std::io::BufReader::new(fq));
+) -> Result<AlignStats> {
+    let mut out = open_output(out_path, &opt)?;
+    align_fastq_to_output(&fm, fastq_path, out.as_mut(), opt)
+}
 
-    let mut out_box: Box<dyn Write> = if let Some(p) = out_path {
-        Box::new(std::io::BufWriter::new(std::fs::File::create(p)?))
-    } else {
-        Box::new(std::io::BufWriter::new(std::io::stdout()))
+/// 加载索引；其记录的参考文件在构建后被修改过时向 stderr 输出警告
+fn load_index(index_path: &str) -> Result<FMIndex> {
+    let fm = FMIndex::load_from_file(index_path)?;
+    if let Some(warning) = fm.meta().and_then(IndexMeta::reference_warning) {
+        eprintln!("[bwa-rust] Warning: {}", warning);
+    }
+    Ok(fm)
+}
+
+/// 单端比对，记录交给任意 [`OutputFormat`] 序列化：依次调用 `write_header`、逐条 `write_record` 与 `finish`
+pub fn align_fastq_to_output(
+    fm: &FMIndex,
+    fastq_path: &str,
+    out: &mut dyn OutputFormat,
+    opt: AlignOpt,
+) -> Result<AlignStats> {
+    align_sources_to_output(fm, &[(fastq_path, None)], None, &[], out, opt)
+}
+
+/// 按清单（[`read_manifest`]）依次比对多个 FASTQ，写入同一输出：
+/// 头部包含各读组的 `@RG` 行，每个文件的记录带上其读组的 `RG:Z` 标签
+pub fn align_manifest(
+    index_path: &str,
+    manifest_path: &str,
+    out_path: Option<&str>,
+    opt: AlignOpt,
+) -> Result<AlignStats> {
+    let entries = read_manifest(manifest_path)?;
+    let fm = load_index(index_path)?;
+    let mut out = open_output(out_path, &opt)?;
+    align_manifest_to_output(&fm, &entries, out.as_mut(), opt)
+}
+
+/// 同 [`align_manifest`]，使用已加载的索引与已解析的清单条目
+pub fn align_manifest_to_output(
+    fm: &FMIndex,
+    entries: &[ManifestEntry],
+    out: &mut dyn OutputFormat,
+    opt: AlignOpt,
+) -> Result<AlignStats> {
+    let sources: Vec<(&str, Option<&str>)> = entries.iter().map(|e| (e.path.as_str(), e.read_group_id())).collect();
+    align_sources_to_output(fm, &sources, None, &rg_header_lines(entries)?, out, opt)
+}
+
+/// `samse`：读取 `aln` 写出的 `.sai` 文件（见 [`write_sai_file`](super::sai::write_sai_file)），
+/// 跳过种子搜索，由保存的 SMEM 直接延伸并输出比对结果。`.sai` 须与 `fastq_path` 逐条对应。
+pub fn align_sai(
+    index_path: &str,
+    sai_path: &str,
+    fastq_path: &str,
+    out_path: Option<&str>,
+    opt: AlignOpt,
+) -> Result<AlignStats> {
+    let fm = load_index(index_path)?;
+    let mut out = open_output(out_path, &opt)?;
+    align_sai_to_output(&fm, sai_path, fastq_path, out.as_mut(), opt)
+}
+
+/// 同 [`align_sai`]，使用已加载的索引并写入任意 [`OutputFormat`]
+pub fn align_sai_to_output(
+    fm: &FMIndex,
+    sai_path: &str,
+    fastq_path: &str,
+    out: &mut dyn OutputFormat,
+    opt: AlignOpt,
+) -> Result<AlignStats> {
+    align_sources_to_output(fm, &[(fastq_path, None)], Some(sai_path), &[], out, opt)
+}
+
+/// 单端比对多个 `(FASTQ 路径, 读组 ID)` 来源，共用一个头部与输出
+fn align_sources_to_output(
+    fm: &FMIndex,
+    sources: &[(&str, Option<&str>)],
+    sai_path: Option<&str>,
+    header_lines: &[String],
+    out: &mut dyn OutputFormat,
+    opt: AlignOpt,
+) -> Result<AlignStats> {
+    let started = Instant::now();
+    let opt_rg = opt_read_group(&opt)?;
+    let mut header_lines = header_lines.to_vec();
+    if let Some((line, _)) = &opt_rg {
+        add_rg_line(&mut header_lines, line.clone())?;
+    }
+    out.write_header_with_lines(&header_contigs(fm, &opt)?, &header_lines)?;
+    let sw_params = opt.sw_params();
+    let pool = build_pool(opt.threads)?;
+
+    let mut report = opt.score_report.then(ScoreReport::new);
+    let mut coverage = coverage_track(fm, &opt);
+    let mut stats = AlignStats::new();
+    let mut verify_failures = 0usize;
+    let mut qual_checked = false;
+    let mut progress_reported = 0u64;
+    // 顺序执行时跨 read 复用；并行时每个工作线程各持有一份
+    let mut bufs = CandidateBuffers::new();
+
+    // 批量读取 reads 并行处理：同一批共享线程池调度，按输入顺序输出
+    let batch_size = opt.batch_size;
+    let mut sai_reader = sai_path.map(|p| SaiReader::open(p, fm)).transpose()?;
+    for &(fastq_path, read_group) in sources {
+        // 清单中未指定读组的文件使用 `opt.read_group`
+        let read_group = read_group.or(opt_rg.as_ref().map(|(_, id)| id.as_str()));
+        let mut reader = FastqReader::new(open_maybe_gzip(fastq_path)?);
+        loop {
+            let mut batch: Vec<FastqRecord> = Vec::with_capacity(batch_size);
+            for _ in 0..batch_size {
+                match reader.next_record()? {
+                    Some(rec) => batch.push(rec),
+                    None => break,
+                }
+            }
+            if batch.is_empty() {
+                break;
+            }
+            normalize_quals(batch.iter_mut().map(|r| &mut r.qual), &opt, &mut qual_checked);
+            let sais: Vec<Option<Vec<SaiRecord>>> = match sai_reader.as_mut() {
+                Some(reader) => batch
+                    .iter()
+                    .map(|rec| next_sai_for(reader, rec).map(Some))
+                    .collect::<Result<_>>()?,
+                None => vec![None; batch.len()],
+            };
+
+            if let Some(pool) = &pool {
+                let results: Vec<Vec<SamRecord>> = pool.install(|| {
+                    batch
+                        .par_iter()
+                        .zip(&sais)
+                        .map_init(CandidateBuffers::new, |bufs, (rec, sai)| {
+                            align_single_read_records_with_buf(fm, rec, sai.as_deref(), sw_params, &opt, bufs)
+                        })
+                        .collect()
+                });
+
+                for mut records in results {
+                    tag_read_group(&mut records, read_group);
+                    for record in &records {
+                        out.write_record(record)?;
+                    }
+                    records.iter().for_each(|r| stats.add(r));
+                    verify_failures += report_nm_discrepancies(fm, &records, &opt);
+                    if let Some(report) = report.as_mut() {
+                        records.iter().for_each(|r| report.add(r));
+                    }
+                    if let Some(coverage) = coverage.as_mut() {
+                        records.iter().for_each(|r| coverage.add(r));
+                    }
+                }
+            } else {
+                for (rec, sai) in batch.iter().zip(&sais) {
+                    let mut records =
+                        align_single_read_records_with_buf(fm, rec, sai.as_deref(), sw_params, &opt, &mut bufs);
+                    tag_read_group(&mut records, read_group);
+                    for record in &records {
+                        out.write_record(record)?;
+                    }
+                    records.iter().for_each(|r| stats.add(r));
+                    verify_failures += report_nm_discrepancies(fm, &records, &opt);
+                    if let Some(report) = report.as_mut() {
+                        records.iter().for_each(|r| report.add(r));
+                    }
+                    if let Some(coverage) = coverage.as_mut() {
+                        records.iter().for_each(|r| coverage.add(r));
+                    }
+                }
+            }
+            report_progress(&stats, &mut progress_reported, started, &opt);
+        }
+    }
+
+    out.finish()?;
+    if let Some(report) = report {
+        eprint!("{}", report);
+    }
+    if opt.verify_tolerance.is_some() {
+        eprintln!("[verify] {} record(s) with inconsistent NM", verify_failures);
+    }
+    if let (Some(coverage), Some(path)) = (coverage, opt.coverage_path.as_deref()) {
+        coverage.write_bedgraph_file(path)?;
+    }
+    stats.elapsed_secs = started.elapsed().as_secs_f64();
+    if !opt.quiet {
+        eprint!("{}", stats.summary());
+    }
+    Ok(stats)
+}
+
+/// 每比对这么多条 read（双端时按 mate 计）向 stderr 输出一行进度
+pub const PROGRESS_INTERVAL: u64 = 1_000_000;
+
+/// 自上次输出后跨过 [`PROGRESS_INTERVAL`] 的整数倍时输出进度行；统计在主线程按批累加，无需原子计数
+fn report_progress(stats: &AlignStats, reported: &mut u64, started: Instant, opt: &AlignOpt) {
+    if opt.quiet || stats.reads / PROGRESS_INTERVAL <= *reported / PROGRESS_INTERVAL {
+        return;
+    }
+    *reported = stats.reads;
+    eprintln!("{}", stats.progress_line(started.elapsed().as_secs_f64()));
+}
+
+/// 解析 `opt.read_group`，返回其 `(@RG 头部行, ID)`
+fn opt_read_group(opt: &AlignOpt) -> Result<Option<(String, String)>> {
+    let Some(spec) = opt.read_group.as_deref() else {
+        return Ok(None);
     };
+    let fields = parse_read_group(spec)?;
+    let id = fields
+        .iter()
+        .find(|(tag, _)| tag == "ID")
+        .map(|(_, v)| v.clone())
+        .unwrap_or_default();
+    Ok(Some((format_rg_line(&fields), id)))
+}
 
-    // SAM header
-    let contig_info: Vec<(&str, u32)> = fm.contigs.iter().map(|c| (c.name.as_str(), c.len)).collect();
-    sam::write_header(&mut out_box, &contig_info)?;
+/// 为来自带读组文件的记录追加 `RG:Z` 标签
+fn tag_read_group(records: &mut [SamRecord], read_group: Option<&str>) {
+    if let Some(id) = read_group {
+        for record in records.iter_mut() {
+            record.push_tag("RG", TagValue::Str(id.to_string()));
+        }
+    }
+}
 
-    let sw_params = SwParams {
-        match_score: opt.match_score,
-        mismatch_penalty: opt.mismatch_penalty,
-        gap_open: opt.gap_open,
-        gap_extend: opt.gap_extend,
-        band_width: opt.band_width,
+/// 设置了 `opt.verify_tolerance` 时重算各已比对记录的编辑距离，将 NM 不一致的记录输出到 stderr，返回其条数
+fn report_nm_discrepancies(fm: &FMIndex, records: &[SamRecord], opt: &AlignOpt) -> usize {
+    let Some(tolerance) = opt.verify_tolerance else {
+        return 0;
     };
+    let mut flagged = 0;
+    for record in records {
+        if let Some(d) = verify_nm(fm, record, tolerance) {
+            eprintln!(
+                "[verify] NM:i:{} but edit distance is {}: {}",
+                d.reported, d.recomputed, record
+            );
+            flagged += 1;
+        }
+    }
+    flagged
+}
 
-    // 仅在多线程模式下创建自定义 rayon 线程池，单线程直接顺序执行以减少开销
-    let pool = if opt.threads > 1 {
-        let p = rayon::ThreadPoolBuilder::new()
-            .num_threads(opt.threads)
-            .build()
-            .map_err(|e| anyhow::anyhow!("failed to build thread pool with {} threads: {}", opt.threads, e))?;
-        Some(p)
-    } else {
-        None
+/// 设置了 `opt.coverage_path` 时按索引 contig 顺序创建覆盖度轨道
+fn coverage_track(fm: &FMIndex, opt: &AlignOpt) -> Option<CoverageTrack> {
+    opt.coverage_path.as_ref()?;
+    let contigs: Vec<(&str, u32)> = fm.contigs.iter().map(|c| (c.name.as_str(), c.len)).collect();
+    Some(CoverageTrack::new(&contigs))
+}
+
+/// `@SQ` 头部使用的 contig `(名称, 长度)`，供 [`OutputFormat::write_header`] 使用。
+///
+/// 默认为索引顺序；设置了 `opt.sq_order` 时按其重排，须恰好列出每个 contig 一次，
+/// 否则报错。仅影响头部顺序，记录中的 contig 与 POS 不变。
+pub fn header_contigs<'a>(fm: &'a FMIndex, opt: &AlignOpt) -> Result<Vec<(&'a str, u32)>> {
+    let contigs: Vec<(&str, u32)> = fm.contigs.iter().map(|c| (c.name.as_str(), c.len)).collect();
+    let Some(order) = &opt.sq_order else {
+        return Ok(contigs);
     };
+    let mut placed = vec![false; contigs.len()];
+    let mut out = Vec::with_capacity(contigs.len());
+    for name in order {
+        let idx = contigs
+            .iter()
+            .position(|&(n, _)| n == name)
+            .ok_or_else(|| anyhow::anyhow!("@SQ order names unknown contig '{}'", name))?;
+        if std::mem::replace(&mut placed[idx], true) {
+            anyhow::bail!("@SQ order lists contig '{}' more than once", name);
+        }
+        out.push(contigs[idx]);
+    }
+    if let Some(idx) = placed.iter().position(|&p| !p) {
+        anyhow::bail!("@SQ order is missing contig '{}'", contigs[idx].0);
+    }
+    Ok(out)
+}
+
+/// 按 `opt.output_format` 打开输出：SAM 的 `{contig}` 模板按 contig 拆分，`.bam` 路径写 BGZF 压缩的 BAM，
+/// 否则写单一文件或 stdout
+pub fn open_output(out_path: Option<&str>, opt: &AlignOpt) -> Result<Box<dyn OutputFormat>> {
+    match out_path {
+        Some(p) if bam::is_bam_path(p) => {
+            if opt.output_format != OutputMode::Sam {
+                anyhow::bail!("BAM output requires --output-format sam");
+            }
+            Ok(Box::new(BamFormat::create(p, opt.compress_level)?))
+        }
+        Some(p) if ContigSplitWriter::is_template(p) => {
+            if opt.output_format != OutputMode::Sam {
+                anyhow::bail!("split {{contig}} output is only supported for SAM");
+            }
+            Ok(Box::new(ContigSplitWriter::new(p, Vec::new(), opt.compress_level)))
+        }
+        Some(p) => Ok(opt.output_format.formatter(OutputWriter::create(p, opt.compress_level)?)),
+        None => Ok(opt.output_format.formatter(OutputWriter::stdout())),
+    }
+}
+
+/// 仅在多线程模式下创建自定义 rayon 线程池，单线程直接顺序执行以减少开销
+pub(crate) fn build_pool(threads: usize) -> Result<Option<rayon::ThreadPool>> {
+    if threads <= 1 {
+        return Ok(None);
+    }
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map(Some)
+        .map_err(|e| anyhow::anyhow!("failed to build thread pool with {} threads: {}", threads, e))
+}
+
+/// 双端比对：从索引文件加载后调用 [`align_fastq_paired_with_fm`]
+pub fn align_fastq_paired(
+    index_path: &str,
+    r1_path: &str,
+    r2_path: &str,
+    out_path: Option<&str>,
+    opt: AlignOpt,
+) -> Result<AlignStats> {
+    let fm = Arc::new(load_index(index_path)?);
+    align_fastq_paired_with_fm(fm, r1_path, r2_path, out_path, opt)
+}
+
+/// 双端比对：按顺序同步读取 R1/R2，两端各自独立比对后补全 mate 字段，
+/// 每对 reads 依次输出 R1、R2 的全部记录。
+pub fn align_fastq_paired_with_fm(
+    fm: Arc<FMIndex>,
+    r1_path: &str,
+    r2_path: &str,
+    out_path: Option<&str>,
+    opt: AlignOpt,
+) -> Result<AlignStats> {
+    let mut out = open_output(out_path, &opt)?;
+    align_fastq_paired_to_output(&fm, r1_path, r2_path, out.as_mut(), opt)
+}
+
+/// 双端比对，记录交给任意 [`OutputFormat`] 序列化
+pub fn align_fastq_paired_to_output(
+    fm: &FMIndex,
+    r1_path: &str,
+    r2_path: &str,
+    out: &mut dyn OutputFormat,
+    opt: AlignOpt,
+) -> Result<AlignStats> {
+    let started = Instant::now();
+    let mut reader = PairedFastqReader::new_separate(open_maybe_gzip(r1_path)?, open_maybe_gzip(r2_path)?);
+    let opt_rg = opt_read_group(&opt)?;
+    let header_lines: Vec<String> = opt_rg.iter().map(|(line, _)| line.clone()).collect();
+    out.write_header_with_lines(&header_contigs(fm, &opt)?, &header_lines)?;
+    let pool = build_pool(opt.threads)?;
+    let pairing = opt.pairing_opt();
+    let mut report = opt.score_report.then(ScoreReport::new);
+    let mut coverage = coverage_track(fm, &opt);
+    let mut stats = AlignStats::new();
+    let mut verify_failures = 0usize;
+    let mut qual_checked = false;
+    let mut progress_reported = 0u64;
 
-    // 批量读取 reads 并行处理
-    let batch_size = 1000;
     loop {
-        let mut batch: Vec<FastqRecord> = Vec::with_capacity(batch_size);
-        for _ in 0..batch_size {
-            match reader.next_record()? {
-                Some(rec) => batch.push(rec),
+        let mut batch: Vec<ReadPair> = Vec::with_capacity(opt.batch_size);
+        for _ in 0..opt.batch_size {
+            match reader.next_pair()? {
+                Some(pair) => batch.push(pair),
                 None => break,
             }
         }
         if batch.is_empty() {
             break;
         }
+        normalize_quals(
+            batch.iter_mut().flat_map(|p| [&mut p.qual1, &mut p.qual2]),
+            &opt,
+            &mut qual_checked,
+        );
 
-        if let Some(pool) = &pool {
-            let fm_ref = Arc::clone(&fm);
-            let results: Vec<Vec<String>> = pool.install(|| {
+        let results: Vec<Vec<SamRecord>> = match &pool {
+            Some(pool) => pool.install(|| {
                 batch
                     .par_iter()
-                    .map(|rec| align_single_read(&fm_ref, rec, sw_params, &opt))
+                    .map_init(CandidateBuffers::new, |bufs, pair| {
+                        align_pair_records_with_buf(fm, pair, &opt, &pairing, bufs)
+                    })
+                    .collect()
+            }),
+            None => {
+                let mut bufs = CandidateBuffers::new();
+                batch
+                    .iter()
+                    .map(|pair| align_pair_records_with_buf(fm, pair, &opt, &pairing, &mut bufs))
                     .collect()
-            });
-
-            for lines in results {
-                for line in lines {
-                    writeln!(out_box, "{}", line)?;
-                }
             }
-        } else {
-            for rec in &batch {
-                for line in align_single_read(&fm, rec, sw_params, &opt) {
-                    writeln!(out_box, "{}", line)?;
-                }
+        };
+        for mut records in results {
+            tag_read_group(&mut records, opt_rg.as_ref().map(|(_, id)| id.as_str()));
+            for record in &records {
+                out.write_record(record)?;
+            }
+            records.iter().for_each(|r| stats.add(r));
+            verify_failures += report_nm_discrepancies(fm, &records, &opt);
+            if let Some(report) = report.as_mut() {
+                records.iter().for_each(|r| report.add(r));
+            }
+            if let Some(coverage) = coverage.as_mut() {
+                records.iter().for_each(|r| coverage.add(r));
             }
         }
+        report_progress(&stats, &mut progress_reported, started, &opt);
+    }
+
+    out.finish()?;
+    if let Some(report) = report {
+        eprint!("{}", report);
+    }
+    if opt.verify_tolerance.is_some() {
+        eprintln!("[verify] {} record(s) with inconsistent NM", verify_failures);
+    }
+    if let (Some(coverage), Some(path)) = (coverage, opt.coverage_path.as_deref()) {
+        coverage.write_bedgraph_file(path)?;
+    }
+    stats.elapsed_secs = started.elapsed().as_secs_f64();
+    if !opt.quiet {
+        eprint!("{}", stats.summary());
+    }
+    Ok(stats)
+}
+
+/// 插入片段估计时，两端主比对均须达到的最低 MAPQ（只采用唯一比对的 read 对）
+pub const ESTIMATE_MIN_MAPQ: u8 = 20;
+
+/// 插入片段估计时判定 proper pair 所用的宽窗口上限
+const ESTIMATE_MAX_INSERT: usize = 100_000;
+
+/// 从前 `max_pairs` 对 reads 估计插入片段分布（同 BWA-MEM 的自动估计）。
+///
+/// 以不含 mate rescue 的宽窗口比对，只采用两端主比对 MAPQ 均不低于 [`ESTIMATE_MIN_MAPQ`]、
+/// 同一 contig 上 FR 方向的 proper pair，取其 |TLEN|。没有可用的 read 对时返回 `None`。
+pub fn estimate_insert_size(
+    fm: &FMIndex,
+    r1_path: &str,
+    r2_path: &str,
+    opt: &AlignOpt,
+    max_pairs: usize,
+) -> Result<Option<InsertEstimate>> {
+    let mut reader = PairedFastqReader::new_separate(open_maybe_gzip(r1_path)?, open_maybe_gzip(r2_path)?);
+    let pool = build_pool(opt.threads)?;
+    let pairing = PairingOpt {
+        min_insert: 0,
+        max_insert: ESTIMATE_MAX_INSERT,
+        mate_rescue: false,
+        ..PairingOpt::default()
+    };
+
+    let mut batch: Vec<ReadPair> = Vec::with_capacity(max_pairs.min(opt.batch_size));
+    while batch.len() < max_pairs {
+        match reader.next_pair()? {
+            Some(pair) => batch.push(pair),
+            None => break,
+        }
+    }
+    let results: Vec<Vec<SamRecord>> = match &pool {
+        Some(pool) => pool.install(|| {
+            batch
+                .par_iter()
+                .map_init(CandidateBuffers::new, |bufs, pair| {
+                    align_pair_records_with_buf(fm, pair, opt, &pairing, bufs)
+                })
+                .collect()
+        }),
+        None => {
+            let mut bufs = CandidateBuffers::new();
+            batch
+                .iter()
+                .map(|pair| align_pair_records_with_buf(fm, pair, opt, &pairing, &mut bufs))
+                .collect()
+        }
+    };
+
+    let mut samples = Vec::new();
+    for records in &results {
+        let primaries: Vec<&SamRecord> = records
+            .iter()
+            .filter(|r| r.flag & (flags::SECONDARY | flags::SUPPLEMENTARY) == 0)
+            .collect();
+        let confident = primaries.len() == 2
+            && primaries
+                .iter()
+                .all(|r| r.flag & flags::PROPER_PAIR != 0 && r.mapq >= ESTIMATE_MIN_MAPQ);
+        if confident {
+            samples.push(primaries[0].tlen.unsigned_abs() as i32);
+        }
+    }
+    Ok(InsertEstimate::from_samples(&samples))
+}
+
+/// 比对一对 reads：两端独立比对，设置 READ1/READ2、mate 字段与各自的注释，
+/// 返回 R1 的记录后接 R2 的记录。
+pub fn align_pair_records(fm: &FMIndex, pair: &ReadPair, opt: &AlignOpt, pairing: &PairingOpt) -> Vec<SamRecord> {
+    align_pair_records_with_buf(fm, pair, opt, pairing, &mut CandidateBuffers::new())
+}
+
+/// 同 [`align_pair_records`]，但复用调用方的 [`CandidateBuffers`]
+fn align_pair_records_with_buf(
+    fm: &FMIndex,
+    pair: &ReadPair,
+    opt: &AlignOpt,
+    pairing: &PairingOpt,
+    bufs: &mut CandidateBuffers,
+) -> Vec<SamRecord> {
+    let sw_params = opt.sw_params();
+    let mate = |seq: &[u8], qual: &[u8]| FastqRecord {
+        id: pair.name.clone(),
+        desc: None,
+        seq: seq.to_vec(),
+        qual: qual.to_vec(),
+    };
+    let (m1, m2) = (mate(&pair.seq1, &pair.qual1), mate(&pair.seq2, &pair.qual2));
+    let (m1, r1) = align_trimmed_records(fm, &m1, None, sw_params, opt, bufs);
+    let (m2, r2) = align_trimmed_records(fm, &m2, None, sw_params, opt, bufs);
+    let (mut r1, mut r2) = filter_low_mapq_pair(r1, &m1, r2, &m2, opt);
+    if r1.is_empty() {
+        return Vec::new();
     }
+    tag_mates(
+        &mut r1,
+        &mut r2,
+        pair.desc1.as_deref(),
+        pair.desc2.as_deref(),
+        opt.append_comment,
+    );
+    set_mate_fields(&mut r1, &mut r2, pairing);
+    r1.extend(r2);
+    r1
+}
+
+/// 对单条 read 进行比对，返回一个或多个 SAM 行（不含换行符）。
+///
+/// 库调用入口：评分参数取自 `opt`，输出与 `align` 子命令逐行一致。
+pub fn align_read(fm: &FMIndex, rec: &FastqRecord, opt: &AlignOpt) -> Vec<String> {
+    align_single_read(fm, rec, opt.sw_params(), opt)
+}
 
-    Ok(())
+/// 同 [`align_read`]，但返回结构化的 [`SamRecord`]，便于在写出前过滤或修改；
+/// 其 `Display` 输出与 [`align_read`] 的文本逐行一致。
+pub fn align_read_records(fm: &FMIndex, rec: &FastqRecord, opt: &AlignOpt) -> Vec<SamRecord> {
+    align_single_read_records(fm, rec, opt.sw_params(), opt)
 }
 
 /// 对单条 read 进行比对，返回一个或多个 SAM 行
+///
+/// `opt.per_read_timeout_ms > 0` 时，超出时间预算的 read 输出为带 `ZQ:Z:timeout` 的未比对记录。
 pub(crate) fn align_single_read(fm: &FMIndex, rec: &FastqRecord, sw_params: SwParams, opt: &AlignOpt) -> Vec<String> {
+    align_single_read_records(fm, rec, sw_params, opt)
+        .iter()
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// 对单条 read 进行比对，返回结构化 SAM 记录
+pub(crate) fn align_single_read_records(
+    fm: &FMIndex,
+    rec: &FastqRecord,
+    sw_params: SwParams,
+    opt: &AlignOpt,
+) -> Vec<SamRecord> {
+    align_single_read_records_with_buf(fm, rec, None, sw_params, opt, &mut CandidateBuffers::new())
+}
+
+/// 同 [`align_single_read_records`]，但复用调用方的 [`CandidateBuffers`]；
+/// 给出 `sai` 时使用其中保存的 SMEM 代替种子搜索
+fn align_single_read_records_with_buf(
+    fm: &FMIndex,
+    rec: &FastqRecord,
+    sai: Option<&[SaiRecord]>,
+    sw_params: SwParams,
+    opt: &AlignOpt,
+    bufs: &mut CandidateBuffers,
+) -> Vec<SamRecord> {
+    let mut records = align_uncommented_records(fm, rec, sai, sw_params, opt, bufs);
+    if opt.append_comment {
+        attach_comment(&mut records, rec.desc.as_deref());
+    }
+    records
+}
+
+/// `opt.trim_polyg` 时，read 尾部 poly-G 长度超过此值才剪除（同 fastp 默认值）
+pub const POLYG_MIN_LEN: usize = 10;
+
+/// 按 `opt.trim_polyg` 剪除尾部 poly-G，再按 `opt.per_read_timeout_ms` 设定截止时间后比对并应用 `opt.min_mapq` 过滤，不附加 FASTQ 注释
+fn align_uncommented_records(
+    fm: &FMIndex,
+    rec: &FastqRecord,
+    sai: Option<&[SaiRecord]>,
+    sw_params: SwParams,
+    opt: &AlignOpt,
+    bufs: &mut CandidateBuffers,
+) -> Vec<SamRecord> {
+    let (rec, records) = align_trimmed_records(fm, rec, sai, sw_params, opt, bufs);
+    filter_low_mapq(records, &rec, opt)
+}
+
+/// 同 [`align_uncommented_records`]，但不做 `opt.min_mapq` 过滤；返回剪切后的 read 及其记录
+fn align_trimmed_records<'a>(
+    fm: &FMIndex,
+    rec: &'a FastqRecord,
+    sai: Option<&[SaiRecord]>,
+    sw_params: SwParams,
+    opt: &AlignOpt,
+    bufs: &mut CandidateBuffers,
+) -> (Cow<'a, FastqRecord>, Vec<SamRecord>) {
+    let rec = polyg_trimmed(rec, opt);
+    let deadline =
+        (opt.per_read_timeout_ms > 0).then(|| Instant::now() + Duration::from_millis(opt.per_read_timeout_ms));
+    let records = align_single_read_with_deadline(fm, &rec, sai, sw_params, opt, deadline, bufs);
+    (rec, records)
+}
+
+/// `opt.phred64` 时将一批质量串改写为 Phred+33；否则在首批 reads 疑似 Phred+64 时提示一次
+fn normalize_quals<'a>(quals: impl Iterator<Item = &'a mut Vec<u8>>, opt: &AlignOpt, checked: &mut bool) {
+    if opt.phred64 {
+        quals.for_each(|q| qual::to_phred33(q, PHRED64));
+        return;
+    }
+    if !std::mem::replace(checked, true) {
+        let quals: Vec<&mut Vec<u8>> = quals.collect();
+        if qual::detect_offset(quals.iter().map(|q| q.as_slice())) != PHRED33 {
+            eprintln!("[bwa-rust] Warning: quality values look like Phred+64; use --phred64 to convert them");
+        }
+    }
+}
+
+/// `opt.trim_polyg` 时返回剪除尾部 poly-G 后的副本，否则原样借用
+pub(crate) fn polyg_trimmed<'a>(rec: &'a FastqRecord, opt: &AlignOpt) -> Cow<'a, FastqRecord> {
+    if !opt.trim_polyg {
+        return Cow::Borrowed(rec);
+    }
+    let mut r = rec.clone();
+    trim_homopolymer_tail(&mut r.seq, &mut r.qual, b'G', POLYG_MIN_LEN);
+    Cow::Owned(r)
+}
+
+/// `opt.min_mapq`：主比对 MAPQ 低于阈值的 read 整体丢弃（未比对 read 的 MAPQ 视为 0，同样丢弃），
+/// 或在 `opt.low_mapq_as_unmapped` 时改为输出一条未比对记录
+fn filter_low_mapq(records: Vec<SamRecord>, rec: &FastqRecord, opt: &AlignOpt) -> Vec<SamRecord> {
+    if opt.min_mapq == 0 || passes_min_mapq(&records, opt) {
+        records
+    } else if opt.low_mapq_as_unmapped {
+        as_unmapped(records, rec)
+    } else {
+        Vec::new()
+    }
+}
+
+/// 双端的 `opt.min_mapq` 过滤按 read 对整体进行，保证输出的 mate 总是成对：
+/// 两个 mate 都未通过时整对丢弃（`opt.low_mapq_as_unmapped` 时两者均输出为未比对）；
+/// 只有一个未通过时，该 mate 改为未比对记录，另一 mate 保留原比对
+fn filter_low_mapq_pair(
+    r1: Vec<SamRecord>,
+    rec1: &FastqRecord,
+    r2: Vec<SamRecord>,
+    rec2: &FastqRecord,
+    opt: &AlignOpt,
+) -> (Vec<SamRecord>, Vec<SamRecord>) {
+    if opt.min_mapq == 0 {
+        return (r1, r2);
+    }
+    let (pass1, pass2) = (passes_min_mapq(&r1, opt), passes_min_mapq(&r2, opt));
+    if !pass1 && !pass2 && !opt.low_mapq_as_unmapped {
+        return (Vec::new(), Vec::new());
+    }
+    let r1 = if pass1 { r1 } else { as_unmapped(r1, rec1) };
+    let r2 = if pass2 { r2 } else { as_unmapped(r2, rec2) };
+    (r1, r2)
+}
+
+/// 主比对已比对且 MAPQ 不低于 `opt.min_mapq`
+fn passes_min_mapq(records: &[SamRecord], opt: &AlignOpt) -> bool {
+    records
+        .iter()
+        .find(|r| r.flag & (flags::SECONDARY | flags::SUPPLEMENTARY) == 0)
+        .is_some_and(|r| !r.is_unmapped() && r.mapq >= opt.min_mapq)
+}
+
+/// 以一条未比对记录替换 read 的全部比对；主比对本就未比对时原样保留
+fn as_unmapped(records: Vec<SamRecord>, rec: &FastqRecord) -> Vec<SamRecord> {
+    let primary = records.iter().find(|r| r.flag & (flags::SECONDARY | flags::SUPPLEMENTARY) == 0);
+    if primary.map_or(true, SamRecord::is_unmapped) {
+        records
+    } else {
+        let seq = String::from_utf8_lossy(&rec.seq);
+        let qual = String::from_utf8_lossy(&rec.qual);
+        vec![SamRecord::unmapped(&rec.id, &seq, &qual)]
+    }
+}
+
+/// 候选中是否存在无剪切、无错配的满分全长比对（得分达到 [`SwParams::max_score`]）
+fn is_perfect_hit(candidates: &[AlignCandidate], max_score: i32) -> bool {
+    candidates.iter().any(|c| c.score >= max_score && c.nm == 0)
+}
+
+/// 收集一条链的候选：有 `.sai` 记录时由其 SMEM 延伸，否则做种子搜索
+fn collect_strand_candidates(
+    fm: &FMIndex,
+    sai: Option<&[SaiRecord]>,
+    query_norm: &[u8],
+    query_alpha: &[u8],
+    sw_params: SwParams,
+    is_rev: bool,
+    query_len: usize,
+    opt: &AlignOpt,
+    deadline: Option<Instant>,
+    bufs: &mut CandidateBuffers,
+    candidates: &mut Vec<AlignCandidate>,
+) -> bool {
+    match sai {
+        Some(records) => collect_candidates_from_smems_with_buf(
+            fm,
+            &strand_smems(records, is_rev),
+            query_norm,
+            sw_params,
+            is_rev,
+            query_len,
+            opt,
+            deadline,
+            bufs,
+            candidates,
+        ),
+        None => collect_candidates_with_buf(
+            fm,
+            query_norm,
+            query_alpha,
+            sw_params,
+            is_rev,
+            query_len,
+            opt,
+            deadline,
+            bufs,
+            candidates,
+        ),
+    }
+}
+
+/// 超时 read 的 SAM 行：未比对记录 + `ZQ:Z:timeout`
+fn timeout_record(qname: &str, seq: &str, qual: &str) -> Vec<SamRecord> {
+    let mut record = SamRecord::unmapped(qname, seq, qual);
+    record.push_tag("ZQ", TagValue::Str("timeout".to_string()));
+    vec![record]
+}
+
+fn align_single_read_with_deadline(
+    fm: &FMIndex,
+    rec: &FastqRecord,
+    sai: Option<&[SaiRecord]>,
+    sw_params: SwParams,
+    opt: &AlignOpt,
+    deadline: Option<Instant>,
+    bufs: &mut CandidateBuffers,
+) -> Vec<SamRecord> {
     let qname = &rec.id;
     let seq = &rec.seq;
     let qual = &rec.qual;
@@ -110,15 +820,24 @@ pub(crate) fn align_single_read(fm: &FMIndex, rec: &FastqRecord, sw_params: SwPa
     let qual_fwd = std::str::from_utf8(qual).unwrap_or_else(|_| panic!("FASTQ quality contains invalid UTF-8"));
 
     if seq.is_empty() {
-        return vec![sam::format_unmapped(qname, seq_fwd, qual_fwd)];
+        return vec![SamRecord::unmapped(qname, seq_fwd, qual_fwd)];
     }
 
     // 正向
-    let fwd_norm = dna::normalize_seq(seq);
+    // `opt.iupac` 时保留 IUPAC 兼并碱基供 SW 打分；种子搜索的字母表中它们仍为 N
+    let fwd_norm = if opt.iupac {
+        dna::normalize_seq_iupac(seq)
+    } else {
+        dna::normalize_seq(seq)
+    };
     let fwd_alpha: Vec<u8> = fwd_norm.iter().map(|&b| dna::to_alphabet(b)).collect();
     // 反向互补（复用同一份 revcomp 结果）
     let rc_seq = dna::revcomp(seq);
-    let rev_norm = dna::normalize_seq(&rc_seq);
+    let rev_norm = if opt.iupac {
+        fwd_norm.iter().rev().map(|&b| dna::complement_iupac(b)).collect()
+    } else {
+        dna::normalize_seq(&rc_seq)
+    };
     let rev_alpha: Vec<u8> = rev_norm.iter().map(|&b| dna::to_alphabet(b)).collect();
 
     let mut all_candidates: Vec<AlignCandidate> = Vec::new();
@@ -126,30 +845,43 @@ pub(crate) fn align_single_read(fm: &FMIndex, rec: &FastqRecord, sw_params: SwPa
     let query_len = seq.len();
 
     // 正向对齐候选
-    collect_candidates(
+    let fwd_done = collect_strand_candidates(
         fm,
+        sai,
         &fwd_norm,
         &fwd_alpha,
         sw_params,
         false,
         query_len,
         opt,
+        deadline,
+        bufs,
         &mut all_candidates,
     );
+    // 正向已得到满分全长比对时可跳过反向互补链（strand_shortcut）
+    let skip_rev = opt.strand_shortcut && is_perfect_hit(&all_candidates, sw_params.max_score(query_len));
     // 反向互补对齐候选
-    collect_candidates(
-        fm,
-        &rev_norm,
-        &rev_alpha,
-        sw_params,
-        true,
-        query_len,
-        opt,
-        &mut all_candidates,
-    );
+    let rev_done = fwd_done
+        && (skip_rev
+            || collect_strand_candidates(
+                fm,
+                sai,
+                &rev_norm,
+                &rev_alpha,
+                sw_params,
+                true,
+                query_len,
+                opt,
+                deadline,
+                bufs,
+                &mut all_candidates,
+            ));
+    if !rev_done {
+        return timeout_record(qname, seq_fwd, qual_fwd);
+    }
 
     if all_candidates.is_empty() {
-        return vec![sam::format_unmapped(qname, seq_fwd, qual_fwd)];
+        return vec![SamRecord::unmapped(qname, seq_fwd, qual_fwd)];
     }
 
     // 按得分降序排列
@@ -166,13 +898,16 @@ pub(crate) fn align_single_read(fm: &FMIndex, rec: &FastqRecord, sw_params: SwPa
 
     // 去重：位置和方向相同的只保留得分最高的
     dedup_candidates(&mut all_candidates);
+    // 与主比对覆盖相同 read 区间、参考区间重叠过半的次要比对只是主比对的重复；
+    // 回文 read 在另一条链上落到同一位点的比对也在此移除，不会被当作次优比对压低 MAPQ
+    remove_redundant_secondaries(&mut all_candidates);
 
     if all_candidates.is_empty() || all_candidates[0].sort_score < opt.score_threshold {
-        return vec![sam::format_unmapped(qname, seq_fwd, qual_fwd)];
+        return vec![SamRecord::unmapped(qname, seq_fwd, qual_fwd)];
     }
 
     let max_aln = opt.max_alignments_per_read;
-    let mut sam_lines = Vec::with_capacity(all_candidates.len().min(max_aln));
+    let mut records = Vec::with_capacity(all_candidates.len().min(max_aln));
 
     let needs_rev_output = all_candidates
         .iter()
@@ -187,12 +922,9 @@ pub(crate) fn align_single_read(fm: &FMIndex, rec: &FastqRecord, sw_params: SwPa
         (String::new(), String::new())
     };
 
-    let best_sort_score = all_candidates[0].sort_score;
-    let second_best_sort_score = if all_candidates.len() > 1 {
-        all_candidates[1].sort_score
-    } else {
-        0
-    };
+    // 主比对 MAPQ：与库调用共用 `mapq::mapq`，次优得分、种子覆盖与重复命中均计入
+    let regs: Vec<_> = all_candidates.iter().map(AlignCandidate::to_aln_reg).collect();
+    let primary_mapq = mapq(&regs, &sw_params, opt.min_seed_len);
     let best_raw_score = all_candidates[0].score;
     let second_best_raw_score = if all_candidates.len() > 1 {
         all_candidates[1].score
@@ -202,9 +934,21 @@ pub(crate) fn align_single_read(fm: &FMIndex, rec: &FastqRecord, sw_params: SwPa
 
     // Classify alignments into primary, secondary, and supplementary
     let classification = classify_alignments(&all_candidates);
+    // XA:Z：次要比对压缩为主记录上的标签，不再单独输出
+    let xa_tag = if opt.xa_tag {
+        let reported = all_candidates
+            .iter()
+            .take(max_aln)
+            .take_while(|c| c.sort_score >= opt.score_threshold)
+            .count();
+        generate_xa_tag(&all_candidates[..reported], &classification)
+    } else {
+        String::new()
+    };
 
     for (idx, cand) in all_candidates.iter().enumerate() {
-        if cand.sort_score < opt.score_threshold {
+        // 限制输出的比对数量
+        if idx >= max_aln || cand.sort_score < opt.score_threshold {
             break;
         }
 
@@ -222,15 +966,12 @@ pub(crate) fn align_single_read(fm: &FMIndex, rec: &FastqRecord, sw_params: SwPa
 
         match align_type {
             AlignmentType::Primary => {}
+            AlignmentType::Secondary if opt.xa_tag => continue,
             AlignmentType::Secondary => flag |= 0x100,
             AlignmentType::Supplementary => flag |= 0x800,
         }
 
-        let mapq = if idx == 0 {
-            compute_mapq(best_sort_score, second_best_sort_score)
-        } else {
-            0
-        };
+        let mapq = if idx == 0 { primary_mapq } else { 0 };
 
         let sub_score = if idx == 0 {
             second_best_raw_score
@@ -247,7 +988,7 @@ pub(crate) fn align_single_read(fm: &FMIndex, rec: &FastqRecord, sw_params: SwPa
 
         // Generate MD:Z tag
         let md_tag = if !cand.ref_seq.is_empty() && !cand.query_seq.is_empty() {
-            sam::generate_md_tag(&cand.ref_seq, &cand.query_seq, &cand.cigar)
+            md_tag(&cand.query_seq, &cand.ref_seq, &cand.cigar, 0)
         } else {
             String::new()
         };
@@ -255,7 +996,7 @@ pub(crate) fn align_single_read(fm: &FMIndex, rec: &FastqRecord, sw_params: SwPa
         // Generate SA:Z tag for supplementary alignments
         let sa_tag = generate_sa_tag(idx, &all_candidates, &classification);
 
-        let sam_line = sam::format_record_with_optional_tags(
+        let mut record = SamRecord::mapped(
             qname,
             flag,
             &cand.rname,
@@ -264,29 +1005,48 @@ pub(crate) fn align_single_read(fm: &FMIndex, rec: &FastqRecord, sw_params: SwPa
             &cand.cigar,
             out_seq,
             out_qual,
-            cand.score,
-            sub_score,
-            cand.nm,
-            &md_tag,
-            &sa_tag,
         );
-        sam_lines.push(sam_line);
-
-        // 限制输出的比对数量
-        if idx + 1 >= max_aln {
-            break;
+        record.push_tag("AS", TagValue::Int(cand.score as i64));
+        record.push_tag("XS", TagValue::Int(sub_score as i64));
+        record.push_tag("NM", TagValue::Int(cand.nm as i64));
+        if !md_tag.is_empty() {
+            record.push_tag("MD", TagValue::Str(md_tag));
+        }
+        if !sa_tag.is_empty() {
+            record.push_tag("SA", TagValue::Str(sa_tag));
+        }
+        if align_type == AlignmentType::Primary && !xa_tag.is_empty() {
+            record.push_tag("XA", TagValue::Str(xa_tag.clone()));
+        }
+        // ZR:Z：比对区对应的参考序列（正链，自 POS 起，长度为 CIGAR 消耗的参考长度）
+        if opt.emit_ref && !cand.ref_seq.is_empty() {
+            record.push_tag("ZR", TagValue::Str(String::from_utf8_lossy(&cand.ref_seq).into_owned()));
+        }
+        // Z5:Z / Z3:Z：原始 read 方向上被软剪切的 5' / 3' 端碱基（常为接头）
+        if opt.clip_tags {
+            let (head, tail) = (&seq_fwd[..cand.query_start], &seq_fwd[cand.query_end..]);
+            if !head.is_empty() {
+                record.push_tag("Z5", TagValue::Str(head.to_string()));
+            }
+            if !tail.is_empty() {
+                record.push_tag("Z3", TagValue::Str(tail.to_string()));
+            }
         }
+        records.push(record);
     }
 
-    sam_lines
+    records
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::align::candidate::collect_candidates;
+    use crate::align::sw::SwMode;
     use crate::index::builder::build_fm_index;
     use crate::io::fastq::FastqRecord;
-    use crate::testutil::build_test_fm;
+    use crate::io::sam;
+    use crate::testutil::{build_test_fm, build_test_fm_contigs, random_dna, Lcg};
     use crate::util::dna;
     use std::io::Cursor;
 
@@ -309,6 +1069,11 @@ mod tests {
             gap_open: 2,
             gap_extend: 1,
             band_width: 16,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
         };
         let opt = default_opt();
         let lines = align_single_read(&fm, &rec, sw, &opt);
@@ -331,6 +1096,11 @@ mod tests {
             gap_open: 2,
             gap_extend: 1,
             band_width: 16,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
         };
         let opt = default_opt();
         let lines = align_single_read(&fm, &rec, sw, &opt);
@@ -354,6 +1124,11 @@ mod tests {
             gap_open: 2,
             gap_extend: 1,
             band_width: 16,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
         };
         let opt = AlignOpt {
             score_threshold: 10,
@@ -387,6 +1162,11 @@ mod tests {
             gap_open: 2,
             gap_extend: 1,
             band_width: 16,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
         };
         let opt = AlignOpt {
             score_threshold: 10,
@@ -404,6 +1184,35 @@ mod tests {
         }
     }
 
+    #[test]
+    fn reverse_strand_record_has_revcomp_seq_and_reversed_qual() {
+        let reference = b"GATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCCTCGGTTAGCCTAGCAATGG";
+        let fm = build_test_fm(reference);
+        let segment = &reference[5..45];
+        let qual: Vec<u8> = (0..segment.len()).map(|i| b'!' + (i % 40) as u8).collect();
+        let record = |seq: Vec<u8>| {
+            let rec = FastqRecord {
+                id: "r".to_string(),
+                desc: None,
+                seq,
+                qual: qual.clone(),
+            };
+            align_read_records(&fm, &rec, &default_opt()).remove(0)
+        };
+
+        let rev = record(dna::revcomp(segment));
+        assert_eq!(rev.flag & flags::REVERSE, flags::REVERSE);
+        assert_eq!(rev.seq.as_bytes(), segment);
+        let reversed: Vec<u8> = qual.iter().rev().copied().collect();
+        assert_eq!(rev.qual.as_bytes(), reversed.as_slice());
+
+        let fwd = record(segment.to_vec());
+        assert_eq!(fwd.flag & flags::REVERSE, 0);
+        assert_eq!(fwd.seq.as_bytes(), segment);
+        assert_eq!(fwd.qual.as_bytes(), qual.as_slice());
+        assert_eq!(fwd.pos, rev.pos);
+    }
+
     #[test]
     fn align_single_read_prefers_best_revcomp_candidate_before_threshold() {
         let fasta = b">chr_exact\nAACCTTGGAACC\n>chr_partial\nGGTTCCAAAAAA\n";
@@ -420,6 +1229,11 @@ mod tests {
             gap_open: 6,
             gap_extend: 1,
             band_width: 100,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
         };
         let opt = AlignOpt {
             match_score: 1,
@@ -460,6 +1274,11 @@ mod tests {
             gap_open: 6,
             gap_extend: 1,
             band_width: 64,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
         };
         let opt = AlignOpt {
             match_score: 1,
@@ -495,6 +1314,11 @@ mod tests {
             gap_open: 6,
             gap_extend: 1,
             band_width: 64,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
         };
         let opt = AlignOpt {
             match_score: 1,
@@ -530,6 +1354,11 @@ mod tests {
             gap_open: 6,
             gap_extend: 1,
             band_width: 64,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
         };
         let opt = AlignOpt {
             match_score: 1,
@@ -549,4 +1378,1368 @@ mod tests {
         assert_eq!(fields[5], "20M");
         assert!(!lines[0].contains("\tNM:i:0"));
     }
+
+    #[test]
+    fn align_single_read_over_reference_n_does_not_count_n_as_mismatch() {
+        let left = b"GATTACAGGCTTCAAGTCCGATGCATTGCA";
+        let right = b"TCGGATCCTAGCAATGGTACCGTTAGCCTA";
+        let mut reference = left.to_vec();
+        reference.extend_from_slice(b"NNNN");
+        reference.extend_from_slice(right);
+        let fm = build_test_fm(&reference);
+
+        let mut seq = left.to_vec();
+        seq.extend_from_slice(b"ACGT");
+        seq.extend_from_slice(right);
+        let rec = FastqRecord {
+            id: "over_n".to_string(),
+            desc: None,
+            seq: seq.clone(),
+            qual: vec![b'I'; seq.len()],
+        };
+        let opt = default_opt();
+        let sw = SwParams {
+            match_score: opt.match_score,
+            mismatch_penalty: opt.mismatch_penalty,
+            gap_open: opt.gap_open,
+            gap_extend: opt.gap_extend,
+            band_width: opt.band_width,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
+        };
+
+        let lines = align_single_read(&fm, &rec, sw, &opt);
+        let fields: Vec<&str> = lines[0].split('\t').collect();
+        assert_eq!(fields[3], "1");
+        assert_eq!(fields[5], "64M");
+        assert!(lines[0].contains("\tNM:i:0\t"), "{}", lines[0]);
+    }
+
+    #[test]
+    fn split_output_writes_one_file_per_contig() {
+        let fasta = b">chrA\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCC\n>chrB\nTCGGTTAGCCTAGCAATGGTACCGTTCAGGACTTAGCA\n";
+        let fm = Arc::new(build_fm_index(Cursor::new(&fasta[..]), 4).unwrap().fm);
+
+        let dir = std::env::temp_dir().join(format!("bwa_rust_split_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let fq_path = dir.join("reads.fq");
+        std::fs::write(
+            &fq_path,
+            "@a1\nGGCTTCAAGTCCGATGCATTGCATC\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n\
+             @b1\nAGCCTAGCAATGGTACCGTTCAGGA\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n\
+             @u1\nTTTTTTTTTTTTTTTTTTTTTTTTT\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n",
+        )
+        .unwrap();
+        let template = dir.join("out.{contig}.sam");
+
+        align_fastq_with_fm_opt(
+            fm,
+            fq_path.to_str().unwrap(),
+            Some(template.to_str().unwrap()),
+            default_opt(),
+        )
+        .unwrap();
+
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        let a = read("out.chrA.sam");
+        let b = read("out.chrB.sam");
+        let u = read("out.unmapped.sam");
+        for text in [&a, &b, &u] {
+            assert!(text.starts_with("@HD"));
+            assert_eq!(text.lines().filter(|l| l.starts_with("@SQ")).count(), 2);
+        }
+        let records = |text: &str| -> Vec<String> {
+            text.lines()
+                .filter(|l| !l.starts_with('@'))
+                .map(|l| l.split('\t').take(3).collect::<Vec<_>>().join("\t"))
+                .collect()
+        };
+        assert_eq!(records(&a), vec!["a1\t0\tchrA"]);
+        assert_eq!(records(&b), vec!["b1\t0\tchrB"]);
+        assert_eq!(records(&u), vec!["u1\t4\t*"]);
+        assert!(!dir.join("out.{contig}.sam").exists());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn manifest_tags_each_file_with_its_read_group() {
+        let fasta = b">chrA\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCC\n>chrB\nTCGGTTAGCCTAGCAATGGTACCGTTCAGGACTTAGCA\n";
+        let fm = build_fm_index(Cursor::new(&fasta[..]), 4).unwrap().fm;
+
+        let dir = std::env::temp_dir().join(format!("bwa_rust_manifest_run_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("lane1.fq"),
+            "@a1\nGGCTTCAAGTCCGATGCATTGCATC\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("lane2.fq"),
+            "@b1\nAGCCTAGCAATGGTACCGTTCAGGA\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n",
+        )
+        .unwrap();
+        let manifest = dir.join("list.tsv");
+        std::fs::write(&manifest, "lane1.fq\tID:L1\tSM:s1\nlane2.fq\tID:L2\tSM:s1\n").unwrap();
+        let out_path = dir.join("out.sam");
+
+        let entries = read_manifest(&manifest).unwrap();
+        let opt = default_opt();
+        let mut out = open_output(out_path.to_str(), &opt).unwrap();
+        let stats = align_manifest_to_output(&fm, &entries, out.as_mut(), opt).unwrap();
+        drop(out);
+        assert_eq!(stats.reads, 2);
+
+        let text = std::fs::read_to_string(&out_path).unwrap();
+        let rg: Vec<&str> = text.lines().filter(|l| l.starts_with("@RG")).collect();
+        assert_eq!(rg, vec!["@RG\tID:L1\tSM:s1", "@RG\tID:L2\tSM:s1"]);
+        let records: Vec<&str> = text.lines().filter(|l| !l.starts_with('@')).collect();
+        assert!(
+            records[0].starts_with("a1\t") && records[0].ends_with("\tRG:Z:L1"),
+            "{}",
+            records[0]
+        );
+        assert!(
+            records[1].starts_with("b1\t") && records[1].ends_with("\tRG:Z:L2"),
+            "{}",
+            records[1]
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn read_group_option_merges_with_manifest_groups_by_id() {
+        let fasta = b">chrA\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCC\n";
+        let fm = build_fm_index(Cursor::new(&fasta[..]), 4).unwrap().fm;
+        let dir = std::env::temp_dir().join(format!("bwa_rust_rg_merge_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("lane1.fq"),
+            "@a1\nGGCTTCAAGTCCGATGCATTGCATC\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n",
+        )
+        .unwrap();
+        let manifest = dir.join("list.tsv");
+        std::fs::write(&manifest, "lane1.fq\tID:L1\tSM:s1\tPL:ILLUMINA\n").unwrap();
+        let entries = read_manifest(&manifest).unwrap();
+        let out_path = dir.join("out.sam");
+        let run = |read_group: &str| {
+            let opt = AlignOpt {
+                read_group: Some(read_group.to_string()),
+                ..default_opt()
+            };
+            let mut out = open_output(out_path.to_str(), &opt).unwrap();
+            align_manifest_to_output(&fm, &entries, out.as_mut(), opt).map_err(|e| e.to_string())
+        };
+
+        // 同一 ID、字段相同（顺序不同）只输出一行 @RG
+        run("ID:L1\tPL:ILLUMINA\tSM:s1").unwrap();
+        let text = std::fs::read_to_string(&out_path).unwrap();
+        assert_eq!(text.lines().filter(|l| l.starts_with("@RG")).count(), 1);
+
+        let err = run("ID:L1\tSM:s2").unwrap_err();
+        assert!(err.contains("read group 'L1' defined twice"), "{}", err);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn long_read_touching_short_contig_end_aligns_full_length() {
+        let mut rng = Lcg::new(17);
+        let chr1 = rng.dna(250);
+        let chr2 = rng.dna(300);
+        let fm = build_test_fm_contigs(&[("chr1", &chr1), ("chr2", &chr2)]);
+        let read = |seq: Vec<u8>| FastqRecord {
+            id: "long".to_string(),
+            desc: None,
+            qual: vec![b'I'; seq.len()],
+            seq,
+        };
+
+        // 200bp read 的末端恰为 contig 末端，且带两个错配
+        let mut seq = chr1[50..].to_vec();
+        seq[20] = if seq[20] == b'A' { b'C' } else { b'A' };
+        seq[180] = if seq[180] == b'A' { b'C' } else { b'A' };
+        for rec in [read(seq.clone()), read(dna::revcomp(&seq))] {
+            let records = align_read_records(&fm, &rec, &default_opt());
+            assert_eq!(
+                (records[0].rname.as_str(), records[0].pos, records[0].cigar.as_str()),
+                ("chr1", 51, "200M")
+            );
+            assert_eq!(records[0].tag("NM"), Some(&TagValue::Int(2)));
+        }
+
+        // 越过 contig 末端的部分被软剪切，不会延伸进下一条 contig
+        let overhang = [&chr1[100..], &rng.dna(50)[..]].concat();
+        let records = align_read_records(&fm, &read(overhang), &default_opt());
+        assert_eq!(
+            (records[0].rname.as_str(), records[0].pos, records[0].cigar.as_str()),
+            ("chr1", 101, "150M50S")
+        );
+    }
+
+    #[test]
+    fn no_clip_aligns_end_to_end_or_leaves_unmapped() {
+        let mut rng = Lcg::new(31);
+        let chr1 = rng.dna(300);
+        let fm = build_test_fm_contigs(&[("chr1", &chr1)]);
+        let read = |seq: Vec<u8>| FastqRecord {
+            id: "amp".to_string(),
+            desc: None,
+            qual: vec![b'I'; seq.len()],
+            seq,
+        };
+        let no_clip = AlignOpt {
+            no_clip: true,
+            ..default_opt()
+        };
+
+        // 带错配的 read 完整放置
+        let mut seq = chr1[100..200].to_vec();
+        for i in [90, 95] {
+            seq[i] = if seq[i] == b'A' { b'C' } else { b'A' };
+        }
+        let records = align_read_records(&fm, &read(seq), &no_clip);
+        assert_eq!((records[0].pos, records[0].cigar.as_str()), (101, "100M"));
+        assert_eq!(records[0].tag("NM"), Some(&TagValue::Int(2)));
+
+        // 越过 contig 末端 50bp：无法端到端放置，标记为未比对
+        let overhang = [&chr1[250..], &rng.dna(50)[..]].concat();
+        let records = align_read_records(&fm, &read(overhang.clone()), &default_opt());
+        assert_eq!(records[0].cigar, "50M50S");
+        let records = align_read_records(&fm, &read(overhang), &no_clip);
+        assert_eq!(records.len(), 1);
+        assert_ne!(records[0].flag & 0x4, 0);
+    }
+
+    #[test]
+    fn n_penalty_lowers_score_of_read_with_n_bases() {
+        let chr1 = Lcg::new(37).dna(300);
+        let fm = build_test_fm_contigs(&[("chr1", &chr1)]);
+        let mut seq = chr1[100..180].to_vec();
+        for i in [40, 41, 42] {
+            seq[i] = b'N';
+        }
+        let rec = FastqRecord {
+            id: "n".to_string(),
+            desc: None,
+            qual: vec![b'I'; seq.len()],
+            seq,
+        };
+        let score = |n_penalty: i32| {
+            let opt = AlignOpt {
+                n_penalty,
+                ..default_opt()
+            };
+            let records = align_read_records(&fm, &rec, &opt);
+            assert_eq!((records[0].pos, records[0].cigar.as_str()), (101, "80M"));
+            match records[0].tag("AS") {
+                Some(&TagValue::Int(s)) => s as i32,
+                other => panic!("unexpected AS {:?}", other),
+            }
+        };
+
+        // 默认每个 N 罚 1 分；n_penalty 为 0 时 N 位置不计分
+        let neutral = score(0);
+        assert_eq!(neutral, 77 * default_opt().match_score);
+        assert_eq!(score(default_opt().n_penalty), neutral - 3 * default_opt().n_penalty);
+        assert_eq!(score(2), neutral - 6);
+    }
+
+    #[test]
+    fn iupac_option_scores_ambiguity_codes_as_matches() {
+        let chr1 = Lcg::new(43).dna(300);
+        let as_score = |fm: &FMIndex, seq: &[u8], iupac: bool| {
+            let rec = FastqRecord {
+                id: "r".to_string(),
+                desc: None,
+                qual: vec![b'I'; seq.len()],
+                seq: seq.to_vec(),
+            };
+            let opt = AlignOpt { iupac, ..default_opt() };
+            let records = align_read_records(fm, &rec, &opt);
+            assert_eq!((records[0].pos, records[0].cigar.as_str()), (101, "80M"));
+            match records[0].tag("AS") {
+                Some(&TagValue::Int(s)) => s as i32,
+                other => panic!("unexpected AS {:?}", other),
+            }
+        };
+        let (full, n_cost) = (80 * default_opt().match_score, default_opt().n_penalty);
+        // 与参考碱基兼容的兼并码：A/G -> R，C/T -> Y
+        let ambiguous = |b: u8| if matches!(b, b'A' | b'G') { b'R' } else { b'Y' };
+
+        // read 中的 IUPAC 碱基：默认折叠为 N 并罚分，--iupac 时按匹配计分
+        let fm = build_test_fm_contigs(&[("chr1", &chr1)]);
+        let mut read = chr1[100..180].to_vec();
+        for i in [30, 50] {
+            read[i] = ambiguous(read[i]);
+        }
+        assert_eq!(
+            as_score(&fm, &read, false),
+            full - 2 * (default_opt().match_score + n_cost)
+        );
+        assert_eq!(as_score(&fm, &read, true), full);
+        // 反向互补 read 的兼并码同样取互补后匹配
+        let rc: Vec<u8> = read.iter().rev().map(|&b| dna::complement_iupac(b)).collect();
+        assert_eq!(as_score(&fm, &rc, true), full);
+
+        // 参考中的 IUPAC 碱基：仅无损索引保存，--iupac 时恢复后按匹配计分
+        let mut reference = chr1.clone();
+        reference[140] = ambiguous(reference[140]);
+        let fasta = [b">chr1\n".as_slice(), &reference, b"\n"].concat();
+        let lossless = crate::index::builder::build_fm_index_lossless(std::io::Cursor::new(fasta), 4, 1)
+            .unwrap()
+            .fm;
+        let read = &chr1[100..180];
+        assert_eq!(
+            as_score(&lossless, read, false),
+            full - default_opt().match_score - n_cost
+        );
+        assert_eq!(as_score(&lossless, read, true), full);
+    }
+
+    #[test]
+    fn palindromic_read_counts_one_locus_for_mapq() {
+        let mut rng = Lcg::new(23);
+        // 完全回文的 60bp 片段：正反两链比对到同一位置
+        let half = rng.dna(30);
+        let palindrome = [&half[..], &dna::revcomp(&half)[..]].concat();
+        assert_eq!(dna::revcomp(&palindrome), palindrome);
+        let chr1 = [&rng.dna(100)[..], &palindrome[..], &rng.dna(100)[..]].concat();
+        let fm = build_test_fm_contigs(&[("chr1", &chr1)]);
+        let rec = FastqRecord {
+            id: "pal".to_string(),
+            desc: None,
+            qual: vec![b'I'; palindrome.len()],
+            seq: palindrome,
+        };
+
+        let records = align_read_records(&fm, &rec, &default_opt());
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].pos, records[0].cigar.as_str()), (101, "60M"));
+        assert_eq!(records[0].tag("XS"), Some(&TagValue::Int(0)));
+        assert!(records[0].mapq > 0, "mapq {}", records[0].mapq);
+    }
+
+    #[test]
+    fn read_group_option_adds_header_line_and_tags() {
+        let fasta = b">chrA\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCC\n";
+        let fm = build_fm_index(Cursor::new(&fasta[..]), 4).unwrap().fm;
+        let dir = std::env::temp_dir().join(format!("bwa_rust_read_group_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let fq_path = dir.join("reads.fq");
+        let out_path = dir.join("out.sam");
+        std::fs::write(
+            &fq_path,
+            "@a1\nGGCTTCAAGTCCGATGCATTGCATC\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n@u1\nACACACACACACACACACACACACA\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n",
+        )
+        .unwrap();
+
+        let opt = AlignOpt {
+            read_group: Some("ID:lane1\\tSM:sample1\\tPL:ILLUMINA".to_string()),
+            ..default_opt()
+        };
+        let mut out = open_output(out_path.to_str(), &opt).unwrap();
+        align_fastq_to_output(&fm, fq_path.to_str().unwrap(), out.as_mut(), opt).unwrap();
+        drop(out);
+
+        let text = std::fs::read_to_string(&out_path).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        let rg: Vec<&str> = text.lines().filter(|l| l.starts_with("@RG")).collect();
+        assert_eq!(rg, vec!["@RG\tID:lane1\tSM:sample1\tPL:ILLUMINA"]);
+        let records: Vec<&str> = text.lines().filter(|l| !l.starts_with('@')).collect();
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|r| r.ends_with("\tRG:Z:lane1")), "{:?}", records);
+    }
+
+    #[test]
+    fn batched_and_unbatched_output_identical() {
+        let fasta = b">chrA\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCC\n>chrB\nTCGGTTAGCCTAGCAATGGTACCGTTCAGGACTTAGCA\n";
+        let fm = Arc::new(build_fm_index(Cursor::new(&fasta[..]), 4).unwrap().fm);
+
+        let dir = std::env::temp_dir().join(format!("bwa_rust_batch_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let fq_path = dir.join("reads.fq");
+        let mut fq = String::new();
+        for i in 0..7 {
+            let seq = match i % 3 {
+                0 => "GGCTTCAAGTCCGATGCATTGCATC",
+                1 => "AGCCTAGCAATGGTACCGTTCAGGA",
+                _ => "TTTTTTTTTTTTTTTTTTTTTTTTT",
+            };
+            fq.push_str(&format!("@r{}\n{}\n+\n{}\n", i, seq, "I".repeat(seq.len())));
+        }
+        std::fs::write(&fq_path, fq).unwrap();
+
+        let run = |batch_size: usize, threads: usize| {
+            let out = dir.join(format!("out_{}_{}.sam", batch_size, threads));
+            let opt = AlignOpt {
+                batch_size,
+                threads,
+                ..default_opt()
+            };
+            align_fastq_with_fm_opt(
+                Arc::clone(&fm),
+                fq_path.to_str().unwrap(),
+                Some(out.to_str().unwrap()),
+                opt,
+            )
+            .unwrap();
+            std::fs::read_to_string(out).unwrap()
+        };
+
+        let unbatched = run(1, 1);
+        assert_eq!(unbatched.lines().filter(|l| !l.starts_with('@')).count(), 7);
+        assert_eq!(run(3, 1), unbatched);
+        assert_eq!(run(AlignOpt::default().batch_size, 1), unbatched);
+        assert_eq!(run(3, 2), unbatched);
+        assert_eq!(run(AlignOpt::default().batch_size, 4), unbatched);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn sq_order_reorders_header_only() {
+        let fasta = b">chrA\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCC\n>chrB\nTCGGTTAGCCTAGCAATGGTACCGTTCAGGACTTAGCA\n>chrM\nACGTTGCAACGGTACCGATTACGGATCCATGCAGTCAGG\n";
+        let fm = Arc::new(build_fm_index(Cursor::new(&fasta[..]), 4).unwrap().fm);
+
+        let dir = std::env::temp_dir().join(format!("bwa_rust_sq_order_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let fq_path = dir.join("reads.fq");
+        std::fs::write(
+            &fq_path,
+            "@b1\nAGCCTAGCAATGGTACCGTTCAGGA\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n",
+        )
+        .unwrap();
+
+        let run = |sq_order: Option<Vec<String>>| {
+            let out = dir.join("out.sam");
+            let opt = AlignOpt {
+                sq_order,
+                ..default_opt()
+            };
+            align_fastq_with_fm_opt(
+                Arc::clone(&fm),
+                fq_path.to_str().unwrap(),
+                Some(out.to_str().unwrap()),
+                opt,
+            )
+            .map(|_| std::fs::read_to_string(out).unwrap())
+        };
+        let order = |names: &[&str]| Some(names.iter().map(|&n| String::from(n)).collect::<Vec<_>>());
+        let sq_names = |text: &str| -> Vec<String> {
+            text.lines()
+                .filter(|l| l.starts_with("@SQ"))
+                .map(|l| l.split('\t').nth(1).unwrap().trim_start_matches("SN:").to_string())
+                .collect()
+        };
+        let body =
+            |text: &str| -> Vec<String> { text.lines().filter(|l| !l.starts_with('@')).map(String::from).collect() };
+
+        let plain = run(None).unwrap();
+        assert_eq!(sq_names(&plain), vec!["chrA", "chrB", "chrM"]);
+        let reordered = run(order(&["chrM", "chrB", "chrA"])).unwrap();
+        assert_eq!(sq_names(&reordered), vec!["chrM", "chrB", "chrA"]);
+        assert!(reordered.contains("@SQ\tSN:chrM\tLN:39\n"));
+        // 记录（RNAME / POS）不受影响
+        assert_eq!(body(&reordered), body(&plain));
+        assert!(body(&plain)[0].starts_with("b1\t0\tchrB\t"));
+
+        let err = run(order(&["chrB", "chrA"])).unwrap_err().to_string();
+        assert!(err.contains("missing contig 'chrM'"), "{}", err);
+        assert!(run(order(&["chrA", "chrB", "chrM", "chrX"])).is_err());
+        assert!(run(order(&["chrA", "chrB", "chrB", "chrM"])).is_err());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn partial_alignments_have_cigar_covering_read() {
+        let fasta = b">chrA\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCC\n>chrB\nTCGGTTAGCCTAGCAATGGTACCGTTCAGGACTTAGCA\n";
+        let fm = Arc::new(build_fm_index(Cursor::new(&fasta[..]), 4).unwrap().fm);
+
+        let dir = std::env::temp_dir().join(format!("bwa_rust_clip_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let fq_path = dir.join("reads.fq");
+        // 两端各带一段不在参考中的序列，只有中间部分能比对上
+        let reads = [
+            ("left", "CCCCCCCCGGCTTCAAGTCCGATGCATTGCATC"),
+            ("right", "AGCCTAGCAATGGTACCGTTCAGGAAAAAAAAAAA"),
+            ("both", "CCCCCCGGCTTCAAGTCCGATGCATTGCATCAAAAAAA"),
+        ];
+        let fq: String = reads
+            .iter()
+            .map(|(name, seq)| format!("@{}\n{}\n+\n{}\n", name, seq, "I".repeat(seq.len())))
+            .collect();
+        std::fs::write(&fq_path, fq).unwrap();
+        let out = dir.join("out.sam");
+        align_fastq_with_fm_opt(
+            fm,
+            fq_path.to_str().unwrap(),
+            Some(out.to_str().unwrap()),
+            default_opt(),
+        )
+        .unwrap();
+
+        // 与 samtools quickcheck 相同的一致性要求：CIGAR 的 M/I/S/=/X 之和等于 SEQ 长度
+        sam::validate_sam_file(out.to_str().unwrap()).unwrap();
+        let text = std::fs::read_to_string(&out).unwrap();
+        let records: Vec<Vec<&str>> = text
+            .lines()
+            .filter(|l| !l.starts_with('@'))
+            .map(|l| l.split('\t').collect())
+            .collect();
+        assert_eq!(records.len(), 3);
+        for fields in &records {
+            assert_ne!(fields[1], "4", "{} unmapped", fields[0]);
+        }
+        assert!(records[0][5].contains('S'), "{}", records[0][5]);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn estimate_insert_size_recovers_known_insert() {
+        let mut rng = Lcg::new(23);
+        let reference = rng.dna(6000);
+        let mut next = |bound: usize| rng.below(bound as u32) as usize;
+        let fm = build_test_fm_contigs(&[("chr1", &reference)]);
+
+        // 片段长度 300 ± 20，R1 取片段左端正链，R2 取右端反向互补
+        let dir = std::env::temp_dir().join(format!("bwa_rust_insert_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (mut fq1, mut fq2) = (String::new(), String::new());
+        let read_len = 60;
+        for i in 0..200 {
+            let insert = 280 + next(41);
+            let start = next(reference.len() - insert);
+            let r1 = &reference[start..start + read_len];
+            let r2 = dna::revcomp(&reference[start + insert - read_len..start + insert]);
+            let qual = "I".repeat(read_len);
+            fq1.push_str(&format!("@p{}\n{}\n+\n{}\n", i, String::from_utf8_lossy(r1), qual));
+            fq2.push_str(&format!("@p{}\n{}\n+\n{}\n", i, String::from_utf8_lossy(&r2), qual));
+        }
+        // 两端相距很远的离群 read 对不应影响估计
+        fq1.push_str(&format!(
+            "@far\n{}\n+\n{}\n",
+            String::from_utf8_lossy(&reference[10..70]),
+            "I".repeat(60)
+        ));
+        fq2.push_str(&format!(
+            "@far\n{}\n+\n{}\n",
+            String::from_utf8_lossy(&dna::revcomp(&reference[5900..5960])),
+            "I".repeat(60)
+        ));
+        let (p1, p2) = (dir.join("r1.fq"), dir.join("r2.fq"));
+        std::fs::write(&p1, fq1).unwrap();
+        std::fs::write(&p2, fq2).unwrap();
+
+        let est = estimate_insert_size(&fm, p1.to_str().unwrap(), p2.to_str().unwrap(), &default_opt(), 1000)
+            .unwrap()
+            .unwrap();
+        assert!(est.pairs >= 190, "{:?}", est);
+        assert!((est.mean - 300.0).abs() < 5.0, "{:?}", est);
+        assert!((est.median - 300.0).abs() < 6.0, "{:?}", est);
+        assert!(est.stddev > 8.0 && est.stddev < 16.0, "{:?}", est);
+
+        // 只取前 50 对
+        let head = estimate_insert_size(&fm, p1.to_str().unwrap(), p2.to_str().unwrap(), &default_opt(), 50)
+            .unwrap()
+            .unwrap();
+        assert!(head.pairs <= 50);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn reused_buffers_match_fresh_buffers() {
+        let fasta = b">chrA\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCC\n>chrB\nTCGGTTAGCCTAGCAATGGTACCGTTCAGGACTTAGCA\n";
+        let fm = build_fm_index(Cursor::new(&fasta[..]), 4).unwrap().fm;
+        let opt = default_opt();
+        // 长短交替，缓冲区须在不同尺寸间正确复用
+        let seqs = [
+            "GGCTTCAAGTCCGATGCATTGCATCGGATCC",
+            "AGCCTAGCAATGGTACC",
+            "TCGGTTAGCCTAGCAATGGTACCGTTCAGGACTTAGCA",
+            "CATTGCATCGG",
+        ];
+        let mut bufs = CandidateBuffers::new();
+        for (i, seq) in seqs.iter().enumerate() {
+            let rec = FastqRecord {
+                id: format!("r{}", i),
+                desc: None,
+                seq: seq.as_bytes().to_vec(),
+                qual: vec![b'I'; seq.len()],
+            };
+            let reused = align_single_read_records_with_buf(&fm, &rec, None, opt.sw_params(), &opt, &mut bufs);
+            assert_eq!(reused, align_read_records(&fm, &rec, &opt), "read {}", i);
+        }
+    }
+
+    #[test]
+    fn read_with_only_end_seed_is_placed_by_smem_chain() {
+        let reference = random_dna(11, 300);
+        let fm = build_test_fm(&reference);
+
+        // 前 57bp 每 8bp 一个错配，中间无 >= 19bp 的精确匹配；唯一种子位于 read 末端
+        let mut seq = reference[100..180].to_vec();
+        for i in (8..=56).step_by(8) {
+            seq[i] = if seq[i] == b'A' { b'C' } else { b'A' };
+        }
+        let rec = FastqRecord {
+            id: "end_seed".to_string(),
+            desc: None,
+            qual: vec![b'I'; seq.len()],
+            seq,
+        };
+        let records = align_read_records(&fm, &rec, &default_opt());
+        assert!(!records[0].is_unmapped());
+        assert_eq!(records[0].flag & flags::REVERSE, 0);
+        assert_eq!(records[0].pos, 101);
+    }
+
+    #[test]
+    fn min_mapq_filters_multi_mapping_reads() {
+        let mut rng = Lcg::new(5);
+        // 重复单元出现两次，其余为唯一序列
+        let repeat = rng.dna(60);
+        let unique = rng.dna(300);
+        let mut reference = unique.clone();
+        reference.extend_from_slice(&repeat);
+        reference.extend(rng.dna(100));
+        reference.extend_from_slice(&repeat);
+        let fm = build_test_fm(&reference);
+
+        let read = |id: &str, seq: &[u8]| FastqRecord {
+            id: id.to_string(),
+            desc: None,
+            seq: seq.to_vec(),
+            qual: vec![b'I'; seq.len()],
+        };
+        let uniq = read("uniq", &unique[100..150]);
+        let multi = read("multi", &repeat[5..55]);
+        let mapq = |opt: &AlignOpt, rec: &FastqRecord| -> Vec<(u16, u8)> {
+            align_read_records(&fm, rec, opt).iter().map(|r| (r.flag, r.mapq)).collect()
+        };
+
+        let plain = default_opt();
+        let multi_mapq = mapq(&plain, &multi)[0].1;
+        assert!(mapq(&plain, &uniq)[0].1 >= 30);
+        assert!(multi_mapq < 30, "multi-mapping read MAPQ {}", multi_mapq);
+
+        let drop = AlignOpt {
+            min_mapq: 30,
+            ..default_opt()
+        };
+        assert_eq!(mapq(&drop, &uniq), mapq(&plain, &uniq));
+        assert!(align_read_records(&fm, &multi, &drop).is_empty());
+        let unmapped_read = read("none", &[b'T'; 40]);
+        assert!(align_read_records(&fm, &unmapped_read, &drop).is_empty());
+
+        let unmap = AlignOpt {
+            low_mapq_as_unmapped: true,
+            ..drop
+        };
+        let records = align_read_records(&fm, &multi, &unmap);
+        assert_eq!(records.len(), 1);
+        assert!(records[0].is_unmapped());
+        assert_eq!(records[0].seq, String::from_utf8_lossy(&multi.seq));
+        assert_eq!(mapq(&unmap, &uniq), mapq(&plain, &uniq));
+    }
+
+    #[test]
+    fn mapq_drops_for_short_and_repetitive_reads() {
+        let mut rng = Lcg::new(23);
+        let unique = rng.dna(600);
+        let repeat = rng.dna(120);
+        let mut reference = unique.clone();
+        reference.extend_from_slice(&repeat);
+        reference.extend(rng.dna(200));
+        reference.extend_from_slice(&repeat);
+        reference.extend(rng.dna(200));
+        let fm = build_test_fm(&reference);
+        let opt = default_opt();
+
+        let read = |seq: &[u8]| FastqRecord {
+            id: "r".to_string(),
+            desc: None,
+            seq: seq.to_vec(),
+            qual: vec![b'I'; seq.len()],
+        };
+        let primary = |fm: &FMIndex, seq: &[u8], opt: &AlignOpt| align_read_records(fm, &read(seq), opt).remove(0);
+
+        let long = primary(&fm, &unique[200..350], &opt);
+        assert_eq!((long.pos, long.cigar.as_str(), long.mapq), (201, "150M", 60));
+        // 唯一但很短的比对：次优得分以 min_seed_len 为下限
+        let short = primary(&fm, &unique[200..230], &opt);
+        assert_eq!((short.pos, short.cigar.as_str()), (201, "30M"));
+        assert!(
+            short.mapq > 0 && short.mapq < long.mapq,
+            "short read MAPQ {}",
+            short.mapq
+        );
+        // 重复序列：两处拷贝同分
+        let rep = primary(&fm, &repeat, &opt);
+        assert_eq!(rep.cigar, "120M");
+        assert_eq!(rep.mapq, 0);
+
+        // 只延伸一个 contig 时，另一拷贝没有成为候选，但种子出现次数仍计入 sub_n
+        let contigs = build_test_fm_contigs(&[("a", &[&unique[..300], &repeat[..]].concat()), ("b", &repeat)]);
+        let one_contig = AlignOpt {
+            max_contigs_per_read: 1,
+            ..default_opt()
+        };
+        let rep = primary(&contigs, &repeat, &one_contig);
+        assert_eq!(rep.cigar, "120M");
+        // 4.343 * ln(2) ≈ 3
+        assert_eq!(rep.mapq, 57);
+    }
+
+    #[test]
+    fn min_mapq_filters_read_pairs_as_a_unit() {
+        let mut rng = Lcg::new(9);
+        let repeat = rng.dna(60);
+        let unique = rng.dna(400);
+        let mut reference = unique.clone();
+        reference.extend_from_slice(&repeat);
+        reference.extend(rng.dna(100));
+        reference.extend_from_slice(&repeat);
+        let fm = build_test_fm(&reference);
+
+        let pair = |seq1: &[u8], seq2: &[u8]| ReadPair {
+            name: "p".to_string(),
+            desc1: None,
+            seq1: seq1.to_vec(),
+            qual1: vec![b'I'; seq1.len()],
+            desc2: None,
+            seq2: seq2.to_vec(),
+            qual2: vec![b'I'; seq2.len()],
+        };
+        let uniq_mate = dna::revcomp(&unique[250..300]);
+        let opt = AlignOpt {
+            min_mapq: 30,
+            ..default_opt()
+        };
+        let pairing = PairingOpt::default();
+        let primaries = |records: &[SamRecord]| -> Vec<SamRecord> {
+            records
+                .iter()
+                .filter(|r| r.flag & (flags::SECONDARY | flags::SUPPLEMENTARY) == 0)
+                .cloned()
+                .collect()
+        };
+
+        // 一端唯一、一端多重比对：低 MAPQ 的 mate 改为未比对，read 对仍成对输出
+        let mixed = align_pair_records(&fm, &pair(&unique[100..150], &repeat[5..55]), &opt, &pairing);
+        let mates = primaries(&mixed);
+        assert_eq!(mates.len(), 2);
+        assert!(!mates[0].is_unmapped() && mates[0].mapq >= 30);
+        assert!(mates[1].is_unmapped());
+        assert_ne!(mates[0].flag & flags::MUNMAP, 0);
+        assert_ne!(mates[1].flag & flags::PAIRED, 0);
+
+        // 一端完全未比对时同样保留两端
+        let half = align_pair_records(&fm, &pair(&unique[100..150], &[b'T'; 50]), &opt, &pairing);
+        assert_eq!(primaries(&half).len(), 2);
+
+        // 两端均未通过时整对丢弃；low_mapq_as_unmapped 时两端都输出为未比对
+        let both_low = pair(&repeat[5..55], &[b'T'; 50]);
+        assert!(align_pair_records(&fm, &both_low, &opt, &pairing).is_empty());
+        let unmap = AlignOpt {
+            low_mapq_as_unmapped: true,
+            ..opt.clone()
+        };
+        let records = align_pair_records(&fm, &both_low, &unmap, &pairing);
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(SamRecord::is_unmapped));
+
+        // 两端均通过时与不过滤一致
+        let good = pair(&unique[100..150], &uniq_mate);
+        assert_eq!(
+            align_pair_records(&fm, &good, &opt, &pairing),
+            align_pair_records(&fm, &good, &default_opt(), &pairing)
+        );
+    }
+
+    #[test]
+    fn expired_deadline_reports_timeout_unmapped() {
+        // 重复参考：read 在多个拷贝处均有种子命中
+        let unit = b"GATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCC";
+        let reference: Vec<u8> = unit.iter().copied().cycle().take(unit.len() * 20).collect();
+        let fm = build_test_fm(&reference);
+        let seq = unit[3..33].to_vec();
+        let rec = FastqRecord {
+            id: "slow".to_string(),
+            desc: None,
+            seq: seq.clone(),
+            qual: vec![b'I'; seq.len()],
+        };
+        let opt = default_opt();
+
+        let lines: Vec<String> = align_single_read_with_deadline(
+            &fm,
+            &rec,
+            None,
+            opt.sw_params(),
+            &opt,
+            Some(Instant::now()),
+            &mut CandidateBuffers::new(),
+        )
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+        assert_eq!(lines.len(), 1);
+        let fields: Vec<&str> = lines[0].split('\t').collect();
+        assert_eq!(fields[1], "4");
+        assert_eq!(fields[2], "*");
+        assert_eq!(fields.last(), Some(&"ZQ:Z:timeout"));
+
+        // 无截止时间时正常比对
+        let lines: Vec<String> = align_single_read_with_deadline(
+            &fm,
+            &rec,
+            None,
+            opt.sw_params(),
+            &opt,
+            None,
+            &mut CandidateBuffers::new(),
+        )
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+        assert!(!lines[0].contains("ZQ:Z:timeout"));
+        assert_ne!(lines[0].split('\t').nth(1), Some("4"));
+
+        // 宽裕的超时预算不影响结果
+        let generous = AlignOpt {
+            per_read_timeout_ms: 60_000,
+            ..default_opt()
+        };
+        assert_eq!(align_read(&fm, &rec, &generous), lines);
+    }
+
+    #[test]
+    fn strand_shortcut_skips_reverse_pass_on_perfect_forward_hit() {
+        // 参考含 x 的正向拷贝与带两个错配的反向互补拷贝：反向链比对会报告一条次要比对
+        let mut rng = Lcg::new(41);
+        let x = rng.dna(40);
+        let mut decoy = x.clone();
+        for i in [4, 34] {
+            decoy[i] = if decoy[i] == b'A' { b'C' } else { b'A' };
+        }
+        let reference = [
+            rng.dna(100),
+            x.clone(),
+            rng.dna(100),
+            dna::revcomp(&decoy),
+            rng.dna(100),
+        ]
+        .concat();
+        let fm = build_test_fm(&reference);
+        let rec = |id: &str, seq: Vec<u8>| FastqRecord {
+            id: id.to_string(),
+            desc: None,
+            qual: vec![b'I'; seq.len()],
+            seq,
+        };
+        let has_rev = |records: &[SamRecord]| records.iter().any(|r| r.flag & flags::REVERSE != 0);
+
+        let both = default_opt();
+        let shortcut = AlignOpt {
+            strand_shortcut: true,
+            ..default_opt()
+        };
+
+        let perfect = rec("perfect", x.clone());
+        let full = align_read_records(&fm, &perfect, &both);
+        assert_eq!((full[0].pos, full[0].flag & flags::REVERSE), (101, 0));
+        assert!(has_rev(&full), "reverse pass reports the decoy: {:?}", full);
+        let short = align_read_records(&fm, &perfect, &shortcut);
+        assert!(!has_rev(&short), "perfect forward hit must skip the reverse pass");
+        // 主比对位置与得分不变，但未见到反向链的次优比对（XS 为 0）
+        assert_eq!((short[0].pos, &short[0].cigar), (full[0].pos, &full[0].cigar));
+        assert_eq!(short[0].tag("AS"), full[0].tag("AS"));
+        assert_eq!(short[0].tag("XS"), Some(&TagValue::Int(0)));
+        assert_ne!(full[0].tag("XS"), Some(&TagValue::Int(0)));
+
+        // 非满分的正向 read 仍比对两条链，结果不变
+        let mut mm_seq = x.clone();
+        mm_seq[2] = if mm_seq[2] == b'A' { b'C' } else { b'A' };
+        let mismatched = rec("mm", mm_seq);
+        let short = align_read_records(&fm, &mismatched, &shortcut);
+        assert_ne!(short[0].tag("XS"), Some(&TagValue::Int(0)));
+        assert_eq!(short, align_read_records(&fm, &mismatched, &both));
+    }
+
+    #[test]
+    fn align_read_records_display_matches_text_output() {
+        let reference = b"GATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCCTAGCAATGGTACCGTTAGCCTA";
+        let fm = build_test_fm(reference);
+        let opt = default_opt();
+        let mut mm = reference[5..40].to_vec();
+        mm[17] = if mm[17] == b'A' { b'C' } else { b'A' };
+        let reads = [
+            mm,
+            dna::revcomp(&reference[10..45]),
+            b"TTTTTTTTTTTTTTTTTTTTTTTTT".to_vec(),
+        ];
+        for (i, seq) in reads.into_iter().enumerate() {
+            let rec = FastqRecord {
+                id: format!("r{}", i),
+                desc: None,
+                qual: vec![b'I'; seq.len()],
+                seq,
+            };
+            let records = align_read_records(&fm, &rec, &opt);
+            let lines = align_read(&fm, &rec, &opt);
+            let rendered: Vec<String> = records.iter().map(ToString::to_string).collect();
+            assert_eq!(rendered, lines);
+
+            let r = &records[0];
+            if r.is_unmapped() {
+                assert_eq!(lines[0], sam::format_unmapped(&r.qname, &r.seq, &r.qual));
+                continue;
+            }
+            let int = |tag: &str| match r.tag(tag) {
+                Some(TagValue::Int(v)) => *v,
+                other => panic!("{} tag: {:?}", tag, other),
+            };
+            let md = match r.tag("MD") {
+                Some(TagValue::Str(v)) => v.as_str(),
+                _ => "",
+            };
+            let expected = sam::format_record_with_optional_tags(
+                &r.qname,
+                r.flag,
+                &r.rname,
+                r.pos,
+                r.mapq,
+                &r.cigar,
+                &r.seq,
+                &r.qual,
+                int("AS") as i32,
+                int("XS") as i32,
+                int("NM") as u32,
+                md,
+                "",
+            );
+            assert_eq!(lines[0], expected);
+        }
+    }
+
+    #[test]
+    fn min_chain_score_skips_weak_chains() {
+        let reference = b"GATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCCTAGCAATGGTACCGTTAGCCTA";
+        let fm = build_test_fm(reference);
+        // 前 25bp 与参考精确匹配，后 25bp 为无关序列
+        let mut seq = reference[5..30].to_vec();
+        seq.extend_from_slice(b"CCCCCCCCCCCCCCCCCCCCCCCCC");
+        let rec = FastqRecord {
+            id: "weak".to_string(),
+            desc: None,
+            seq: seq.clone(),
+            qual: vec![b'I'; seq.len()],
+        };
+        let opt = default_opt();
+        let sw = SwParams {
+            match_score: opt.match_score,
+            mismatch_penalty: opt.mismatch_penalty,
+            gap_open: opt.gap_open,
+            gap_extend: opt.gap_extend,
+            band_width: opt.band_width,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
+        };
+
+        let lines = align_single_read(&fm, &rec, sw, &opt);
+        let flag: u16 = lines[0].split('\t').nth(1).unwrap().parse().unwrap();
+        assert_eq!(flag & sam::flags::UNMAP, 0, "weak read maps without a chain threshold");
+
+        let strict = AlignOpt {
+            min_chain_score: 0.6,
+            ..default_opt()
+        };
+        let fwd_norm = dna::normalize_seq(&seq);
+        let fwd_alpha: Vec<u8> = fwd_norm.iter().map(|&b| dna::to_alphabet(b)).collect();
+        let mut candidates = Vec::new();
+        collect_candidates(
+            &fm,
+            &fwd_norm,
+            &fwd_alpha,
+            sw,
+            false,
+            seq.len(),
+            &strict,
+            &mut candidates,
+        );
+        assert!(candidates.is_empty(), "weak chain must not be extended");
+
+        let lines = align_single_read(&fm, &rec, sw, &strict);
+        assert_eq!(lines.len(), 1);
+        let flag: u16 = lines[0].split('\t').nth(1).unwrap().parse().unwrap();
+        assert_ne!(flag & sam::flags::UNMAP, 0);
+    }
+
+    #[test]
+    fn append_comment_adds_co_tag_to_every_record() {
+        let reference = b"GATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCCTAGCAATGGTACCGTTAGCCTA";
+        let fm = build_test_fm(reference);
+        let rec = FastqRecord {
+            id: "r1".to_string(),
+            desc: Some("BC:Z:ACGT".to_string()),
+            seq: reference[5..40].to_vec(),
+            qual: vec![b'I'; 35],
+        };
+        let plain = align_read(&fm, &rec, &default_opt());
+        assert!(plain.iter().all(|l| !l.contains("CO:Z:")));
+
+        let opt = AlignOpt {
+            append_comment: true,
+            ..default_opt()
+        };
+        let lines = align_read(&fm, &rec, &opt);
+        assert_eq!(lines.len(), plain.len());
+        assert!(lines.iter().all(|l| l.ends_with("\tCO:Z:BC:Z:ACGT")));
+    }
+
+    #[test]
+    fn emit_ref_attaches_aligned_reference_bases() {
+        let reference = b"GATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCCTAGCAATGGTACCGTTAGCCTA";
+        let fm = build_test_fm(reference);
+        let mut seq = reference[8..48].to_vec();
+        seq[20] = if seq[20] == b'A' { b'C' } else { b'A' };
+        let rec = FastqRecord {
+            id: "r1".to_string(),
+            desc: None,
+            seq: dna::revcomp(&seq),
+            qual: vec![b'I'; 40],
+        };
+        let plain = align_read_records(&fm, &rec, &default_opt());
+        assert!(plain.iter().all(|r| r.tag("ZR").is_none()));
+
+        let opt = AlignOpt {
+            emit_ref: true,
+            ..default_opt()
+        };
+        let records = align_read_records(&fm, &rec, &opt);
+        let primary = &records[0];
+        assert!(primary.flag & sam::flags::REVERSE != 0);
+        assert_eq!(primary.cigar, "40M");
+        let pos = primary.pos as usize - 1;
+        match primary.tag("ZR") {
+            Some(TagValue::Str(zr)) => assert_eq!(zr.as_bytes(), &reference[pos..pos + 40]),
+            other => panic!("missing ZR tag: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn clip_tags_carry_soft_clipped_read_ends() {
+        let reference = random_dna(3, 200);
+        let fm = build_test_fm(&reference);
+        // 接头与相邻参考逐碱基错配，延伸穿过它们总不如软剪切
+        let mismatch = |r: &[u8]| -> Vec<u8> { r.iter().map(|&b| if b == b'A' { b'C' } else { b'A' }).collect() };
+        let (head, tail) = (mismatch(&reference[48..60]), mismatch(&reference[120..130]));
+        let mut seq = head.clone();
+        seq.extend_from_slice(&reference[60..120]);
+        seq.extend_from_slice(&tail);
+        // BWA-MEM 打分且软剪切不额外扣分，让接头被剪切而不是以错配 / indel 吸收
+        let opt = AlignOpt {
+            match_score: 1,
+            mismatch_penalty: 4,
+            gap_open: 6,
+            gap_extend: 1,
+            clip_penalty: 0,
+            clip_tags: true,
+            ..default_opt()
+        };
+
+        for rev in [false, true] {
+            let rec = FastqRecord {
+                id: "junk".to_string(),
+                desc: None,
+                seq: if rev { dna::revcomp(&seq) } else { seq.clone() },
+                qual: vec![b'I'; seq.len()],
+            };
+            let records = align_read_records(&fm, &rec, &opt);
+            let primary = &records[0];
+            assert_eq!(primary.pos, 61, "rev={}", rev);
+            // 标签沿原始 read 方向：反向比对时 5' 端为反向互补后的 tail
+            let (z5, z3) = if rev {
+                (dna::revcomp(&tail), dna::revcomp(&head))
+            } else {
+                (head.clone(), tail.clone())
+            };
+            assert_eq!(primary.tag("Z5"), Some(&TagValue::Str(String::from_utf8(z5).unwrap())));
+            assert_eq!(primary.tag("Z3"), Some(&TagValue::Str(String::from_utf8(z3).unwrap())));
+            let plain = AlignOpt {
+                clip_tags: false,
+                ..opt.clone()
+            };
+            assert!(align_read_records(&fm, &rec, &plain)[0].tag("Z5").is_none());
+        }
+    }
+
+    #[test]
+    fn trim_polyg_removes_dark_cycle_tail_before_aligning() {
+        let reference = random_dna(7, 200);
+        let fm = build_test_fm(&reference);
+        // 读段末端的参考碱基不是 G，剪除长度恰为人工 poly-G 尾
+        let end = (110..).find(|&e| reference[e - 1] != b'G').unwrap();
+        let mut seq = reference[50..end].to_vec();
+        seq.extend_from_slice(&[b'G'; 30]);
+        let rec = FastqRecord {
+            id: "polyg".to_string(),
+            desc: None,
+            qual: vec![b'I'; seq.len()],
+            seq,
+        };
+        let opt = AlignOpt {
+            trim_polyg: true,
+            ..default_opt()
+        };
+        let primary = &align_read_records(&fm, &rec, &opt)[0];
+        assert_eq!((primary.pos, primary.cigar.clone()), (51, format!("{}M", end - 50)));
+        assert_eq!(primary.seq.as_bytes(), &reference[50..end]);
+        assert_eq!(primary.qual.len(), end - 50);
+    }
+
+    /// 记录收到的 header、记录与 finish 调用的模拟输出格式
+    #[derive(Default)]
+    struct RecordingFormat {
+        contigs: Vec<(String, u32)>,
+        records: Vec<SamRecord>,
+        finished: bool,
+    }
+
+    impl OutputFormat for RecordingFormat {
+        fn write_header(&mut self, contigs: &[(&str, u32)]) -> Result<()> {
+            self.contigs = contigs.iter().map(|&(n, l)| (n.to_string(), l)).collect();
+            Ok(())
+        }
+
+        fn write_record(&mut self, rec: &SamRecord) -> Result<()> {
+            assert!(!self.finished);
+            self.records.push(rec.clone());
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<()> {
+            self.finished = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn pipeline_feeds_records_to_output_format() {
+        let fasta = b">chrA\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCC\n>chrB\nTCGGTTAGCCTAGCAATGGTACCGTTCAGGACTTAGCA\n";
+        let fm = build_fm_index(Cursor::new(&fasta[..]), 4).unwrap().fm;
+        let fq_path = std::env::temp_dir().join(format!("bwa_rust_mock_fmt_{}.fq", std::process::id()));
+        std::fs::write(
+            &fq_path,
+            "@a1\nGGCTTCAAGTCCGATGCATTGCATC\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n\
+             @u1\nTTTTTTTTTTTTTTTTTTTTTTTTT\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n\
+             @b1\nAGCCTAGCAATGGTACCGTTCAGGA\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n",
+        )
+        .unwrap();
+
+        let mut out = RecordingFormat::default();
+        let stats = align_fastq_to_output(&fm, fq_path.to_str().unwrap(), &mut out, default_opt()).unwrap();
+        std::fs::remove_file(&fq_path).ok();
+
+        assert_eq!(out.contigs, vec![("chrA".to_string(), 38), ("chrB".to_string(), 38)]);
+        let received: Vec<(&str, &str)> = out.records.iter().map(|r| (r.qname.as_str(), r.rname.as_str())).collect();
+        assert_eq!(received, vec![("a1", "chrA"), ("u1", "*"), ("b1", "chrB")]);
+        assert!(out.finished);
+        assert_eq!(stats.reads, 3);
+    }
+
+    #[test]
+    fn samse_from_sai_matches_direct_alignment() {
+        let fasta = b">chrA\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCC\n>chrB\nTCGGTTAGCCTAGCAATGGTACCGTTCAGGACTTAGCA\n";
+        let fm = build_fm_index(Cursor::new(&fasta[..]), 4).unwrap().fm;
+        let dir = std::env::temp_dir().join(format!("bwa_rust_samse_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let fq_path = dir.join("reads.fq");
+        let sai_path = dir.join("reads.sai");
+        std::fs::write(
+            &fq_path,
+            "@a1\nGGCTTCAAGTCCGATGCATTGCATC\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n\
+             @b1\nTGCTAAGTCCTGAACGGTACCATTG\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n\
+             @u1\nCCCCCCCCCCCCCCCCCCCCCCCCC\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n",
+        )
+        .unwrap();
+        let (fq, sai) = (fq_path.to_str().unwrap(), sai_path.to_str().unwrap());
+
+        let opt = default_opt();
+        assert_eq!(crate::align::sai::write_sai_file(&fm, fq, sai, &opt).unwrap(), 3);
+        let mut direct = RecordingFormat::default();
+        align_fastq_to_output(&fm, fq, &mut direct, opt.clone()).unwrap();
+        let mut staged = RecordingFormat::default();
+        let stats = align_sai_to_output(&fm, sai, fq, &mut staged, opt.clone()).unwrap();
+        assert_eq!(stats.reads, 3);
+        assert_eq!(staged.records, direct.records);
+        assert!(staged.records.iter().any(|r| r.flag & flags::REVERSE != 0));
+
+        // .sai 与 FASTQ 不对应时报错
+        std::fs::write(
+            &fq_path,
+            "@zz\nGGCTTCAAGTCCGATGCATTGCATC\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n",
+        )
+        .unwrap();
+        let err = align_sai_to_output(&fm, sai, fq, &mut RecordingFormat::default(), opt).unwrap_err();
+        std::fs::remove_dir_all(&dir).ok();
+        assert!(err.to_string().contains("does not match"), "{}", err);
+    }
+
+    #[test]
+    fn xa_tag_replaces_secondary_records() {
+        let mut reference = random_dna(3, 300);
+        // 完全相同的两份拷贝：位置靠前者为主比对
+        let copy = reference[50..110].to_vec();
+        reference[200..260].copy_from_slice(&copy);
+        let fm = build_test_fm(&reference);
+        let rec = FastqRecord {
+            id: "rep".to_string(),
+            desc: None,
+            seq: copy,
+            qual: vec![b'I'; 60],
+        };
+
+        let records = align_read_records(&fm, &rec, &default_opt());
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[0].pos, records[1].pos), (51, 201));
+        assert_eq!(records[1].flag & flags::SECONDARY, flags::SECONDARY);
+
+        let opt = AlignOpt {
+            xa_tag: true,
+            ..default_opt()
+        };
+        let records = align_read_records(&fm, &rec, &opt);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].pos, 51);
+        assert_eq!(
+            records[0].tag("XA"),
+            Some(&TagValue::Str("chr1,+201,60M,0;".to_string()))
+        );
+    }
+
+    #[test]
+    fn coverage_path_writes_bedgraph_of_overlapping_reads() {
+        let fasta = b">chrA\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCC\n";
+        let fm = build_fm_index(Cursor::new(&fasta[..]), 4).unwrap().fm;
+        let dir = std::env::temp_dir().join(format!("bwa_rust_coverage_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let fq_path = dir.join("reads.fq");
+        let bg_path = dir.join("cov.bedgraph");
+        std::fs::write(
+            &fq_path,
+            "@a1\nGGCTTCAAGTCCGATGCATTGCATC\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n\
+             @a2\nAAGTCCGATGCATTGCATCGGATCC\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n",
+        )
+        .unwrap();
+
+        let opt = AlignOpt {
+            coverage_path: Some(bg_path.to_str().unwrap().to_string()),
+            ..default_opt()
+        };
+        let mut out = RecordingFormat::default();
+        align_fastq_to_output(&fm, fq_path.to_str().unwrap(), &mut out, opt).unwrap();
+        let bedgraph = std::fs::read_to_string(&bg_path).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(bedgraph, "chrA\t7\t13\t1\nchrA\t13\t32\t2\nchrA\t32\t38\t1\n");
+    }
+
+    #[test]
+    fn phred64_qualities_are_written_as_phred33() {
+        let fasta = b">chrA\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCC\n";
+        let fm = build_fm_index(Cursor::new(&fasta[..]), 4).unwrap().fm;
+        let dir = std::env::temp_dir().join(format!("bwa_rust_phred64_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let fq_path = dir.join("reads.fq");
+        std::fs::write(
+            &fq_path,
+            "@a1\nGGCTTCAAGTCCGATGCATTGCATC\n+\nhhhhhhhhhhhhhhhhhhhhhhhhB\n",
+        )
+        .unwrap();
+
+        let opt = AlignOpt {
+            phred64: true,
+            ..default_opt()
+        };
+        let mut out = RecordingFormat::default();
+        align_fastq_to_output(&fm, fq_path.to_str().unwrap(), &mut out, opt).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(out.records[0].qual, format!("{}#", "I".repeat(24)));
+    }
+
+    #[test]
+    fn bam_out_path_writes_bgzf_bam() {
+        let fasta = b">chrA\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCC\n";
+        let fm = build_fm_index(Cursor::new(&fasta[..]), 4).unwrap().fm;
+        let dir = std::env::temp_dir().join(format!("bwa_rust_bam_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let fq_path = dir.join("reads.fq");
+        let bam_path = dir.join("out.bam");
+        std::fs::write(
+            &fq_path,
+            "@a1\nGGCTTCAAGTCCGATGCATTGCATC\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n",
+        )
+        .unwrap();
+
+        let mut out = open_output(bam_path.to_str(), &default_opt()).unwrap();
+        align_fastq_to_output(&fm, fq_path.to_str().unwrap(), out.as_mut(), default_opt()).unwrap();
+        drop(out);
+        let raw = std::fs::read(&bam_path).unwrap();
+        let paf = AlignOpt {
+            output_format: OutputMode::Paf,
+            ..default_opt()
+        };
+        assert!(open_output(bam_path.to_str(), &paf).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+
+        let mut bam = Vec::new();
+        std::io::Read::read_to_end(&mut flate2::read::MultiGzDecoder::new(&raw[..]), &mut bam).unwrap();
+        assert_eq!(&raw[12..14], b"BC");
+        assert_eq!(&bam[..4], b"BAM\x01");
+        let l_text = u32::from_le_bytes(bam[4..8].try_into().unwrap()) as usize;
+        assert!(std::str::from_utf8(&bam[8..8 + l_text]).unwrap().contains("SN:chrA\tLN:38"));
+        // 唯一一条记录：refID 0，POS 7（0-based）
+        let rec = &bam[8 + l_text + 4 + 4 + 5 + 4..];
+        assert_eq!(&rec[4..12], &[0, 0, 0, 0, 7, 0, 0, 0]);
+    }
+
+    #[test]
+    fn paf_output_mode_skips_unmapped_reads() {
+        let fasta = b">chrA\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCC\n";
+        let fm = Arc::new(build_fm_index(Cursor::new(&fasta[..]), 4).unwrap().fm);
+        let dir = std::env::temp_dir().join(format!("bwa_rust_paf_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let fq_path = dir.join("reads.fq");
+        std::fs::write(
+            &fq_path,
+            "@a1\nGGCTTCAAGTCCGATGCATTGCATC\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n\
+             @u1\nTTTTTTTTTTTTTTTTTTTTTTTTT\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n",
+        )
+        .unwrap();
+        let out_path = dir.join("out.paf");
+        let opt = AlignOpt {
+            output_format: OutputMode::Paf,
+            ..default_opt()
+        };
+        align_fastq_with_fm_opt(fm, fq_path.to_str().unwrap(), Some(out_path.to_str().unwrap()), opt).unwrap();
+
+        let text = std::fs::read_to_string(&out_path).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        let cols: Vec<&str> = text.lines().next().unwrap().split('\t').collect();
+        assert_eq!(text.lines().count(), 1, "{}", text);
+        assert_eq!(&cols[..9], &["a1", "25", "0", "25", "+", "chrA", "38", "7", "32"]);
+    }
 }
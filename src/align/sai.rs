@@ -0,0 +1,257 @@
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::index::fm::FMIndex;
+use crate::io::fastq::{FastqReader, FastqRecord};
+use crate::io::gzip::open_maybe_gzip;
+use crate::util::dna;
+
+use super::candidate::seed_min_len;
+use super::pipeline::{build_pool, polyg_trimmed};
+use super::seed::{find_smem_intervals, SmemInterval};
+use super::AlignOpt;
+
+/// `.sai` 文件魔数（"BWARSAI\0"，小端）
+pub const SAI_MAGIC: u64 = u64::from_le_bytes(*b"BWARSAI\0");
+/// `.sai` 文件格式版本
+pub const SAI_VERSION: u32 = 1;
+
+/// 一条 read 某一链方向上的种子搜索结果（`aln` 写出、`samse` 读回）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SaiRecord {
+    /// 是否为反向互补链
+    pub is_rev: bool,
+    /// 各 SMEM 的 SA 区间 `[l, r)`
+    pub intervals: Vec<(u32, u32)>,
+    /// 与 `intervals` 一一对应的 query 区间 `[qb, qe)`（该链方向上的坐标）
+    pub spans: Vec<(u32, u32)>,
+}
+
+impl SaiRecord {
+    pub fn from_smems(is_rev: bool, smems: &[SmemInterval]) -> Self {
+        Self {
+            is_rev,
+            intervals: smems.iter().map(|iv| (iv.sa_l as u32, iv.sa_r as u32)).collect(),
+            spans: smems.iter().map(|iv| (iv.qb as u32, iv.qe as u32)).collect(),
+        }
+    }
+
+    pub fn smems(&self) -> Vec<SmemInterval> {
+        self.intervals
+            .iter()
+            .zip(&self.spans)
+            .map(|(&(sa_l, sa_r), &(qb, qe))| SmemInterval {
+                qb: qb as usize,
+                qe: qe as usize,
+                sa_l: sa_l as usize,
+                sa_r: sa_r as usize,
+            })
+            .collect()
+    }
+}
+
+/// `records` 中 `is_rev` 链的 SMEM（无该链记录时为空）
+pub fn strand_smems(records: &[SaiRecord], is_rev: bool) -> Vec<SmemInterval> {
+    records
+        .iter()
+        .filter(|r| r.is_rev == is_rev)
+        .flat_map(SaiRecord::smems)
+        .collect()
+}
+
+/// 计算一条 read 正、反两链的 SMEM，与比对流程使用相同的 poly-G 剪切与最小种子长度
+pub fn sai_records(fm: &FMIndex, rec: &FastqRecord, opt: &AlignOpt) -> Vec<SaiRecord> {
+    let rec = polyg_trimmed(rec, opt);
+    let fwd: Vec<u8> = dna::normalize_seq(&rec.seq).into_iter().map(dna::to_alphabet).collect();
+    let rev: Vec<u8> = dna::normalize_seq(&dna::revcomp(&rec.seq))
+        .into_iter()
+        .map(dna::to_alphabet)
+        .collect();
+    [(false, fwd), (true, rev)]
+        .into_iter()
+        .map(|(is_rev, alpha)| {
+            let smems = find_smem_intervals(fm, &alpha, seed_min_len(opt, alpha.len()));
+            SaiRecord::from_smems(is_rev, &smems)
+        })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaiHeader {
+    magic: u64,
+    version: u32,
+    /// 生成时索引的 BWT 长度，用于检查 `samse` 使用的是同一索引
+    bwt_len: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaiEntry {
+    name: String,
+    records: Vec<SaiRecord>,
+}
+
+/// `.sai` 写出器：文件头后按 FASTQ 顺序逐条写入 read 名与其 [`SaiRecord`]
+pub struct SaiWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> SaiWriter<W> {
+    pub fn new(mut out: W, fm: &FMIndex) -> Result<Self> {
+        let header = SaiHeader {
+            magic: SAI_MAGIC,
+            version: SAI_VERSION,
+            bwt_len: fm.bwt.len() as u64,
+        };
+        bincode::serialize_into(&mut out, &header)?;
+        Ok(Self { out })
+    }
+
+    pub fn write_read(&mut self, name: &str, records: &[SaiRecord]) -> Result<()> {
+        let entry = SaiEntry {
+            name: name.to_string(),
+            records: records.to_vec(),
+        };
+        bincode::serialize_into(&mut self.out, &entry)?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// `.sai` 读取器；文件头须与 `fm` 匹配
+pub struct SaiReader<R: BufRead> {
+    reader: R,
+}
+
+impl<R: BufRead> SaiReader<R> {
+    pub fn new(mut reader: R, fm: &FMIndex) -> Result<Self> {
+        let header: SaiHeader =
+            bincode::deserialize_from(&mut reader).map_err(|e| anyhow!("invalid .sai file: {}", e))?;
+        if header.magic != SAI_MAGIC {
+            bail!("invalid .sai file: bad magic number");
+        }
+        if header.version != SAI_VERSION {
+            bail!(
+                "unsupported .sai version: expected {}, got {}",
+                SAI_VERSION,
+                header.version
+            );
+        }
+        if header.bwt_len != fm.bwt.len() as u64 {
+            bail!(".sai file was not generated from this index");
+        }
+        Ok(Self { reader })
+    }
+
+    /// 下一条 read 的 `(名称, 记录)`；文件结束时返回 `None`
+    pub fn next_read(&mut self) -> Result<Option<(String, Vec<SaiRecord>)>> {
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let entry: SaiEntry =
+            bincode::deserialize_from(&mut self.reader).map_err(|e| anyhow!("truncated .sai file: {}", e))?;
+        Ok(Some((entry.name, entry.records)))
+    }
+}
+
+impl SaiReader<BufReader<std::fs::File>> {
+    pub fn open(path: impl AsRef<Path>, fm: &FMIndex) -> Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path).map_err(|e| anyhow!("cannot open '{}': {}", path.display(), e))?;
+        Self::new(BufReader::new(file), fm)
+    }
+}
+
+/// 对 `fastq_path` 中的每条 read 做种子搜索并写出 `.sai` 文件，返回 read 数
+pub fn write_sai_file(fm: &FMIndex, fastq_path: &str, sai_path: &str, opt: &AlignOpt) -> Result<u64> {
+    let file = std::fs::File::create(sai_path).map_err(|e| anyhow!("cannot create '{}': {}", sai_path, e))?;
+    let mut writer = SaiWriter::new(BufWriter::new(file), fm)?;
+    let mut reader = FastqReader::new(open_maybe_gzip(fastq_path)?);
+    let pool = build_pool(opt.threads)?;
+    let mut n_reads = 0u64;
+    loop {
+        let mut batch: Vec<FastqRecord> = Vec::with_capacity(opt.batch_size);
+        for _ in 0..opt.batch_size {
+            match reader.next_record()? {
+                Some(rec) => batch.push(rec),
+                None => break,
+            }
+        }
+        if batch.is_empty() {
+            break;
+        }
+        let results: Vec<Vec<SaiRecord>> = match &pool {
+            Some(pool) => pool.install(|| batch.par_iter().map(|rec| sai_records(fm, rec, opt)).collect()),
+            None => batch.iter().map(|rec| sai_records(fm, rec, opt)).collect(),
+        };
+        for (rec, records) in batch.iter().zip(&results) {
+            writer.write_read(&rec.id, records)?;
+        }
+        n_reads += batch.len() as u64;
+    }
+    writer.finish()?;
+    Ok(n_reads)
+}
+
+/// 读取 `.sai` 中下一条 read 的记录，并检查其与 FASTQ 中的 read 对应
+pub(crate) fn next_sai_for<R: BufRead>(reader: &mut SaiReader<R>, rec: &FastqRecord) -> Result<Vec<SaiRecord>> {
+    match reader.next_read()? {
+        Some((name, records)) if name == rec.id => Ok(records),
+        Some((name, _)) => bail!(".sai read '{}' does not match FASTQ read '{}'", name, rec.id),
+        None => bail!(".sai file ends before FASTQ read '{}'", rec.id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{build_test_fm, random_dna};
+
+    fn reference() -> Vec<u8> {
+        random_dna(11, 300)
+    }
+
+    fn read(id: &str, seq: &[u8]) -> FastqRecord {
+        FastqRecord {
+            id: id.to_string(),
+            desc: None,
+            qual: vec![b'I'; seq.len()],
+            seq: seq.to_vec(),
+        }
+    }
+
+    #[test]
+    fn sai_roundtrip_and_header_checks() {
+        let reference = reference();
+        let fm = build_test_fm(&reference);
+        let opt = AlignOpt::default();
+        let r1 = read("r1", &reference[20..90]);
+        let r2 = read("r2", &dna::revcomp(&reference[150..220]));
+        let recs1 = sai_records(&fm, &r1, &opt);
+        assert_eq!(recs1.len(), 2);
+        assert_eq!(strand_smems(&recs1, false).len(), 1);
+        assert_eq!(strand_smems(&recs1, false)[0].qe, 70);
+
+        let mut buf = Vec::new();
+        let mut writer = SaiWriter::new(&mut buf, &fm).unwrap();
+        writer.write_read("r1", &recs1).unwrap();
+        writer.write_read("r2", &sai_records(&fm, &r2, &opt)).unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = SaiReader::new(&buf[..], &fm).unwrap();
+        assert_eq!(next_sai_for(&mut reader, &r1).unwrap(), recs1);
+        assert!(next_sai_for(&mut reader, &r1).is_err());
+        assert!(reader.next_read().unwrap().is_none());
+
+        let other = build_test_fm(&reference[..200]);
+        assert!(SaiReader::new(&buf[..], &other).is_err());
+        assert!(SaiReader::new(&b"not a sai file"[..], &fm).is_err());
+    }
+}
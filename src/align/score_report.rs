@@ -0,0 +1,176 @@
+use std::fmt;
+
+use crate::io::sam::{flags, SamRecord, TagValue};
+
+use super::sw::parse_cigar;
+
+/// 统计的 CIGAR 操作（按输出顺序）
+const REPORT_OPS: [char; 4] = ['M', 'I', 'D', 'S'];
+
+/// 汇报的得分分位点
+const QUANTILES: [(f64, &str); 5] = [
+    (0.0, "min"),
+    (0.25, "p25"),
+    (0.5, "median"),
+    (0.75, "p75"),
+    (1.0, "max"),
+];
+
+/// 单个 CIGAR 操作的累计：碱基数与出现次数（游程数）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpTally {
+    pub bases: u64,
+    pub events: u64,
+}
+
+/// 比对打分报告：汇总主比对的 CIGAR 操作分布与 AS 得分分位数，
+/// 用于按经验调整 `--gap-open` / `--mismatch` 等罚分。
+///
+/// 每条 read 只统计主比对（忽略 secondary / supplementary 记录）。
+#[derive(Debug, Clone, Default)]
+pub struct ScoreReport {
+    /// 统计的 read 数
+    pub reads: u64,
+    /// 未比对的 read 数
+    pub unmapped: u64,
+    /// 按 `REPORT_OPS` 顺序：M / I / D / S
+    pub ops: [OpTally; 4],
+    /// 主比对的 NM 总和
+    pub nm_total: u64,
+    scores: Vec<i32>,
+}
+
+impl ScoreReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 累加一条 SAM 记录；secondary / supplementary 记录被忽略
+    pub fn add(&mut self, record: &SamRecord) {
+        if record.flag & (flags::SECONDARY | flags::SUPPLEMENTARY) != 0 {
+            return;
+        }
+        self.reads += 1;
+        if record.is_unmapped() {
+            self.unmapped += 1;
+            return;
+        }
+        for (op, len) in parse_cigar(&record.cigar) {
+            if let Some(i) = REPORT_OPS.iter().position(|&o| o == op) {
+                self.ops[i].bases += len as u64;
+                self.ops[i].events += 1;
+            }
+        }
+        if let Some(TagValue::Int(nm)) = record.tag("NM") {
+            self.nm_total += (*nm).max(0) as u64;
+        }
+        if let Some(TagValue::Int(score)) = record.tag("AS") {
+            self.scores.push(*score as i32);
+        }
+    }
+
+    /// 已比对的 read 数
+    pub fn aligned(&self) -> u64 {
+        self.reads - self.unmapped
+    }
+
+    /// 指定 CIGAR 操作（`M`/`I`/`D`/`S`）的累计；其他操作返回 `None`
+    pub fn op(&self, op: char) -> Option<OpTally> {
+        REPORT_OPS.iter().position(|&o| o == op).map(|i| self.ops[i])
+    }
+
+    /// AS 得分的分位数（最近秩法，`q` ∈ [0, 1]）；无得分时返回 `None`
+    pub fn score_quantile(&self, q: f64) -> Option<i32> {
+        if self.scores.is_empty() {
+            return None;
+        }
+        let mut sorted = self.scores.clone();
+        sorted.sort_unstable();
+        let idx = ((sorted.len() - 1) as f64 * q.clamp(0.0, 1.0)).round() as usize;
+        Some(sorted[idx])
+    }
+}
+
+impl fmt::Display for ScoreReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "[score-report] reads: {}  aligned: {}  unmapped: {}",
+            self.reads,
+            self.aligned(),
+            self.unmapped
+        )?;
+        write!(f, "[score-report] CIGAR bases/events:")?;
+        for (op, tally) in REPORT_OPS.iter().zip(&self.ops) {
+            write!(f, "  {} {}/{}", op, tally.bases, tally.events)?;
+        }
+        writeln!(f)?;
+        let aligned_bases = self.ops[0].bases;
+        if aligned_bases > 0 {
+            writeln!(
+                f,
+                "[score-report] per aligned base: NM {:.4}  I {:.4}  D {:.4}",
+                self.nm_total as f64 / aligned_bases as f64,
+                self.ops[1].bases as f64 / aligned_bases as f64,
+                self.ops[2].bases as f64 / aligned_bases as f64
+            )?;
+        }
+        if !self.scores.is_empty() {
+            write!(f, "[score-report] AS:")?;
+            for (q, label) in QUANTILES {
+                write!(f, "  {} {}", label, self.score_quantile(q).unwrap_or(0))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapped(flag: u16, cigar: &str, score: i64, nm: i64) -> SamRecord {
+        let mut r = SamRecord::mapped("r", flag, "chr1", 1, 60, cigar, "", "");
+        r.push_tag("AS", TagValue::Int(score));
+        r.push_tag("NM", TagValue::Int(nm));
+        r
+    }
+
+    #[test]
+    fn report_tallies_cigar_ops_and_scores() {
+        let mut report = ScoreReport::new();
+        report.add(&mapped(0, "10M2I5M1D3M", 30, 3));
+        report.add(&mapped(16, "2S18M", 36, 0));
+        report.add(&mapped(0, "5S5M3D5M", 12, 3));
+        // secondary / supplementary 不计入
+        report.add(&mapped(flags::SECONDARY, "20M", 40, 0));
+        report.add(&mapped(flags::SUPPLEMENTARY, "10M10S", 20, 0));
+        report.add(&SamRecord::unmapped("u", "ACGT", "IIII"));
+
+        assert_eq!(report.reads, 4);
+        assert_eq!(report.aligned(), 3);
+        assert_eq!(report.unmapped, 1);
+        assert_eq!(report.op('M'), Some(OpTally { bases: 46, events: 6 }));
+        assert_eq!(report.op('I'), Some(OpTally { bases: 2, events: 1 }));
+        assert_eq!(report.op('D'), Some(OpTally { bases: 4, events: 2 }));
+        assert_eq!(report.op('S'), Some(OpTally { bases: 7, events: 2 }));
+        assert_eq!(report.op('N'), None);
+        assert_eq!(report.nm_total, 6);
+
+        assert_eq!(report.score_quantile(0.0), Some(12));
+        assert_eq!(report.score_quantile(0.5), Some(30));
+        assert_eq!(report.score_quantile(1.0), Some(36));
+
+        let text = report.to_string();
+        assert!(text.contains("M 46/6  I 2/1  D 4/2  S 7/2"), "{}", text);
+        assert!(text.contains("median 30"), "{}", text);
+    }
+
+    #[test]
+    fn empty_report_has_no_quantiles() {
+        let report = ScoreReport::new();
+        assert_eq!(report.score_quantile(0.5), None);
+        assert!(!report.to_string().contains("AS:"));
+    }
+}
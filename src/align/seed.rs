@@ -1,12 +1,16 @@
+use std::collections::HashSet;
+
+use crate::index::bifm::{BiFMIndex, BiInterval};
 use crate::index::fm::FMIndex;
+use crate::util::dna;
 
 /// Default maximum occurrences for MEM seeds (skip highly repetitive seeds)
 pub const DEFAULT_MAX_OCC: usize = 500;
 
 /// 对齐区域结构，类似 BWA 的 mem_alnreg_t。
 ///
-/// 当前版本（v0.1.0）的 pipeline 使用 `candidate::AlignCandidate` 作为内部候选表示。
-/// `AlnReg` 保留为公开 API 类型，供未来配对端（PE）对齐和库模式调用使用。
+/// pipeline 使用 `candidate::AlignCandidate` 作为内部候选表示，计算 MAPQ 时经
+/// `AlignCandidate::to_aln_reg` 转换为 `AlnReg` 交给 [`super::mapq::mapq`]，库调用与 CLI 共用同一模型。
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AlnReg {
     /// read 上的区间 [qb, qe)
@@ -27,6 +31,10 @@ pub struct AlnReg {
     pub nm: u32,
     /// 是否反向互补
     pub is_rev: bool,
+    /// 比对区间内被种子覆盖的 query 碱基数
+    pub seed_cov: usize,
+    /// 与该区域同分的其他参考位置数（如重复种子的额外出现次数）
+    pub sub_n: usize,
 }
 
 /// MEM 种子
@@ -37,6 +45,12 @@ pub struct MemSeed {
     pub qe: usize,
     pub rb: u32,
     pub re: u32,
+    /// 所属 SMEM 的 SA 区间大小，即该匹配在参考中的出现次数；
+    /// 唯一种子为 1，可作为链构建与 MAPQ 的廉价重复度权重
+    pub occ: u32,
+    /// 种子来自 read 的反向互补链（`qb`/`qe` 为该链方向上的坐标）；
+    /// 仅 [`find_smem_seeds_both_strands`] 会置位，单链搜索结果均为 `false`
+    pub is_rev: bool,
 }
 
 /// SMEM 搜索：对 read 的每个位置，找到包含该位置的最长精确匹配（MEM）。
@@ -55,21 +69,112 @@ pub fn find_smem_seeds_with_max_occ(fm: &FMIndex, query_alpha: &[u8], min_len: u
         return Vec::new();
     }
 
+    let mut checkpoint = SmemCheckpoint::new();
+    resume_smem_search(fm, query_alpha, &mut checkpoint);
+    seeds_from_checkpoint(fm, &checkpoint, min_len, max_occ)
+}
+
+/// 对 read 的正、反两链分别做 SMEM 搜索并合并种子：反向互补链上的种子 `is_rev = true`，
+/// 其 `qb`/`qe` 为反向互补序列上的坐标。`read_norm` 为 [`dna::normalize_seq`] 规范化后的 ASCII 序列。
+///
+/// 自身反向互补（回文）的匹配会在两链上以同一参考位置各被找到一次，此时只保留正链种子。
+/// 结果可直接交给 [`build_chains`](super::chain::build_chains)，两链的链在同一排序中比较，
+/// 从而直接选出全局最优链。
+pub fn find_smem_seeds_both_strands(fm: &FMIndex, read_norm: &[u8], min_len: usize) -> Vec<MemSeed> {
+    find_smem_seeds_both_strands_with_max_occ(fm, read_norm, min_len, DEFAULT_MAX_OCC)
+}
+
+/// 同 [`find_smem_seeds_both_strands`]，但可指定最大出现次数限制
+pub fn find_smem_seeds_both_strands_with_max_occ(
+    fm: &FMIndex,
+    read_norm: &[u8],
+    min_len: usize,
+    max_occ: usize,
+) -> Vec<MemSeed> {
+    let n = read_norm.len();
+    let fwd: Vec<u8> = read_norm.iter().map(|&b| dna::to_alphabet(b)).collect();
+    let rev: Vec<u8> = dna::revcomp(read_norm).into_iter().map(dna::to_alphabet).collect();
+
+    let mut seeds = find_smem_seeds_with_max_occ(fm, &fwd, min_len, max_occ);
+    let fwd_hits: HashSet<(usize, usize, usize, u32)> = seeds.iter().map(|s| (s.contig, s.qb, s.qe, s.rb)).collect();
+    for mut s in find_smem_seeds_with_max_occ(fm, &rev, min_len, max_occ) {
+        // 换算到正链坐标后与正链种子重合者为回文匹配
+        if !fwd_hits.contains(&(s.contig, n - s.qe, n - s.qb, s.rb)) {
+            s.is_rev = true;
+            seeds.push(s);
+        }
+    }
+    seeds
+}
+
+/// 单个右端点的最长左扩展结果：query 区间 `[qb, qe)` 及其 SA 区间 `[sa_l, sa_r)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SmemInterval {
+    pub qb: usize,
+    pub qe: usize,
+    pub sa_l: usize,
+    pub sa_r: usize,
+}
+
+/// SMEM 搜索的可缓存中间状态：按右端点 `qe = 1, 2, …` 依次保存最长左扩展结果
+/// （该位置字符在参考中不出现时为 `None`）。
+///
+/// 右端点 `qe` 的结果只依赖 query 前缀 `[0, qe)`，因此 read 仅后缀变化时（迭代精修、增量比对），
+/// 可用 [`SmemCheckpoint::truncate`] 截到未变化的前缀，再用 [`resume_smem_search`] 只补算后缀。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SmemCheckpoint {
+    intervals: Vec<Option<SmemInterval>>,
+}
+
+impl SmemCheckpoint {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 已计算的右端点数，即检查点覆盖的 query 前缀长度
+    pub fn len(&self) -> usize {
+        self.intervals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    /// 各右端点的结果，下标 `i` 对应 `qe = i + 1`
+    pub fn intervals(&self) -> &[Option<SmemInterval>] {
+        &self.intervals
+    }
+
+    /// 只保留右端点不超过 `pos` 的结果（query 从 `pos` 起发生变化时调用）
+    pub fn truncate(&mut self, pos: usize) {
+        self.intervals.truncate(pos);
+    }
+}
+
+/// 从检查点继续 SMEM 搜索，补算右端点 `checkpoint.len() + 1 ..= query_alpha.len()` 的结果。
+///
+/// 调用方须保证 query 前缀 `[0, checkpoint.len())` 与生成检查点时相同；
+/// 检查点长于 query 时先截断到 query 长度。对空检查点调用即为完整搜索。
+pub fn resume_smem_search(fm: &FMIndex, query_alpha: &[u8], checkpoint: &mut SmemCheckpoint) {
+    let n = query_alpha.len();
+    checkpoint.truncate(n);
     let bwt_len = fm.bwt.len();
-    let mut raw_mems: Vec<(usize, usize, usize, usize)> = Vec::new(); // (qb, qe, sa_l, sa_r)
 
-    // 第一步：对每个右端点 qe，通过增量左扩展找到最长精确匹配。
-    // 从单字符 query[qe-1] 开始，逐步向左调用 rank_range 扩展 SA 区间，
+    // 对每个右端点 qe，从单字符 query[qe-1] 开始逐步向左调用 rank_range 扩展 SA 区间，
     // 直到区间为空或到达 query 左端。
-    for qe in 1..=n {
+    for qe in checkpoint.len() + 1..=n {
         let (mut l, mut r) = fm.rank_range(query_alpha[qe - 1], 0, bwt_len);
         if l >= r {
+            checkpoint.intervals.push(None);
             continue;
         }
 
-        let mut best_qb = qe - 1;
-        let mut best_l = l;
-        let mut best_r = r;
+        let mut best = SmemInterval {
+            qb: qe - 1,
+            qe,
+            sa_l: l,
+            sa_r: r,
+        };
 
         // 增量左扩展：每步 O(1)
         for qb in (0..qe.saturating_sub(1)).rev() {
@@ -79,23 +184,71 @@ pub fn find_smem_seeds_with_max_occ(fm: &FMIndex, query_alpha: &[u8], min_len: u
             }
             l = nl;
             r = nr;
-            best_qb = qb;
-            best_l = l;
-            best_r = r;
+            best = SmemInterval {
+                qb,
+                qe,
+                sa_l: l,
+                sa_r: r,
+            };
         }
+        checkpoint.intervals.push(Some(best));
+    }
+}
 
-        let match_len = qe - best_qb;
-        if match_len >= min_len {
-            raw_mems.push((best_qb, qe, best_l, best_r));
-        }
+/// 由检查点生成 SMEM 种子：保留长度不小于 `min_len` 的匹配，过滤被包含者后展开为种子，
+/// 与 [`find_smem_seeds_with_max_occ`] 的结果相同
+pub fn seeds_from_checkpoint(
+    fm: &FMIndex,
+    checkpoint: &SmemCheckpoint,
+    min_len: usize,
+    max_occ: usize,
+) -> Vec<MemSeed> {
+    seeds_from_smems(fm, &smems_from_checkpoint(checkpoint, min_len), max_occ)
+}
+
+/// SMEM 搜索但不展开为种子：返回长度不小于 `min_len`、不被其他匹配包含的 query 区间及其 SA 区间。
+///
+/// 结果只依赖索引与 query，可缓存（如 `aln` 写出的 `.sai` 文件），之后用 [`seeds_from_smems`] 展开。
+pub fn find_smem_intervals(fm: &FMIndex, query_alpha: &[u8], min_len: usize) -> Vec<SmemInterval> {
+    if min_len == 0 || min_len > query_alpha.len() {
+        return Vec::new();
     }
+    let mut checkpoint = SmemCheckpoint::new();
+    resume_smem_search(fm, query_alpha, &mut checkpoint);
+    smems_from_checkpoint(&checkpoint, min_len)
+}
+
+/// 将 SMEM 展开为种子，SA 区间大小超过 `max_occ` 者跳过
+pub fn seeds_from_smems(fm: &FMIndex, smems: &[SmemInterval], max_occ: usize) -> Vec<MemSeed> {
+    let raw_mems: Vec<(usize, usize, usize, usize)> = smems.iter().map(|iv| (iv.qb, iv.qe, iv.sa_l, iv.sa_r)).collect();
+    expand_mems(fm, &raw_mems, max_occ)
+}
 
-    // 第二步：过滤被包含的 MEM，保留 SMEM
+fn smems_from_checkpoint(checkpoint: &SmemCheckpoint, min_len: usize) -> Vec<SmemInterval> {
+    if min_len == 0 {
+        return Vec::new();
+    }
+    let mut raw_mems: Vec<(usize, usize, usize, usize)> = checkpoint
+        .intervals
+        .iter()
+        .flatten()
+        .filter(|iv| iv.qe - iv.qb >= min_len)
+        .map(|iv| (iv.qb, iv.qe, iv.sa_l, iv.sa_r))
+        .collect();
+
+    // 过滤被包含的 MEM，保留 SMEM
     filter_contained(&mut raw_mems);
 
-    // 第三步：将区间展开为具体种子，跳过高度重复的种子
+    raw_mems
+        .into_iter()
+        .map(|(qb, qe, sa_l, sa_r)| SmemInterval { qb, qe, sa_l, sa_r })
+        .collect()
+}
+
+/// 将 SMEM 区间展开为具体种子，跳过高度重复的种子
+fn expand_mems(fm: &FMIndex, raw_mems: &[(usize, usize, usize, usize)], max_occ: usize) -> Vec<MemSeed> {
     let mut seeds = Vec::new();
-    for (qb, qe, l, r) in &raw_mems {
+    for (qb, qe, l, r) in raw_mems {
         let occ = r - l;
         if occ > max_occ {
             // Skip highly repetitive seeds to avoid memory explosion
@@ -103,7 +256,8 @@ pub fn find_smem_seeds_with_max_occ(fm: &FMIndex, query_alpha: &[u8], min_len: u
         }
         let seed_len = (qe - qb) as u32;
         fm.for_each_sa_interval_position(*l, *r, |sa_pos| {
-            if let Some((ci, off)) = fm.map_text_pos(sa_pos) {
+            // 跳过起点落在长 N 区间（组装缺口）内的种子
+            if let Some((ci, off, false)) = fm.map_text_pos_checked(sa_pos) {
                 let contig_len = fm.contigs[ci].len;
                 if off + seed_len <= contig_len {
                     seeds.push(MemSeed {
@@ -112,6 +266,8 @@ pub fn find_smem_seeds_with_max_occ(fm: &FMIndex, query_alpha: &[u8], min_len: u
                         qe: *qe,
                         rb: off,
                         re: off + seed_len,
+                        occ: occ as u32,
+                        is_rev: false,
                     });
                 }
             }
@@ -122,6 +278,103 @@ pub fn find_smem_seeds_with_max_occ(fm: &FMIndex, query_alpha: &[u8], min_len: u
     seeds
 }
 
+/// 基于双向 FM 索引的 SMEM 搜索（对应 BWA 的 `bwt_smem1`）。
+///
+/// 从位置 x 出发先向右扩展，记录区间大小发生变化的各前缀；再对这些候选同时向左扩展，
+/// 无法继续扩展且未被更长候选覆盖者即为覆盖 x 的 SMEM。下一轮从最长右扩展的终点开始，
+/// 每个 read 的扩展次数为均摊线性。输出与 [`find_smem_seeds`] 相同。
+pub fn find_smem_seeds_bidir(bi: &BiFMIndex, query_alpha: &[u8], min_len: usize) -> Vec<MemSeed> {
+    find_smem_seeds_bidir_with_max_occ(bi, query_alpha, min_len, DEFAULT_MAX_OCC)
+}
+
+/// 同 [`find_smem_seeds_bidir`]，但可指定最大出现次数限制
+pub fn find_smem_seeds_bidir_with_max_occ(
+    bi: &BiFMIndex,
+    query_alpha: &[u8],
+    min_len: usize,
+    max_occ: usize,
+) -> Vec<MemSeed> {
+    let n = query_alpha.len();
+    if min_len == 0 || n == 0 || min_len > n {
+        return Vec::new();
+    }
+
+    let mut smems: Vec<(usize, usize, BiInterval)> = Vec::new();
+    let mut x = 0usize;
+    while x < n {
+        x = smem_at(bi, query_alpha, x, &mut smems);
+    }
+
+    let mut raw_mems: Vec<(usize, usize, usize, usize)> = smems
+        .into_iter()
+        .filter(|(qb, qe, _)| qe - qb >= min_len)
+        .map(|(qb, qe, iv)| {
+            let (l, r) = iv.forward_range();
+            (qb, qe, l, r)
+        })
+        .collect();
+    // 相邻两轮可能报告同一个 SMEM
+    filter_contained(&mut raw_mems);
+    expand_mems(&bi.fwd, &raw_mems, max_occ)
+}
+
+/// 求覆盖位置 `x` 的全部 SMEM，追加 `(qb, qe, 区间)` 到 `out`；返回下一轮的起点
+fn smem_at(bi: &BiFMIndex, q: &[u8], x: usize, out: &mut Vec<(usize, usize, BiInterval)>) -> usize {
+    let Some(mut ik) = bi.init(q[x]) else {
+        return x + 1;
+    };
+
+    // 向右扩展：记录区间大小变化前的各前缀 (qe, 区间)
+    let mut prev: Vec<(usize, BiInterval)> = Vec::new();
+    let mut qe = x + 1;
+    while qe < q.len() {
+        match bi.bi_extend(ik, q[qe], true) {
+            Some(ok) => {
+                if ok.s != ik.s {
+                    prev.push((qe, ik));
+                }
+                ik = ok;
+                qe += 1;
+            }
+            None => break,
+        }
+    }
+    prev.push((qe, ik));
+    // 按 qe 降序（区间由小到大）
+    prev.reverse();
+    let next = prev[0].0;
+
+    // 向左扩展：不能再扩展且没有更长候选存活时输出
+    let mut found: Vec<(usize, usize, BiInterval)> = Vec::new();
+    let mut qb = x;
+    loop {
+        let c = if qb == 0 { None } else { Some(q[qb - 1]) };
+        let mut curr: Vec<(usize, BiInterval)> = Vec::new();
+        for &(end, iv) in &prev {
+            match c.and_then(|c| bi.bi_extend(iv, c, false)) {
+                Some(ok) => {
+                    if curr.last().map_or(true, |&(_, last)| last.s != ok.s) {
+                        curr.push((end, ok));
+                    }
+                }
+                None => {
+                    if curr.is_empty() && found.last().map_or(true, |&(b, _, _)| qb < b) {
+                        found.push((qb, end, iv));
+                    }
+                }
+            }
+        }
+        if curr.is_empty() {
+            break;
+        }
+        prev = curr;
+        qb -= 1;
+    }
+    found.reverse();
+    out.extend(found);
+    next
+}
+
 /// 过滤被其他区间完全包含的 MEM
 fn filter_contained(mems: &mut Vec<(usize, usize, usize, usize)>) {
     if mems.len() <= 1 {
@@ -147,6 +400,7 @@ fn dedup_seeds(seeds: &mut Vec<MemSeed>) {
     seeds.sort_by(|a, b| {
         a.contig
             .cmp(&b.contig)
+            .then(a.is_rev.cmp(&b.is_rev))
             .then(a.qb.cmp(&b.qb))
             .then(a.qe.cmp(&b.qe))
             .then(a.rb.cmp(&b.rb))
@@ -158,7 +412,7 @@ fn dedup_seeds(seeds: &mut Vec<MemSeed>) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::testutil::build_test_fm;
+    use crate::testutil::{build_test_fm, random_dna, Lcg};
     use crate::util::dna;
 
     #[test]
@@ -224,6 +478,27 @@ mod tests {
         assert!(seeds.is_empty());
     }
 
+    #[test]
+    fn seeds_report_occurrence_count() {
+        let mut rng = Lcg::new(11);
+        let (unique, dup, spacer) = (rng.dna(40), rng.dna(30), rng.dna(40));
+        // 参考：unique + dup + spacer + dup，dup 出现两次
+        let mut reference = unique.clone();
+        reference.extend_from_slice(&dup);
+        reference.extend_from_slice(&spacer);
+        reference.extend_from_slice(&dup);
+        let fm = build_test_fm(&reference);
+        let to_alpha = |s: &[u8]| -> Vec<u8> { s.iter().map(|&b| dna::to_alphabet(b)).collect() };
+
+        let seeds = find_smem_seeds(&fm, &to_alpha(&dup), 19);
+        assert_eq!(seeds.len(), 2);
+        assert!(seeds.iter().all(|s| s.occ == 2 && s.qe - s.qb == 30));
+
+        let seeds = find_smem_seeds(&fm, &to_alpha(&unique[5..35]), 19);
+        assert_eq!(seeds.len(), 1);
+        assert_eq!(seeds[0].occ, 1);
+    }
+
     #[test]
     fn smem_seeds_have_valid_coordinates() {
         let fm = build_test_fm(b"ACGTACGTACGTACGTACGT");
@@ -294,4 +569,130 @@ mod tests {
         // AAA appears many times in AAAAAAAAAA..., so with max_occ=2 most should be filtered
         assert!(seeds_limited.len() <= seeds_unlimited.len());
     }
+
+    #[test]
+    fn smem_skips_seeds_inside_n_runs() {
+        let mut reference = b"ACGTTGCAAGCT".to_vec();
+        reference.extend_from_slice(&[b'N'; 20]);
+        reference.extend_from_slice(b"TTGACCGATGCA");
+        let fm = build_test_fm(&reference);
+
+        let gap: Vec<u8> = [b'N'; 12].iter().map(|&b| dna::to_alphabet(b)).collect();
+        assert!(find_smem_seeds(&fm, &gap, 4).is_empty());
+
+        let flank: Vec<u8> = b"TTGACCGATG".iter().map(|&b| dna::to_alphabet(b)).collect();
+        let seeds = find_smem_seeds(&fm, &flank, 4);
+        assert_eq!(seeds.len(), 1);
+        assert_eq!(seeds[0].rb, 32);
+    }
+
+    #[test]
+    fn smem_bidir_matches_unidirectional() {
+        let mut rng = Lcg::new(7);
+        let mut next = || rng.next_u32() >> 16;
+        let bases = [b'A', b'C', b'G', b'T'];
+        let mut reference: Vec<u8> = (0..600).map(|_| bases[next() as usize % 4]).collect();
+        // 重复片段与 N 区间
+        let repeat = reference[100..160].to_vec();
+        reference[400..460].copy_from_slice(&repeat);
+        reference[300..305].copy_from_slice(b"NNNNN");
+        let bi = BiFMIndex::new(build_test_fm(&reference));
+
+        for round in 0..200 {
+            let start = next() as usize % 500;
+            let len = 20 + next() as usize % 80;
+            let mut read = reference[start..(start + len).min(reference.len())].to_vec();
+            for _ in 0..round % 5 {
+                let i = next() as usize % read.len();
+                read[i] = bases[next() as usize % 4];
+            }
+            let alpha: Vec<u8> = read.iter().map(|&b| dna::to_alphabet(b)).collect();
+            for min_len in [1, 5, 12] {
+                assert_eq!(
+                    find_smem_seeds_bidir(&bi, &alpha, min_len),
+                    find_smem_seeds(&bi.fwd, &alpha, min_len),
+                    "round {} min_len {}",
+                    round,
+                    min_len
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn smem_bidir_reports_overlapping_smems() {
+        // read 由参考中两段不相邻的片段拼接而成，交界处两个 SMEM 相互重叠
+        let bi = BiFMIndex::new(build_test_fm(b"TTACGTACCGGATTTTTTCCGGATCAGTAGGC"));
+        let alpha: Vec<u8> = b"ACGTACCGGATCAGTA".iter().map(|&b| dna::to_alphabet(b)).collect();
+        let seeds = find_smem_seeds_bidir(&bi, &alpha, 4);
+        let spans: Vec<(usize, usize)> = seeds.iter().map(|s| (s.qb, s.qe)).collect();
+        assert_eq!(spans, vec![(0, 11), (5, 16)]);
+        assert_eq!((seeds[0].rb, seeds[1].rb), (2, 18));
+    }
+
+    #[test]
+    fn smem_resume_from_checkpoint_matches_full_search() {
+        let mut rng = Lcg::new(11);
+        let mut next = || rng.next_u32() >> 16;
+        let bases = [b'A', b'C', b'G', b'T'];
+        let reference: Vec<u8> = (0..2000).map(|_| bases[next() as usize % 4]).collect();
+        let fm = build_test_fm(&reference);
+        let alpha = |seq: &[u8]| -> Vec<u8> { seq.iter().map(|&b| dna::to_alphabet(b)).collect() };
+
+        let original = alpha(&reference[300..500]);
+        let mut checkpoint = SmemCheckpoint::new();
+        resume_smem_search(&fm, &original, &mut checkpoint);
+        assert_eq!(checkpoint.len(), original.len());
+        assert_eq!(
+            seeds_from_checkpoint(&fm, &checkpoint, 19, DEFAULT_MAX_OCC),
+            find_smem_seeds(&fm, &original, 19)
+        );
+
+        // 保留前 120 个碱基，后缀换成参考另一处的序列
+        for cut in [0, 1, 120, 199] {
+            let mut changed = original[..cut].to_vec();
+            changed.extend(alpha(&reference[1200..1400 - cut]));
+            let mut resumed = checkpoint.clone();
+            resumed.truncate(cut);
+            resume_smem_search(&fm, &changed, &mut resumed);
+
+            let mut full = SmemCheckpoint::new();
+            resume_smem_search(&fm, &changed, &mut full);
+            assert_eq!(resumed, full, "cut {}", cut);
+            assert_eq!(
+                seeds_from_checkpoint(&fm, &resumed, 19, DEFAULT_MAX_OCC),
+                find_smem_seeds(&fm, &changed, 19),
+                "cut {}",
+                cut
+            );
+        }
+
+        // 检查点长于新 query 时自动截断
+        let shorter = original[..50].to_vec();
+        let mut cp = checkpoint.clone();
+        resume_smem_search(&fm, &shorter, &mut cp);
+        assert_eq!(cp.intervals(), &checkpoint.intervals()[..50]);
+    }
+
+    #[test]
+    fn both_strand_seeds_are_tagged_and_palindromes_deduped() {
+        let mut reference = random_dna(5, 300);
+        // 20bp 回文：自身即为反向互补
+        let half = reference[10..20].to_vec();
+        let palindrome = [half.clone(), dna::revcomp(&half)].concat();
+        reference[250..270].copy_from_slice(&palindrome);
+        let fm = build_test_fm(&reference);
+
+        let read = dna::revcomp(&reference[100..160]);
+        let seeds = find_smem_seeds_both_strands(&fm, &read, 19);
+        assert!(seeds.iter().any(|s| s.is_rev && (s.qb, s.qe, s.rb) == (0, 60, 100)));
+        let chains = crate::align::chain::build_chains(&seeds, 100);
+        assert!(chains[0].is_rev());
+        assert_eq!(chains[0].score, 60);
+
+        let seeds = find_smem_seeds_both_strands(&fm, &palindrome, 20);
+        assert_eq!(seeds.len(), 1);
+        assert!(!seeds[0].is_rev);
+        assert_eq!(seeds[0].rb, 250);
+    }
 }
@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::io::sam::{flags, parse_cigar_ops, SamRecord};
+
+use super::candidate::cigar_ref_length;
+
+/// 摘要中“高质量比对”的 MAPQ 下限
+pub const HIGH_MAPQ: u8 = 30;
+
+/// 比对运行汇总：read 数、比对/未比对数、MAPQ 分布、各 contig 的比对数、
+/// 比对跨度与剪切长度分布以及耗时。
+///
+/// 每条 read（双端时每个 mate）只统计主比对记录，secondary / supplementary 被忽略。
+/// 可经 serde 序列化为 JSON（`--stats-json`）供看板等程序读取。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AlignStats {
+    /// 统计的 read 数
+    pub reads: u64,
+    /// 已比对的 read 数
+    pub mapped: u64,
+    /// 未比对的 read 数
+    pub unmapped: u64,
+    /// 已比对 read 的 MAPQ 分布：MAPQ → read 数
+    pub mapq_histogram: BTreeMap<u8, u64>,
+    /// 各 contig 上的主比对数：contig 名 → read 数
+    pub per_contig: BTreeMap<String, u64>,
+    /// 已比对 read 消耗的参考长度（CIGAR 中 M/D/N/=/X 之和）分布：长度 → read 数
+    #[serde(default)]
+    pub ref_span_histogram: BTreeMap<u32, u64>,
+    /// 已比对 read 两端剪切碱基总数（S/H）分布：长度 → read 数；用于发现参数不当导致的过度剪切
+    #[serde(default)]
+    pub clip_histogram: BTreeMap<u32, u64>,
+    /// 比对耗时（秒，不含索引加载）
+    pub elapsed_secs: f64,
+}
+
+impl AlignStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 累加一条 SAM 记录；secondary / supplementary 记录被忽略
+    pub fn add(&mut self, record: &SamRecord) {
+        if record.flag & (flags::SECONDARY | flags::SUPPLEMENTARY) != 0 {
+            return;
+        }
+        self.reads += 1;
+        if record.is_unmapped() {
+            self.unmapped += 1;
+            return;
+        }
+        self.mapped += 1;
+        *self.mapq_histogram.entry(record.mapq).or_insert(0) += 1;
+        *self.per_contig.entry(record.rname.clone()).or_insert(0) += 1;
+
+        let span = cigar_ref_length(&record.cigar) as u32;
+        let clipped: u32 = parse_cigar_ops(&record.cigar)
+            .into_iter()
+            .filter(|(op, _)| matches!(op, 'S' | 'H'))
+            .map(|(_, len)| len as u32)
+            .sum();
+        *self.ref_span_histogram.entry(span).or_insert(0) += 1;
+        *self.clip_histogram.entry(clipped).or_insert(0) += 1;
+    }
+
+    /// 已比对 read 参考跨度的 `q` 分位数（`q` ∈ [0, 1]，最近秩法）；无已比对 read 时为 `None`
+    pub fn ref_span_percentile(&self, q: f64) -> Option<u32> {
+        histogram_percentile(&self.ref_span_histogram, q)
+    }
+
+    /// 已比对 read 剪切碱基数的 `q` 分位数（`q` ∈ [0, 1]，最近秩法）；无已比对 read 时为 `None`
+    pub fn clip_percentile(&self, q: f64) -> Option<u32> {
+        histogram_percentile(&self.clip_histogram, q)
+    }
+
+    /// MAPQ 不低于 `min` 的已比对 read 数
+    pub fn mapped_with_mapq(&self, min: u8) -> u64 {
+        self.mapq_histogram.range(min..).map(|(_, &n)| n).sum()
+    }
+
+    /// 比对过程中的进度行（不含换行）
+    pub fn progress_line(&self, elapsed_secs: f64) -> String {
+        format!(
+            "[bwa-rust] Processed {} reads in {:.2} s ({} mapped)",
+            self.reads, elapsed_secs, self.mapped
+        )
+    }
+
+    /// 运行结束时的摘要（含换行）；最后一行为制表符分隔的 `key=value`，便于脚本解析
+    pub fn summary(&self) -> String {
+        let pct = |n: u64| {
+            if self.reads == 0 {
+                0.0
+            } else {
+                n as f64 * 100.0 / self.reads as f64
+            }
+        };
+        let high = self.mapped_with_mapq(HIGH_MAPQ);
+        format!(
+            "[bwa-rust] Processed {} reads in {:.2} s\n\
+             [bwa-rust] {} mapped ({:.2}%), {} unmapped ({:.2}%), {} with MAPQ>={} ({:.2}%)\n\
+             summary\treads={}\tmapped={}\tunmapped={}\tmapq{}={}\telapsed_secs={:.3}\n",
+            self.reads,
+            self.elapsed_secs,
+            self.mapped,
+            pct(self.mapped),
+            self.unmapped,
+            pct(self.unmapped),
+            high,
+            HIGH_MAPQ,
+            pct(high),
+            self.reads,
+            self.mapped,
+            self.unmapped,
+            HIGH_MAPQ,
+            high,
+            self.elapsed_secs
+        )
+    }
+
+    /// 序列化为带缩进的 JSON
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// 将 JSON 写入 `path`
+    pub fn write_json(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let json = self.to_json()?;
+        std::fs::write(path, json + "\n")
+            .map_err(|e| anyhow::anyhow!("cannot write stats to '{}': {}", path.display(), e))
+    }
+}
+
+/// 直方图（取值 → 计数）的最近秩分位数
+fn histogram_percentile(histogram: &BTreeMap<u32, u64>, q: f64) -> Option<u32> {
+    let total: u64 = histogram.values().sum();
+    if total == 0 {
+        return None;
+    }
+    let rank = ((q.clamp(0.0, 1.0) * total as f64).ceil() as u64).max(1);
+    let mut seen = 0u64;
+    for (&value, &count) in histogram {
+        seen += count;
+        if seen >= rank {
+            return Some(value);
+        }
+    }
+    histogram.keys().next_back().copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapped(rname: &str, mapq: u8, flag: u16) -> SamRecord {
+        SamRecord {
+            rname: rname.to_string(),
+            pos: 1,
+            mapq,
+            flag,
+            cigar: "4M".to_string(),
+            ..SamRecord::unmapped("r", "ACGT", "IIII")
+        }
+    }
+
+    #[test]
+    fn stats_count_primary_records_only() {
+        let mut stats = AlignStats::new();
+        stats.add(&mapped("chr1", 60, 0));
+        stats.add(&mapped("chr1", 60, flags::REVERSE));
+        stats.add(&mapped("chr2", 3, 0));
+        stats.add(&mapped("chr2", 0, flags::SECONDARY));
+        stats.add(&mapped("chr1", 0, flags::SUPPLEMENTARY));
+        stats.add(&SamRecord::unmapped("u", "ACGT", "IIII"));
+        assert_eq!((stats.reads, stats.mapped, stats.unmapped), (4, 3, 1));
+        assert_eq!(stats.mapq_histogram, BTreeMap::from([(3, 1), (60, 2)]));
+        assert_eq!(
+            stats.per_contig,
+            BTreeMap::from([("chr1".to_string(), 2), ("chr2".to_string(), 1)])
+        );
+    }
+
+    #[test]
+    fn summary_last_line_is_machine_parseable() {
+        let mut stats = AlignStats::new();
+        stats.add(&mapped("chr1", 60, 0));
+        stats.add(&mapped("chr1", 30, 0));
+        stats.add(&mapped("chr2", 12, 0));
+        stats.add(&SamRecord::unmapped("u", "ACGT", "IIII"));
+        stats.elapsed_secs = 0.5;
+        assert_eq!(stats.mapped_with_mapq(HIGH_MAPQ), 2);
+
+        let summary = stats.summary();
+        assert!(summary.contains("3 mapped (75.00%)"), "{}", summary);
+        let last = summary.lines().last().unwrap();
+        let fields: BTreeMap<&str, &str> = last.split('\t').skip(1).map(|kv| kv.split_once('=').unwrap()).collect();
+        assert_eq!(fields["reads"], "4");
+        assert_eq!(fields["mapped"], "3");
+        assert_eq!(fields["unmapped"], "1");
+        assert_eq!(fields["mapq30"], "2");
+        assert_eq!(fields["elapsed_secs"], "0.500");
+        assert!(AlignStats::new().summary().contains("0 mapped (0.00%)"));
+    }
+
+    #[test]
+    fn stats_json_roundtrip() {
+        let mut stats = AlignStats::new();
+        stats.add(&mapped("chr1", 60, 0));
+        stats.add(&mapped("chr\"2", 17, 0));
+        stats.add(&SamRecord::unmapped("u", "ACGT", "IIII"));
+        stats.elapsed_secs = 1.25;
+
+        let json = stats.to_json().unwrap();
+        assert!(json.contains("\"mapq_histogram\""), "{}", json);
+        let parsed: AlignStats = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, stats);
+
+        let path = std::env::temp_dir().join(format!("bwa_rust_stats_{}.json", std::process::id()));
+        stats.write_json(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(serde_json::from_str::<AlignStats>(&text).unwrap(), stats);
+    }
+
+    #[test]
+    fn stats_summarize_ref_span_and_clipping() {
+        let with_cigar = |cigar: &str, flag: u16| SamRecord {
+            cigar: cigar.to_string(),
+            ..mapped("chr1", 60, flag)
+        };
+        let mut stats = AlignStats::new();
+        for cigar in ["100M", "5S95M", "90M10S", "20S60M20S", "3H50M2D47M", "10S40M1000N50M"] {
+            stats.add(&with_cigar(cigar, 0));
+        }
+        // secondary 与未比对记录不计入
+        stats.add(&with_cigar("50S50M", flags::SECONDARY));
+        stats.add(&SamRecord::unmapped("u", "ACGT", "IIII"));
+
+        assert_eq!(
+            stats.clip_histogram,
+            BTreeMap::from([(0, 1), (3, 1), (5, 1), (10, 2), (40, 1)])
+        );
+        assert_eq!(
+            stats.ref_span_histogram,
+            BTreeMap::from([(60, 1), (90, 1), (95, 1), (99, 1), (100, 1), (1090, 1)])
+        );
+        assert_eq!(stats.clip_percentile(0.0), Some(0));
+        assert_eq!(stats.clip_percentile(0.5), Some(5));
+        assert_eq!(stats.clip_percentile(0.9), Some(40));
+        assert_eq!(stats.clip_percentile(1.0), Some(40));
+        assert_eq!(stats.ref_span_percentile(0.5), Some(95));
+        assert_eq!(AlignStats::new().clip_percentile(0.5), None);
+    }
+}
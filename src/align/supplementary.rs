@@ -101,6 +101,48 @@ pub fn generate_sa_tag(
     entries.concat()
 }
 
+/// Fraction of the shorter reference span shared by two alignments (`0.0` on different contigs).
+pub fn ref_overlap_fraction(a: &AlignCandidate, b: &AlignCandidate) -> f64 {
+    if a.contig_idx != b.contig_idx {
+        return 0.0;
+    }
+    let overlap = a.ref_end().min(b.ref_end()).saturating_sub(a.pos1.max(b.pos1));
+    let shorter = (a.ref_end() - a.pos1).min(b.ref_end() - b.pos1);
+    if shorter == 0 {
+        return 0.0;
+    }
+    f64::from(overlap) / f64::from(shorter)
+}
+
+/// Drop alternatives that cover the same read bases as the primary (`candidates[0]`)
+/// and share more than half of their reference span with it: these are
+/// re-alignments of the primary placement, not alternative mappings.
+pub fn remove_redundant_secondaries(candidates: &mut Vec<AlignCandidate>) {
+    let Some(primary) = candidates.first().cloned() else {
+        return;
+    };
+    let mut idx = 0;
+    candidates.retain(|cand| {
+        idx += 1;
+        idx == 1 || are_non_overlapping(&primary, cand) || ref_overlap_fraction(&primary, cand) <= 0.5
+    });
+}
+
+/// Generate XA:Z tag content listing the secondary alignments.
+///
+/// Each entry is "rname,{+|-}pos,CIGAR,NM;" as in BWA, the sign giving the strand.
+pub fn generate_xa_tag(candidates: &[AlignCandidate], classification: &[(usize, AlignmentType)]) -> String {
+    classification
+        .iter()
+        .filter(|&&(idx, t)| t == AlignmentType::Secondary && idx < candidates.len())
+        .map(|&(idx, _)| {
+            let cand = &candidates[idx];
+            let strand = if cand.is_rev { '-' } else { '+' };
+            format!("{},{}{},{},{};", cand.rname, strand, cand.pos1, cand.cigar, cand.nm)
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -126,6 +168,8 @@ mod tests {
             query_seq: Vec::new(),
             query_start,
             query_end,
+            seed_cov: query_end - query_start,
+            sub_n: 0,
         }
     }
 
@@ -268,4 +312,36 @@ mod tests {
         let sa = generate_sa_tag(0, &candidates, &classification);
         assert!(sa.is_empty());
     }
+
+    #[test]
+    fn xa_tag_lists_secondaries_with_signed_positions() {
+        let mut sec = make_candidate(40, 2, 20, true, "chr2", 500);
+        sec.nm = 2;
+        let candidates = vec![
+            make_candidate(50, 0, 20, false, "chr1", 100),
+            sec,
+            make_candidate(30, 25, 45, false, "chr1", 300),
+        ];
+        let classification = classify_alignments(&candidates);
+        assert_eq!(generate_xa_tag(&candidates, &classification), "chr2,-500,20M,2;");
+        assert_eq!(generate_xa_tag(&candidates[..1], &classification), "");
+    }
+
+    #[test]
+    fn redundant_secondaries_overlapping_primary_on_reference_are_removed() {
+        let primary = make_candidate(50, 0, 20, false, "chr1", 100);
+        // 参考重叠 15/20
+        let shifted = make_candidate(45, 0, 20, false, "chr1", 105);
+        // 参考重叠 5/20
+        let nearby = make_candidate(44, 0, 20, false, "chr1", 115);
+        let mut elsewhere = make_candidate(43, 0, 20, false, "chr2", 100);
+        elsewhere.contig_idx = 1;
+        // 查询不重叠：嵌合片段，保留
+        let chimeric = make_candidate(30, 25, 45, false, "chr1", 102);
+        let mut candidates = vec![primary, shifted, nearby, elsewhere, chimeric];
+        remove_redundant_secondaries(&mut candidates);
+        let kept: Vec<u32> = candidates.iter().map(|c| c.pos1).collect();
+        assert_eq!(kept, vec![100, 115, 100, 102]);
+        assert_eq!(ref_overlap_fraction(&candidates[0], &candidates[2]), 0.0);
+    }
 }
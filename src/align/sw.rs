@@ -1,5 +1,9 @@
 use std::fmt::Write as _;
 
+use crate::align::candidate::cigar_ops_ref_length;
+use crate::align::AlignOpt;
+use crate::util::dna;
+
 const NEG_INF: i32 = i32::MIN / 4;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -39,33 +43,46 @@ fn penalize(score: i32, penalty: i32) -> i32 {
     }
 }
 
+/// 判断一个 `M` 位置是否计入 NM。
+///
+/// 参考序列上的 `N`（掩码区或组装缺口）不代表真实碱基，与任何 read 碱基比对都不视为错配。
 #[inline]
-pub fn nm_from_ops(ops: &[char], query: &[u8], reference: &[u8]) -> u32 {
-    let mut qi = 0usize;
-    let mut rj = 0usize;
-    let mut nm = 0u32;
+fn is_nm_mismatch(query_base: u8, ref_base: u8) -> bool {
+    query_base != ref_base && ref_base != b'N'
+}
 
-    for &op in ops {
+/// 由 CIGAR 字符串计算 NM（编辑距离）：比对区内的错配 + 插入碱基 + 缺失碱基。
+///
+/// 所有比对路径（SW 各变体与链延伸）都经此计算 NM。
+/// CIGAR 的第一个操作从 `query[q_start]`、`reference[r_start]` 开始；`S` 只推进 query、
+/// `H` 与 `P` 不消耗序列，均不计入；`N`（跳过参考）推进参考但不计入。
+/// 相同碱基（包括 read 与参考同为 `N`）不计为错配。
+pub fn edit_distance(query: &[u8], reference: &[u8], cigar: &str, q_start: usize, r_start: usize) -> u32 {
+    let mut qi = q_start;
+    let mut rj = r_start;
+    let mut nm = 0u32;
+    for (op, len) in parse_cigar(cigar) {
         match op {
-            'M' => {
-                if query[qi] != reference[rj] {
-                    nm += 1;
-                }
-                qi += 1;
-                rj += 1;
+            'M' | '=' | 'X' => {
+                let q = query.get(qi..).unwrap_or_default();
+                let r = reference.get(rj..).unwrap_or_default();
+                nm += q.iter().zip(r).take(len).filter(|&(&q, &r)| is_nm_mismatch(q, r)).count() as u32;
+                qi += len;
+                rj += len;
             }
             'I' => {
-                nm += 1;
-                qi += 1;
+                nm += len as u32;
+                qi += len;
             }
             'D' => {
-                nm += 1;
-                rj += 1;
+                nm += len as u32;
+                rj += len;
             }
+            'S' => qi += len,
+            'N' => rj += len,
             _ => {}
         }
     }
-
     nm
 }
 
@@ -82,6 +99,18 @@ pub struct ExtendResult {
     pub ops: Vec<char>,
 }
 
+/// [`banded_sw_with_buf`] 的比对模式。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SwMode {
+    /// 局部比对（Smith-Waterman，得分下限为 0，两端均可截断）
+    #[default]
+    Local,
+    /// 全局比对（Needleman-Wunsch），覆盖完整 query 与 reference，见 [`global_align`]
+    Global,
+    /// query 全局、reference 两端免费：read 完整放置到更长的参考窗口中，见 [`extend_align`]
+    QueryGlobal,
+}
+
 /// Smith-Waterman 评分参数。
 #[derive(Clone, Copy, Debug)]
 pub struct SwParams {
@@ -90,6 +119,86 @@ pub struct SwParams {
     pub gap_open: i32,
     pub gap_extend: i32,
     pub band_width: usize,
+    /// 带中心相对主对角线的偏移：第 `i` 行的带为 `i + diagonal_offset ± band_width`。
+    /// 当 read 起点相对参考窗口起点已知偏移时（由种子 `qb`/`rb` 推出），
+    /// 使带对准种子所在的对角线，见 [`SwParams::anchored`]。
+    pub diagonal_offset: isize,
+    /// 可选的替换打分矩阵 `matrix[q][r]`，下标为 [`dna::to_alphabet`] 编码（`0:$ 1:A 2:C 3:G 4:T 5:N`）。
+    /// 设置后替代 `match_score` / `mismatch_penalty`，可用于区分转换/颠换或令 `N` 不计分，
+    /// 见 [`SwParams::with_matrix`]。
+    pub score_matrix: Option<[[i32; dna::SIGMA]; dna::SIGMA]>,
+    /// 比对模式，默认 [`SwMode::Local`]
+    pub mode: SwMode,
+    /// 启用 IUPAC 兼并碱基匹配：未设置打分矩阵时，参考 `R` 与 query `A`/`G` 等
+    /// 按 [`dna::iupac_matches`] 计为匹配（满分）。默认关闭，保持 ACGTN 逐字节比较。
+    pub iupac: bool,
+    /// 未设置打分矩阵时，任一方为 `N` 的位置的罚分（得分为 `-n_penalty`）。
+    /// 默认取 [`AlignOpt::n_penalty`]；为 0 时 `N` 与任何碱基（包括 `N`）既不算匹配也不算错配，
+    /// 掩码区不会抬高得分。
+    pub n_penalty: i32,
+}
+
+/// 打分与带宽取自 [`AlignOpt::default`]，不在此处重复默认值
+impl Default for SwParams {
+    fn default() -> Self {
+        AlignOpt::default().sw_params()
+    }
+}
+
+impl SwParams {
+    /// 以替换打分矩阵构造参数，间隙罚分与带宽取默认值
+    pub fn with_matrix(matrix: [[i32; dna::SIGMA]; dna::SIGMA]) -> Self {
+        Self {
+            score_matrix: Some(matrix),
+            ..Self::default()
+        }
+    }
+
+    /// query 碱基 `q` 与参考碱基 `r`（ASCII）比对的替换得分
+    #[inline]
+    pub fn subst(&self, q: u8, r: u8) -> i32 {
+        match &self.score_matrix {
+            Some(m) => m[dna::to_alphabet(q) as usize][dna::to_alphabet(r) as usize],
+            None if q == b'N' || r == b'N' => -self.n_penalty,
+            None if q == r || (self.iupac && dna::iupac_matches(q, r)) => self.match_score,
+            None => -self.mismatch_penalty,
+        }
+    }
+
+    /// 长度为 `read_len` 的 read 理论上可得的最高比对得分（全长逐碱基满分匹配）。
+    ///
+    /// 无打分矩阵时为 `read_len * match_score`；有矩阵时按矩阵中的最大单碱基得分计算。
+    /// 阈值过滤、MAPQ 与 strand shortcut 等功能共用此上限。
+    pub fn max_score(&self, read_len: usize) -> i32 {
+        let per_base = match &self.score_matrix {
+            Some(m) => m.iter().flatten().copied().max().unwrap_or(0),
+            None => self.match_score,
+        };
+        read_len as i32 * per_base
+    }
+
+    /// 以种子锚定带的位置：`qb` 为种子在 query 上的起点，`rb` 为种子在参考窗口内的起点
+    /// （相对传入 SW 的 `reference` 切片）。
+    pub fn anchored(self, qb: usize, rb: usize) -> Self {
+        Self {
+            diagonal_offset: rb as isize - qb as isize,
+            ..self
+        }
+    }
+
+    /// 第 `i` 行（1-based）带内的列范围 `[lo, hi]`，已截断到 `[1, n]`；带与矩阵不相交时 `lo > hi`
+    #[inline]
+    pub(crate) fn band_cols(&self, i: usize, n: usize) -> (usize, usize) {
+        let band = self.band_width as isize;
+        let center = i as isize + self.diagonal_offset;
+        let lo = (center - band).max(1);
+        let hi = (center + band).min(n as isize);
+        if lo > hi {
+            (1, 0)
+        } else {
+            (lo as usize, hi as usize)
+        }
+    }
 }
 
 /// Smith-Waterman 对齐结果。
@@ -101,11 +210,46 @@ pub struct SwResult {
     pub ref_start: usize,
     pub ref_end: usize,
     pub cigar: String,
+    /// 比对区内的编辑距离，定义见 [`edit_distance`]。
     pub nm: u32,
 }
 
+impl SwResult {
+    /// 由 CIGAR 与起点重建逐碱基对齐对 `(query 下标, ref 下标)`，便于可视化。
+    ///
+    /// 从 `(query_start, ref_start)` 出发：`M`/`=`/`X` 同时推进两侧，
+    /// `I`/`S` 只推进 query（ref 侧为 `None`），`D`/`N` 只推进 ref（query 侧为 `None`），
+    /// `H`/`P` 不占位。`query` 与 `reference` 为传给比对函数的序列，仅用于校验下标范围。
+    pub fn aligned_pairs(&self, query: &[u8], reference: &[u8]) -> Vec<(Option<usize>, Option<usize>)> {
+        let mut pairs = Vec::new();
+        let (mut qi, mut ri) = (self.query_start, self.ref_start);
+        for (op, len) in parse_cigar(&self.cigar) {
+            for _ in 0..len {
+                match op {
+                    'M' | '=' | 'X' => {
+                        pairs.push((Some(qi), Some(ri)));
+                        qi += 1;
+                        ri += 1;
+                    }
+                    'I' | 'S' => {
+                        pairs.push((Some(qi), None));
+                        qi += 1;
+                    }
+                    'D' | 'N' => {
+                        pairs.push((None, Some(ri)));
+                        ri += 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        debug_assert!(qi <= query.len() && ri <= reference.len(), "CIGAR 超出序列范围");
+        pairs
+    }
+}
+
 /// 带状仿射间隙 Smith-Waterman 局部对齐
-/// 使用可复用的缓冲区以减少内存分配
+/// 使用可复用的缓冲区以减少内存分配；DP 与回溯方向矩阵只保存带内单元，内存为 O(m × band)
 pub fn banded_sw(query: &[u8], reference: &[u8], p: SwParams) -> SwResult {
     banded_sw_with_buf(query, reference, p, &mut SwBuffer::new())
 }
@@ -133,145 +277,17 @@ pub fn global_align_with_buf(query: &[u8], reference: &[u8], p: SwParams, buf: &
         };
     }
 
-    let cols = n + 1;
-    let size = (m + 1) * cols;
-    buf.resize_affine(size);
-    let match_mat = &mut buf.h;
-    let ins_mat = &mut buf.e;
-    let del_mat = &mut buf.f;
-    let match_trace = &mut buf.match_trace;
-    let ins_trace = &mut buf.ins_trace;
-    let del_trace = &mut buf.del_trace;
-
-    let idx = |i: usize, j: usize| i * cols + j;
-
-    match_mat[idx(0, 0)] = 0;
-
-    for i in 1..=m {
-        let cur = idx(i, 0);
-        let prev = idx(i - 1, 0);
-        let open = penalize(match_mat[prev], p.gap_open + p.gap_extend);
-        let extend = penalize(ins_mat[prev], p.gap_extend);
-        if open >= extend {
-            ins_mat[cur] = open;
-            ins_trace[cur] = trace_to_u8(TraceState::Match);
-        } else {
-            ins_mat[cur] = extend;
-            ins_trace[cur] = trace_to_u8(TraceState::Ins);
-        }
-    }
-
-    for j in 1..=n {
-        let cur = idx(0, j);
-        let prev = idx(0, j - 1);
-        let open = penalize(match_mat[prev], p.gap_open + p.gap_extend);
-        let extend = penalize(del_mat[prev], p.gap_extend);
-        if open >= extend {
-            del_mat[cur] = open;
-            del_trace[cur] = trace_to_u8(TraceState::Match);
-        } else {
-            del_mat[cur] = extend;
-            del_trace[cur] = trace_to_u8(TraceState::Del);
-        }
-    }
-
-    for i in 1..=m {
-        for j in 1..=n {
-            let cur = idx(i, j);
-            let diag = idx(i - 1, j - 1);
-            let up = idx(i - 1, j);
-            let left = idx(i, j - 1);
-
-            let subst = if query[i - 1] == reference[j - 1] {
-                p.match_score
-            } else {
-                -p.mismatch_penalty
-            };
-
-            let mut best_prev = match_mat[diag];
-            let mut best_state = TraceState::Match;
-            if ins_mat[diag] > best_prev {
-                best_prev = ins_mat[diag];
-                best_state = TraceState::Ins;
-            }
-            if del_mat[diag] > best_prev {
-                best_prev = del_mat[diag];
-                best_state = TraceState::Del;
-            }
-            if best_prev > NEG_INF / 2 {
-                match_mat[cur] = best_prev + subst;
-                match_trace[cur] = trace_to_u8(best_state);
-            }
-
-            let open_ins = penalize(match_mat[up], p.gap_open + p.gap_extend);
-            let extend_ins = penalize(ins_mat[up], p.gap_extend);
-            if open_ins >= extend_ins {
-                ins_mat[cur] = open_ins;
-                ins_trace[cur] = trace_to_u8(TraceState::Match);
-            } else {
-                ins_mat[cur] = extend_ins;
-                ins_trace[cur] = trace_to_u8(TraceState::Ins);
-            }
-
-            let open_del = penalize(match_mat[left], p.gap_open + p.gap_extend);
-            let extend_del = penalize(del_mat[left], p.gap_extend);
-            if open_del >= extend_del {
-                del_mat[cur] = open_del;
-                del_trace[cur] = trace_to_u8(TraceState::Match);
-            } else {
-                del_mat[cur] = extend_del;
-                del_trace[cur] = trace_to_u8(TraceState::Del);
-            }
-        }
-    }
-
-    let end = idx(m, n);
-    let mut score = match_mat[end];
-    let mut state = TraceState::Match;
-    if ins_mat[end] > score {
-        score = ins_mat[end];
-        state = TraceState::Ins;
-    }
-    if del_mat[end] > score {
-        score = del_mat[end];
-        state = TraceState::Del;
-    }
-
-    let mut ops: Vec<char> = Vec::with_capacity(m.max(n));
-    let mut i = m;
-    let mut j = n;
-    while i > 0 || j > 0 {
-        let cur = idx(i, j);
-        match state {
-            TraceState::Match => {
-                ops.push('M');
-                state = u8_to_trace(match_trace[cur]);
-                i -= 1;
-                j -= 1;
-            }
-            TraceState::Ins => {
-                ops.push('I');
-                state = u8_to_trace(ins_trace[cur]);
-                i -= 1;
-            }
-            TraceState::Del => {
-                ops.push('D');
-                state = u8_to_trace(del_trace[cur]);
-                j -= 1;
-            }
-            TraceState::Start => break,
-        }
-    }
-    ops.reverse();
+    let path = affine_align(query, reference, &p, AffineEnd::Global, buf);
+    let cigar = ops_to_cigar(&path.ops);
 
     SwResult {
-        score,
+        score: path.score,
         query_start: 0,
         query_end: m,
         ref_start: 0,
         ref_end: n,
-        cigar: ops_to_cigar(&ops),
-        nm: nm_from_ops(&ops, query, reference),
+        nm: edit_distance(query, reference, &cigar, 0, 0),
+        cigar,
     }
 }
 
@@ -281,6 +297,13 @@ pub fn semiglobal_align(query: &[u8], reference: &[u8], p: SwParams) -> SwResult
     semiglobal_align_with_buf(query, reference, p, &mut SwBuffer::new())
 }
 
+/// query 全局、reference 两端免费的延伸比对：read 不会被截断，参考窗口两端的多余部分不计罚分。
+///
+/// 等价于以 [`SwMode::QueryGlobal`] 调用 [`banded_sw`]。
+pub fn extend_align(query: &[u8], reference: &[u8], p: SwParams) -> SwResult {
+    semiglobal_align(query, reference, p)
+}
+
 /// 同 [`semiglobal_align`]，但接受外部 [`SwBuffer`] 复用内存。
 pub fn semiglobal_align_with_buf(query: &[u8], reference: &[u8], p: SwParams, buf: &mut SwBuffer) -> SwResult {
     let m = query.len();
@@ -310,143 +333,17 @@ pub fn semiglobal_align_with_buf(query: &[u8], reference: &[u8], p: SwParams, bu
         };
     }
 
-    let cols = n + 1;
-    let size = (m + 1) * cols;
-    buf.resize_affine(size);
-    let match_mat = &mut buf.h;
-    let ins_mat = &mut buf.e;
-    let del_mat = &mut buf.f;
-    let match_trace = &mut buf.match_trace;
-    let ins_trace = &mut buf.ins_trace;
-    let del_trace = &mut buf.del_trace;
-
-    let idx = |i: usize, j: usize| i * cols + j;
-
-    for j in 0..=n {
-        match_mat[idx(0, j)] = 0;
-    }
-
-    for i in 1..=m {
-        let cur = idx(i, 0);
-        let prev = idx(i - 1, 0);
-        let open = penalize(match_mat[prev], p.gap_open + p.gap_extend);
-        let extend = penalize(ins_mat[prev], p.gap_extend);
-        if open >= extend {
-            ins_mat[cur] = open;
-            ins_trace[cur] = trace_to_u8(TraceState::Match);
-        } else {
-            ins_mat[cur] = extend;
-            ins_trace[cur] = trace_to_u8(TraceState::Ins);
-        }
-    }
-
-    for i in 1..=m {
-        for j in 1..=n {
-            let cur = idx(i, j);
-            let diag = idx(i - 1, j - 1);
-            let up = idx(i - 1, j);
-            let left = idx(i, j - 1);
-
-            let subst = if query[i - 1] == reference[j - 1] {
-                p.match_score
-            } else {
-                -p.mismatch_penalty
-            };
-
-            let mut best_prev = match_mat[diag];
-            let mut best_state = TraceState::Match;
-            if ins_mat[diag] > best_prev {
-                best_prev = ins_mat[diag];
-                best_state = TraceState::Ins;
-            }
-            if del_mat[diag] > best_prev {
-                best_prev = del_mat[diag];
-                best_state = TraceState::Del;
-            }
-            if best_prev > NEG_INF / 2 {
-                match_mat[cur] = best_prev + subst;
-                match_trace[cur] = trace_to_u8(best_state);
-            }
-
-            let open_ins = penalize(match_mat[up], p.gap_open + p.gap_extend);
-            let extend_ins = penalize(ins_mat[up], p.gap_extend);
-            if open_ins >= extend_ins {
-                ins_mat[cur] = open_ins;
-                ins_trace[cur] = trace_to_u8(TraceState::Match);
-            } else {
-                ins_mat[cur] = extend_ins;
-                ins_trace[cur] = trace_to_u8(TraceState::Ins);
-            }
-
-            let open_del = penalize(match_mat[left], p.gap_open + p.gap_extend);
-            let extend_del = penalize(del_mat[left], p.gap_extend);
-            if open_del >= extend_del {
-                del_mat[cur] = open_del;
-                del_trace[cur] = trace_to_u8(TraceState::Match);
-            } else {
-                del_mat[cur] = extend_del;
-                del_trace[cur] = trace_to_u8(TraceState::Del);
-            }
-        }
-    }
-
-    let mut best_j = 0usize;
-    let mut score = NEG_INF;
-    let mut state = TraceState::Start;
-    for j in 0..=n {
-        let cur = idx(m, j);
-        if match_mat[cur] > score {
-            score = match_mat[cur];
-            state = TraceState::Match;
-            best_j = j;
-        }
-        if ins_mat[cur] > score {
-            score = ins_mat[cur];
-            state = TraceState::Ins;
-            best_j = j;
-        }
-        if del_mat[cur] > score {
-            score = del_mat[cur];
-            state = TraceState::Del;
-            best_j = j;
-        }
-    }
-
-    let mut ops: Vec<char> = Vec::with_capacity(m.max(best_j));
-    let mut i = m;
-    let mut j = best_j;
-    while i > 0 {
-        let cur = idx(i, j);
-        match state {
-            TraceState::Match => {
-                ops.push('M');
-                state = u8_to_trace(match_trace[cur]);
-                i -= 1;
-                j -= 1;
-            }
-            TraceState::Ins => {
-                ops.push('I');
-                state = u8_to_trace(ins_trace[cur]);
-                i -= 1;
-            }
-            TraceState::Del => {
-                ops.push('D');
-                state = u8_to_trace(del_trace[cur]);
-                j -= 1;
-            }
-            TraceState::Start => break,
-        }
-    }
-    ops.reverse();
+    let path = affine_align(query, reference, &p, AffineEnd::SemiGlobal, buf);
+    let cigar = ops_to_cigar(&path.ops);
 
     SwResult {
-        score,
+        score: path.score,
         query_start: 0,
         query_end: m,
-        ref_start: j,
-        ref_end: best_j,
-        cigar: ops_to_cigar(&ops),
-        nm: nm_from_ops(&ops, query, &reference[j..best_j]),
+        ref_start: path.start.1,
+        ref_end: path.end.1,
+        nm: edit_distance(query, reference, &cigar, 0, path.start.1),
+        cigar,
     }
 }
 
@@ -456,8 +353,10 @@ pub struct SwBuffer {
     e: Vec<i32>,
     f: Vec<i32>,
     match_trace: Vec<u8>,
-    ins_trace: Vec<u8>,
-    del_trace: Vec<u8>,
+    /// [`affine_align`] 的检查点行与两条工作行
+    cells: Vec<AffineCell>,
+    /// [`affine_align`] 当前重算块的打包回溯方向
+    trace: Vec<u8>,
 }
 
 impl Default for SwBuffer {
@@ -473,8 +372,8 @@ impl SwBuffer {
             e: Vec::new(),
             f: Vec::new(),
             match_trace: Vec::new(),
-            ins_trace: Vec::new(),
-            del_trace: Vec::new(),
+            cells: Vec::new(),
+            trace: Vec::new(),
         }
     }
 
@@ -487,424 +386,522 @@ impl SwBuffer {
         self.f.resize(size, NEG_INF);
     }
 
-    fn resize_affine(&mut self, size: usize) {
-        self.h.clear();
-        self.h.resize(size, NEG_INF);
-        self.e.clear();
-        self.e.resize(size, NEG_INF);
-        self.f.clear();
-        self.f.resize(size, NEG_INF);
+    /// 带状局部比对：H 置 0、E/F 置 -inf、方向矩阵（复用 `match_trace`）置为起点
+    fn resize_banded(&mut self, size: usize) {
+        self.resize(size);
         self.match_trace.clear();
-        self.match_trace.resize(size, 0);
-        self.ins_trace.clear();
-        self.ins_trace.resize(size, 0);
-        self.del_trace.clear();
-        self.del_trace.resize(size, 0);
+        self.match_trace.resize(size, trace_to_u8(TraceState::Start));
     }
 }
 
-/// 同 [`banded_sw`]，但接受外部 [`SwBuffer`] 以复用 DP 矩阵内存，适用于热路径。
-pub fn banded_sw_with_buf(query: &[u8], reference: &[u8], p: SwParams, buf: &mut SwBuffer) -> SwResult {
-    let m = query.len();
-    let n = reference.len();
-
-    if m == 0 || n == 0 {
-        return SwResult {
-            score: 0,
-            query_start: 0,
-            query_end: 0,
-            ref_start: 0,
-            ref_end: 0,
-            cigar: String::new(),
-            nm: 0,
-        };
-    }
-
-    let rows = m + 1;
-    let cols = n + 1;
-    let size = rows * cols;
-
-    buf.resize(size);
-    let h = &mut buf.h;
-    let e = &mut buf.e;
-    let f = &mut buf.f;
-
-    let band = p.band_width as isize;
-
-    let mut best_score = 0i32;
-    let mut best_i = 0usize;
-    let mut best_j = 0usize;
+/// 仿射间隙 DP 单元：分别以匹配、插入（消耗 query）、缺失（消耗参考）结尾的最优得分
+#[derive(Clone, Copy)]
+struct AffineCell {
+    m: i32,
+    ins: i32,
+    del: i32,
+}
 
-    for i in 1..=m {
-        let i_isize = i as isize;
-        let mut j_start = 1usize;
-        let mut j_end = n;
-        if band >= 0 {
-            let js = i_isize - band;
-            let je = i_isize + band;
-            if js > 1 {
-                j_start = js as usize;
-            }
-            if je < n as isize {
-                j_end = je as usize;
-            }
+impl AffineCell {
+    const NEG: Self = Self {
+        m: NEG_INF,
+        ins: NEG_INF,
+        del: NEG_INF,
+    };
+
+    /// 三种状态中的最优得分及其状态；并列时依次优先匹配、插入、缺失
+    fn best(self) -> (i32, TraceState) {
+        let mut best = (self.m, TraceState::Match);
+        if self.ins > best.0 {
+            best = (self.ins, TraceState::Ins);
         }
-        if j_start > j_end {
-            continue;
+        if self.del > best.0 {
+            best = (self.del, TraceState::Del);
         }
+        best
+    }
+}
 
-        for j in j_start..=j_end {
-            let idx = i * cols + j;
-            let up_idx = (i - 1) * cols + j;
-            let left_idx = i * cols + (j - 1);
-            let diag_idx = (i - 1) * cols + (j - 1);
-
-            let e_open = h[up_idx] - p.gap_open - p.gap_extend;
-            let e_ext = e[up_idx] - p.gap_extend;
-            e[idx] = e_open.max(e_ext);
+/// 三种状态的回溯方向打包进一个字节：匹配占低 2 位，插入、缺失依次左移 2 位
+fn pack_trace(m: TraceState, ins: TraceState, del: TraceState) -> u8 {
+    trace_to_u8(m) | trace_to_u8(ins) << 2 | trace_to_u8(del) << 4
+}
 
-            let f_open = h[left_idx] - p.gap_open - p.gap_extend;
-            let f_ext = f[left_idx] - p.gap_extend;
-            f[idx] = f_open.max(f_ext);
+/// 取打包方向中状态 `state` 的上一状态
+fn unpack_trace(code: u8, state: TraceState) -> TraceState {
+    let shift = match state {
+        TraceState::Ins => 2,
+        TraceState::Del => 4,
+        _ => 0,
+    };
+    u8_to_trace(code >> shift & 3)
+}
 
-            let subst = if query[i - 1] == reference[j - 1] {
-                p.match_score
-            } else {
-                -p.mismatch_penalty
-            };
+/// [`affine_align`] 的起止规则
+#[derive(Clone, Copy)]
+enum AffineEnd {
+    /// 起点 `(0, 0)`、终点 `(m, n)`，见 [`global_align`]
+    Global,
+    /// query 全长、参考两端免费：第 0 行全为 0，终点取最后一行的最优列，见 [`semiglobal_align`]
+    SemiGlobal,
+    /// 从 `(0, 0)` 沿带延伸，终点为得分最高的单元；行最优低于全局最优 `zdrop` 时停止，见 [`extend_right`]
+    Extend { zdrop: i32 },
+}
 
-            let mut val = h[diag_idx] + subst;
-            if e[idx] > val {
-                val = e[idx];
-            }
-            if f[idx] > val {
-                val = f[idx];
-            }
-            if val < 0 {
-                val = 0;
-            }
-            h[idx] = val;
+/// [`affine_align`] 的回溯结果，坐标为 `(query 下标, 参考下标)`
+struct AffinePath {
+    score: i32,
+    start: (usize, usize),
+    end: (usize, usize),
+    ops: Vec<char>,
+}
 
-            if val > best_score {
-                best_score = val;
-                best_i = i;
-                best_j = j;
-            }
-        }
-    }
+/// 检查点间隔：约 `√m` 行，使检查点与单块方向矩阵的行数相当
+fn checkpoint_stride(m: usize) -> usize {
+    ((m as f64).sqrt().ceil() as usize).max(1)
+}
 
-    if best_score <= 0 {
-        return SwResult {
-            score: 0,
-            query_start: 0,
-            query_end: 0,
-            ref_start: 0,
-            ref_end: 0,
-            cigar: String::new(),
-            nm: 0,
+/// 第 0 行：`free_start` 时参考起点免费（全为 0），否则只有自 `(0, 0)` 起的缺失
+fn affine_first_row(p: &SwParams, free_start: bool, cur: &mut [AffineCell], trace: &mut [u8]) {
+    cur.fill(AffineCell::NEG);
+    trace.fill(0);
+    if free_start {
+        cur.iter_mut().for_each(|c| c.m = 0);
+        return;
+    }
+    cur[0].m = 0;
+    for j in 1..cur.len() {
+        let open = penalize(cur[j - 1].m, p.gap_open + p.gap_extend);
+        let extend = penalize(cur[j - 1].del, p.gap_extend);
+        let (del, from) = if open >= extend {
+            (open, TraceState::Match)
+        } else {
+            (extend, TraceState::Del)
         };
+        cur[j].del = del;
+        trace[j] = pack_trace(TraceState::Start, TraceState::Start, from);
     }
+}
 
-    // backtrack from best cell
-    let mut ops: Vec<char> = Vec::new();
-    let mut i = best_i;
-    let mut j = best_j;
-
-    while i > 0 && j > 0 {
-        let idx = i * cols + j;
-        let h_here = h[idx];
-        if h_here == 0 {
-            break;
+/// 由上一行 `prev` 计算 query 碱基 `q` 所在的一行：第 0 列只有插入，`[j_lo, j_hi]` 列按仿射递推，
+/// 其余单元为 -inf
+fn affine_row(
+    q: u8,
+    reference: &[u8],
+    p: &SwParams,
+    (j_lo, j_hi): (usize, usize),
+    prev: &[AffineCell],
+    cur: &mut [AffineCell],
+    trace: &mut [u8],
+) {
+    cur.fill(AffineCell::NEG);
+    trace.fill(0);
+
+    let gap_ins = |up: AffineCell| {
+        let open = penalize(up.m, p.gap_open + p.gap_extend);
+        let extend = penalize(up.ins, p.gap_extend);
+        if open >= extend {
+            (open, TraceState::Match)
+        } else {
+            (extend, TraceState::Ins)
         }
-
-        let diag_idx = (i - 1) * cols + (j - 1);
-
-        let subst = if query[i - 1] == reference[j - 1] {
-            p.match_score
+    };
+    let (ins, from) = gap_ins(prev[0]);
+    cur[0].ins = ins;
+    trace[0] = pack_trace(TraceState::Start, from, TraceState::Start);
+
+    for j in j_lo..=j_hi {
+        let (best_prev, m_from) = prev[j - 1].best();
+        let (m, m_from) = if best_prev > NEG_INF / 2 {
+            (best_prev + p.subst(q, reference[j - 1]), m_from)
         } else {
-            -p.mismatch_penalty
+            (NEG_INF, TraceState::Start)
         };
-
-        let diag_val = h[diag_idx] + subst;
-        let e_val = e[idx];
-        let f_val = f[idx];
-
-        if h_here == diag_val {
-            ops.push('M');
-            i -= 1;
-            j -= 1;
-        } else if h_here == e_val {
-            ops.push('I');
-            i -= 1;
-        } else if h_here == f_val {
-            ops.push('D');
-            j -= 1;
+        let (ins, ins_from) = gap_ins(prev[j]);
+        let open_del = penalize(cur[j - 1].m, p.gap_open + p.gap_extend);
+        let extend_del = penalize(cur[j - 1].del, p.gap_extend);
+        let (del, del_from) = if open_del >= extend_del {
+            (open_del, TraceState::Match)
         } else {
-            break;
-        }
+            (extend_del, TraceState::Del)
+        };
+        cur[j] = AffineCell { m, ins, del };
+        trace[j] = pack_trace(m_from, ins_from, del_from);
     }
+}
 
-    let query_start = i;
-    let ref_start = j;
-    let query_end = best_i;
-    let ref_end = best_j;
+/// 全局 / 半全局 / 延伸三种仿射间隙比对共用的 DP（[`global_align`]、[`semiglobal_align`]、[`extend_right`]）。
+///
+/// 不保存完整矩阵：前向只保留每 [`checkpoint_stride`] 行一份 DP 行作为检查点，回溯时从检查点
+/// 逐块重算该块的方向矩阵。内存为 O(√m × n)，重算使总计算量约为两遍 DP；
+/// 递推与并列规则与逐单元保存方向矩阵时相同，结果一致。
+fn affine_align(query: &[u8], reference: &[u8], p: &SwParams, end: AffineEnd, buf: &mut SwBuffer) -> AffinePath {
+    affine_align_with_stride(query, reference, p, end, checkpoint_stride(query.len()), buf)
+}
 
-    ops.reverse();
+fn affine_align_with_stride(
+    query: &[u8],
+    reference: &[u8],
+    p: &SwParams,
+    end: AffineEnd,
+    stride: usize,
+    buf: &mut SwBuffer,
+) -> AffinePath {
+    let m = query.len();
+    let n = reference.len();
+    let cols = n + 1;
+    let stride = stride.clamp(1, m.max(1));
+    let n_ckpt = m / stride + 1;
+    let row_cols = |i: usize| match end {
+        AffineEnd::Extend { .. } => p.band_cols(i, n),
+        _ => (1, n),
+    };
+
+    buf.cells.clear();
+    buf.cells.resize((n_ckpt + 2) * cols, AffineCell::NEG);
+    buf.trace.clear();
+    buf.trace.resize((stride + 1) * cols, 0);
+    let (ckpts, work) = buf.cells.split_at_mut(n_ckpt * cols);
+    let (mut prev, mut cur) = work.split_at_mut(cols);
+    let trace = &mut buf.trace;
+
+    // 前向：逐行递推，只保留检查点行
+    affine_first_row(p, matches!(end, AffineEnd::SemiGlobal), prev, &mut trace[..cols]);
+    ckpts[..cols].copy_from_slice(prev);
+    let mut last_row = 0;
+    let (mut best_score, mut best_cell, mut best_state) = (0, (0, 0), TraceState::Start);
+    let mut max_score = 0;
+    for i in 1..=m {
+        let (j_lo, j_hi) = row_cols(i);
+        affine_row(query[i - 1], reference, p, (j_lo, j_hi), prev, cur, &mut trace[..cols]);
+        if i % stride == 0 {
+            ckpts[i / stride * cols..][..cols].copy_from_slice(cur);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+        last_row = i;
+
+        if let AffineEnd::Extend { zdrop } = end {
+            let mut row_best = NEG_INF;
+            for (j, cell) in prev.iter().enumerate().take(j_hi + 1).skip(j_lo) {
+                let (cell_best, cell_state) = cell.best();
+                if cell_best > best_score {
+                    (best_score, best_cell, best_state) = (cell_best, (i, j), cell_state);
+                }
+                max_score = max_score.max(cell_best);
+                row_best = row_best.max(cell_best);
+            }
+            if zdrop > 0 && max_score - row_best >= zdrop {
+                break;
+            }
+        }
+    }
 
-    let mut nm = 0u32;
-    let mut qi = query_start;
-    let mut rj = ref_start;
-    for &op in &ops {
-        match op {
-            'M' => {
-                if query[qi] != reference[rj] {
-                    nm += 1;
+    // 终点：`prev` 为最后计算的一行
+    let (score, (mut i, mut j), mut state) = match end {
+        AffineEnd::Global => {
+            let (score, state) = prev[n].best();
+            (score, (m, n), state)
+        }
+        AffineEnd::SemiGlobal => {
+            let (mut score, mut best_j, mut state) = (NEG_INF, 0, TraceState::Start);
+            for (j, cell) in prev.iter().enumerate() {
+                let (cell_best, cell_state) = cell.best();
+                if cell_best > score {
+                    (score, best_j, state) = (cell_best, j, cell_state);
                 }
-                qi += 1;
-                rj += 1;
             }
-            'I' => {
-                nm += 1;
-                qi += 1;
+            (score, (m, best_j), state)
+        }
+        AffineEnd::Extend { .. } => {
+            if best_score <= 0 {
+                return AffinePath {
+                    score: 0,
+                    start: (0, 0),
+                    end: (0, 0),
+                    ops: Vec::new(),
+                };
             }
-            'D' => {
-                nm += 1;
-                rj += 1;
+            (best_score, best_cell, best_state)
+        }
+    };
+    let end_cell = (i, j);
+
+    // 回溯：第 i 行（i >= 1）属于块 (i - 1) / stride，块内各行由该块起始检查点重算；第 0 行属于块 0
+    let mut ops: Vec<char> = Vec::with_capacity(i.max(j));
+    let mut block = usize::MAX;
+    while i > 0 || (j > 0 && !matches!(end, AffineEnd::SemiGlobal)) {
+        let b = i.saturating_sub(1) / stride;
+        if b != block {
+            block = b;
+            let first = b * stride;
+            prev.copy_from_slice(&ckpts[b * cols..][..cols]);
+            if b == 0 {
+                affine_first_row(p, matches!(end, AffineEnd::SemiGlobal), cur, &mut trace[..cols]);
+            }
+            for r in first + 1..=(first + stride).min(last_row) {
+                let rel = r - first;
+                affine_row(
+                    query[r - 1],
+                    reference,
+                    p,
+                    row_cols(r),
+                    prev,
+                    cur,
+                    &mut trace[rel * cols..][..cols],
+                );
+                std::mem::swap(&mut prev, &mut cur);
             }
-            _ => {}
         }
+        let code = trace[(i - block * stride) * cols + j];
+        let next = unpack_trace(code, state);
+        match state {
+            TraceState::Match => {
+                ops.push('M');
+                i -= 1;
+                j -= 1;
+            }
+            TraceState::Ins => {
+                ops.push('I');
+                i -= 1;
+            }
+            TraceState::Del => {
+                ops.push('D');
+                j -= 1;
+            }
+            TraceState::Start => break,
+        }
+        state = next;
     }
+    ops.reverse();
 
-    let cigar = ops_to_cigar(&ops);
-
-    SwResult {
-        score: best_score,
-        query_start,
-        query_end,
-        ref_start,
-        ref_end,
-        cigar,
-        nm,
+    AffinePath {
+        score,
+        start: (i, j),
+        end: end_cell,
+        ops,
     }
 }
 
-/// 将 CIGAR ops 列表压缩为标准 CIGAR 字符串（游程编码），例如 `['M','M','I','M']` → `"2M1I1M"`。
-pub fn ops_to_cigar(ops: &[char]) -> String {
-    let mut cigar = String::new();
-    if ops.is_empty() {
-        return cigar;
+/// 同 [`banded_sw`]，但接受外部 [`SwBuffer`] 以复用 DP 矩阵内存，适用于热路径。
+///
+/// `p.mode` 为 [`SwMode::Global`] / [`SwMode::QueryGlobal`] 时分别转到 [`global_align_with_buf`] /
+/// [`semiglobal_align_with_buf`]，二者不受带宽限制，以检查点方式只保存 O(√m × n) 的 DP 状态。
+///
+/// 启用 `simd` feature 时，带覆盖整个参考窗口的局部比对改由条带向量化的
+/// `banded_sw_simd` 计算，结果相同；窄带时标量版本更快。
+pub fn banded_sw_with_buf(query: &[u8], reference: &[u8], p: SwParams, buf: &mut SwBuffer) -> SwResult {
+    match p.mode {
+        SwMode::Local => {}
+        SwMode::Global => return global_align_with_buf(query, reference, p, buf),
+        SwMode::QueryGlobal => return semiglobal_align_with_buf(query, reference, p, buf),
     }
-    let mut cur = ops[0];
-    let mut len = 1usize;
-    for &op in &ops[1..] {
-        if op == cur {
-            len += 1;
-        } else {
-            let _ = write!(&mut cigar, "{}{}", len, cur);
-            cur = op;
-            len = 1;
-        }
+    #[cfg(feature = "simd")]
+    if 2 * p.band_width + 1 >= reference.len() {
+        return super::sw_simd::banded_sw_simd(query, reference, p);
     }
-    let _ = write!(&mut cigar, "{}{}", len, cur);
-    cigar
+    banded_sw_scalar(query, reference, p, buf)
 }
 
-/// 从 (0,0) 向右做半全局扩展对齐（类似 BWA ksw_extend）。
-/// query/reference 均从左往右，延伸直到序列末尾或得分跌落超过 zdrop。
-/// 返回实际延伸到的位置和 CIGAR。
-pub fn extend_right(query: &[u8], reference: &[u8], p: SwParams, zdrop: i32) -> ExtendResult {
+/// [`banded_sw_with_buf`] 的标量局部比对
+pub(crate) fn banded_sw_scalar(query: &[u8], reference: &[u8], p: SwParams, buf: &mut SwBuffer) -> SwResult {
     let m = query.len();
     let n = reference.len();
+
     if m == 0 || n == 0 {
-        return ExtendResult {
+        return SwResult {
             score: 0,
-            query_len: 0,
-            ref_len: 0,
-            ops: vec![],
+            query_start: 0,
+            query_end: 0,
+            ref_start: 0,
+            ref_end: 0,
+            cigar: String::new(),
+            nm: 0,
         };
     }
 
+    // 带状存储：第 i 行只保存 `[row_start(i), row_start(i) + width)` 列，
+    // 列 j 在行内的下标为 `j - row_start(i)`（`row_start(i) = i + diagonal_offset - band_width`）。
+    // 带宽覆盖整个参考窗口时退化为矩形存储（`row_start = 0`），避免比 (m+1)*(n+1) 更大。
+    // 带外单元按 H = 0、E = F = -inf 处理，与矩形实现中未写入的单元一致。
     let cols = n + 1;
-    let size = (m + 1) * cols;
-    let mut match_mat = vec![NEG_INF; size];
-    let mut ins_mat = vec![NEG_INF; size];
-    let mut del_mat = vec![NEG_INF; size];
-    let mut match_trace = vec![0u8; size];
-    let mut ins_trace = vec![0u8; size];
-    let mut del_trace = vec![0u8; size];
-
-    let idx = |i: usize, j: usize| i * cols + j;
-    match_mat[idx(0, 0)] = 0;
-
-    for i in 1..=m {
-        let cur = idx(i, 0);
-        let prev = idx(i - 1, 0);
-        let open = penalize(match_mat[prev], p.gap_open + p.gap_extend);
-        let extend = penalize(ins_mat[prev], p.gap_extend);
-        if open >= extend {
-            ins_mat[cur] = open;
-            ins_trace[cur] = trace_to_u8(TraceState::Match);
+    let full = 2 * p.band_width + 1 >= cols;
+    let width = if full { cols } else { 2 * p.band_width + 1 };
+    let row_start = |i: usize| {
+        if full {
+            0
         } else {
-            ins_mat[cur] = extend;
-            ins_trace[cur] = trace_to_u8(TraceState::Ins);
+            i as isize + p.diagonal_offset - p.band_width as isize
         }
-    }
+    };
+    let at = |i: usize, j: usize| {
+        let k = j as isize - row_start(i);
+        (0..width as isize).contains(&k).then(|| i * width + k as usize)
+    };
 
-    for j in 1..=n {
-        let cur = idx(0, j);
-        let prev = idx(0, j - 1);
-        let open = penalize(match_mat[prev], p.gap_open + p.gap_extend);
-        let extend = penalize(del_mat[prev], p.gap_extend);
-        if open >= extend {
-            del_mat[cur] = open;
-            del_trace[cur] = trace_to_u8(TraceState::Match);
-        } else {
-            del_mat[cur] = extend;
-            del_trace[cur] = trace_to_u8(TraceState::Del);
-        }
-    }
+    buf.resize_banded((m + 1) * width);
+    let h = &mut buf.h;
+    let e = &mut buf.e;
+    let f = &mut buf.f;
+    let trace = &mut buf.match_trace;
 
     let mut best_score = 0i32;
     let mut best_i = 0usize;
     let mut best_j = 0usize;
-    let mut max_score = 0i32;
-    let mut best_state = TraceState::Start;
 
     for i in 1..=m {
-        let i_isize = i as isize;
-        let band = p.band_width as isize;
-        let j_lo = if band >= 0 { (i_isize - band).max(1) as usize } else { 1 };
-        let j_hi = if band >= 0 {
-            (i_isize + band).min(n as isize) as usize
-        } else {
-            n
-        };
+        let (j_start, j_end) = p.band_cols(i, n);
+        if j_start > j_end {
+            continue;
+        }
 
-        for j in j_lo..=j_hi {
-            let cur = idx(i, j);
-            let up = idx(i - 1, j);
-            let left = idx(i, j - 1);
-            let diag = idx(i - 1, j - 1);
+        for j in j_start..=j_end {
+            let idx = i * width + (j as isize - row_start(i)) as usize;
+            let (h_up, e_up) = at(i - 1, j).map_or((0, NEG_INF), |u| (h[u], e[u]));
+            let (h_left, f_left) = at(i, j - 1).map_or((0, NEG_INF), |l| (h[l], f[l]));
+            let h_diag = at(i - 1, j - 1).map_or(0, |d| h[d]);
 
-            let subst = if query[i - 1] == reference[j - 1] {
-                p.match_score
-            } else {
-                -p.mismatch_penalty
-            };
+            let e_open = h_up - p.gap_open - p.gap_extend;
+            let e_ext = e_up - p.gap_extend;
+            e[idx] = e_open.max(e_ext);
 
-            let mut best_prev = match_mat[diag];
-            let mut prev_state = TraceState::Match;
-            if ins_mat[diag] > best_prev {
-                best_prev = ins_mat[diag];
-                prev_state = TraceState::Ins;
-            }
-            if del_mat[diag] > best_prev {
-                best_prev = del_mat[diag];
-                prev_state = TraceState::Del;
-            }
-            if best_prev > NEG_INF / 2 {
-                match_mat[cur] = best_prev + subst;
-                match_trace[cur] = trace_to_u8(prev_state);
-            }
+            let f_open = h_left - p.gap_open - p.gap_extend;
+            let f_ext = f_left - p.gap_extend;
+            f[idx] = f_open.max(f_ext);
 
-            let open_ins = penalize(match_mat[up], p.gap_open + p.gap_extend);
-            let extend_ins = penalize(ins_mat[up], p.gap_extend);
-            if open_ins >= extend_ins {
-                ins_mat[cur] = open_ins;
-                ins_trace[cur] = trace_to_u8(TraceState::Match);
-            } else {
-                ins_mat[cur] = extend_ins;
-                ins_trace[cur] = trace_to_u8(TraceState::Ins);
-            }
+            let diag_val = h_diag + p.subst(query[i - 1], reference[j - 1]);
 
-            let open_del = penalize(match_mat[left], p.gap_open + p.gap_extend);
-            let extend_del = penalize(del_mat[left], p.gap_extend);
-            if open_del >= extend_del {
-                del_mat[cur] = open_del;
-                del_trace[cur] = trace_to_u8(TraceState::Match);
-            } else {
-                del_mat[cur] = extend_del;
-                del_trace[cur] = trace_to_u8(TraceState::Del);
+            let mut val = diag_val;
+            if e[idx] > val {
+                val = e[idx];
             }
-
-            let mut cell_best = match_mat[cur];
-            let mut cell_state = TraceState::Match;
-            if ins_mat[cur] > cell_best {
-                cell_best = ins_mat[cur];
-                cell_state = TraceState::Ins;
+            if f[idx] > val {
+                val = f[idx];
             }
-            if del_mat[cur] > cell_best {
-                cell_best = del_mat[cur];
-                cell_state = TraceState::Del;
+            if val < 0 {
+                val = 0;
             }
+            h[idx] = val;
+
+            // 回溯方向：得分为 0 处为局部比对起点；并列时依次优先对角线、插入、缺失
+            let state = if val == 0 {
+                TraceState::Start
+            } else if val == diag_val {
+                TraceState::Match
+            } else if val == e[idx] {
+                TraceState::Ins
+            } else {
+                TraceState::Del
+            };
+            trace[idx] = trace_to_u8(state);
 
-            if cell_best > best_score {
-                best_score = cell_best;
+            if val > best_score {
+                best_score = val;
                 best_i = i;
                 best_j = j;
-                best_state = cell_state;
             }
-            if cell_best > max_score {
-                max_score = cell_best;
-            }
-        }
-
-        // z-drop: if max score seen in this row is too far below global max, stop
-        let row_best = (j_lo..=j_hi)
-            .map(|j| {
-                let cur = idx(i, j);
-                match_mat[cur].max(ins_mat[cur]).max(del_mat[cur])
-            })
-            .max()
-            .unwrap_or(NEG_INF);
-        if zdrop > 0 && max_score - row_best >= zdrop {
-            break;
         }
     }
 
     if best_score <= 0 {
-        return ExtendResult {
+        return SwResult {
             score: 0,
-            query_len: 0,
-            ref_len: 0,
-            ops: vec![],
+            query_start: 0,
+            query_end: 0,
+            ref_start: 0,
+            ref_end: 0,
+            cigar: String::new(),
+            nm: 0,
         };
     }
 
-    // 回溯
+    // 沿方向矩阵从最优单元回溯；走出带外视为起点
     let mut ops: Vec<char> = Vec::new();
     let mut i = best_i;
     let mut j = best_j;
-    let mut state = best_state;
-    while i > 0 || j > 0 {
-        let cur = idx(i, j);
+
+    while i > 0 && j > 0 {
+        let state = at(i, j).map_or(TraceState::Start, |idx| u8_to_trace(trace[idx]));
         match state {
             TraceState::Match => {
                 ops.push('M');
-                state = u8_to_trace(match_trace[cur]);
                 i -= 1;
                 j -= 1;
             }
             TraceState::Ins => {
                 ops.push('I');
-                state = u8_to_trace(ins_trace[cur]);
                 i -= 1;
             }
             TraceState::Del => {
                 ops.push('D');
-                state = u8_to_trace(del_trace[cur]);
                 j -= 1;
             }
             TraceState::Start => break,
         }
     }
+
+    let query_start = i;
+    let ref_start = j;
+    let query_end = best_i;
+    let ref_end = best_j;
+
     ops.reverse();
 
-    ExtendResult {
+    let cigar = ops_to_cigar(&ops);
+    let nm = edit_distance(query, reference, &cigar, query_start, ref_start);
+
+    SwResult {
         score: best_score,
-        query_len: best_i,
-        ref_len: best_j,
-        ops,
+        query_start,
+        query_end,
+        ref_start,
+        ref_end,
+        cigar,
+        nm,
+    }
+}
+
+/// 将 CIGAR ops 列表压缩为标准 CIGAR 字符串（游程编码），例如 `['M','M','I','M']` → `"2M1I1M"`。
+pub fn ops_to_cigar(ops: &[char]) -> String {
+    let mut cigar = String::new();
+    if ops.is_empty() {
+        return cigar;
+    }
+    let mut cur = ops[0];
+    let mut len = 1usize;
+    for &op in &ops[1..] {
+        if op == cur {
+            len += 1;
+        } else {
+            let _ = write!(&mut cigar, "{}{}", len, cur);
+            cur = op;
+            len = 1;
+        }
+    }
+    let _ = write!(&mut cigar, "{}{}", len, cur);
+    cigar
+}
+
+/// 从 (0,0) 向右做半全局扩展对齐（类似 BWA ksw_extend）。
+/// query/reference 均从左往右，延伸直到序列末尾或得分跌落超过 zdrop。
+/// 返回实际延伸到的位置和 CIGAR。
+pub fn extend_right(query: &[u8], reference: &[u8], p: SwParams, zdrop: i32) -> ExtendResult {
+    let m = query.len();
+    let n = reference.len();
+    if m == 0 || n == 0 {
+        return ExtendResult {
+            score: 0,
+            query_len: 0,
+            ref_len: 0,
+            ops: vec![],
+        };
+    }
+
+    let path = affine_align(query, reference, &p, AffineEnd::Extend { zdrop }, &mut SwBuffer::new());
+    ExtendResult {
+        score: path.score,
+        query_len: path.end.0,
+        ref_len: path.end.1,
+        ops: path.ops,
     }
 }
 
@@ -937,9 +934,68 @@ pub fn parse_cigar(cigar: &str) -> Vec<(char, usize)> {
     result
 }
 
+/// 将 CIGAR 中的插入/缺失左移到等价的最左位置（变异检测常用的规范化）。
+///
+/// - `query`：CIGAR 覆盖的完整 query（含软剪切部分）
+/// - `reference`：参考序列，比对从 `ref_start` 开始
+///
+/// 仅在左侧为 `M` 的 indel 上左移，每次移动一个碱基且要求移出/移入的碱基相同，
+/// 因此各位置的匹配/错配状态与比对得分都不变；左侧 `M` 至少保留 1 个碱基，
+/// 不会把 indel 推到比对边缘或与相邻 indel 合并。
+pub fn left_align_indels(
+    cigar: &[(char, usize)],
+    query: &[u8],
+    reference: &[u8],
+    ref_start: usize,
+) -> Vec<(char, usize)> {
+    let mut ops: Vec<(char, usize)> = cigar.to_vec();
+
+    let mut k = 1;
+    while k < ops.len() {
+        let (op, len) = ops[k];
+        if !matches!(op, 'I' | 'D') || ops[k - 1].0 != 'M' {
+            k += 1;
+            continue;
+        }
+
+        // indel 起点在 query / reference 上的坐标
+        let q: usize = ops[..k]
+            .iter()
+            .filter(|(o, _)| matches!(o, 'M' | 'I' | 'S' | '=' | 'X'))
+            .map(|&(_, l)| l)
+            .sum();
+        let r = ref_start + cigar_ops_ref_length(&ops[..k]);
+
+        let mut shift = 0usize;
+        while ops[k - 1].1 - shift >= 2 {
+            let (a, b) = match op {
+                'D' => (reference.get(r - shift - 1), reference.get(r + len - shift - 1)),
+                _ => (query.get(q - shift - 1), query.get(q + len - shift - 1)),
+            };
+            match (a, b) {
+                (Some(a), Some(b)) if a.eq_ignore_ascii_case(b) => shift += 1,
+                _ => break,
+            }
+        }
+
+        if shift > 0 {
+            ops[k - 1].1 -= shift;
+            if ops.get(k + 1).is_some_and(|&(o, _)| o == 'M') {
+                ops[k + 1].1 += shift;
+            } else {
+                ops.insert(k + 1, ('M', shift));
+            }
+        }
+        k += 1;
+    }
+
+    ops
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testutil::{random_dna, Lcg};
 
     fn default_params() -> SwParams {
         SwParams {
@@ -948,6 +1004,11 @@ mod tests {
             gap_open: 1,
             gap_extend: 0,
             band_width: 8,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
         }
     }
 
@@ -998,6 +1059,22 @@ mod tests {
         assert!(res.cigar.contains('D') || res.cigar.contains('M'));
     }
 
+    #[test]
+    fn sw_gap_ops_follow_sam_convention() {
+        // SAM：I 消耗 query（read 多出的碱基），D 消耗参考（read 缺失的碱基）
+        let p = default_params();
+        let r = b"GATTACAGGCTTCAAGTCCGATGC";
+        let deleted = [&r[..12], &r[13..]].concat();
+        let res = banded_sw(&deleted, r, p);
+        assert_eq!(res.cigar, "12M1D11M");
+        assert_eq!(res.nm, 1);
+
+        let inserted = [&r[..13], b"G", &r[13..]].concat();
+        let res = banded_sw(&inserted, r, p);
+        assert_eq!(res.cigar, "13M1I11M");
+        assert_eq!(res.nm, 1);
+    }
+
     #[test]
     fn sw_empty_inputs() {
         let p = default_params();
@@ -1035,6 +1112,59 @@ mod tests {
         assert_eq!(ops_to_cigar(&['M', 'I', 'D', 'M']), "1M1I1D1M");
     }
 
+    #[test]
+    fn aligned_pairs_round_trip_with_insertion_and_deletion() {
+        // query:  AC-GTTAC   (query[4] 为插入的 T，ref[3] 的 A 为缺失)
+        // ref:   GACAGT-AC
+        let query = b"ACGTTAC";
+        let reference = b"GACAGTAC";
+        let res = SwResult {
+            score: 0,
+            query_start: 0,
+            query_end: 7,
+            ref_start: 1,
+            ref_end: 8,
+            cigar: "2M1D2M1I2M".to_string(),
+            nm: 2,
+        };
+        let pairs = res.aligned_pairs(query, reference);
+        let expected = vec![
+            (Some(0), Some(1)),
+            (Some(1), Some(2)),
+            (None, Some(3)),
+            (Some(2), Some(4)),
+            (Some(3), Some(5)),
+            (Some(4), None),
+            (Some(5), Some(6)),
+            (Some(6), Some(7)),
+        ];
+        assert_eq!(pairs, expected);
+
+        // 由对齐对还原两侧序列：匹配列碱基一致，且恰好覆盖比对区间
+        for &(q, r) in &pairs {
+            if let (Some(q), Some(r)) = (q, r) {
+                assert_eq!(query[q], reference[r]);
+            }
+        }
+        let qs: Vec<usize> = pairs.iter().filter_map(|p| p.0).collect();
+        let rs: Vec<usize> = pairs.iter().filter_map(|p| p.1).collect();
+        assert_eq!(qs, (res.query_start..res.query_end).collect::<Vec<_>>());
+        assert_eq!(rs, (res.ref_start..res.ref_end).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn max_score_is_read_len_times_match_score() {
+        let p = SwParams {
+            match_score: 2,
+            ..SwParams::default()
+        };
+        assert_eq!(p.max_score(50), 100);
+        assert_eq!(p.max_score(0), 0);
+        let mut m = [[-1; dna::SIGMA]; dna::SIGMA];
+        m[1][1] = 5;
+        assert_eq!(SwParams::with_matrix(m).max_score(10), 50);
+    }
+
     #[test]
     fn parse_cigar_basic() {
         let parsed = parse_cigar("3M1I2M");
@@ -1135,6 +1265,11 @@ mod tests {
             gap_open: 6,
             gap_extend: 1,
             band_width: 100,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
         };
         let q = b"ACGTACGTACGTACGT";
         let r = b"ACGTACGTACGTACGT";
@@ -1164,6 +1299,11 @@ mod tests {
             gap_open: 2,
             gap_extend: 1,
             band_width: 8,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
         };
         let res = global_align(b"CCCC", b"TTTTCCCC", p);
         assert_eq!(res.cigar, "4D4M");
@@ -1171,6 +1311,86 @@ mod tests {
         assert_eq!(res.score, 2);
     }
 
+    #[test]
+    fn global_align_perfect_match_and_internal_deletion() {
+        let p = SwParams {
+            match_score: 2,
+            mismatch_penalty: 4,
+            gap_open: 4,
+            gap_extend: 2,
+            band_width: 8,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
+        };
+        let res = global_align(b"ACGTACGTAC", b"ACGTACGTAC", p);
+        assert_eq!((res.cigar.as_str(), res.score, res.nm), ("10M", 20, 0));
+        assert_eq!(
+            (res.query_start, res.query_end, res.ref_start, res.ref_end),
+            (0, 10, 0, 10)
+        );
+
+        // 参考中间多出 3 个碱基
+        let res = global_align(b"GATTACACCGGTTA", b"GATTACAGTCCCGGTTA", p);
+        assert_eq!(res.cigar, "7M3D7M");
+        assert_eq!(res.score, 28 - (4 + 3 * 2));
+        assert_eq!(res.nm, 3);
+        assert_eq!((res.query_end, res.ref_end), (14, 17));
+
+        // 不做局部比对的清零：两端间隙与错配全部计分，得分可以为负
+        let res = global_align(b"", b"ACG", p);
+        assert_eq!((res.cigar.as_str(), res.score), ("3D", -10));
+        let res = global_align(b"AAAA", b"TTTT", p);
+        assert_eq!((res.cigar.as_str(), res.score), ("4M", -16));
+    }
+
+    #[test]
+    fn sw_mode_query_global_keeps_full_read_with_free_reference_ends() {
+        let p = SwParams {
+            match_score: 1,
+            mismatch_penalty: 4,
+            gap_open: 10,
+            gap_extend: 1,
+            band_width: 8,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
+        };
+        // read 末端两个错配：局部比对会截掉，query 全局比对必须保留
+        let reference = b"TTTTTTGATTACAGGCTTCAAGTCCGGGGGG";
+        let query = b"GATTACAGGCTTCAAGTGA";
+        let local = banded_sw(query, reference, p);
+        assert_eq!(local.query_end, 17);
+        assert_eq!(local.cigar, "17M");
+
+        let glocal_params = SwParams {
+            mode: SwMode::QueryGlobal,
+            iupac: false,
+            n_penalty: 0,
+            band_width: 32,
+            ..p
+        };
+        let res = banded_sw(query, reference, glocal_params);
+        assert_eq!(res, extend_align(query, reference, p));
+        assert_eq!((res.query_start, res.query_end), (0, query.len()));
+        assert_eq!((res.ref_start, res.ref_end), (6, 25));
+        assert_eq!(res.cigar, "19M");
+        assert_eq!(res.score, 17 - 2 * 4);
+        assert_eq!(res.nm, 2);
+
+        let global_params = SwParams {
+            mode: SwMode::Global,
+            ..p
+        };
+        let res = banded_sw(query, reference, global_params);
+        assert_eq!(res, global_align(query, reference, p));
+        assert_eq!((res.query_end, res.ref_end), (query.len(), reference.len()));
+    }
+
     #[test]
     fn semiglobal_align_finds_single_insertion() {
         let p = SwParams {
@@ -1179,6 +1399,11 @@ mod tests {
             gap_open: 6,
             gap_extend: 1,
             band_width: 32,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
         };
         let res = semiglobal_align(b"GGCCAAATTGGCCAATTGGCC", b"TTTGGCCAATTGGCCAATTGGCCTTT", p);
         assert_eq!(res.ref_start, 3);
@@ -1187,4 +1412,393 @@ mod tests {
         assert!(!res.cigar.contains('S'));
         assert_eq!(res.nm, 1);
     }
+
+    #[test]
+    fn nm_ignores_reference_n() {
+        assert_eq!(edit_distance(b"ACGTACGT", b"ACGNNCGT", "8M", 0, 0), 0);
+        assert_eq!(edit_distance(b"ACGTACGT", b"ACGNNCGA", "8M", 0, 0), 1);
+    }
+
+    #[test]
+    fn edit_distance_counts_deleted_bases_not_clips_or_n_matches() {
+        // read：2bp 软剪切 + ACGNAC + GTAG；参考从第 2 位起：ACGNAC + AAA（缺失）+ GTTG
+        let query = b"TTACGNACGTAG";
+        let reference = b"GGACGNACAAAGTTG";
+        // read 的 N 对参考 N 不计，缺失 3 个碱基计 3，末端 1 个错配，软剪切不计
+        assert_eq!(edit_distance(query, reference, "2S6M3D2M1X1M", 0, 2), 4);
+        assert_eq!(edit_distance(query, reference, "2S6M3D4M", 0, 2), 4);
+        assert_eq!(edit_distance(query, reference, "6M3D4M", 2, 2), 4);
+        assert_eq!(edit_distance(b"ACGT", b"AC", "2M2I", 0, 0), 2);
+    }
+
+    #[test]
+    fn alignment_over_reference_n_reports_lower_nm() {
+        let p = SwParams {
+            match_score: 2,
+            mismatch_penalty: 1,
+            gap_open: 6,
+            gap_extend: 1,
+            band_width: 8,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
+        };
+        let q = b"ACGTTGCAACGTTGCA";
+        let r = b"ACGTTGCNNNGTTGCA";
+
+        let global = global_align(q, r, p);
+        assert_eq!(global.cigar, "16M");
+        assert_eq!(global.nm, 0);
+
+        let local = banded_sw(q, r, p);
+        assert_eq!(local.cigar, "16M");
+        assert_eq!(local.nm, 0);
+    }
+
+    #[test]
+    fn iupac_reference_codes_match_at_full_score() {
+        let q = b"ACGTAGCTAGGATCCA";
+        let r = b"ACRTAGCYAGGAWCCA";
+        let p = SwParams {
+            band_width: 8,
+            ..SwParams::default()
+        };
+        let plain = banded_sw(q, r, p);
+        assert!(plain.score < q.len() as i32 * p.match_score);
+
+        let p = SwParams { iupac: true, ..p };
+        let res = banded_sw(q, r, p);
+        assert_eq!(res.cigar, "16M");
+        assert_eq!(res.score, q.len() as i32 * p.match_score);
+        // R 不兼容 C，仍按错配计分
+        assert_eq!(p.subst(b'C', b'R'), -p.mismatch_penalty);
+    }
+
+    #[test]
+    fn poly_n_stretch_scores_zero() {
+        let q = b"ACGTACGTNNNNNNNNACGTACGT";
+        let r = b"ACGTACGTNNNNNNNNACGTACGT";
+        let p = SwParams {
+            band_width: 8,
+            n_penalty: 0,
+            ..SwParams::default()
+        };
+        assert_eq!(p.subst(b'N', b'N'), 0);
+        assert_eq!(p.subst(b'A', b'N'), 0);
+        let res = banded_sw(q, r, p);
+        assert_eq!(res.cigar, "24M");
+        assert_eq!(res.score, 16 * p.match_score);
+        assert_eq!(res.nm, 0);
+
+        let p = SwParams { n_penalty: 1, ..p };
+        let res = banded_sw(q, r, p);
+        assert_eq!(res.cigar, "24M");
+        assert_eq!(res.score, 16 * p.match_score - 8);
+    }
+
+    #[test]
+    fn left_align_deletion_in_homopolymer() {
+        // ref: ACG TTTT CA，query 少一个 T；SW 可能把缺失放在 run 末尾
+        let reference = b"ACGTTTTCA";
+        let query = b"ACGTTTCA";
+        let cigar = vec![('M', 6), ('D', 1), ('M', 2)];
+        let aligned = left_align_indels(&cigar, query, reference, 0);
+        assert_eq!(aligned, vec![('M', 3), ('D', 1), ('M', 5)]);
+    }
+
+    #[test]
+    fn left_align_insertion_in_tandem_repeat() {
+        // query 多一个 CA 重复单元
+        let reference = b"GTCACACAGG";
+        let query = b"GTCACACACAGG";
+        let cigar = vec![('M', 8), ('I', 2), ('M', 2)];
+        let aligned = left_align_indels(&cigar, query, reference, 0);
+        assert_eq!(aligned, vec![('M', 2), ('I', 2), ('M', 8)]);
+    }
+
+    #[test]
+    fn left_align_respects_ref_start_and_clips() {
+        let reference = b"NNNNACGTTTTCA";
+        let query = b"XXACGTTTCA";
+        let cigar = vec![('S', 2), ('M', 6), ('D', 1), ('M', 2)];
+        let aligned = left_align_indels(&cigar, query, reference, 4);
+        assert_eq!(aligned, vec![('S', 2), ('M', 3), ('D', 1), ('M', 5)]);
+
+        // 非重复区的 indel 保持原位
+        let cigar = vec![('M', 2), ('D', 1), ('M', 6)];
+        assert_eq!(left_align_indels(&cigar, b"ACTTTTCA", b"ACGTTTTCA", 0), cigar);
+    }
+
+    #[test]
+    fn sw_anchored_band_reaches_off_diagonal_match() {
+        // read 与参考窗口第 30 位起完全匹配，远离主对角线
+        let reference = b"TTGACCATAGGCTAACGTTCAGCATGAGTCGATTACAGGCTTCAAGTCCGATGC";
+        let query = &reference[30..50];
+        let p = SwParams {
+            band_width: 4,
+            ..SwParams::default()
+        };
+
+        let centered = banded_sw(query, reference, p);
+        assert!(
+            centered.score < 40,
+            "main-diagonal band should miss the hit: {:?}",
+            centered
+        );
+
+        let anchored = banded_sw(query, reference, p.anchored(0, 30));
+        assert_eq!(anchored.score, 40);
+        assert_eq!(anchored.cigar, "20M");
+        assert_eq!((anchored.ref_start, anchored.ref_end), (30, 50));
+
+        // 种子在 query 中部时偏移同样由 rb - qb 推出
+        assert_eq!(p.anchored(5, 35).diagonal_offset, 30);
+        assert_eq!(banded_sw(query, reference, p.anchored(5, 35)).score, 40);
+    }
+
+    #[test]
+    fn sw_band_anchored_on_seed_near_read_end_keeps_full_alignment() {
+        // 种子位于 read 末端；read 开头之后有 2 bp 缺失，比对沿种子对角线向左延伸
+        let reference = b"CAGTTGACCATAGGCTAACGTTCAGCATGAGTCGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCCTCGGTTAGC";
+        let mut query = reference[40..46].to_vec();
+        query.extend_from_slice(&reference[48..72]);
+        let p = SwParams {
+            match_score: 2,
+            mismatch_penalty: 4,
+            gap_open: 2,
+            gap_extend: 1,
+            band_width: 4,
+            ..SwParams::default()
+        };
+
+        let centered = banded_sw(&query, reference, p);
+        assert!(
+            centered.score < 50,
+            "main-diagonal band should miss the hit: {:?}",
+            centered
+        );
+
+        // 种子：query[26..30] = reference[68..72]
+        let (qb, rb) = (26, 68);
+        assert_eq!(&query[qb..qb + 4], &reference[rb..rb + 4]);
+        let anchored = banded_sw(&query, reference, p.anchored(qb, rb));
+        assert_eq!(anchored.cigar, "6M2D24M");
+        assert_eq!((anchored.query_start, anchored.query_end), (0, 30));
+        assert_eq!((anchored.ref_start, anchored.ref_end), (40, 72));
+        assert_eq!(anchored.score, 60 - 4);
+    }
+
+    /// 转换（A↔G、C↔T）罚 1、颠换罚 3、`N` 与任意碱基计 0 分的矩阵
+    fn ts_tv_matrix() -> [[i32; dna::SIGMA]; dna::SIGMA] {
+        let mut m = [[-3; dna::SIGMA]; dna::SIGMA];
+        for (a, row) in m.iter_mut().enumerate() {
+            row[a] = 2;
+            row[5] = 0;
+        }
+        m[5] = [0; dna::SIGMA];
+        for (a, b) in [(1, 3), (3, 1), (2, 4), (4, 2)] {
+            m[a][b] = -1;
+        }
+        m
+    }
+
+    #[test]
+    fn sw_score_matrix_models_transitions_and_neutral_n() {
+        let p = SwParams {
+            gap_open: 6,
+            ..SwParams::with_matrix(ts_tv_matrix())
+        };
+        let reference = b"ACGTTGCAACGTTGCA";
+        // 第 8 位 A→G 为转换，A→C 为颠换，A→N 不计分
+        let transition = banded_sw(b"ACGTTGCGACGTTGCA", reference, p);
+        let transversion = banded_sw(b"ACGTTGCCACGTTGCA", reference, p);
+        let neutral = banded_sw(b"ACGTTGCNACGTTGCA", reference, p);
+        assert_eq!(transition.score, 15 * 2 - 1);
+        assert_eq!(transversion.score, 15 * 2 - 3);
+        assert_eq!(neutral.score, 15 * 2);
+        assert_eq!(neutral.cigar, "16M");
+        // NM 仍按碱基差异统计
+        assert_eq!((transition.nm, neutral.nm), (1, 1));
+    }
+
+    #[test]
+    fn sw_flat_matrix_matches_match_mismatch_scoring() {
+        let flat = SwParams {
+            gap_open: 3,
+            ..SwParams::default()
+        };
+        let mut m = [[-flat.mismatch_penalty; dna::SIGMA]; dna::SIGMA];
+        // 涉及 N 的位置按 n_penalty 计分
+        let n = dna::to_alphabet(b'N') as usize;
+        for (a, row) in m.iter_mut().enumerate() {
+            row[a] = flat.match_score;
+            row[n] = -flat.n_penalty;
+        }
+        m[n] = [-flat.n_penalty; dna::SIGMA];
+        let matrix = SwParams {
+            score_matrix: Some(m),
+            ..flat
+        };
+        let q = b"ACGTTGCAGGTTACNATGCA";
+        let r = b"TTACGTTGCAACGTTACNATGGCATT";
+        assert_eq!(banded_sw(q, r, matrix), banded_sw(q, r, flat));
+        assert_eq!(global_align(q, r, matrix), global_align(q, r, flat));
+        assert_eq!(semiglobal_align(q, r, matrix), semiglobal_align(q, r, flat));
+    }
+
+    /// 旧的矩形存储实现（(m+1)*(n+1) 矩阵、按得分重算回溯），用于校验带状存储
+    fn rectangular_sw(query: &[u8], reference: &[u8], p: SwParams) -> (i32, usize, usize, usize, usize, String) {
+        let (m, n) = (query.len(), reference.len());
+        let cols = n + 1;
+        let mut h = vec![0i32; (m + 1) * cols];
+        let mut e = vec![NEG_INF; (m + 1) * cols];
+        let mut f = vec![NEG_INF; (m + 1) * cols];
+        let (mut best, mut bi, mut bj) = (0, 0, 0);
+        for i in 1..=m {
+            let (lo, hi) = p.band_cols(i, n);
+            for j in lo..=hi {
+                let idx = i * cols + j;
+                e[idx] = (h[idx - cols] - p.gap_open - p.gap_extend).max(e[idx - cols] - p.gap_extend);
+                f[idx] = (h[idx - 1] - p.gap_open - p.gap_extend).max(f[idx - 1] - p.gap_extend);
+                let diag = h[idx - cols - 1] + p.subst(query[i - 1], reference[j - 1]);
+                h[idx] = diag.max(e[idx]).max(f[idx]).max(0);
+                if h[idx] > best {
+                    (best, bi, bj) = (h[idx], i, j);
+                }
+            }
+        }
+        let (mut i, mut j, mut ops) = (bi, bj, Vec::new());
+        while best > 0 && i > 0 && j > 0 {
+            let idx = i * cols + j;
+            if h[idx] == 0 {
+                break;
+            }
+            if h[idx] == h[idx - cols - 1] + p.subst(query[i - 1], reference[j - 1]) {
+                ops.push('M');
+                (i, j) = (i - 1, j - 1);
+            } else if h[idx] == e[idx] {
+                ops.push('I');
+                i -= 1;
+            } else {
+                ops.push('D');
+                j -= 1;
+            }
+        }
+        ops.reverse();
+        if best == 0 {
+            return (0, 0, 0, 0, 0, String::new());
+        }
+        (best, i, bi, j, bj, ops_to_cigar(&ops))
+    }
+
+    #[test]
+    fn banded_storage_matches_rectangular_matrix() {
+        let mut rng = Lcg::new(7);
+        let mut rand = |k: u32| rng.below(k);
+        let mut buf = SwBuffer::new();
+        for _ in 0..300 {
+            let reference: Vec<u8> = (0..20 + rand(120)).map(|_| b"ACGT"[rand(4) as usize]).collect();
+            let start = rand(reference.len() as u32 / 2) as usize;
+            let mut query: Vec<u8> = reference[start..].iter().take(10 + rand(80) as usize).copied().collect();
+            for _ in 0..rand(6) {
+                let pos = rand(query.len() as u32) as usize;
+                match rand(3) {
+                    0 => query[pos] = b"ACGT"[rand(4) as usize],
+                    1 => query.insert(pos, b"ACGT"[rand(4) as usize]),
+                    _ => {
+                        query.remove(pos);
+                    }
+                }
+            }
+            if query.is_empty() {
+                continue;
+            }
+            let p = SwParams {
+                gap_extend: rand(2) as i32,
+                band_width: 1 + rand(40) as usize,
+                ..default_params()
+            }
+            .anchored(0, start + rand(5) as usize);
+            let got = banded_sw_with_buf(&query, &reference, p, &mut buf);
+            let want = rectangular_sw(&query, &reference, p);
+            assert_eq!(
+                (
+                    got.score,
+                    got.query_start,
+                    got.query_end,
+                    got.ref_start,
+                    got.ref_end,
+                    got.cigar
+                ),
+                want,
+                "band {} offset {}",
+                p.band_width,
+                p.diagonal_offset
+            );
+        }
+    }
+
+    #[test]
+    fn banded_storage_scales_with_band_not_reference() {
+        let reference = random_dna(3, 4000);
+        let query = reference[1000..3000].to_vec();
+        let p = SwParams {
+            band_width: 16,
+            ..SwParams::default()
+        }
+        .anchored(0, 1000);
+        let mut buf = SwBuffer::new();
+        let res = banded_sw_with_buf(&query, &reference, p, &mut buf);
+        assert_eq!((res.cigar.as_str(), res.ref_start), ("2000M", 1000));
+        assert_eq!(buf.h.len(), (query.len() + 1) * 33);
+        assert_eq!(buf.match_trace.len(), buf.h.len());
+    }
+
+    #[test]
+    fn affine_storage_keeps_checkpoints_not_full_matrix() {
+        // 与候选精修相同的窗口：read 两侧各留 read 长度以上的参考
+        let reference = random_dna(5, 8000);
+        let mut query = reference[3000..5000].to_vec();
+        query.insert(1000, b'A');
+        let (m, cols) = (query.len(), reference.len() + 1);
+        let mut buf = SwBuffer::new();
+        let res = semiglobal_align_with_buf(&query, &reference, SwParams::default(), &mut buf);
+        assert_eq!((res.ref_start, res.ref_end, res.nm), (3000, 5000, 1));
+
+        // ⌈√2001⌉ = 45 行一个检查点：45 个检查点 + 2 条工作行，方向矩阵只保存一块 46 行
+        assert_eq!(checkpoint_stride(m), 45);
+        assert_eq!(buf.cells.len(), 47 * cols);
+        assert_eq!(buf.trace.len(), 46 * cols);
+        assert!(buf.cells.len() * std::mem::size_of::<AffineCell>() + buf.trace.len() < (m + 1) * cols);
+    }
+
+    #[test]
+    fn checkpointed_traceback_matches_single_block() {
+        let mut rng = Lcg::new(41);
+        for _ in 0..200 {
+            let (m, n) = (1 + rng.below(40) as usize, 1 + rng.below(60) as usize);
+            let (query, reference) = (rng.dna(m), rng.dna(n));
+            let p = SwParams {
+                band_width: rng.below(12) as usize,
+                ..SwParams::default()
+            };
+            for end in [
+                AffineEnd::Global,
+                AffineEnd::SemiGlobal,
+                AffineEnd::Extend { zdrop: 20 },
+            ] {
+                // stride = m 时整个矩阵为一块，等同于保存完整方向矩阵
+                let full = affine_align_with_stride(&query, &reference, &p, end, query.len(), &mut SwBuffer::new());
+                for stride in [1, 2, 5] {
+                    let part = affine_align_with_stride(&query, &reference, &p, end, stride, &mut SwBuffer::new());
+                    assert_eq!(
+                        (part.score, part.start, part.end, &part.ops),
+                        (full.score, full.start, full.end, &full.ops)
+                    );
+                }
+            }
+        }
+    }
 }
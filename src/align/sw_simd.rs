@@ -0,0 +1,540 @@
+use super::sw::{banded_sw, edit_distance, ops_to_cigar, SwMode, SwParams, SwResult};
+
+/// 每个向量的 lane 数（对应 AVX2 的 8×i32）
+const LANES: usize = 8;
+const NEG_INF: i32 = i32::MIN / 4;
+
+/// 前向打分最多保存的检查点列数；检查点间隔至少为 [`MIN_CHECKPOINT_GAP`] 列
+const MAX_CHECKPOINTS: usize = 64;
+const MIN_CHECKPOINT_GAP: usize = 32;
+
+/// 定长 lane 向量。
+///
+/// 比对代码不使用 `unsafe`（crate 仅在 `index::mmap` 中放开），`std::simd` 又仅限 nightly，因此逐 lane 运算写成定长数组上的简单循环，
+/// 由编译器自动向量化为 SSE2/AVX2/NEON 指令；不支持 SIMD 的目标上退化为普通标量代码，照常编译。
+#[derive(Clone, Copy, Debug)]
+struct Lanes([i32; LANES]);
+
+impl Lanes {
+    #[inline]
+    fn splat(v: i32) -> Self {
+        Self([v; LANES])
+    }
+
+    #[inline]
+    fn add(self, other: Self) -> Self {
+        Self(std::array::from_fn(|k| self.0[k] + other.0[k]))
+    }
+
+    #[inline]
+    fn sub(self, v: i32) -> Self {
+        Self(std::array::from_fn(|k| self.0[k] - v))
+    }
+
+    #[inline]
+    fn max(self, other: Self) -> Self {
+        Self(std::array::from_fn(|k| self.0[k].max(other.0[k])))
+    }
+
+    /// 各 lane 右移一位（lane `k` 取 lane `k-1` 的值），lane 0 填入 `first`
+    #[inline]
+    fn shift_in(self, first: i32) -> Self {
+        Self(std::array::from_fn(|k| if k == 0 { first } else { self.0[k - 1] }))
+    }
+
+    /// 行号 `rows` 落在 `[lo, hi]` 内的 lane 保留原值，其余置为 `fill`
+    #[inline]
+    fn keep(self, rows: Self, lo: i32, hi: i32, fill: i32) -> Self {
+        Self(std::array::from_fn(|k| {
+            if (lo..=hi).contains(&rows.0[k]) {
+                self.0[k]
+            } else {
+                fill
+            }
+        }))
+    }
+
+    #[inline]
+    fn any_gt(self, other: Self) -> bool {
+        self.0.iter().zip(other.0).any(|(&a, b)| a > b)
+    }
+
+    #[inline]
+    fn hmax(self) -> i32 {
+        self.0.iter().copied().max().unwrap_or(NEG_INF)
+    }
+}
+
+/// 完整 DP 矩阵中一列的带内单元（第 `first_row..first_row + h.len()` 行）的 H 与横向间隙 F；
+/// 其余行与带外单元一样视为 `H = 0`、`F = -inf`
+struct Column {
+    col: usize,
+    first_row: usize,
+    h: Vec<i32>,
+    f: Vec<i32>,
+}
+
+impl Column {
+    /// 第 0 列：所有单元均为边界
+    fn zero() -> Self {
+        Self {
+            col: 0,
+            first_row: 0,
+            h: Vec::new(),
+            f: Vec::new(),
+        }
+    }
+
+    fn at(&self, i: usize) -> (i32, i32) {
+        i.checked_sub(self.first_row)
+            .filter(|&k| k < self.h.len())
+            .map_or((0, NEG_INF), |k| (self.h[k], self.f[k]))
+    }
+}
+
+/// 同 [`banded_sw`]，但打分阶段使用 Farrar 条带（striped）向量化布局。
+///
+/// 结果（得分、起止坐标与 CIGAR）与标量版本完全一致。条带布局按整列计算，
+/// 带宽接近参考窗口长度时才有收益（150bp / 1kb 窗口约快一倍）；窄带下标量版本更快，
+/// 因此 [`banded_sw_with_buf`](super::sw::banded_sw_with_buf) 只在带覆盖整个窗口时转到这里。
+pub fn banded_sw_simd(query: &[u8], reference: &[u8], p: SwParams) -> SwResult {
+    if p.mode != SwMode::Local {
+        return banded_sw(query, reference, p);
+    }
+    let empty = SwResult {
+        score: 0,
+        query_start: 0,
+        query_end: 0,
+        ref_start: 0,
+        ref_end: 0,
+        cigar: String::new(),
+        nm: 0,
+    };
+    if query.is_empty() || reference.is_empty() {
+        return empty;
+    }
+
+    // 1. 前向条带打分：最高分及其终点，并每隔若干列保存一份带内的 H/F 列
+    let gap = MIN_CHECKPOINT_GAP.max((reference.len() + MAX_CHECKPOINTS - 1) / MAX_CHECKPOINTS);
+    let (best, qe, re, checkpoints) = striped_best_cell(query, reference, &p, Some(gap));
+    if best <= 0 {
+        return empty;
+    }
+
+    // 2. 反向条带打分：在两条前缀的反转序列上求局部比对，得到终于 (qe, re) 的最优比对的起点
+    let ref_lo = (p.diagonal_offset - p.band_width as isize).clamp(0, re as isize) as usize;
+    let rev_query: Vec<u8> = query[..qe].iter().rev().copied().collect();
+    let rev_ref: Vec<u8> = reference[ref_lo..re].iter().rev().copied().collect();
+    let rev_p = SwParams {
+        diagonal_offset: re as isize - qe as isize - p.diagonal_offset,
+        ..p
+    };
+    let (rev_best, rev_i, rev_j, _) = striped_best_cell(&rev_query, &rev_ref, &rev_p, None);
+    debug_assert_eq!(rev_best, best);
+    let rs = re - rev_j;
+
+    // 3. 从不晚于起点的检查点列到终点的小框内重算标量 DP 并回溯；框内各单元与完整矩阵一致。
+    //    得分并列的比对可能使标量回溯的起点早于反向打分的起点，此时退回到第 0 列重算
+    debug_assert!(rev_i <= qe);
+    let left = checkpoints.iter().rev().find(|c| c.col <= rs);
+    let traced = left
+        .and_then(|c| box_traceback(query, reference, &p, c, qe, re))
+        .or_else(|| box_traceback(query, reference, &p, &Column::zero(), qe, re));
+    let Some((query_start, ref_start, ops)) = traced else {
+        // 第 0 列是矩阵本身的边界，回溯不会越过它
+        debug_assert!(false, "traceback escaped column 0");
+        return empty;
+    };
+
+    let cigar = ops_to_cigar(&ops);
+    let nm = edit_distance(query, reference, &cigar, query_start, ref_start);
+    SwResult {
+        score: best,
+        query_start,
+        query_end: qe,
+        ref_start,
+        ref_end: re,
+        cigar,
+        nm,
+    }
+}
+
+/// 条带布局的带状仿射间隙 SW 纯打分 DP，返回 `(最高分, query_end, ref_end, 检查点列)`（1-based 终点）。
+///
+/// 外层按参考列推进，query 行按 Farrar 布局分入向量：第 `s` 段向量的 lane `k` 对应第 `k * seg + s + 1` 行。
+/// 段内的纵向间隙（`I`）在主循环中顺带计算，跨 lane 的部分由 lazy-F 循环补齐。
+/// 带外单元与标量版本一样视为 `H = 0`、间隙分数为负无穷；最高分相同时取行优先顺序下的第一个单元，
+/// 与标量版本的遍历顺序一致。`checkpoint_gap` 为 `Some(g)` 时保存每个 `g` 的倍数列的带内单元。
+fn striped_best_cell(
+    query: &[u8],
+    reference: &[u8],
+    p: &SwParams,
+    checkpoint_gap: Option<usize>,
+) -> (i32, usize, usize, Vec<Column>) {
+    let m = query.len();
+    let mut checkpoints = Vec::new();
+    if m == 0 || reference.is_empty() {
+        return (0, 0, 0, checkpoints);
+    }
+    let seg = (m + LANES - 1) / LANES;
+    let goe = p.gap_open + p.gap_extend;
+    let ge = p.gap_extend;
+
+    let striped: Vec<[u8; LANES]> = (0..seg)
+        .map(|s| std::array::from_fn(|k| query.get(k * seg + s).copied().unwrap_or(0)))
+        .collect();
+    let rows: Vec<Lanes> = (0..seg)
+        .map(|s| Lanes(std::array::from_fn(|k| (k * seg + s + 1) as i32)))
+        .collect();
+
+    // query profile：每种参考碱基一组替换得分向量，按碱基首次出现时计算
+    let mut profile_slot = [usize::MAX; 256];
+    let mut profile: Vec<Lanes> = Vec::new();
+
+    let zero = Lanes::splat(0);
+    let mut h = vec![zero; seg];
+    let mut f = vec![Lanes::splat(NEG_INF); seg];
+    let (mut best, mut best_i, mut best_j) = (0i32, 0usize, 0usize);
+    let band = p.band_width as i64;
+    let offset = p.diagonal_offset as i64;
+
+    for (jj, &rb) in reference.iter().enumerate() {
+        let j = jj + 1;
+        // 第 i 行的带为 [i + offset - band, i + offset + band]，反解出第 j 列带内的行区间
+        let lo = (j as i64 - offset - band).max(1);
+        let hi = (j as i64 - offset + band).min(m as i64);
+        if lo > hi {
+            h.fill(zero);
+            f.fill(Lanes::splat(NEG_INF));
+            continue;
+        }
+        let (lo, hi) = (lo as i32, hi as i32);
+
+        if profile_slot[rb as usize] == usize::MAX {
+            profile_slot[rb as usize] = profile.len();
+            profile.extend(striped.iter().map(|q| Lanes(std::array::from_fn(|k| p.subst(q[k], rb)))));
+        }
+        let subst = &profile[profile_slot[rb as usize]..][..seg];
+
+        let mut diag = h[seg - 1].shift_in(0);
+        let mut e = Lanes::splat(NEG_INF);
+        for s in 0..seg {
+            let f_new = h[s].sub(goe).max(f[s].sub(ge)).keep(rows[s], lo, hi, NEG_INF);
+            let e_cur = e.keep(rows[s], lo, hi, NEG_INF);
+            let h_new = diag.add(subst[s]).max(f_new).max(e_cur).max(zero).keep(rows[s], lo, hi, 0);
+            diag = h[s];
+            h[s] = h_new;
+            f[s] = f_new;
+            e = h_new.sub(goe).max(e_cur.sub(ge));
+        }
+
+        // lazy-F：把纵向间隙跨 lane 边界传播，直到不再能改进任何单元
+        e = e.shift_in(NEG_INF);
+        'lazy: for _ in 0..LANES {
+            for s in 0..seg {
+                let e_cur = e.keep(rows[s], lo, hi, NEG_INF);
+                if !e_cur.any_gt(h[s].sub(goe)) {
+                    break 'lazy;
+                }
+                h[s] = h[s].max(e_cur);
+                e = e_cur.sub(ge);
+            }
+            e = e.shift_in(NEG_INF);
+        }
+
+        if checkpoint_gap.is_some_and(|g| j % g == 0) {
+            let (lo, hi) = (lo as usize, hi as usize);
+            let lane = |v: &[Lanes], i: usize| v[(i - 1) % seg].0[(i - 1) / seg];
+            checkpoints.push(Column {
+                col: j,
+                first_row: lo,
+                h: (lo..=hi).map(|i| lane(&h, i)).collect(),
+                f: (lo..=hi).map(|i| lane(&f, i)).collect(),
+            });
+        }
+
+        let top = h.iter().fold(zero, |acc, &v| acc.max(v)).hmax();
+        if top > 0 && top >= best {
+            let i = (0..seg)
+                .flat_map(|s| (0..LANES).map(move |k| (s, k)))
+                .filter(|&(s, k)| h[s].0[k] == top)
+                .map(|(s, k)| k * seg + s + 1)
+                .min()
+                .unwrap_or(usize::MAX);
+            if top > best || i < best_i {
+                best = top;
+                best_i = i;
+                best_j = j;
+            }
+        }
+    }
+
+    (best, best_i, best_j, checkpoints)
+}
+
+/// 在列 `left.col + 1 ..= re`、行 `1..=qe` 的带内单元上重算标量 DP 并从 `(qe, re)` 回溯。
+///
+/// 左边界取自前向打分保存的检查点列，框内各单元的值与方向与在完整矩阵上运行 [`banded_sw`] 时相同，
+/// 递推与并列规则也与之一致，因此回溯路径相同。返回 `(query_start, ref_start, ops)`；
+/// 回溯到达左边界上得分非 0 的单元（起点在检查点之前）时返回 `None`。
+fn box_traceback(
+    query: &[u8],
+    reference: &[u8],
+    p: &SwParams,
+    left: &Column,
+    qe: usize,
+    re: usize,
+) -> Option<(usize, usize, Vec<char>)> {
+    let n = reference.len();
+    let c0 = left.col;
+    let band = p.band_width as isize;
+    let width = 2 * p.band_width + 1;
+    let row_start = |i: usize| i as isize + p.diagonal_offset - band;
+    // 第 r_lo 行之前的行在框内各列都在带外
+    let r_lo = (c0 as isize + 1 - p.diagonal_offset - band).max(1) as usize;
+    if r_lo > qe {
+        return None;
+    }
+    let at = |i: usize, j: usize| {
+        if i < r_lo || i > qe || j <= c0 {
+            return None;
+        }
+        let k = j as isize - row_start(i);
+        (0..width as isize).contains(&k).then(|| (i - r_lo) * width + k as usize)
+    };
+
+    let size = (qe - r_lo + 1) * width;
+    let mut h = vec![0i32; size];
+    let mut e = vec![NEG_INF; size];
+    let mut f = vec![NEG_INF; size];
+    let mut trace = vec![Trace::Start; size];
+
+    for i in r_lo..=qe {
+        let (j_lo, j_hi) = p.band_cols(i, n);
+        for j in j_lo.max(c0 + 1)..=j_hi.min(re) {
+            let idx = (i - r_lo) * width + (j as isize - row_start(i)) as usize;
+            let (h_up, e_up) = at(i - 1, j).map_or((0, NEG_INF), |u| (h[u], e[u]));
+            let (h_left, f_left) = if j - 1 == c0 {
+                left.at(i)
+            } else {
+                at(i, j - 1).map_or((0, NEG_INF), |l| (h[l], f[l]))
+            };
+            let h_diag = if j - 1 == c0 {
+                left.at(i - 1).0
+            } else {
+                at(i - 1, j - 1).map_or(0, |d| h[d])
+            };
+
+            e[idx] = (h_up - p.gap_open - p.gap_extend).max(e_up - p.gap_extend);
+            f[idx] = (h_left - p.gap_open - p.gap_extend).max(f_left - p.gap_extend);
+            let diag_val = h_diag + p.subst(query[i - 1], reference[j - 1]);
+            let val = diag_val.max(e[idx]).max(f[idx]).max(0);
+            h[idx] = val;
+            trace[idx] = if val == 0 {
+                Trace::Start
+            } else if val == diag_val {
+                Trace::Match
+            } else if val == e[idx] {
+                Trace::Ins
+            } else {
+                Trace::Del
+            };
+        }
+    }
+
+    let (mut i, mut j) = (qe, re);
+    let mut ops = Vec::new();
+    while i > 0 && j > 0 {
+        if j == c0 {
+            if left.at(i).0 == 0 {
+                break;
+            }
+            return None;
+        }
+        match at(i, j).map_or(Trace::Start, |idx| trace[idx]) {
+            Trace::Match => {
+                ops.push('M');
+                i -= 1;
+                j -= 1;
+            }
+            Trace::Ins => {
+                ops.push('I');
+                i -= 1;
+            }
+            Trace::Del => {
+                ops.push('D');
+                j -= 1;
+            }
+            Trace::Start => break,
+        }
+    }
+    ops.reverse();
+    Some((i, j, ops))
+}
+
+/// 回溯方向，与标量版本的方向矩阵相同
+#[derive(Clone, Copy)]
+enum Trace {
+    Start,
+    Match,
+    Ins,
+    Del,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::align::sw::{banded_sw_scalar, SwBuffer};
+    use crate::testutil::random_dna;
+
+    /// 标量实现（`banded_sw` 在宽带时本身会转到 SIMD 版本）
+    fn scalar(query: &[u8], reference: &[u8], p: SwParams) -> SwResult {
+        banded_sw_scalar(query, reference, p, &mut SwBuffer::new())
+    }
+    use crate::util::dna;
+
+    struct Lcg(u32);
+
+    impl Lcg {
+        fn next(&mut self, bound: usize) -> usize {
+            self.0 = self.0.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (self.0 >> 16) as usize % bound
+        }
+
+        fn seq(&mut self, len: usize) -> Vec<u8> {
+            (0..len).map(|_| b"ACGTN"[self.next(5)]).collect()
+        }
+    }
+
+    /// 由参考片段随机引入错配与插入/缺失得到 query
+    fn mutate(rng: &mut Lcg, src: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(src.len());
+        let mut i = 0;
+        while i < src.len() {
+            match rng.next(40) {
+                0 => out.push(b"ACGT"[rng.next(4)]),
+                1 => i += 1 + rng.next(6),
+                2 => out.extend((0..1 + rng.next(12)).map(|_| b"ACGT"[rng.next(4)])),
+                _ => {
+                    out.push(src[i]);
+                    i += 1;
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn sw_simd_matches_scalar_on_random_inputs() {
+        let mut rng = Lcg(7);
+        for case in 0..2000 {
+            let p = SwParams {
+                match_score: 1 + rng.next(3) as i32,
+                mismatch_penalty: 1 + rng.next(4) as i32,
+                gap_open: rng.next(6) as i32,
+                gap_extend: rng.next(3) as i32,
+                band_width: rng.next(40),
+                diagonal_offset: rng.next(21) as isize - 10,
+                score_matrix: None,
+                mode: SwMode::Local,
+                iupac: false,
+                n_penalty: 0,
+            };
+            // 四分之一的用例改用随机替换矩阵
+            let p = if rng.next(4) == 0 {
+                let mut m = [[0i32; dna::SIGMA]; dna::SIGMA];
+                for (a, row) in m.iter_mut().enumerate() {
+                    for (b, v) in row.iter_mut().enumerate() {
+                        *v = if a == b {
+                            1 + rng.next(3) as i32
+                        } else {
+                            rng.next(5) as i32 - 4
+                        };
+                    }
+                }
+                SwParams {
+                    score_matrix: Some(m),
+                    ..p
+                }
+            } else {
+                p
+            };
+            // 参考较长时会保存多个检查点，回溯框从中间的检查点列开始
+            let ref_len = rng.next(400);
+            let reference = rng.seq(ref_len);
+            let query = if reference.len() > 10 && rng.next(4) != 0 {
+                let a = rng.next(reference.len() / 2);
+                let b = a + rng.next(reference.len() - a);
+                mutate(&mut rng, &reference[a..b])
+            } else {
+                let len = rng.next(90);
+                rng.seq(len)
+            };
+            assert_eq!(
+                banded_sw_simd(&query, &reference, p),
+                scalar(&query, &reference, p),
+                "case {} {:?}\nq={}\nr={}",
+                case,
+                p,
+                String::from_utf8_lossy(&query),
+                String::from_utf8_lossy(&reference)
+            );
+        }
+    }
+
+    #[test]
+    fn sw_simd_handles_gaps_crossing_lane_boundaries() {
+        // 长插入跨越多个条带段，需要 lazy-F 循环才能补齐
+        let reference = b"ACGTTGCAACGGTACCGATTACGGATCCATGCAGTCAGGTACCA".to_vec();
+        let mut query = reference[..20].to_vec();
+        query.extend_from_slice(b"CCCCCCCCCCCC");
+        query.extend_from_slice(&reference[20..]);
+        let p = SwParams {
+            match_score: 2,
+            mismatch_penalty: 4,
+            gap_open: 1,
+            gap_extend: 1,
+            band_width: 20,
+            diagonal_offset: 0,
+            score_matrix: None,
+            mode: SwMode::Local,
+            iupac: false,
+            n_penalty: 0,
+        };
+        let res = banded_sw_simd(&query, &reference, p);
+        assert_eq!(res, scalar(&query, &reference, p));
+        assert_eq!(res.cigar, "20M12I24M");
+    }
+
+    #[test]
+    fn sw_simd_traceback_box_starts_at_checkpoint_before_alignment() {
+        // 比对位于长参考的末端：回溯框从起点前最近的检查点列开始，而不是第 0 列
+        let reference = random_dna(11, 3000);
+        let query = reference[2500..2600].to_vec();
+        let p = SwParams::default().anchored(0, 2500);
+        let (best, qe, re, checkpoints) = striped_best_cell(&query, &reference, &p, Some(64));
+        assert_eq!((qe, re), (100, 2600));
+        let left = checkpoints.iter().rev().find(|c| c.col <= 2500).unwrap();
+        assert_eq!(left.col, 2496);
+        let (qs, rs, ops) = box_traceback(&query, &reference, &p, left, qe, re).unwrap();
+        assert_eq!((qs, rs, ops_to_cigar(&ops)), (0, 2500, "100M".to_string()));
+        assert_eq!(banded_sw_simd(&query, &reference, p), scalar(&query, &reference, p));
+        assert!(best > 0);
+    }
+
+    #[test]
+    fn sw_simd_empty_inputs() {
+        let p = SwParams::default();
+        assert_eq!(banded_sw_simd(b"", b"ACGT", p), scalar(b"", b"ACGT", p));
+        assert_eq!(banded_sw_simd(b"ACGT", b"", p), scalar(b"ACGT", b"", p));
+        assert_eq!(banded_sw_simd(b"AAAA", b"CCCC", p).score, 0);
+        let glocal = SwParams {
+            mode: SwMode::QueryGlobal,
+            ..p
+        };
+        assert_eq!(
+            banded_sw_simd(b"ACGA", b"TTACGTT", glocal),
+            banded_sw(b"ACGA", b"TTACGTT", glocal)
+        );
+    }
+}
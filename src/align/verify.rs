@@ -0,0 +1,98 @@
+use crate::index::fm::FMIndex;
+use crate::io::sam::{SamRecord, TagValue};
+use crate::util::dna;
+
+use super::candidate::cigar_ops_ref_length;
+use super::sw::parse_cigar;
+
+/// 记录的 NM 与重算的编辑距离不一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NmDiscrepancy {
+    /// 记录中的 `NM:i`
+    pub reported: u32,
+    /// 比对部分 read 与参考区间的编辑距离
+    pub recomputed: u32,
+}
+
+/// 重算已比对记录的编辑距离：SEQ 去掉软剪切后的部分与 CIGAR 覆盖的参考区间比较。
+///
+/// 未比对、contig 不在索引中、CIGAR 为 `*` 或参考区间越界时返回 `None`。
+pub fn recompute_edit_distance(fm: &FMIndex, record: &SamRecord) -> Option<u32> {
+    if record.is_unmapped() || record.cigar == "*" || record.pos == 0 {
+        return None;
+    }
+    let contig = fm.contigs.iter().find(|c| c.name == record.rname)?;
+    let ops = parse_cigar(&record.cigar);
+    let ref_len = cigar_ops_ref_length(&ops);
+    let start = record.pos as usize - 1;
+    if start + ref_len > contig.len as usize {
+        return None;
+    }
+    let lead = match ops.first() {
+        Some(&('S', len)) => len,
+        _ => 0,
+    };
+    let trail = match ops.last() {
+        Some(&('S', len)) if ops.len() > 1 => len,
+        _ => 0,
+    };
+    let seq = record.seq.as_bytes();
+    let aligned = seq.get(lead..seq.len().checked_sub(trail)?)?;
+    let offset = contig.offset as usize + start;
+    let reference = fm.decode_text(offset, offset + ref_len);
+    Some(dna::edit_distance(&dna::normalize_seq(aligned), &reference) as u32)
+}
+
+/// 检查记录的 `NM` 是否可信：低于重算的编辑距离（不可能的比对）或比它多出 `tolerance` 以上时返回差异。
+///
+/// 仿射罚分下的最优比对可能略多于最少编辑次数，因此允许 NM 在 `tolerance` 内偏大；
+/// 无 `NM` 标签或无法重算的记录不检查。
+pub fn verify_nm(fm: &FMIndex, record: &SamRecord, tolerance: u32) -> Option<NmDiscrepancy> {
+    let reported = match record.tag("NM") {
+        Some(&TagValue::Int(nm)) => u32::try_from(nm).ok()?,
+        _ => return None,
+    };
+    let recomputed = recompute_edit_distance(fm, record)?;
+    (reported < recomputed || reported - recomputed > tolerance).then_some(NmDiscrepancy { reported, recomputed })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::align::{align_read_records, AlignOpt};
+    use crate::io::fastq::FastqRecord;
+    use crate::testutil::{build_test_fm, random_dna};
+
+    #[test]
+    fn corrupted_nm_is_flagged() {
+        let reference = random_dna(5, 200);
+        let fm = build_test_fm(&reference);
+        let mut seq = reference[40..100].to_vec();
+        seq[30] = if seq[30] == b'A' { b'C' } else { b'A' };
+        let rec = FastqRecord {
+            id: "r".to_string(),
+            desc: None,
+            qual: vec![b'I'; seq.len()],
+            seq,
+        };
+        let mut record = align_read_records(&fm, &rec, &AlignOpt::default()).remove(0);
+        assert_eq!(record.tag("NM"), Some(&TagValue::Int(1)));
+        assert_eq!(recompute_edit_distance(&fm, &record), Some(1));
+        assert_eq!(verify_nm(&fm, &record, 0), None);
+
+        for (corrupt, tolerance) in [(0, 2), (4, 2)] {
+            let nm = record.tags.iter_mut().find(|(t, _)| t == "NM").unwrap();
+            nm.1 = TagValue::Int(corrupt);
+            assert_eq!(
+                verify_nm(&fm, &record, tolerance),
+                Some(NmDiscrepancy {
+                    reported: corrupt as u32,
+                    recomputed: 1
+                })
+            );
+        }
+        // 容差内的偏大不报告
+        assert_eq!(verify_nm(&fm, &record, 3), None);
+        assert_eq!(verify_nm(&fm, &SamRecord::unmapped("u", "ACGT", "IIII"), 0), None);
+    }
+}
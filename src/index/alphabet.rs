@@ -0,0 +1,171 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::util::dna;
+
+/// FM 索引使用的字母表描述：哨兵编码、字母表大小以及 符号 ↔ 字节 映射。
+///
+/// 随索引一起写入磁盘，使 `backward_search`、文本解码等路径不依赖
+/// `util::dna` 中的 DNA 全局常量，从而可以用同一套索引处理蛋白或自定义字母表。
+///
+/// 编码 `code` 的取值范围为 `[0, sigma)`，其中 `sentinel` 为 contig 分隔符，
+/// 不会出现在 query 中。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AlphabetSpec {
+    /// 哨兵（`$`，contig 分隔符）的编码
+    pub sentinel: u8,
+    /// 解码表：`symbols[code]` 为该编码对应的字节，长度即 sigma
+    pub symbols: Vec<u8>,
+    /// 额外的 字节 → 编码 映射（如 DNA 中 `U` → `T`）
+    pub aliases: Vec<(u8, u8)>,
+    /// 未列出字节的回退编码（DNA 为 `N`）；`None` 表示拒绝未知字节
+    pub unknown: Option<u8>,
+    /// 编码时是否忽略大小写
+    pub case_insensitive: bool,
+}
+
+impl AlphabetSpec {
+    /// 默认 DNA 字母表：`{0:$, 1:A, 2:C, 3:G, 4:T, 5:N}`，与 `util::dna` 一致。
+    pub fn dna() -> Self {
+        Self {
+            sentinel: 0,
+            symbols: b"$ACGTN".to_vec(),
+            aliases: vec![(b'U', dna::to_alphabet(b'U'))],
+            unknown: Some(dna::to_alphabet(b'N')),
+            case_insensitive: true,
+        }
+    }
+
+    /// 自定义字母表：哨兵编码为 0，`symbols` 依次编码为 `1..=symbols.len()`。
+    ///
+    /// 区分大小写、拒绝未列出的字节；`symbols` 不能为空、不能重复，也不能包含 `$`。
+    pub fn new(symbols: &[u8]) -> Result<Self> {
+        if symbols.is_empty() {
+            return Err(anyhow!("alphabet must contain at least one symbol"));
+        }
+        if symbols.len() > u8::MAX as usize - 1 {
+            return Err(anyhow!("alphabet has too many symbols ({})", symbols.len()));
+        }
+        let mut table = Vec::with_capacity(symbols.len() + 1);
+        table.push(b'$');
+        table.extend_from_slice(symbols);
+        let spec = Self {
+            sentinel: 0,
+            symbols: table,
+            aliases: Vec::new(),
+            unknown: None,
+            case_insensitive: false,
+        };
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    /// 纯数值字母表：编码即字节本身（用于直接以数值文本构建的索引）。
+    pub fn numeric(sigma: u8) -> Self {
+        Self {
+            sentinel: 0,
+            symbols: (0..sigma).collect(),
+            aliases: Vec::new(),
+            unknown: None,
+            case_insensitive: false,
+        }
+    }
+
+    /// 按 sigma 推断字母表：等于 DNA 字母表大小时为 [`AlphabetSpec::dna`]，否则为数值字母表。
+    pub fn for_sigma(sigma: u8) -> Self {
+        if sigma as usize == dna::SIGMA {
+            Self::dna()
+        } else {
+            Self::numeric(sigma)
+        }
+    }
+
+    /// 字母表大小（含哨兵）
+    #[inline]
+    pub fn sigma(&self) -> u8 {
+        self.symbols.len() as u8
+    }
+
+    /// 将单个字节编码为字母表编码；哨兵或无法识别的字节返回 `None`（若无回退编码）。
+    pub fn encode(&self, b: u8) -> Option<u8> {
+        let key = if self.case_insensitive {
+            b.to_ascii_uppercase()
+        } else {
+            b
+        };
+        let found = self
+            .symbols
+            .iter()
+            .position(|&s| s == key)
+            .map(|i| i as u8)
+            .filter(|&code| code != self.sentinel)
+            .or_else(|| self.aliases.iter().find(|&&(from, _)| from == key).map(|&(_, code)| code));
+        found.or(self.unknown)
+    }
+
+    /// 将字节序列编码；任一字节无法编码时返回 `None`。
+    pub fn encode_seq(&self, seq: &[u8]) -> Option<Vec<u8>> {
+        seq.iter().map(|&b| self.encode(b)).collect()
+    }
+
+    /// 将编码解码回字节；越界编码返回回退编码对应的字节（若有），否则返回 `?`。
+    #[inline]
+    pub fn decode(&self, code: u8) -> u8 {
+        match self.symbols.get(code as usize) {
+            Some(&b) => b,
+            None => self.unknown.and_then(|u| self.symbols.get(u as usize).copied()).unwrap_or(b'?'),
+        }
+    }
+
+    /// 检查字母表自身的一致性
+    pub fn validate(&self) -> Result<()> {
+        let sigma = self.symbols.len();
+        if sigma < 2 || sigma > u8::MAX as usize {
+            return Err(anyhow!("alphabet size must be between 2 and 255, got {}", sigma));
+        }
+        if self.sentinel as usize >= sigma {
+            return Err(anyhow!("alphabet sentinel code {} out of range", self.sentinel));
+        }
+        for (i, a) in self.symbols.iter().enumerate() {
+            if self.symbols[..i].contains(a) {
+                return Err(anyhow!("alphabet symbol 0x{:02X} is listed twice", a));
+            }
+        }
+        let in_range = |code: u8| (code as usize) < sigma && code != self.sentinel;
+        if self.aliases.iter().any(|&(_, code)| !in_range(code)) || self.unknown.is_some_and(|u| !in_range(u)) {
+            return Err(anyhow!("alphabet alias or fallback code out of range"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dna_spec_matches_dna_module() {
+        let spec = AlphabetSpec::dna();
+        assert_eq!(spec.sigma() as usize, dna::SIGMA);
+        for &b in b"ACGTUNacgtunX-" {
+            assert_eq!(spec.encode(b), Some(dna::to_alphabet(b)), "byte {}", b as char);
+        }
+        for code in 1..dna::SIGMA as u8 {
+            assert_eq!(spec.decode(code), dna::from_alphabet(code));
+        }
+        assert!(spec.validate().is_ok());
+    }
+
+    #[test]
+    fn custom_spec_rejects_unknown_and_duplicates() {
+        let spec = AlphabetSpec::new(b"HPEW").unwrap();
+        assert_eq!(spec.sigma(), 5);
+        assert_eq!(spec.encode_seq(b"HPEW"), Some(vec![1, 2, 3, 4]));
+        assert_eq!(spec.encode(b'h'), None);
+        assert_eq!(spec.encode(b'$'), None);
+        assert_eq!(spec.decode(3), b'E');
+        assert!(AlphabetSpec::new(b"").is_err());
+        assert!(AlphabetSpec::new(b"ABA").is_err());
+        assert!(AlphabetSpec::new(b"A$").is_err());
+    }
+}
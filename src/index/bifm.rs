@@ -0,0 +1,184 @@
+use super::fm::FMIndex;
+use super::{bwt, sa};
+
+/// 双向 SA 区间（对应 BWA 的 `bwtintv_t`）：
+/// - `k`：模式串 P 在正向索引中的 SA 区间起点
+/// - `l`：反向串 rev(P) 在反向索引中的 SA 区间起点
+/// - `s`：区间大小（两个方向相同，即 P 的出现次数）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BiInterval {
+    pub k: usize,
+    pub l: usize,
+    pub s: usize,
+}
+
+impl BiInterval {
+    /// 正向索引中的左闭右开区间 `[k, k + s)`
+    #[inline]
+    pub fn forward_range(&self) -> (usize, usize) {
+        (self.k, self.k + self.s)
+    }
+}
+
+/// 双向 FM 索引：同时保存文本与其反向串的 FM 索引，支持在模式串两端扩展。
+///
+/// 反向索引的文本由各 contig 原地反转得到（分隔符位置不变），
+/// 因此 P 在正向文本中的每次出现都与 rev(P) 在反向文本中的一次出现一一对应。
+/// 反向索引只用于计数，不做定位。
+#[derive(Debug)]
+pub struct BiFMIndex {
+    pub fwd: FMIndex,
+    pub rev: FMIndex,
+}
+
+impl BiFMIndex {
+    /// 由正向索引构建反向索引（需重建一次 SA/BWT）
+    pub fn new(fwd: FMIndex) -> Self {
+        let mut text = fwd.text.to_vec();
+        for contig in &fwd.contigs {
+            let start = contig.offset as usize;
+            text[start..start + contig.len as usize].reverse();
+        }
+        let sa_arr = sa::build_sa(&text);
+        let bwt_arr = bwt::build_bwt(&text, &sa_arr);
+        let rev = FMIndex::build_with_alphabet(
+            text,
+            bwt_arr,
+            sa_arr,
+            fwd.contigs.clone(),
+            fwd.alphabet.clone(),
+            fwd.bwt.block(),
+        );
+        Self { fwd, rev }
+    }
+
+    /// 符号能否参与匹配（哨兵与越界符号不能）
+    #[inline]
+    pub fn is_matchable(&self, c: u8) -> bool {
+        c != self.fwd.alphabet.sentinel && c < self.fwd.sigma
+    }
+
+    /// 单字符 `c` 的双向区间；符号不可匹配或不出现时返回 `None`
+    pub fn init(&self, c: u8) -> Option<BiInterval> {
+        if !self.is_matchable(c) {
+            return None;
+        }
+        let (k, end) = self.fwd.rank_range(c, 0, self.fwd.bwt.len());
+        (k < end).then_some(BiInterval { k, l: k, s: end - k })
+    }
+
+    /// 在模式串左端（`forward == false`）或右端（`forward == true`）扩展字符 `c`。
+    ///
+    /// 向左扩展在正向索引上做 rank，同时按字典序累加比 `c` 小的各符号（含哨兵）
+    /// 的区间大小来更新反向区间起点；向右扩展对称地在反向索引上进行。
+    /// 扩展后区间为空时返回 `None`。
+    pub fn bi_extend(&self, iv: BiInterval, c: u8, forward: bool) -> Option<BiInterval> {
+        if !self.is_matchable(c) {
+            return None;
+        }
+        let (idx, start, other) = if forward {
+            (&self.rev, iv.l, iv.k)
+        } else {
+            (&self.fwd, iv.k, iv.l)
+        };
+        let end = start + iv.s;
+        let mut smaller = 0usize;
+        for b in 0..c {
+            smaller += (idx.occ(b, end) - idx.occ(b, start)) as usize;
+        }
+        let (nl, nr) = idx.rank_range(c, start, end);
+        if nl >= nr {
+            return None;
+        }
+        let s = nr - nl;
+        let other = other + smaller;
+        Some(if forward {
+            BiInterval { k: other, l: nl, s }
+        } else {
+            BiInterval { k: nl, l: other, s }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::fm::Contig;
+    use crate::testutil::build_test_fm;
+    use crate::util::dna;
+
+    fn alpha(seq: &[u8]) -> Vec<u8> {
+        seq.iter().map(|&b| dna::to_alphabet(b)).collect()
+    }
+
+    fn two_contig_fm() -> FMIndex {
+        let mut text = alpha(b"ACGTTGCAACGG");
+        text.push(0);
+        text.extend(alpha(b"GGCAACGTTA"));
+        text.push(0);
+        let contigs = vec![
+            Contig {
+                name: "a".to_string(),
+                len: 12,
+                offset: 0,
+            },
+            Contig {
+                name: "b".to_string(),
+                len: 10,
+                offset: 13,
+            },
+        ];
+        let sa_arr = sa::build_sa(&text);
+        let bwt_arr = bwt::build_bwt(&text, &sa_arr);
+        FMIndex::build(text, bwt_arr, sa_arr, contigs, dna::SIGMA as u8, 4)
+    }
+
+    /// 任意扩展顺序得到的区间都应与两个方向的 backward_search 一致
+    fn assert_consistent(bi: &BiFMIndex, pat: &[u8]) {
+        let rev_pat: Vec<u8> = pat.iter().rev().copied().collect();
+        let expect_fwd = bi.fwd.backward_search(pat);
+        let expect_rev = bi.rev.backward_search(&rev_pat);
+        for mid in 0..pat.len() {
+            let mut iv = bi.init(pat[mid]);
+            for &c in &pat[mid + 1..] {
+                iv = iv.and_then(|iv| bi.bi_extend(iv, c, true));
+            }
+            for &c in pat[..mid].iter().rev() {
+                iv = iv.and_then(|iv| bi.bi_extend(iv, c, false));
+            }
+            match (iv, expect_fwd, expect_rev) {
+                (Some(iv), Some(f), Some(r)) => {
+                    assert_eq!(iv.forward_range(), f, "{:?} mid {}", pat, mid);
+                    assert_eq!((iv.l, iv.l + iv.s), r, "{:?} mid {}", pat, mid);
+                }
+                (None, None, None) => {}
+                other => panic!("{:?} mid {}: {:?}", pat, mid, other),
+            }
+        }
+    }
+
+    #[test]
+    fn bi_extend_matches_backward_search_single_contig() {
+        let bi = BiFMIndex::new(build_test_fm(b"ACGTACGTTAGCCGATACGTAGGA"));
+        for pat in [&b"ACGT"[..], b"CGTA", b"TAG", b"GGA", b"ACGTTAGC", b"TTT", b"A"] {
+            assert_consistent(&bi, &alpha(pat));
+        }
+    }
+
+    #[test]
+    fn bi_extend_matches_backward_search_across_contigs() {
+        let bi = BiFMIndex::new(two_contig_fm());
+        for pat in [&b"CAACG"[..], b"ACG", b"GG", b"TTA", b"ACGG", b"CGTTG", b"GGCA"] {
+            assert_consistent(&bi, &alpha(pat));
+        }
+    }
+
+    #[test]
+    fn bi_extend_rejects_unmatchable_symbols() {
+        let bi = BiFMIndex::new(build_test_fm(b"ACGTACGT"));
+        assert!(bi.init(0).is_none());
+        let iv = bi.init(dna::to_alphabet(b'A')).unwrap();
+        assert!(bi.bi_extend(iv, 0, true).is_none());
+        assert!(bi.bi_extend(iv, dna::SIGMA as u8, false).is_none());
+    }
+}
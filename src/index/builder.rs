@@ -4,8 +4,12 @@ use std::path::Path;
 
 use anyhow::Result;
 
-use super::{bwt, fm, sa};
-use crate::io::fasta::FastaReader;
+use super::alphabet::AlphabetSpec;
+use super::packed::PackedSeq;
+use super::rank::RankBwt;
+use super::{fm, sa};
+use crate::io::fasta::{FastaReader, FastaRecord};
+use crate::io::twobit;
 use crate::util::dna;
 
 /// Result of building an FM index from FASTA
@@ -16,21 +20,234 @@ pub struct IndexBuildResult {
     pub total_len: usize,
 }
 
+/// Projected resource usage of an index build, from a FASTA pass only
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEstimate {
+    pub n_seqs: usize,
+    pub total_len: usize,
+    /// Indexed text length (bases plus one sentinel per contig)
+    pub text_len: usize,
+    pub block_size: usize,
+    pub text_bytes: u64,
+    pub bwt_bytes: u64,
+    pub sa_bytes: u64,
+    pub occ_bytes: u64,
+    /// Approximate size of the uncompressed `.fm` file
+    pub disk_bytes: u64,
+    /// Approximate peak memory while building (SA construction working set)
+    pub peak_build_bytes: u64,
+}
+
+impl IndexEstimate {
+    /// Estimate sizes for a reference of `n_seqs` contigs, `total_len` bases and
+    /// `name_bytes` bytes of contig names, with Occ sampled every `block_size` rows
+    /// and one SA entry kept every `sa_sample_rate` rows (`<= 1` keeps the full SA).
+    pub fn new(n_seqs: usize, total_len: usize, name_bytes: usize, block_size: usize, sa_sample_rate: u32) -> Self {
+        let sigma = dna::SIGMA as u64;
+        let text_len = total_len + n_seqs;
+        let n = text_len as u64;
+        // packed text: 2-bit bases and a special-position bitmap in u64 words,
+        // plus one (start, len, code) run per sentinel
+        let text_bytes = 8 + 8 * ((n + 31) / 32) + 8 + 8 * ((n + 63) / 64) + 8 + 9 * n_seqs as u64;
+        // bit-plane packed BWT plus its two-level rank samples
+        let (bwt_bytes, occ_bytes) = RankBwt::byte_sizes(text_len, dna::SIGMA as u8, block_size);
+        let sa_rate = sa_sample_rate.max(1) as u64;
+        let sa_bytes = 4 * ((n + sa_rate - 1) / sa_rate);
+        // bincode: fixed header fields, a u64 length per Vec/String, contig records
+        let contig_bytes = n_seqs as u64 * (8 + 4 + 4) + name_bytes as u64;
+        // rank BWT: len, sigma, bit width, block sizes and three Vec lengths
+        let rank_header = 8 + 1 + 1 + 4 + 4 + 3 * 8;
+        let disk_bytes = 8
+            + 4
+            + 1
+            + 4
+            + 3 * 8
+            + 4 * sigma
+            + text_bytes
+            + rank_header
+            + bwt_bytes
+            + sa_bytes
+            + occ_bytes
+            + contig_bytes;
+        // the text stays 2-bit packed while building: SA-IS keeps a u32 copy of the
+        // symbols, usize suffixes and type flags, and writes a byte-per-row BWT and
+        // the sampled SA as it induces; the byte BWT then becomes the rank dictionary.
+        let sa_working = n * (4 + std::mem::size_of::<usize>() as u64 + 1);
+        let peak_build_bytes = text_bytes + n + sa_bytes + sa_working.max(bwt_bytes + occ_bytes);
+        Self {
+            n_seqs,
+            total_len,
+            text_len,
+            block_size,
+            text_bytes,
+            bwt_bytes,
+            sa_bytes,
+            occ_bytes,
+            disk_bytes,
+            peak_build_bytes,
+        }
+    }
+}
+
+/// Numeric reference text (2-bit packed as contigs stream in) and contig table read from FASTA
+struct ReferenceText {
+    text: PackedSeq,
+    contigs: Vec<fm::Contig>,
+    /// Original bytes the collapsed alphabet cannot reproduce (lossless builds only)
+    raw_patches: Option<Vec<(u32, Vec<u8>)>>,
+    n_seqs: usize,
+    total_len: usize,
+}
+
+/// FM index construction parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildOpt {
+    /// Occ sampling interval in BWT rows
+    pub block_size: usize,
+    /// Keep one SA entry every this many rows (`1` keeps the full SA)
+    pub sa_sample_rate: u32,
+    /// Also store the reference bytes the collapsed alphabet cannot reproduce
+    pub lossless: bool,
+}
+
+impl Default for BuildOpt {
+    fn default() -> Self {
+        Self {
+            block_size: 512,
+            sa_sample_rate: 1,
+            lossless: false,
+        }
+    }
+}
+
+/// Build an FM index from a buffered FASTA reader, streaming contigs into a
+/// 2-bit packed text.
+///
+/// The text is never held one byte per symbol and the full suffix array is never
+/// materialised: SA-IS emits the BWT and the sampled SA while inducing, so peak
+/// memory is the packed text plus the suffix-array construction working set (see
+/// [`IndexEstimate::peak_build_bytes`]).
+pub fn build_from_fasta<R: BufRead>(reader: R, opt: &BuildOpt) -> Result<IndexBuildResult> {
+    build_index(reader, opt.block_size, opt.sa_sample_rate, opt.lossless)
+}
+
 /// Build an FM index from a buffered FASTA reader
 pub fn build_fm_index<R: BufRead>(reader: R, block_size: usize) -> Result<IndexBuildResult> {
+    build_fm_index_sampled(reader, block_size, 1)
+}
+
+/// Build an FM index keeping one SA entry every `sa_sample_rate` rows
+/// (`1` keeps the full SA); other positions are recovered by LF-mapping.
+pub fn build_fm_index_sampled<R: BufRead>(
+    reader: R,
+    block_size: usize,
+    sa_sample_rate: u32,
+) -> Result<IndexBuildResult> {
+    build_index(reader, block_size, sa_sample_rate, false)
+}
+
+/// Like [`build_fm_index_sampled`], but also stores every reference byte the
+/// collapsed alphabet cannot reproduce (IUPAC codes, lowercase, `U`), so
+/// [`fm::FMIndex::decode_text_lossless`] returns the FASTA sequence exactly.
+pub fn build_fm_index_lossless<R: BufRead>(
+    reader: R,
+    block_size: usize,
+    sa_sample_rate: u32,
+) -> Result<IndexBuildResult> {
+    build_index(reader, block_size, sa_sample_rate, true)
+}
+
+fn build_index<R: BufRead>(
+    reader: R,
+    block_size: usize,
+    sa_sample_rate: u32,
+    lossless: bool,
+) -> Result<IndexBuildResult> {
+    if block_size == 0 {
+        anyhow::bail!("block size must be greater than zero");
+    }
+    Ok(index_from_reference(
+        read_reference(reader, lossless)?,
+        block_size,
+        sa_sample_rate,
+    ))
+}
+
+fn index_from_reference(reference: ReferenceText, block_size: usize, sa_sample_rate: u32) -> IndexBuildResult {
+    let ReferenceText {
+        text,
+        contigs,
+        raw_patches,
+        n_seqs,
+        total_len,
+    } = reference;
+
+    // the text stays packed throughout: SA-IS reads it symbol by symbol and writes
+    // the BWT and sampled SA rows during its last induce pass, so the full SA is
+    // never materialised
+    let induced = sa::bwt_and_sparse_sa_from_codes(text.len(), text.iter(), sa_sample_rate);
+    let mut fm =
+        fm::FMIndex::build_from_induced(text, induced, sa_sample_rate, contigs, AlphabetSpec::dna(), block_size);
+    if let Some(patches) = raw_patches {
+        fm.set_raw_patches(patches);
+    }
+
+    IndexBuildResult { fm, n_seqs, total_len }
+}
+
+/// Estimate index sizes from a buffered FASTA reader without building the index
+pub fn estimate_index<R: BufRead>(reader: R, block_size: usize, sa_sample_rate: u32) -> Result<IndexEstimate> {
     if block_size == 0 {
         anyhow::bail!("block size must be greater than zero");
     }
+    Ok(estimate_from_reference(
+        &read_reference(reader, false)?,
+        block_size,
+        sa_sample_rate,
+    ))
+}
+
+fn estimate_from_reference(reference: &ReferenceText, block_size: usize, sa_sample_rate: u32) -> IndexEstimate {
+    let name_bytes = reference.contigs.iter().map(|c| c.name.len()).sum();
+    IndexEstimate::new(
+        reference.n_seqs,
+        reference.total_len,
+        name_bytes,
+        block_size,
+        sa_sample_rate,
+    )
+}
 
+/// Read FASTA records into the numeric text (contigs separated by sentinels);
+/// with `lossless`, also collect the raw bytes lost by the collapsed alphabet.
+fn read_reference<R: BufRead>(reader: R, lossless: bool) -> Result<ReferenceText> {
     let mut fasta = FastaReader::new(reader);
+    if lossless {
+        fasta = fasta.keep_case();
+    }
+    collect_reference(fasta.map(|r| r.map_err(Into::into)), lossless)
+}
 
+/// Read a reference file by path: `.2bit` files are decoded directly, anything
+/// else is parsed as (optionally gzipped) FASTA.
+fn read_reference_path(path: &Path, lossless: bool) -> Result<ReferenceText> {
+    if twobit::is_2bit_path(path) {
+        collect_reference(twobit::read_2bit_file(path)?.into_iter().map(Ok), lossless)
+    } else {
+        read_reference(crate::io::gzip::open_maybe_gzip(path)?, lossless)
+    }
+}
+
+fn collect_reference(records: impl Iterator<Item = Result<FastaRecord>>, lossless: bool) -> Result<ReferenceText> {
     let mut n_seqs = 0usize;
     let mut total_len = 0usize;
-    let mut text: Vec<u8> = Vec::new();
+    let mut text = PackedSeq::default();
     let mut contigs: Vec<fm::Contig> = Vec::new();
+    let mut raw_patches: Vec<(u32, Vec<u8>)> = Vec::new();
     let mut seen_names: HashSet<String> = HashSet::new();
 
-    while let Some(rec) = fasta.next_record()? {
+    for rec in records {
+        let rec = rec?;
         if rec.seq.is_empty() {
             anyhow::bail!("FASTA sequence '{}' is empty", rec.id);
         }
@@ -39,11 +256,16 @@ pub fn build_fm_index<R: BufRead>(reader: R, block_size: usize) -> Result<IndexB
         }
         n_seqs += 1;
         total_len += rec.seq.len();
-        let norm = dna::normalize_seq(&rec.seq);
         let start =
             u32::try_from(text.len()).map_err(|_| anyhow::anyhow!("reference text exceeds u32 address space"))?;
-        for b in norm {
-            text.push(dna::to_alphabet(b));
+        if lossless {
+            let (codes, patches) = dna::encode_lossless(&rec.seq);
+            codes.into_iter().for_each(|c| text.push(c));
+            raw_patches.extend(patches.into_iter().map(|(p, bytes)| (start + p as u32, bytes)));
+        } else {
+            for b in dna::normalize_seq(&rec.seq) {
+                text.push(dna::to_alphabet(b));
+            }
         }
         let text_len_u32 =
             u32::try_from(text.len()).map_err(|_| anyhow::anyhow!("reference text exceeds u32 address space"))?;
@@ -64,19 +286,68 @@ pub fn build_fm_index<R: BufRead>(reader: R, block_size: usize) -> Result<IndexB
         anyhow::bail!("FASTA contains only empty sequences");
     }
 
-    let sa_arr = sa::build_sa(&text);
-    let bwt_arr = bwt::build_bwt(&text, &sa_arr);
-    let fm = fm::FMIndex::build(text, bwt_arr, sa_arr, contigs, dna::SIGMA as u8, block_size);
-
-    Ok(IndexBuildResult { fm, n_seqs, total_len })
+    Ok(ReferenceText {
+        text,
+        contigs,
+        raw_patches: lossless.then_some(raw_patches),
+        n_seqs,
+        total_len,
+    })
 }
 
-/// Convenience: build FM index from a FASTA file path
+/// Convenience: build FM index from a FASTA (or `.2bit`) file path
 pub fn build_fm_from_fasta(path: impl AsRef<Path>, block_size: usize) -> Result<IndexBuildResult> {
-    let path = path.as_ref();
-    let fh = std::fs::File::open(path).map_err(|e| anyhow::anyhow!("cannot open FASTA '{}': {}", path.display(), e))?;
-    let buf = std::io::BufReader::new(fh);
-    build_fm_index(buf, block_size)
+    build_fm_from_fasta_sampled(path, block_size, 1)
+}
+
+/// Convenience: build FM index with a sampled SA from a FASTA (or `.2bit`) file path
+pub fn build_fm_from_fasta_sampled(
+    path: impl AsRef<Path>,
+    block_size: usize,
+    sa_sample_rate: u32,
+) -> Result<IndexBuildResult> {
+    build_index_from_path(path.as_ref(), block_size, sa_sample_rate, false)
+}
+
+/// Convenience: build a lossless FM index from a FASTA (or `.2bit`) file path
+pub fn build_fm_from_fasta_lossless(
+    path: impl AsRef<Path>,
+    block_size: usize,
+    sa_sample_rate: u32,
+) -> Result<IndexBuildResult> {
+    build_index_from_path(path.as_ref(), block_size, sa_sample_rate, true)
+}
+
+fn build_index_from_path(
+    path: &Path,
+    block_size: usize,
+    sa_sample_rate: u32,
+    lossless: bool,
+) -> Result<IndexBuildResult> {
+    if block_size == 0 {
+        anyhow::bail!("block size must be greater than zero");
+    }
+    Ok(index_from_reference(
+        read_reference_path(path, lossless)?,
+        block_size,
+        sa_sample_rate,
+    ))
+}
+
+/// Convenience: estimate index sizes from a FASTA (or `.2bit`) file path
+pub fn estimate_index_from_fasta(
+    path: impl AsRef<Path>,
+    block_size: usize,
+    sa_sample_rate: u32,
+) -> Result<IndexEstimate> {
+    if block_size == 0 {
+        anyhow::bail!("block size must be greater than zero");
+    }
+    Ok(estimate_from_reference(
+        &read_reference_path(path.as_ref(), false)?,
+        block_size,
+        sa_sample_rate,
+    ))
 }
 
 #[cfg(test)]
@@ -141,7 +412,12 @@ mod tests {
         let fm = &result.fm;
         let offset = fm.contigs[0].offset as usize;
         let len = fm.contigs[0].len as usize;
-        let recovered: Vec<u8> = fm.text[offset..offset + len].iter().map(|&c| dna::from_alphabet(c)).collect();
+        let recovered: Vec<u8> = fm
+            .text
+            .unpack(offset, offset + len)
+            .into_iter()
+            .map(dna::from_alphabet)
+            .collect();
         assert_eq!(recovered, b"ACGTN");
     }
 
@@ -188,4 +464,155 @@ mod tests {
         let err = build_fm_index(cursor, 0).unwrap_err();
         assert!(err.to_string().contains("block size"));
     }
+
+    #[test]
+    fn estimate_matches_built_index_sizes() {
+        let data = b">c1\nACGTACGTAC\n>c2\nGGCCTTAA\n";
+        let est = estimate_index(Cursor::new(&data[..]), 4, 1).unwrap();
+        let built = build_fm_index(Cursor::new(&data[..]), 4).unwrap();
+        assert_eq!(est.n_seqs, 2);
+        assert_eq!(est.total_len, 18);
+        assert_eq!(est.text_len, built.fm.text.len());
+        assert_eq!(est.sa_bytes, 4 * built.fm.sa.len() as u64);
+        let (bwt_bytes, occ_bytes) = RankBwt::byte_sizes(built.fm.bwt.len(), built.fm.sigma, built.fm.bwt.block());
+        assert_eq!((est.bwt_bytes, est.occ_bytes), (bwt_bytes, occ_bytes));
+
+        let on_disk = bincode::serialize(&built.fm).unwrap().len() as u64;
+        let diff = est.disk_bytes.abs_diff(on_disk);
+        assert!(diff * 10 < on_disk, "estimate {} vs actual {}", est.disk_bytes, on_disk);
+        assert!(est.peak_build_bytes > est.text_bytes + est.sa_bytes + est.occ_bytes);
+        assert!(estimate_index(Cursor::new(&data[..]), 0, 1).is_err());
+
+        let sparse = estimate_index(Cursor::new(&data[..]), 4, 8).unwrap();
+        let built = build_fm_index_sampled(Cursor::new(&data[..]), 4, 8).unwrap();
+        assert_eq!(sparse.sa_bytes, 4 * built.fm.sa.len() as u64);
+        assert!(sparse.disk_bytes < est.disk_bytes);
+    }
+
+    #[test]
+    fn sampled_build_locates_like_full_sa() {
+        let data = b">c1\nGATTACAGGCTTCAAGTCCGATGCATTG\n>c2\nCATCGGATCCTAGCAATGGTACCG\n";
+        let full = build_fm_index(Cursor::new(&data[..]), 4).unwrap().fm;
+        let sparse = build_fm_index_sampled(Cursor::new(&data[..]), 4, 5).unwrap().fm;
+        assert_eq!(sparse.sa_sample_rate, 5);
+        assert_eq!(sparse.sa.len(), (full.sa.len() + 4) / 5);
+        let n = full.sa.len();
+        assert_eq!(sparse.locate(0, n), full.locate(0, n));
+
+        // Loading re-derives the sentinel rows
+        let path = std::env::temp_dir().join(format!("bwa_rust_sparse_sa_{}.fm", std::process::id()));
+        sparse.save_to_file(&path).unwrap();
+        let loaded = fm::FMIndex::load_from_file(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(loaded.locate(0, n), full.locate(0, n));
+    }
+
+    #[test]
+    fn induced_build_matches_full_sa_then_sparsify() {
+        let mut fasta = Vec::new();
+        for (i, seed) in [3u32, 5, 8].into_iter().enumerate() {
+            fasta.extend(format!(">c{}\n", i).bytes());
+            fasta.extend(crate::testutil::random_dna(seed, 2000));
+            fasta.push(b'\n');
+        }
+        let reference = read_reference(Cursor::new(&fasta[..]), false).unwrap();
+        let text = reference.text.clone();
+        let full_sa = sa::build_sa_packed(&text);
+        for rate in [1u32, 4] {
+            let mut expected = fm::FMIndex::build_from_packed(
+                text.clone(),
+                full_sa.clone(),
+                reference.contigs.clone(),
+                AlphabetSpec::dna(),
+                16,
+            );
+            expected.sparsify_sa(rate);
+            let built = build_fm_index_sampled(Cursor::new(&fasta[..]), 16, rate).unwrap().fm;
+            assert_eq!(built.sa, expected.sa);
+            assert_eq!(built.sa_sample_rate, expected.sa_sample_rate);
+            assert_eq!(built.c, expected.c);
+            let n = full_sa.len();
+            assert!((0..n).all(|i| built.bwt.get(i) == expected.bwt.get(i)));
+            assert_eq!(built.locate(0, n), full_sa);
+        }
+    }
+
+    #[test]
+    fn lossless_build_recovers_iupac_reference() {
+        let data = b">chr1\nACGTRYacgtNNKM\nSWBDHV\n>chr2\nuuACGT\n>chr3\nACGT\n";
+        let result = build_fm_index_lossless(Cursor::new(&data[..]), 4, 3).unwrap();
+        let path = std::env::temp_dir().join(format!("bwa_rust_lossless_{}.fm", std::process::id()));
+        result.fm.save_to_file(&path).unwrap();
+        let fm = fm::FMIndex::load_from_file(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert!(fm.is_lossless());
+        let expected: [&[u8]; 3] = [b"ACGTRYacgtNNKMSWBDHV", b"uuACGT", b"ACGT"];
+        for (contig, want) in fm.contigs.iter().zip(expected) {
+            let (start, end) = (contig.offset as usize, (contig.offset + contig.len) as usize);
+            assert_eq!(fm.decode_text_lossless(start, end), want.to_vec());
+        }
+        // 跨片段边界的部分区间
+        assert_eq!(fm.decode_text_lossless(5, 8), b"Yac".to_vec());
+        // FM 搜索仍使用折叠字母表
+        let pat: Vec<u8> = b"ACGTNN".iter().map(|&b| dna::to_alphabet(b)).collect();
+        assert!(fm.backward_search(&pat).is_some());
+    }
+
+    #[test]
+    fn default_build_is_not_lossless() {
+        let result = build_fm_index(Cursor::new(&b">chr1\nACGTRY\n"[..]), 4).unwrap();
+        assert!(!result.fm.is_lossless());
+        assert_eq!(result.fm.decode_text_lossless(0, 6), b"ACGTNN".to_vec());
+    }
+
+    #[test]
+    fn build_from_2bit_path_matches_fasta() {
+        // 单条序列 "ACGTACGT"（T=0 C=1 A=2 G=3），末两位为 N 块
+        let mut blob = Vec::new();
+        for word in [0x1A41_2743u32, 0, 1, 0] {
+            blob.extend_from_slice(&word.to_le_bytes());
+        }
+        blob.extend_from_slice(&[3, b'c', b'h', b'r']);
+        blob.extend_from_slice(&24u32.to_le_bytes());
+        for word in [8u32, 1, 6, 2, 0, 0] {
+            blob.extend_from_slice(&word.to_le_bytes());
+        }
+        blob.extend_from_slice(&[0x9C, 0x9C]);
+        let path = std::env::temp_dir().join(format!("bwa_rust_ref_{}.2bit", std::process::id()));
+        std::fs::write(&path, &blob).unwrap();
+        let result = build_fm_from_fasta(&path, 4);
+        let estimate = estimate_index_from_fasta(&path, 4, 1);
+        std::fs::remove_file(&path).ok();
+
+        let result = result.unwrap();
+        assert_eq!(result.fm.contigs[0].name, "chr");
+        assert_eq!(result.fm.decode_text_lossless(0, 8), b"ACGTACNN".to_vec());
+        assert_eq!(estimate.unwrap().total_len, 8);
+    }
+
+    #[test]
+    fn streamed_build_matches_byte_text_build() {
+        let data = b">c1\nACGTNNNNNACGTTGCA\n>c2\nggccRTTAAC\n";
+        let opt = BuildOpt {
+            block_size: 64,
+            ..BuildOpt::default()
+        };
+        let built = build_from_fasta(Cursor::new(&data[..]), &opt).unwrap();
+        let text = built.fm.text.to_vec();
+        let sa_arr = sa::build_sa(&text);
+        let bwt_arr = crate::index::bwt::build_bwt(&text, &sa_arr);
+        let expected =
+            fm::FMIndex::build_with_alphabet(text, bwt_arr, sa_arr, built.fm.contigs.clone(), AlphabetSpec::dna(), 64);
+        assert_eq!(built.fm.sa, expected.sa);
+        assert_eq!(built.fm.c, expected.c);
+        assert_eq!(built.fm.bwt, expected.bwt);
+        assert_eq!(built.fm.decode_text(0, 4), b"ACGT".to_vec());
+
+        let lossless = BuildOpt { lossless: true, ..opt };
+        let built = build_from_fasta(Cursor::new(&data[..]), &lossless).unwrap();
+        let c2 = &built.fm.contigs[1];
+        let (start, end) = (c2.offset as usize, (c2.offset + c2.len) as usize);
+        assert_eq!(built.fm.decode_text_lossless(start, end), b"ggccRTTAAC".to_vec());
+    }
 }
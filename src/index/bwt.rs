@@ -1,3 +1,5 @@
+use super::sa::bwt_and_sparse_sa_from_codes;
+
 /// 根据后缀数组构建 BWT。
 /// text 为数值化字母表（0..SIGMA），sa 为后缀数组位置。
 #[must_use]
@@ -15,9 +17,61 @@ pub fn build_bwt(text: &[u8], sa: &[u32]) -> Vec<u8> {
     bwt
 }
 
+/// 一次 SA-IS 同时得到 BWT 与每 `sample_rate` 行保留一项的稀疏 SA（采样方式同
+/// [`FMIndex::sparsify_sa`](super::fm::FMIndex::sparsify_sa)），不生成完整的 `u32` 后缀数组：
+/// 最后一轮诱导排序确定每一行时直接写出该行的 BWT 字符，只保留采样行的 SA 值。
+///
+/// 结果与 `build_bwt(text, &build_sa(text))` 及对其每 `sample_rate` 行的采样一致；
+/// `sample_rate <= 1` 时返回完整 SA。
+#[must_use]
+pub fn build_bwt_and_sparse_sa(text: &[u8], sample_rate: u32) -> (Vec<u8>, Vec<u32>) {
+    let induced = bwt_and_sparse_sa_from_codes(text.len(), text.iter().copied(), sample_rate);
+    (induced.bwt, induced.sparse_sa)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::index::sa::{build_sa, bwt_and_sparse_sa_from_codes};
+    use crate::testutil::Lcg;
+
+    #[test]
+    fn bwt_and_sparse_sa_match_full_construction() {
+        let mut rng = Lcg::new(99);
+        for len in [0usize, 1, 2, 7, 300, 5000] {
+            let mut text: Vec<u8> = (0..len).map(|_| rng.below(6) as u8).collect();
+            if let Some(last) = text.last_mut() {
+                *last = 0;
+            }
+            let sa = build_sa(&text);
+            let bwt = build_bwt(&text, &sa);
+            for rate in [0u32, 1, 2, 5, 32] {
+                let (b, sparse) = build_bwt_and_sparse_sa(&text, rate);
+                assert_eq!(b, bwt, "len {} rate {}", len, rate);
+                let expected: Vec<u32> = sa.iter().copied().step_by(rate.max(1) as usize).collect();
+                assert_eq!(sparse, expected, "len {} rate {}", len, rate);
+            }
+        }
+    }
+
+    #[test]
+    fn induced_sentinel_rows_match_full_sa() {
+        let mut rng = Lcg::new(7);
+        let mut text = Vec::new();
+        for _ in 0..20 {
+            let len = 1 + rng.below(200) as usize;
+            text.extend((0..len).map(|_| 1 + rng.below(4) as u8));
+            text.push(0);
+        }
+        let sa = build_sa(&text);
+        let bwt = build_bwt(&text, &sa);
+        let expected: Vec<(u32, u32)> = (0..sa.len())
+            .filter(|&row| bwt[row] == 0)
+            .map(|row| (row as u32, sa[row]))
+            .collect();
+        let induced = bwt_and_sparse_sa_from_codes(text.len(), text.iter().copied(), 4);
+        assert_eq!(induced.sentinel_rows, expected);
+    }
 
     #[test]
     fn bwt_single_contig() {
@@ -1,10 +1,36 @@
+use std::borrow::Cow;
+use std::io::{Read, Write};
 use std::path::Path;
 
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 
+use super::alphabet::AlphabetSpec;
+use super::mmap::{self, Scalar, Slab};
+use super::packed::PackedSeq;
+use super::rank::{RankBwt, RankShape};
+use super::sa::InducedBwt;
+
 const FM_MAGIC: u64 = 0x424D_4146_4D5F_5253; // "BWAFM_RS"
-const FM_VERSION: u32 = 2;
+/// v3：新增 `alphabet` 与 `raw_patches`（无损索引）字段，`text` 为 2-bit 压缩的 [`PackedSeq`]，
+/// BWT 与 Occ 采样合并为位平面压缩的两级 rank 结构 [`RankBwt`]；v1–v2 文件需重新构建
+const FM_VERSION: u32 = 3;
+
+/// 内存映射布局（[`FMIndex::save_mmap_layout`]）的文件头魔数
+const MMAP_MAGIC: [u8; 8] = *b"BWAFMMAP";
+const MMAP_LAYOUT_VERSION: u32 = 1;
+/// 区段：元数据（bincode）、C 表、SA、BWT 位平面、超级块计数、细块计数
+const MMAP_SECTIONS: usize = 6;
+/// 魔数、布局版本、区段数，以及每个区段的 `(字节偏移, 元素数)`
+const MMAP_HEADER_LEN: usize = 8 + 4 + 4 + MMAP_SECTIONS * 16;
+/// 区段起点对齐到 8 字节，映射后可直接按 `u64` 读取
+const MMAP_ALIGN: usize = 8;
+
+/// 视为组装缺口（硬掩码区）的最短连续 N 长度
+pub const MIN_N_RUN_LEN: u32 = 10;
+
+/// `search_with_mismatches` 返回区间数的上限，防止重复区域上的分支爆炸
+pub const MAX_MISMATCH_INTERVALS: usize = 1024;
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct IndexMeta {
@@ -13,6 +39,22 @@ pub struct IndexMeta {
     pub build_timestamp: Option<String>,
 }
 
+impl IndexMeta {
+    /// 构建时记录的参考文件仍存在、但修改时间晚于 `build_timestamp` 时返回警告信息
+    /// （索引可能已与参考不一致）。参考文件不存在（索引被移动）或元数据不全时返回 `None`。
+    pub fn reference_warning(&self) -> Option<String> {
+        let reference = self.reference_file.as_deref()?;
+        let built = chrono::DateTime::parse_from_rfc3339(self.build_timestamp.as_deref()?).ok()?;
+        let modified: chrono::DateTime<chrono::Utc> = std::fs::metadata(reference).ok()?.modified().ok()?.into();
+        (modified > built).then(|| {
+            format!(
+                "reference '{}' was modified after this index was built ({}); the index may be stale",
+                reference, built
+            )
+        })
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Contig {
     pub name: String,
@@ -22,100 +64,167 @@ pub struct Contig {
 
 /// 朴素 FM 索引实现：
 /// - 支持任意有限字母表，字母以 [0..sigma) 进行编码（0 预留为 $）。
-/// - 采用定长分块的 Occ 采样（块内顺扫补偿），便于后续替换为压缩结构。
+/// - BWT 以位平面压缩存储，Occ 由两级 rank 字典（超级块 + 细块计数，块内 popcount）求得，见 [`RankBwt`]。
 /// - 保存完整 SA（MVP），方便从区间获得位置；后续可替换为稀疏采样。
 ///
 /// # 内存占用说明
 ///
-/// `text` 字段保存原始文本，占用 O(n) 空间。这是必要的权衡：
+/// `text` 字段保存原始文本，以 2-bit 压缩（[`PackedSeq`]）存储。这是必要的权衡：
 /// - 比对时需要参考序列进行 Smith-Waterman 扩展
 /// - 从 BWT 重建文本的开销远大于保存原始文本
-/// - 未来可考虑压缩存储或按需加载
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FMIndex {
     pub magic: u64,
     pub version: u32,
     pub sigma: u8,
-    pub block: u32,
     /// C\[i\] = 文本中字母 < i 的累计数量
-    pub c: Vec<u32>,
-    /// BWT 序列（与 SA 同长度）
-    pub bwt: Vec<u8>,
-    /// Occ 采样（按块存储，行优先展平）：occ_samples[block_id * sigma + c]
-    pub occ_samples: Vec<u32>,
+    pub c: Slab<u32>,
+    /// BWT 序列（与 SA 同长度）及其 rank 采样
+    pub bwt: RankBwt,
     /// SA（完整或稀疏采样）
-    pub sa: Vec<u32>,
+    pub sa: Slab<u32>,
     /// 稀疏 SA 采样间隔（0 表示完整 SA）
     pub sa_sample_rate: u32,
     /// contig 元信息（名称、长度、起始偏移）
     pub contigs: Vec<Contig>,
-    /// 原始文本（数值化字母表，包含 contig 间的 0 分隔符）
+    /// 原始文本（数值化字母表，包含 contig 间的 0 分隔符），2-bit 压缩存储
     ///
     /// # 用途
     /// 比对时需要恢复参考序列进行 SW 扩展。虽然占用 O(n) 空间，
     /// 但避免了从 BWT 重建的 O(n) 时间开销。
-    pub text: Vec<u8>,
+    pub text: PackedSeq,
     /// 可选的构建元数据
     pub meta: Option<IndexMeta>,
+    /// 字母表描述（哨兵编码、符号 ↔ 字节映射）
+    pub alphabet: AlphabetSpec,
+    /// 无损索引（`--lossless`）保存的原始字节段 `(文本起点, 原始字节)`，按起点升序；
+    /// 非无损索引为 `None`。仅用于恢复参考序列，搜索与比对仍使用折叠后的 `text`
+    pub raw_patches: Option<Vec<(u32, Vec<u8>)>>,
+    /// 长 N 区间（文本坐标，左闭右开，按起点升序），由 `text` 推导，不写入磁盘
+    #[serde(skip)]
+    n_runs: Vec<(u32, u32)>,
+    /// 稀疏 SA 下 BWT 字符为哨兵的行及其 SA 值 `(row, pos)`（按行升序），由 `text` 推导，不写入磁盘。
+    /// 多 contig 文本中经过哨兵的 LF-mapping 不成立，定位时在这些行直接取值。
+    #[serde(skip)]
+    sentinel_rows: Vec<(u32, u32)>,
+}
+
+/// 内存映射布局中以 bincode 保存的小字段；C 表、SA 与 BWT rank 结构的数组各自成段
+#[derive(Serialize, Deserialize)]
+struct MmapMeta<'a> {
+    version: u32,
+    sigma: u8,
+    sa_sample_rate: u32,
+    rank: RankShape,
+    contigs: Cow<'a, [Contig]>,
+    text: Cow<'a, PackedSeq>,
+    meta: Cow<'a, Option<IndexMeta>>,
+    alphabet: Cow<'a, AlphabetSpec>,
+    raw_patches: Cow<'a, Option<Vec<(u32, Vec<u8>)>>>,
+    /// 保存下来，加载时无需解码整个 BWT 重新推导
+    sentinel_rows: Cow<'a, [(u32, u32)]>,
 }
 
 impl FMIndex {
+    /// 以 `sigma` 推断字母表（见 [`AlphabetSpec::for_sigma`]）构建索引
     pub fn build(text: Vec<u8>, bwt: Vec<u8>, sa: Vec<u32>, contigs: Vec<Contig>, sigma: u8, block: usize) -> Self {
+        Self::build_with_alphabet(text, bwt, sa, contigs, AlphabetSpec::for_sigma(sigma), block)
+    }
+
+    /// 使用指定字母表构建索引；`text` 需已按该字母表编码
+    pub fn build_with_alphabet(
+        text: Vec<u8>,
+        bwt: Vec<u8>,
+        sa: Vec<u32>,
+        contigs: Vec<Contig>,
+        alphabet: AlphabetSpec,
+        block: usize,
+    ) -> Self {
         assert!(block > 0, "block size must be greater than zero");
-        let n = bwt.len();
-        let sigma_us = sigma as usize;
-        assert!(sigma_us > 0, "sigma must be greater than zero");
+        let sigma = alphabet.sigma();
+        assert!(sigma > 0, "sigma must be greater than zero");
         assert_eq!(bwt.len(), text.len(), "BWT/text length mismatch");
         assert_eq!(sa.len(), text.len(), "SA/text length mismatch");
-        // 计算 C 表
-        let mut freq = vec![0u32; sigma_us];
-        for &ch in &bwt {
-            let ci = ch as usize;
-            if ci < sigma_us {
-                freq[ci] += 1;
-            }
-        }
-        let mut c = vec![0u32; sigma_us];
-        let mut acc = 0u32;
-        for i in 0..sigma_us {
-            c[i] = acc;
-            acc += freq[i];
-        }
-
-        // 采样 Occ
-        let block_u = block as u32;
-        let num_blocks = if n == 0 { 0 } else { (n + block - 1) / block };
-        let mut occ_samples = vec![0u32; num_blocks * sigma_us];
-        let mut running = vec![0u32; sigma_us];
-        for bi in 0..num_blocks {
-            // 记录到块起始位置的累计
-            for a in 0..sigma_us {
-                occ_samples[bi * sigma_us + a] = running[a];
-            }
-            // 扫描本块内容，更新 running
-            let start = bi * block;
-            let end = ((bi + 1) * block).min(n);
-            for &ch in &bwt[start..end] {
-                let ci = ch as usize;
-                if ci < sigma_us {
-                    running[ci] += 1;
-                }
-            }
+        let c = c_table(bwt.iter().copied(), sigma);
+        // 位平面压缩 BWT 并采样 Occ
+        let bwt = RankBwt::new(&bwt, sigma, block);
+        Self::from_parts(c, bwt, sa, contigs, PackedSeq::from_codes(&text), alphabet)
+    }
+
+    /// 由 2-bit 压缩文本与完整 SA 构建索引，不生成逐字节的文本或 BWT：
+    /// BWT 第 `i` 行为 `text[sa[i] - 1]`（循环），直接流式写入 rank 字典；
+    /// C 表按文本统计（BWT 是文本的一个排列）。结果与 [`FMIndex::build_with_alphabet`] 一致。
+    pub fn build_from_packed(
+        text: PackedSeq,
+        sa: Vec<u32>,
+        contigs: Vec<Contig>,
+        alphabet: AlphabetSpec,
+        block: usize,
+    ) -> Self {
+        assert!(block > 0, "block size must be greater than zero");
+        let sigma = alphabet.sigma();
+        assert!(sigma > 0, "sigma must be greater than zero");
+        assert_eq!(sa.len(), text.len(), "SA/text length mismatch");
+        let n = text.len();
+        let c = c_table(text.iter(), sigma);
+        let bwt = RankBwt::from_symbols(n, sa.iter().map(|&p| text.get((p as usize + n - 1) % n)), sigma, block);
+        Self::from_parts(c, bwt, sa, contigs, text, alphabet)
+    }
+
+    /// 由 SA-IS 直接产生的 BWT 与采样 SA 构建索引（见
+    /// [`build_bwt_and_sparse_sa`](super::bwt::build_bwt_and_sparse_sa)），全程不生成完整 SA；
+    /// `rate` 须与产生 `induced` 时的采样间隔一致。结果与先构建完整 SA 再 [`FMIndex::sparsify_sa`] 相同。
+    pub(crate) fn build_from_induced(
+        text: PackedSeq,
+        induced: InducedBwt,
+        rate: u32,
+        contigs: Vec<Contig>,
+        alphabet: AlphabetSpec,
+        block: usize,
+    ) -> Self {
+        assert!(block > 0, "block size must be greater than zero");
+        let sigma = alphabet.sigma();
+        assert!(sigma > 0, "sigma must be greater than zero");
+        assert_eq!(induced.bwt.len(), text.len(), "BWT/text length mismatch");
+        let InducedBwt {
+            bwt,
+            sparse_sa,
+            sentinel_rows,
+        } = induced;
+        let c = c_table(text.iter(), sigma);
+        let bwt = RankBwt::new(&bwt, sigma, block);
+        let mut fm = Self::from_parts(c, bwt, sparse_sa, contigs, text, alphabet);
+        if rate > 1 {
+            fm.sa_sample_rate = rate;
+            fm.sentinel_rows = sentinel_rows;
         }
+        fm
+    }
 
+    fn from_parts(
+        c: Vec<u32>,
+        bwt: RankBwt,
+        sa: Vec<u32>,
+        contigs: Vec<Contig>,
+        text: PackedSeq,
+        alphabet: AlphabetSpec,
+    ) -> Self {
+        let n_runs = find_n_runs(&text, &alphabet);
         Self {
             magic: FM_MAGIC,
             version: FM_VERSION,
-            sigma,
-            block: block_u,
-            c,
+            sigma: alphabet.sigma(),
+            c: c.into(),
             bwt,
-            occ_samples,
-            sa,
+            sa: sa.into(),
             sa_sample_rate: 0,
             contigs,
             text,
             meta: None,
+            alphabet,
+            raw_patches: None,
+            n_runs,
+            sentinel_rows: Vec::new(),
         }
     }
 
@@ -136,14 +245,24 @@ impl FMIndex {
         fm
     }
 
-    /// 将完整 SA 转换为稀疏采样
-    fn sparsify_sa(&mut self, rate: u32) {
+    /// 将完整 SA 转换为每 `rate` 行保留一项的稀疏采样；`rate <= 1` 时保持完整 SA。
+    /// 须在完整 SA 上调用（即构建后、尚未稀疏化时）。
+    pub fn sparsify_sa(&mut self, rate: u32) {
+        if rate <= 1 || self.sa_sample_rate > 1 {
+            return;
+        }
         let n = self.sa.len();
         let mut sparse = Vec::with_capacity(n / rate as usize + 1);
         for i in (0..n).step_by(rate as usize) {
             sparse.push(self.sa[i]);
         }
-        self.sa = sparse;
+        // 完整 SA 仍在手边，直接取 BWT 为哨兵的行
+        let sentinel = self.alphabet.sentinel;
+        self.sentinel_rows = (0..n)
+            .filter(|&row| self.bwt.get(row) == sentinel)
+            .map(|row| (row as u32, self.sa[row]))
+            .collect();
+        self.sa = sparse.into();
         self.sa_sample_rate = rate;
     }
 
@@ -159,42 +278,95 @@ impl FMIndex {
             if idx % rate == 0 {
                 return (self.sa[idx / rate] + steps) % n;
             }
+            let ch = self.bwt.get(idx);
+            if ch == self.alphabet.sentinel {
+                if let Ok(k) = self.sentinel_rows.binary_search_by_key(&(idx as u32), |&(row, _)| row) {
+                    return (self.sentinel_rows[k].1 + steps) % n;
+                }
+            }
             // LF-mapping: idx = C[BWT[idx]] + Occ(BWT[idx], idx)
-            let ch = self.bwt[idx];
             idx = self.c[ch as usize] as usize + self.occ(ch, idx) as usize;
             steps += 1;
         }
     }
 
+    /// 一步 LF-mapping：第 `row` 行后缀向左扩展一个符号（BWT\[row]）后所在的行。
+    ///
+    /// 多 contig 文本中 BWT\[row] 为哨兵时结果不对应真实的前一个后缀（各哨兵的相对顺序不随 LF 保持）。
+    #[inline]
+    pub fn lf(&self, row: usize) -> usize {
+        let ch = self.bwt.get(row);
+        self.c[ch as usize] as usize + self.occ(ch, row) as usize
+    }
+
+    /// 仅由 BWT 经 LF-mapping 重建完整文本（字母表编码，含 contig 间的哨兵），可用于校验索引完整性。
+    ///
+    /// 以哨兵开头的后缀位于前 `C[1]` 行：从每一行出发反复 LF，直到 BWT 字符为哨兵，
+    /// 即得到该哨兵之前的一整条 contig；哨兵在文本中的位置由 SA（[`FMIndex::sa_value`]）给出。
+    pub fn reconstruct_text(&self) -> Vec<u8> {
+        let n = self.bwt.len();
+        let sentinel = self.alphabet.sentinel;
+        let mut text = vec![sentinel; n];
+        let n_sentinels = self.c.get(sentinel as usize + 1).map_or(n, |&c| c as usize);
+        for start in 0..n_sentinels {
+            let mut pos = self.sa_value(start) as usize;
+            let mut row = start;
+            loop {
+                let ch = self.bwt.get(row);
+                if ch == sentinel || pos == 0 {
+                    break;
+                }
+                pos -= 1;
+                text[pos] = ch;
+                row = self.lf(row);
+            }
+        }
+        text
+    }
+
     pub fn set_meta(&mut self, meta: IndexMeta) {
         self.meta = Some(meta);
     }
 
+    /// 构建元数据（旧索引或库调用构建的索引可能没有）
+    pub fn meta(&self) -> Option<&IndexMeta> {
+        self.meta.as_ref()
+    }
+
     fn validate(&self) -> Result<()> {
+        self.validate_layout()?;
+        for (i, ch) in self.bwt.iter().enumerate() {
+            if ch as usize >= self.sigma as usize {
+                return Err(anyhow!("invalid FM index file: BWT symbol out of range at {}", i));
+            }
+        }
+        for (i, &pos) in self.sa.iter().enumerate() {
+            if pos as usize >= self.text.len() {
+                return Err(anyhow!("invalid FM index file: SA position out of range at {}", i));
+            }
+        }
+        Ok(())
+    }
+
+    /// 结构一致性检查：不逐项扫描 BWT 与 SA，内存映射加载时不会因此读入整个文件
+    fn validate_layout(&self) -> Result<()> {
         if self.sigma == 0 {
             return Err(anyhow!("invalid FM index file: sigma must be greater than zero"));
         }
-        if self.block == 0 {
-            return Err(anyhow!("invalid FM index file: block size must be greater than zero"));
+        self.alphabet.validate().map_err(|e| anyhow!("invalid FM index file: {}", e))?;
+        if self.alphabet.sigma() != self.sigma {
+            return Err(anyhow!("invalid FM index file: alphabet size does not match sigma"));
         }
+        self.text.validate().map_err(|e| anyhow!("invalid FM index file: {}", e))?;
+        self.bwt.validate().map_err(|e| anyhow!("invalid FM index file: {}", e))?;
         if self.bwt.len() != self.text.len() {
             return Err(anyhow!("invalid FM index file: BWT/text length mismatch"));
         }
         if self.c.len() != self.sigma as usize {
             return Err(anyhow!("invalid FM index file: C table length does not match sigma"));
         }
-        let expected_blocks = if self.bwt.is_empty() {
-            0
-        } else {
-            (self.bwt.len() + self.block as usize - 1) / self.block as usize
-        };
-        let expected_occ_len = expected_blocks * self.sigma as usize;
-        if self.occ_samples.len() != expected_occ_len {
-            return Err(anyhow!(
-                "invalid FM index file: occ_samples length mismatch (expected {}, got {})",
-                expected_occ_len,
-                self.occ_samples.len()
-            ));
+        if self.bwt.sigma() != self.sigma {
+            return Err(anyhow!("invalid FM index file: BWT alphabet size does not match sigma"));
         }
         if self.sa_sample_rate <= 1 {
             if self.sa.len() != self.bwt.len() {
@@ -211,16 +383,6 @@ impl FMIndex {
                 ));
             }
         }
-        for (i, &ch) in self.bwt.iter().enumerate() {
-            if ch as usize >= self.sigma as usize {
-                return Err(anyhow!("invalid FM index file: BWT symbol out of range at {}", i));
-            }
-        }
-        for (i, &pos) in self.sa.iter().enumerate() {
-            if pos as usize >= self.text.len() {
-                return Err(anyhow!("invalid FM index file: SA position out of range at {}", i));
-            }
-        }
         let mut expected_offset = 0u32;
         for contig in &self.contigs {
             if contig.offset != expected_offset {
@@ -235,27 +397,26 @@ impl FMIndex {
             }
             expected_offset = end.saturating_add(1);
         }
+        if let Some(patches) = &self.raw_patches {
+            let mut prev_end = 0usize;
+            for (start, bytes) in patches {
+                let start = *start as usize;
+                if start < prev_end || start + bytes.len() > self.text.len() {
+                    return Err(anyhow!(
+                        "invalid FM index file: raw patch at {} is out of order or range",
+                        start
+                    ));
+                }
+                prev_end = start + bytes.len();
+            }
+        }
         Ok(())
     }
 
+    /// 返回 BWT\[0..pos) 中 c 的出现次数
     #[inline]
     pub fn occ(&self, c: u8, pos: usize) -> u32 {
-        // 返回 BWT[0..pos) 中 c 的出现次数
-        if pos == 0 {
-            return 0;
-        }
-        let sigma_us = self.sigma as usize;
-        let block = self.block as usize;
-        let bi = (pos - 1) / block; // 所在块编号
-        let base = self.occ_samples[bi * sigma_us + c as usize];
-        let start = bi * block;
-        let mut add = 0u32;
-        for &ch in &self.bwt[start..pos] {
-            if ch == c {
-                add += 1;
-            }
-        }
-        base + add
+        self.bwt.occ(c, pos)
     }
 
     #[inline]
@@ -267,7 +428,7 @@ impl FMIndex {
         (nl, nr)
     }
 
-    /// 反向搜索精确匹配，pat 已经是编码后的字母表（不应包含 0）
+    /// 反向搜索精确匹配，pat 已经是编码后的字母表；含哨兵或越界符号时返回 `None`
     pub fn backward_search(&self, pat: &[u8]) -> Option<(usize, usize)> {
         if self.bwt.is_empty() {
             return None;
@@ -275,6 +436,10 @@ impl FMIndex {
         let mut l = 0usize;
         let mut r = self.bwt.len();
         for &a in pat.iter().rev() {
+            // 哨兵与越界符号不会出现在任何匹配中
+            if a == self.alphabet.sentinel || a >= self.sigma {
+                return None;
+            }
             let (nl, nr) = self.rank_range(a, l, r);
             if nl >= nr {
                 return None;
@@ -285,35 +450,332 @@ impl FMIndex {
         Some((l, r))
     }
 
+    /// 求 query 在参考中出现的最长前缀：返回 (前缀长度, 该前缀的 SA 区间)。
+    ///
+    /// 前缀长度为 0（首个符号即无匹配或 query 为空）时区间为 `None`。
+    /// FM 索引只支持向左扩展，因此对前缀长度做二分：前缀可匹配具有单调性，
+    /// 每次用 `backward_search` 验证，共 O(m log m) 次 rank 查询。
+    pub fn longest_prefix_match(&self, query_alpha: &[u8]) -> (usize, Option<(usize, usize)>) {
+        // 哨兵或越界符号不可能匹配，前缀不会跨过它们
+        let limit = query_alpha
+            .iter()
+            .position(|&a| a == self.alphabet.sentinel || a >= self.sigma)
+            .unwrap_or(query_alpha.len());
+
+        let mut best = (0usize, None);
+        let (mut lo, mut hi) = (1usize, limit);
+        while lo <= hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.backward_search(&query_alpha[..mid]) {
+                Some(iv) => {
+                    best = (mid, Some(iv));
+                    lo = mid + 1;
+                }
+                None => hi = mid - 1,
+            }
+        }
+        best
+    }
+
+    /// 编码后的模式串在参考中的出现次数（即 SA 区间大小）；不存在时为 0
+    pub fn count(&self, pat: &[u8]) -> usize {
+        self.backward_search(pat).map_or(0, |(l, r)| r - l)
+    }
+
+    /// 沿编码序列 `seq` 滑动长度为 `k` 的窗口，返回每个 k-mer 的出现次数
+    /// （第 `i` 项对应 `seq[i..i + k]`，共 `seq.len() - k + 1` 项；`k == 0` 或序列过短时为空）。
+    ///
+    /// 单向 FM 索引只能向左扩展，无法从区间中去掉右端符号，因此每个窗口仍从右端做一次
+    /// backward search；但若在位置 `p` 处失配，说明 `seq[p..i + k]` 不在参考中，
+    /// 所有起点在 `[i, p]` 的窗口都包含该子串，可直接记 0 并跳过。
+    pub fn count_kmers(&self, seq: &[u8], k: usize) -> Vec<usize> {
+        if k == 0 || seq.len() < k {
+            return Vec::new();
+        }
+        let n = seq.len() - k + 1;
+        let mut counts = vec![0usize; n];
+        let mut i = 0usize;
+        while i < n {
+            let (mut l, mut r) = (0usize, self.bwt.len());
+            let mut miss = None;
+            for p in (i..i + k).rev() {
+                let a = seq[p];
+                if a == self.alphabet.sentinel || a >= self.sigma {
+                    miss = Some(p);
+                    break;
+                }
+                (l, r) = self.rank_range(a, l, r);
+                if l >= r {
+                    miss = Some(p);
+                    break;
+                }
+            }
+            match miss {
+                // 起点 i..=p 的窗口均为 0（已初始化）
+                Some(p) => i = p + 1,
+                None => {
+                    counts[i] = r - l;
+                    i += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    /// 允许至多 `max_mm` 个替换的反向搜索（BWA `aln` 式有界回溯）：返回可达 SA 区间
+    /// `(l, r, 替换数)`，按替换数、区间起点升序。
+    ///
+    /// 每一步在当前符号上做精确扩展，预算未用完时再依次尝试其余可匹配符号（不含哨兵与回退符号）；
+    /// query 中的哨兵、回退或越界符号只能以替换方式通过。结果数达到
+    /// [`MAX_MISMATCH_INTERVALS`] 后停止搜索，重复区域上的结果因此可能不完整。
+    pub fn search_with_mismatches(&self, pat: &[u8], max_mm: u32) -> Vec<(usize, usize, u32)> {
+        let mut hits = Vec::new();
+        if !self.bwt.is_empty() {
+            self.mismatch_descend(pat, (0, self.bwt.len()), 0, max_mm, &mut hits);
+        }
+        hits.sort_unstable_by_key(|&(l, _, mm)| (mm, l));
+        hits
+    }
+
+    fn mismatch_descend(
+        &self,
+        pat: &[u8],
+        (l, r): (usize, usize),
+        mm: u32,
+        max_mm: u32,
+        hits: &mut Vec<(usize, usize, u32)>,
+    ) {
+        if hits.len() >= MAX_MISMATCH_INTERVALS {
+            return;
+        }
+        let Some((&a, rest)) = pat.split_last() else {
+            hits.push((l, r, mm));
+            return;
+        };
+        for c in 0..self.sigma {
+            if c == self.alphabet.sentinel || self.alphabet.unknown == Some(c) {
+                continue;
+            }
+            let cost = mm + u32::from(c != a);
+            if cost > max_mm {
+                continue;
+            }
+            let (nl, nr) = self.rank_range(c, l, r);
+            if nl < nr {
+                self.mismatch_descend(rest, (nl, nr), cost, max_mm, hits);
+            }
+        }
+    }
+
     pub fn save_to_file(&self, path: impl AsRef<Path>) -> Result<()> {
         let mut f = std::fs::File::create(path)?;
         bincode::serialize_into(&mut f, self)?;
         Ok(())
     }
 
+    /// 以 gzip 压缩格式保存索引，`level` 取 0（仅存储）到 9（最小体积）。
+    /// [`FMIndex::load_from_file`] 会根据文件头自动识别压缩格式。
+    pub fn save_to_file_compressed(&self, path: impl AsRef<Path>, level: u32) -> Result<()> {
+        crate::io::gzip::validate_compress_level(level)?;
+        let f = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let mut enc = flate2::write::GzEncoder::new(f, flate2::Compression::new(level));
+        bincode::serialize_into(&mut enc, self)?;
+        std::io::Write::flush(&mut enc.finish()?)?;
+        Ok(())
+    }
+
+    /// 以零拷贝布局保存索引，供 [`FMIndex::mmap_from_file`] 映射加载。
+    ///
+    /// 文件头（魔数 `BWAFMMAP`、布局版本、区段表）之后依次是 bincode 编码的小字段
+    /// （contig、压缩文本、字母表等），以及 C 表、SA、BWT 位平面、超级块计数和细块计数
+    /// 五个小端整数数组；每个区段起点按 8 字节对齐，区段表记录其字节偏移与元素数。
+    pub fn save_mmap_layout(&self, path: impl AsRef<Path>) -> Result<()> {
+        let meta = bincode::serialize(&MmapMeta {
+            version: self.version,
+            sigma: self.sigma,
+            sa_sample_rate: self.sa_sample_rate,
+            rank: self.bwt.shape(),
+            contigs: Cow::Borrowed(&self.contigs),
+            text: Cow::Borrowed(&self.text),
+            meta: Cow::Borrowed(&self.meta),
+            alphabet: Cow::Borrowed(&self.alphabet),
+            raw_patches: Cow::Borrowed(&self.raw_patches),
+            sentinel_rows: Cow::Borrowed(&self.sentinel_rows),
+        })?;
+        let sizes = [
+            (meta.len(), 1),
+            (self.c.len(), 4),
+            (self.sa.len(), 4),
+            (self.bwt.planes().len(), 8),
+            (self.bwt.super_counts().len(), 8),
+            (self.bwt.block_counts().len(), 2),
+        ];
+        let mut header = Vec::with_capacity(MMAP_HEADER_LEN);
+        header.extend_from_slice(&MMAP_MAGIC);
+        header.extend_from_slice(&MMAP_LAYOUT_VERSION.to_le_bytes());
+        header.extend_from_slice(&(MMAP_SECTIONS as u32).to_le_bytes());
+        let mut offsets = [0usize; MMAP_SECTIONS];
+        let mut end = MMAP_HEADER_LEN;
+        for (offset, &(len, width)) in offsets.iter_mut().zip(&sizes) {
+            *offset = align_up(end, MMAP_ALIGN);
+            end = *offset + len * width;
+            header.extend_from_slice(&(*offset as u64).to_le_bytes());
+            header.extend_from_slice(&(len as u64).to_le_bytes());
+        }
+
+        let mut w = std::io::BufWriter::new(std::fs::File::create(path)?);
+        w.write_all(&header)?;
+        let mut written = header.len();
+        for (i, &offset) in offsets.iter().enumerate() {
+            w.write_all(&[0u8; MMAP_ALIGN][..offset - written])?;
+            match i {
+                0 => w.write_all(&meta)?,
+                1 => u32::write_le(&self.c, &mut w)?,
+                2 => u32::write_le(&self.sa, &mut w)?,
+                3 => u64::write_le(self.bwt.planes(), &mut w)?,
+                4 => u64::write_le(self.bwt.super_counts(), &mut w)?,
+                _ => u16::write_le(self.bwt.block_counts(), &mut w)?,
+            }
+            written = offset + sizes[i].0 * sizes[i].1;
+        }
+        w.flush()?;
+        Ok(())
+    }
+
+    /// 内存映射 [`FMIndex::save_mmap_layout`] 写出的文件：C 表、SA 与 BWT rank 结构的数组
+    /// （[`RankBwt::planes`]、[`RankBwt::super_counts`]、[`RankBwt::block_counts`]）直接借用映射，
+    /// 由操作系统按需分页载入，不复制到堆上；只有 contig 表与压缩文本等小字段被反序列化。
+    ///
+    /// 只做结构检查，不逐项校验 BWT 符号与 SA 值（那会读入整个文件）；文件在映射期间不应被改写。
+    /// 需要小端平台。
+    pub fn mmap_from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let map = mmap::map_file(path.as_ref())?;
+        let header = map
+            .get(..MMAP_HEADER_LEN)
+            .ok_or_else(|| anyhow!("invalid FM index file: truncated memory-mapped layout header"))?;
+        if header[..8] != MMAP_MAGIC {
+            return Err(anyhow!("invalid FM index file: not a memory-mapped layout"));
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().expect("4-byte slice"));
+        let u64_at = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().expect("8-byte slice"));
+        let layout_version = u32_at(8);
+        if layout_version != MMAP_LAYOUT_VERSION {
+            return Err(anyhow!(
+                "unsupported memory-mapped index layout: expected {}, got {}",
+                MMAP_LAYOUT_VERSION,
+                layout_version
+            ));
+        }
+        if u32_at(12) as usize != MMAP_SECTIONS {
+            return Err(anyhow!("invalid FM index file: unexpected memory-mapped section count"));
+        }
+        let mut sections = [(0usize, 0usize); MMAP_SECTIONS];
+        for (i, section) in sections.iter_mut().enumerate() {
+            let at = 16 + i * 16;
+            *section = (usize::try_from(u64_at(at))?, usize::try_from(u64_at(at + 8))?);
+        }
+
+        let (meta_at, meta_len) = sections[0];
+        let meta_bytes = meta_at
+            .checked_add(meta_len)
+            .and_then(|end| map.get(meta_at..end))
+            .ok_or_else(|| anyhow!("invalid FM index file: metadata section out of range"))?;
+        let meta: MmapMeta = bincode::deserialize(meta_bytes)?;
+        if meta.version != FM_VERSION {
+            return Err(anyhow!(
+                "unsupported FM index version: expected {}, got {}",
+                FM_VERSION,
+                meta.version
+            ));
+        }
+        let bwt = RankBwt::from_parts(
+            meta.rank,
+            Slab::mapped(&map, sections[3].0, sections[3].1)?,
+            Slab::mapped(&map, sections[4].0, sections[4].1)?,
+            Slab::mapped(&map, sections[5].0, sections[5].1)?,
+        );
+        let mut idx = Self {
+            magic: FM_MAGIC,
+            version: meta.version,
+            sigma: meta.sigma,
+            c: Slab::mapped(&map, sections[1].0, sections[1].1)?,
+            bwt,
+            sa: Slab::mapped(&map, sections[2].0, sections[2].1)?,
+            sa_sample_rate: meta.sa_sample_rate,
+            contigs: meta.contigs.into_owned(),
+            text: meta.text.into_owned(),
+            meta: meta.meta.into_owned(),
+            alphabet: meta.alphabet.into_owned(),
+            raw_patches: meta.raw_patches.into_owned(),
+            n_runs: Vec::new(),
+            sentinel_rows: meta.sentinel_rows.into_owned(),
+        };
+        idx.validate_layout()?;
+        idx.n_runs = find_n_runs(&idx.text, &idx.alphabet);
+        Ok(idx)
+    }
+
+    /// 读取 [`FMIndex::save_to_file`] / [`FMIndex::save_to_file_compressed`] 写出的索引，整体反序列化到内存。
+    ///
+    /// [`FMIndex::save_mmap_layout`] 写出的文件按魔数识别，转由 [`FMIndex::mmap_from_file`] 映射加载。
     pub fn load_from_file(path: impl AsRef<Path>) -> Result<Self> {
-        let f = std::fs::File::open(path.as_ref())?;
-        let idx: Self = bincode::deserialize_from(f)?;
-        if idx.magic != FM_MAGIC {
+        let mut f = std::io::BufReader::new(std::fs::File::open(path.as_ref())?);
+        let head = std::io::BufRead::fill_buf(&mut f)?;
+        if head.starts_with(&MMAP_MAGIC) {
+            return Self::mmap_from_file(path);
+        }
+        let is_gzip = head.starts_with(&[0x1f, 0x8b]);
+        let mut reader: Box<dyn Read> = if is_gzip {
+            Box::new(flate2::read::GzDecoder::new(f))
+        } else {
+            Box::new(f)
+        };
+
+        // 先读出 magic + version（bincode 定长小端），再按版本选择磁盘布局
+        let mut head = [0u8; 12];
+        reader
+            .read_exact(&mut head)
+            .map_err(|e| anyhow!("invalid FM index file: cannot read header: {}", e))?;
+        let magic = u64::from_le_bytes(head[..8].try_into().expect("8-byte slice"));
+        let version = u32::from_le_bytes(head[8..].try_into().expect("4-byte slice"));
+        if magic != FM_MAGIC {
             return Err(anyhow!(
                 "invalid FM index file: bad magic number (expected 0x{:016X}, got 0x{:016X})",
                 FM_MAGIC,
-                idx.magic
+                magic
+            ));
+        }
+        // v3 起文本与 BWT 为压缩布局，旧文件无法按新布局解析，要求重新构建
+        if version < FM_VERSION {
+            return Err(anyhow!(
+                "unsupported FM index version {}: indexes written before version {} use an older on-disk layout; \
+                 rebuild the index with `bwa-rust index`",
+                version,
+                FM_VERSION
             ));
         }
-        if idx.version != FM_VERSION && idx.version != 1 {
+        if version != FM_VERSION {
             return Err(anyhow!(
-                "unsupported FM index version: expected {} (or 1), got {}",
+                "unsupported FM index version: expected {}, got {}",
                 FM_VERSION,
-                idx.version
+                version
             ));
         }
+        let body = std::io::Cursor::new(head).chain(reader);
+        let mut idx: Self = bincode::deserialize_from(body)?;
         idx.validate()?;
+        idx.n_runs = find_n_runs(&idx.text, &idx.alphabet);
+        if idx.sa_sample_rate > 1 {
+            idx.sentinel_rows = find_sentinel_rows(&idx.text.to_vec(), &idx.bwt.to_vec(), idx.alphabet.sentinel);
+        }
         Ok(idx)
     }
 
-    /// 取出 SA 区间对应的文本位置
-    pub fn sa_interval_positions(&self, l: usize, r: usize) -> Vec<u32> {
+    /// 定位 SA 区间 `[l, r)` 中每一行对应的文本位置（按行顺序）。
+    ///
+    /// 完整 SA（`sa_sample_rate <= 1`）直接切片返回；稀疏 SA 对每一行沿 BWT 做
+    /// LF-mapping，直到落在采样行上，再加上走过的步数（见 [`FMIndex::sa_value`]）。
+    pub fn locate(&self, l: usize, r: usize) -> Vec<u32> {
         if self.sa_sample_rate <= 1 {
             self.sa[l..r].to_vec()
         } else {
@@ -321,6 +783,11 @@ impl FMIndex {
         }
     }
 
+    /// 取出 SA 区间对应的文本位置
+    pub fn sa_interval_positions(&self, l: usize, r: usize) -> Vec<u32> {
+        self.locate(l, r)
+    }
+
     pub fn for_each_sa_interval_position<F>(&self, l: usize, r: usize, mut f: F)
     where
         F: FnMut(u32),
@@ -336,10 +803,90 @@ impl FMIndex {
         }
     }
 
+    /// 非唯一区域注释：沿每条 contig 滑动 k-mer，返回被出现次数 > 1 的 k-mer 覆盖的区间
+    /// `(contig_idx, start, end)`（contig 内坐标，左闭右开，按 contig、起点升序）。
+    ///
+    /// 连续的重复 k-mer 构成一个区间 `[首个起点, 末个起点 + k)`，相互重叠或相邻的区间会合并。
+    /// 只统计正向文本中的出现次数（不含反向互补）；含回退符号（DNA 中为 `N`）的 k-mer 跳过。
+    pub fn non_unique_regions(&self, k: usize) -> Vec<(usize, u32, u32)> {
+        let mut regions: Vec<(usize, u32, u32)> = Vec::new();
+        if k == 0 {
+            return regions;
+        }
+        for (ci, contig) in self.contigs.iter().enumerate() {
+            let len = contig.len as usize;
+            if len < k {
+                continue;
+            }
+            let offset = contig.offset as usize;
+            let seq = self.text.unpack(offset, offset + len);
+            for start in 0..=len - k {
+                let kmer = &seq[start..start + k];
+                if self.alphabet.unknown.is_some_and(|u| kmer.contains(&u)) {
+                    continue;
+                }
+                let repeated = self.backward_search(kmer).is_some_and(|(l, r)| r - l > 1);
+                if !repeated {
+                    continue;
+                }
+                let (s, e) = (start as u32, (start + k) as u32);
+                match regions.last_mut() {
+                    Some(last) if last.0 == ci && s <= last.2 => last.2 = last.2.max(e),
+                    _ => regions.push((ci, s, e)),
+                }
+            }
+        }
+        regions
+    }
+
+    /// 按字母表将文本区间 `[start, end)` 解码为字节序列
+    pub fn decode_text(&self, start: usize, end: usize) -> Vec<u8> {
+        self.text
+            .unpack(start, end)
+            .into_iter()
+            .map(|code| self.alphabet.decode(code))
+            .collect()
+    }
+
+    /// 是否为无损索引（保存了原始字节段）
+    pub fn is_lossless(&self) -> bool {
+        self.raw_patches.is_some()
+    }
+
+    /// 设置无损模式的原始字节段（文本坐标，按起点升序、互不重叠）
+    pub fn set_raw_patches(&mut self, patches: Vec<(u32, Vec<u8>)>) {
+        self.raw_patches = Some(patches);
+    }
+
+    /// 解码文本 `[start, end)`；无损索引会覆盖原始字节段，逐字节恢复构建时的输入
+    pub fn decode_text_lossless(&self, start: usize, end: usize) -> Vec<u8> {
+        let mut out = self.decode_text(start, end);
+        let Some(patches) = &self.raw_patches else {
+            return out;
+        };
+        // 第一个可能与 [start, end) 相交的片段：起点不晚于 start 的最后一个
+        let first = patches.partition_point(|(p, _)| (*p as usize) <= start).saturating_sub(1);
+        for (p, bytes) in &patches[first..] {
+            let p = *p as usize;
+            if p >= end {
+                break;
+            }
+            let lo = p.max(start);
+            let hi = (p + bytes.len()).min(end);
+            if lo < hi {
+                out[lo - start..hi - start].copy_from_slice(&bytes[lo - p..hi - p]);
+            }
+        }
+        out
+    }
+
     /// 将文本位置映射到 (contig_index, contig_offset)。若落在分隔符($)位置，则返回 None。
     pub fn map_text_pos(&self, pos: u32) -> Option<(usize, u32)> {
-        if self.contigs.is_empty() {
-            return None;
+        // 单 contig 参考（病毒基因组、单条染色体）：只需判断是否落在哨兵上，免去二分查找
+        match self.contigs.as_slice() {
+            [] => return None,
+            [c] => return (pos >= c.offset && pos - c.offset < c.len).then(|| (0, pos - c.offset)),
+            _ => {}
         }
         let mut lo = 0usize;
         let mut hi = self.contigs.len();
@@ -356,6 +903,67 @@ impl FMIndex {
         }
         None
     }
+
+    /// 长 N 区间列表（文本坐标 `[start, end)`，长度至少 [`MIN_N_RUN_LEN`]）。
+    pub fn n_runs(&self) -> &[(u32, u32)] {
+        &self.n_runs
+    }
+
+    /// 文本位置是否落在长 N 区间（组装缺口）内。
+    pub fn in_n_run(&self, pos: u32) -> bool {
+        let idx = self.n_runs.partition_point(|&(start, _)| start <= pos);
+        idx > 0 && pos < self.n_runs[idx - 1].1
+    }
+
+    /// 同 [`FMIndex::map_text_pos`]，额外返回该位置是否位于长 N 区间内，
+    /// 供比对流程拒绝或降权落入组装缺口的位置。
+    pub fn map_text_pos_checked(&self, pos: u32) -> Option<(usize, u32, bool)> {
+        self.map_text_pos(pos).map(|(ci, off)| (ci, off, self.in_n_run(pos)))
+    }
+}
+
+/// BWT 字符为哨兵的行及其 SA 值（按行升序）。
+///
+/// 这些行对应紧跟哨兵之后（或文本开头）的后缀；它们在 SA 中的相对顺序即后缀的字典序，
+/// 因此把起点按后缀排序后与按行升序的哨兵行一一对应。
+fn find_sentinel_rows(text: &[u8], bwt: &[u8], sentinel: u8) -> Vec<(u32, u32)> {
+    let n = text.len();
+    let rows = bwt.iter().enumerate().filter(|&(_, &c)| c == sentinel).map(|(i, _)| i as u32);
+    let mut starts: Vec<usize> = (0..n).filter(|&p| text[(p + n - 1) % n] == sentinel).collect();
+    starts.sort_unstable_by(|&a, &b| text[a..].cmp(&text[b..]));
+    rows.zip(starts.into_iter().map(|p| p as u32)).collect()
+}
+
+fn align_up(x: usize, align: usize) -> usize {
+    (x + align - 1) / align * align
+}
+
+/// C 表：`C[i]` = 符号序列中小于 `i` 的符号数；不小于 `sigma` 的符号不计入
+fn c_table(symbols: impl Iterator<Item = u8>, sigma: u8) -> Vec<u32> {
+    let mut freq = vec![0u32; sigma as usize];
+    for ch in symbols {
+        if let Some(f) = freq.get_mut(ch as usize) {
+            *f += 1;
+        }
+    }
+    let mut c = vec![0u32; sigma as usize];
+    let mut acc = 0u32;
+    for (ci, f) in c.iter_mut().zip(&freq) {
+        *ci = acc;
+        acc += f;
+    }
+    c
+}
+
+/// 文本中长度不小于 [`MIN_N_RUN_LEN`] 的连续 N 区间（字母表不含 `N` 时为空）
+fn find_n_runs(text: &PackedSeq, alphabet: &AlphabetSpec) -> Vec<(u32, u32)> {
+    let Some(n_code) = alphabet.symbols.iter().position(|&b| b == b'N').map(|i| i as u8) else {
+        return Vec::new();
+    };
+    text.runs_of(n_code)
+        .into_iter()
+        .filter(|&(start, end)| end - start >= MIN_N_RUN_LEN)
+        .collect()
 }
 
 #[cfg(test)]
@@ -418,7 +1026,6 @@ mod tests {
         assert_eq!(loaded.magic, fm.magic);
         assert_eq!(loaded.version, fm.version);
         assert_eq!(loaded.sigma, fm.sigma);
-        assert_eq!(loaded.block, fm.block);
         assert_eq!(loaded.c, fm.c);
         assert_eq!(loaded.bwt, fm.bwt);
         assert_eq!(loaded.sa, fm.sa);
@@ -430,6 +1037,33 @@ mod tests {
         std::fs::remove_file(path).ok();
     }
 
+    #[test]
+    fn fm_compressed_save_load_roundtrip() {
+        let text = vec![1u8, 2, 3, 4, 1, 2, 0];
+        let sa_arr = sa::build_sa(&text);
+        let bwt_arr = bwt::build_bwt(&text, &sa_arr);
+        let contigs = vec![Contig {
+            name: "chr1".to_string(),
+            len: 6,
+            offset: 0,
+        }];
+        let fm = FMIndex::build(text, bwt_arr, sa_arr, contigs, 6, 4);
+
+        let dir = std::env::temp_dir();
+        let mut raw = Vec::new();
+        for level in [1u32, 9] {
+            let path = dir.join(format!("bwa_rust_fm_gz_{}_{}.fm", level, std::process::id()));
+            fm.save_to_file_compressed(&path, level).unwrap();
+            let bytes = std::fs::read(&path).unwrap();
+            assert_eq!(&bytes[..2], &[0x1f, 0x8b]);
+            let loaded = FMIndex::load_from_file(&path).unwrap();
+            raw.push(bincode::serialize(&loaded).unwrap());
+            std::fs::remove_file(&path).ok();
+        }
+        assert_eq!(raw[0], raw[1]);
+        assert_eq!(raw[0], bincode::serialize(&fm).unwrap());
+    }
+
     #[test]
     fn fm_map_text_pos_basic() {
         // Two contigs: [0..3) and [4..7), separator at pos 3
@@ -459,6 +1093,22 @@ mod tests {
         assert_eq!(fm.map_text_pos(100), None);
     }
 
+    #[test]
+    fn fm_map_text_pos_single_contig_fast_path() {
+        // 单 contig：[0..5)，哨兵位于 5
+        let fm = build_toy_fm(&[1, 2, 3, 4, 1]);
+        assert_eq!(fm.contigs.len(), 1);
+        for pos in 0..5 {
+            assert_eq!(fm.map_text_pos(pos), Some((0, pos)));
+        }
+        assert_eq!(fm.map_text_pos(5), None); // separator
+        assert_eq!(fm.map_text_pos(u32::MAX), None);
+        // 经 SA 定位得到的位置同样映射正确
+        let (l, r) = fm.backward_search(&[3, 4]).unwrap();
+        let hits: Vec<_> = (l..r).filter_map(|row| fm.map_text_pos(fm.sa[row])).collect();
+        assert_eq!(hits, vec![(0, 2)]);
+    }
+
     #[test]
     fn fm_occ_correctness() {
         let fm = build_toy_fm(&[1, 2, 1, 2, 3]); // ACACG$
@@ -466,7 +1116,7 @@ mod tests {
         let n = fm.bwt.len();
         for c in 0..fm.sigma {
             let total = fm.occ(c, n);
-            let manual: u32 = fm.bwt.iter().filter(|&&b| b == c).count() as u32;
+            let manual: u32 = fm.bwt.iter().filter(|&b| b == c).count() as u32;
             assert_eq!(total, manual, "occ mismatch for c={}", c);
         }
     }
@@ -568,9 +1218,9 @@ mod tests {
     fn fm_occ_boundary_values() {
         let fm = build_toy_fm(&[1, 1, 1, 2, 2, 3]);
         assert_eq!(fm.occ(1, 0), 0);
-        assert_eq!(fm.occ(1, 1), fm.bwt[..1].iter().filter(|&&b| b == 1).count() as u32);
+        assert_eq!(fm.occ(1, 1), u32::from(fm.bwt.get(0) == 1));
         let n = fm.bwt.len();
-        let total_a = fm.bwt.iter().filter(|&&b| b == 1).count() as u32;
+        let total_a = fm.bwt.iter().filter(|&b| b == 1).count() as u32;
         assert_eq!(fm.occ(1, n), total_a);
     }
 
@@ -586,12 +1236,28 @@ mod tests {
         let path = tmp.to_str().unwrap();
         fm.save_to_file(path).unwrap();
         let loaded = FMIndex::load_from_file(path).unwrap();
-        let meta = loaded.meta.unwrap();
+        let meta = loaded.meta().unwrap();
         assert_eq!(meta.reference_file.as_deref(), Some("test.fa"));
         assert_eq!(meta.build_args.as_deref(), Some("bwa-rust index test.fa"));
         std::fs::remove_file(path).ok();
     }
 
+    #[test]
+    fn reference_warning_flags_reference_modified_after_build() {
+        let reference = std::env::temp_dir().join(format!("bwa_rust_meta_ref_{}.fa", std::process::id()));
+        std::fs::write(&reference, ">chr1\nACGT\n").unwrap();
+        let meta = |stamp: &str| IndexMeta {
+            reference_file: Some(reference.to_string_lossy().into_owned()),
+            build_args: None,
+            build_timestamp: Some(stamp.to_string()),
+        };
+        assert!(meta("2000-01-01T00:00:00Z").reference_warning().is_some());
+        assert!(meta("2999-01-01T00:00:00Z").reference_warning().is_none());
+        std::fs::remove_file(&reference).ok();
+        // 参考文件已不存在：不警告
+        assert!(meta("2000-01-01T00:00:00Z").reference_warning().is_none());
+    }
+
     #[test]
     #[should_panic(expected = "block size must be greater than zero")]
     fn fm_build_rejects_zero_block_size() {
@@ -607,26 +1273,360 @@ mod tests {
     }
 
     #[test]
-    fn fm_load_rejects_zero_block_size() {
+    fn fm_load_rejects_bwt_sigma_mismatch() {
         let mut fm = build_toy_fm(&[1, 2, 3, 4]);
-        fm.block = 0;
+        fm.bwt = RankBwt::new(&fm.bwt.to_vec(), 7, 4);
         let tmp = std::env::temp_dir().join("bwa_rust_test_fm_invalid_block.fm");
         let path = tmp.to_str().unwrap();
         fm.save_to_file(path).unwrap();
         let err = FMIndex::load_from_file(path).unwrap_err();
-        assert!(err.to_string().contains("block size"));
+        assert!(err.to_string().contains("alphabet size"), "{}", err);
         std::fs::remove_file(path).ok();
     }
 
     #[test]
-    fn fm_load_rejects_invalid_occ_samples_len() {
+    fn fm_load_rejects_bwt_length_mismatch() {
         let mut fm = build_toy_fm(&[1, 2, 3, 4]);
-        fm.occ_samples.pop();
+        let bwt = fm.bwt.to_vec();
+        fm.bwt = RankBwt::new(&bwt[..bwt.len() - 1], 6, 4);
         let tmp = std::env::temp_dir().join("bwa_rust_test_fm_invalid_occ.fm");
         let path = tmp.to_str().unwrap();
         fm.save_to_file(path).unwrap();
         let err = FMIndex::load_from_file(path).unwrap_err();
-        assert!(err.to_string().contains("occ_samples"));
+        assert!(err.to_string().contains("BWT/text length mismatch"), "{}", err);
         std::fs::remove_file(path).ok();
     }
+
+    #[test]
+    fn fm_flags_positions_inside_n_runs() {
+        // ACGT + 12×N（缺口）+ ACGT + NN（短 N，不视为缺口）
+        let mut text = vec![1u8, 2, 3, 4];
+        text.extend(std::iter::repeat(5u8).take(12));
+        text.extend_from_slice(&[1, 2, 3, 4, 5, 5]);
+        let fm = build_toy_fm(&text);
+
+        assert_eq!(fm.n_runs(), &[(4, 16)]);
+        assert_eq!(fm.map_text_pos_checked(3), Some((0, 3, false)));
+        assert_eq!(fm.map_text_pos_checked(4), Some((0, 4, true)));
+        assert_eq!(fm.map_text_pos_checked(15), Some((0, 15, true)));
+        assert_eq!(fm.map_text_pos_checked(16), Some((0, 16, false)));
+        assert_eq!(fm.map_text_pos_checked(20), Some((0, 20, false)));
+        assert_eq!(fm.map_text_pos_checked(22), None);
+
+        let path = std::env::temp_dir().join(format!("bwa_rust_n_runs_{}.fm", std::process::id()));
+        fm.save_to_file(&path).unwrap();
+        let loaded = FMIndex::load_from_file(&path).unwrap();
+        assert_eq!(loaded.n_runs(), fm.n_runs());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn longest_prefix_match_stops_where_read_diverges() {
+        // ACGTTGCA + 哨兵
+        let fm = build_toy_fm(&[1, 2, 3, 4, 4, 3, 2, 1]);
+        // 前半 ACGT 与参考一致，后半 AAAA 偏离：ACGTA 不存在
+        let query = [1u8, 2, 3, 4, 1, 1, 1, 1];
+        let (len, iv) = fm.longest_prefix_match(&query);
+        assert_eq!(len, 4);
+        let (l, r) = iv.unwrap();
+        assert_eq!(r - l, 1);
+        assert_eq!(fm.sa_interval_positions(l, r), vec![0]);
+
+        // 完整匹配
+        let (len, iv) = fm.longest_prefix_match(&[4, 3, 2]);
+        assert_eq!(len, 3);
+        assert_eq!(iv, fm.backward_search(&[4, 3, 2]));
+
+        // 首符号即无匹配 / 空 query / 哨兵截断
+        assert_eq!(fm.longest_prefix_match(&[5, 1]), (0, None));
+        assert_eq!(fm.longest_prefix_match(&[]), (0, None));
+        assert_eq!(fm.longest_prefix_match(&[1, 2, 0, 3]).0, 2);
+    }
+
+    #[test]
+    fn reconstruct_text_roundtrips_through_lf() {
+        let fm = build_toy_fm(&[1, 2, 3, 4, 1, 2, 3, 4, 4, 1]);
+        assert_eq!(fm.reconstruct_text(), fm.text.to_vec());
+        // 第 0 行为末尾哨兵后缀，LF 一步得到以最后一个碱基开头的后缀
+        assert_eq!(fm.sa_value(fm.lf(0)), fm.sa_value(0) - 1);
+
+        // 多 contig（含 N 区间）+ 稀疏 SA
+        let fasta = ">a\nACGTTGCAAC\n>b\nGGNNNNNNNNNNNNTA\n>c\nT\n";
+        let mut fm = crate::index::builder::build_fm_index(std::io::Cursor::new(fasta), 4)
+            .unwrap()
+            .fm;
+        fm.sparsify_sa(3);
+        assert_eq!(fm.reconstruct_text(), fm.text.to_vec());
+    }
+
+    #[test]
+    fn search_with_mismatches_finds_substituted_occurrences() {
+        // ACGTACGTTAGGCAT
+        let fm = build_toy_fm(&[1, 2, 3, 4, 1, 2, 3, 4, 4, 1, 3, 3, 2, 1, 4]);
+        let positions = |hits: &[(usize, usize, u32)], mm: u32| -> Vec<u32> {
+            let mut pos: Vec<u32> = hits
+                .iter()
+                .filter(|h| h.2 == mm)
+                .flat_map(|&(l, r, _)| fm.sa_interval_positions(l, r))
+                .collect();
+            pos.sort_unstable();
+            pos
+        };
+
+        // 0 个替换：与精确搜索一致
+        let exact = fm.search_with_mismatches(&[2, 3, 4, 4], 0);
+        let (l, r) = fm.backward_search(&[2, 3, 4, 4]).unwrap();
+        assert_eq!(exact, vec![(l, r, 0)]);
+        assert!(fm.search_with_mismatches(&[4, 4, 4, 4], 0).is_empty());
+
+        // 1 个替换：CGTC 不存在，CGTA(1)、CGTT(5) 各差一个碱基
+        let hits = fm.search_with_mismatches(&[2, 3, 4, 2], 1);
+        assert!(positions(&hits, 0).is_empty());
+        assert_eq!(positions(&hits, 1), vec![1, 5]);
+
+        // 2 个替换：CGTAG 与 CGTAC(1) 差 1，与 CGTTA(5)、GTTAG(6) 差 2
+        let hits = fm.search_with_mismatches(&[2, 3, 4, 1, 3], 2);
+        assert!(positions(&hits, 0).is_empty());
+        assert_eq!(positions(&hits, 1), vec![1]);
+        assert_eq!(positions(&hits, 2), vec![5, 6]);
+        assert!(hits.windows(2).all(|w| w[0].2 <= w[1].2));
+
+        // N 只能以替换方式通过
+        let hits = fm.search_with_mismatches(&[1, 3, 5, 2], 1);
+        assert_eq!(positions(&hits, 1), vec![9]);
+    }
+
+    #[test]
+    fn count_kmers_matches_per_window_backward_search() {
+        // ACGTACGTTA
+        let fm = build_toy_fm(&[1, 2, 3, 4, 1, 2, 3, 4, 4, 1]);
+        assert_eq!(fm.count(&[1, 2, 3, 4]), 2);
+        assert_eq!(fm.count(&[4, 4, 4]), 0);
+        assert_eq!(fm.count(&[]), 11);
+
+        // 含缺失子串（GG）、未知符号（N = 5）与哨兵的查询
+        let seq = [1u8, 2, 3, 3, 4, 1, 2, 3, 4, 5, 4, 1, 0, 1];
+        for k in 1..=5 {
+            let expected: Vec<usize> = seq.windows(k).map(|w| fm.count(w)).collect();
+            assert_eq!(fm.count_kmers(&seq, k), expected, "k={}", k);
+        }
+        assert_eq!(fm.count_kmers(&[1, 2, 3, 4, 4], 2), vec![2, 2, 2, 1]);
+        assert!(fm.count_kmers(&seq, 0).is_empty());
+        assert!(fm.count_kmers(&[1, 2], 3).is_empty());
+    }
+
+    #[test]
+    fn fm_custom_alphabet_build_search_and_roundtrip() {
+        // 4 符号自定义字母表（非 DNA），两条序列以哨兵分隔
+        let alphabet = AlphabetSpec::new(b"WXYZ").unwrap();
+        let mut text = alphabet.encode_seq(b"WXYZZYXW").unwrap();
+        text.push(alphabet.sentinel);
+        text.extend(alphabet.encode_seq(b"ZZWX").unwrap());
+        text.push(alphabet.sentinel);
+        let contigs = vec![
+            Contig {
+                name: "s1".to_string(),
+                len: 8,
+                offset: 0,
+            },
+            Contig {
+                name: "s2".to_string(),
+                len: 4,
+                offset: 9,
+            },
+        ];
+        let sa_arr = sa::build_sa(&text);
+        let bwt_arr = bwt::build_bwt(&text, &sa_arr);
+        let fm = FMIndex::build_with_alphabet(text, bwt_arr, sa_arr, contigs, alphabet.clone(), 4);
+        assert_eq!(fm.sigma, 5);
+
+        let search = |fm: &FMIndex, pat: &[u8]| -> Vec<u32> {
+            let codes = fm.alphabet.encode_seq(pat).unwrap();
+            match fm.backward_search(&codes) {
+                Some((l, r)) => {
+                    let mut pos = fm.sa_interval_positions(l, r);
+                    pos.sort_unstable();
+                    pos
+                }
+                None => Vec::new(),
+            }
+        };
+        assert_eq!(search(&fm, b"WX"), vec![0, 11]);
+        assert_eq!(search(&fm, b"ZZ"), vec![3, 9]);
+        assert_eq!(search(&fm, b"XW"), vec![6]);
+        assert!(search(&fm, b"WW").is_empty());
+        // 跨越哨兵的模式不匹配
+        assert!(fm.backward_search(&[1, alphabet.sentinel, 4]).is_none());
+        assert_eq!(fm.decode_text(9, 13), b"ZZWX".to_vec());
+        assert!(fm.n_runs().is_empty());
+
+        let path = std::env::temp_dir().join(format!("bwa_rust_fm_alpha_{}.fm", std::process::id()));
+        fm.save_to_file(&path).unwrap();
+        let loaded = FMIndex::load_from_file(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(loaded.alphabet, alphabet);
+        assert_eq!(search(&loaded, b"ZZ"), vec![3, 9]);
+    }
+
+    #[test]
+    fn fm_rejects_unpacked_legacy_versions() {
+        let fm = build_toy_fm(&[1, 2, 3, 4, 1, 2]);
+        let mut bytes = bincode::serialize(&fm).unwrap();
+        let path = std::env::temp_dir().join(format!("bwa_rust_fm_legacy_{}.fm", std::process::id()));
+        for version in 1..FM_VERSION {
+            bytes[8..12].copy_from_slice(&version.to_le_bytes());
+            std::fs::write(&path, &bytes).unwrap();
+            let err = FMIndex::load_from_file(&path).unwrap_err().to_string();
+            assert!(err.contains(&format!("version {}", version)), "{}", err);
+            assert!(err.contains("rebuild the index"), "{}", err);
+        }
+
+        bytes[8..12].copy_from_slice(&99u32.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        let err = FMIndex::load_from_file(&path).unwrap_err().to_string();
+        std::fs::remove_file(&path).ok();
+        assert!(err.contains("unsupported FM index version"), "{}", err);
+    }
+
+    #[test]
+    fn non_unique_regions_reports_duplicated_segment() {
+        use crate::util::dna;
+        // chr1 中 dup 段出现两次，chr2 为唯一序列
+        let dup = b"GATTACAGGCTT";
+        let mut chr1 = b"CCTAGCAATG".to_vec();
+        chr1.extend_from_slice(dup);
+        chr1.extend_from_slice(b"TCGGATCCAC");
+        chr1.extend_from_slice(dup);
+        chr1.extend_from_slice(b"AACGTTCA");
+        let chr2 = b"TGCATCGGTACCGTAGAC";
+
+        let mut text: Vec<u8> = chr1.iter().map(|&b| dna::to_alphabet(b)).collect();
+        text.push(0);
+        text.extend(chr2.iter().map(|&b| dna::to_alphabet(b)));
+        text.push(0);
+        let contigs = vec![
+            Contig {
+                name: "chr1".to_string(),
+                len: chr1.len() as u32,
+                offset: 0,
+            },
+            Contig {
+                name: "chr2".to_string(),
+                len: chr2.len() as u32,
+                offset: chr1.len() as u32 + 1,
+            },
+        ];
+        let sa_arr = sa::build_sa(&text);
+        let bwt_arr = bwt::build_bwt(&text, &sa_arr);
+        let fm = FMIndex::build(text, bwt_arr, sa_arr, contigs, 6, 4);
+
+        let regions = fm.non_unique_regions(8);
+        assert_eq!(regions, vec![(0, 10, 22), (0, 32, 44)]);
+        assert!(fm.non_unique_regions(0).is_empty());
+        assert!(fm.non_unique_regions(100).is_empty());
+    }
+
+    #[test]
+    fn locate_matches_full_sa_for_every_rate() {
+        use crate::util::dna;
+        let mut text: Vec<u8> = b"GATTACAGGATTACACCGTAGATTA".iter().map(|&b| dna::to_alphabet(b)).collect();
+        text.push(0);
+        let second_offset = text.len() as u32;
+        text.extend(b"TTAGATTACAGG".iter().map(|&b| dna::to_alphabet(b)));
+        text.push(0);
+        let contigs = vec![
+            Contig {
+                name: "c1".to_string(),
+                len: 25,
+                offset: 0,
+            },
+            Contig {
+                name: "c2".to_string(),
+                len: 12,
+                offset: second_offset,
+            },
+        ];
+        let sa_arr = sa::build_sa(&text);
+        let bwt_arr = bwt::build_bwt(&text, &sa_arr);
+        let pattern: Vec<u8> = b"GATTA".iter().map(|&b| dna::to_alphabet(b)).collect();
+
+        for rate in [1u32, 2, 3, 8, 64] {
+            let mut fm = FMIndex::build(text.clone(), bwt_arr.clone(), sa_arr.clone(), contigs.clone(), 6, 4);
+            fm.sparsify_sa(rate);
+            assert_eq!(fm.sa.len(), (sa_arr.len() + rate as usize - 1) / rate as usize);
+            assert_eq!(fm.locate(0, sa_arr.len()), sa_arr, "rate {}", rate);
+
+            let (l, r) = fm.backward_search(&pattern).unwrap();
+            let mut hits = fm.sa_interval_positions(l, r);
+            hits.sort_unstable();
+            assert_eq!(hits, vec![0, 8, 20, second_offset + 3], "rate {}", rate);
+        }
+    }
+
+    #[test]
+    fn mmap_layout_roundtrips_with_borrowed_arrays() {
+        let mut fasta = Vec::new();
+        for (i, seed) in [21u32, 22, 23].into_iter().enumerate() {
+            let mut seq = crate::testutil::random_dna(seed, 900);
+            seq[300..330].fill(b'N');
+            fasta.extend(format!(">c{}\n", i).bytes());
+            fasta.extend(seq);
+            fasta.push(b'\n');
+        }
+        for rate in [1u32, 4] {
+            let mut fm = crate::index::builder::build_fm_index_sampled(std::io::Cursor::new(&fasta[..]), 64, rate)
+                .unwrap()
+                .fm;
+            fm.set_meta(IndexMeta {
+                reference_file: Some("ref.fa".to_string()),
+                ..IndexMeta::default()
+            });
+            let path = std::env::temp_dir().join(format!("bwa_rust_mmap_{}_{}.fm", rate, std::process::id()));
+            fm.save_mmap_layout(&path).unwrap();
+            let mapped = FMIndex::mmap_from_file(&path).unwrap();
+            // load_from_file 按魔数识别映射布局
+            let via_load = FMIndex::load_from_file(&path).unwrap();
+            std::fs::remove_file(&path).ok();
+
+            for idx in [&mapped, &via_load] {
+                assert!(idx.c.is_mapped() && idx.sa.is_mapped());
+                assert_eq!(idx.c, fm.c);
+                assert_eq!(idx.sa, fm.sa);
+                assert_eq!(idx.bwt, fm.bwt);
+                assert_eq!(idx.sa_sample_rate, fm.sa_sample_rate);
+                assert_eq!(idx.n_runs(), fm.n_runs());
+                assert_eq!(idx.meta().unwrap().reference_file.as_deref(), Some("ref.fa"));
+                let n = fm.bwt.len();
+                assert_eq!(idx.locate(0, n), fm.locate(0, n));
+                let pat: Vec<u8> = fasta[500..530].iter().map(|&b| crate::util::dna::to_alphabet(b)).collect();
+                assert_eq!(idx.backward_search(&pat), fm.backward_search(&pat));
+            }
+
+            // 映射索引仍可另存为 bincode 格式
+            let copy = std::env::temp_dir().join(format!("bwa_rust_mmap_copy_{}_{}.fm", rate, std::process::id()));
+            mapped.save_to_file(&copy).unwrap();
+            let owned = FMIndex::load_from_file(&copy).unwrap();
+            std::fs::remove_file(&copy).ok();
+            assert!(!owned.sa.is_mapped());
+            assert_eq!(owned.sa, fm.sa);
+            assert_eq!(owned.bwt, fm.bwt);
+        }
+    }
+
+    #[test]
+    fn mmap_from_file_rejects_truncated_or_foreign_files() {
+        let fm = build_toy_fm(&[1, 2, 3, 4, 1, 2]);
+        let path = std::env::temp_dir().join(format!("bwa_rust_mmap_bad_{}.fm", std::process::id()));
+        fm.save_mmap_layout(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 4]).unwrap();
+        let err = FMIndex::mmap_from_file(&path).unwrap_err().to_string();
+        assert!(err.contains("past the end"), "{}", err);
+        std::fs::write(&path, &bytes[..20]).unwrap();
+        assert!(FMIndex::mmap_from_file(&path).is_err());
+        fm.save_to_file(&path).unwrap();
+        let err = FMIndex::mmap_from_file(&path).unwrap_err().to_string();
+        assert!(err.contains("not a memory-mapped layout"), "{}", err);
+        std::fs::remove_file(&path).ok();
+    }
 }
@@ -0,0 +1,220 @@
+//! 内存映射的索引文件与其上的零拷贝数组视图。
+//!
+//! crate 中仅此模块允许 `unsafe`：映射文件本身（[`map_file`]）以及把映射中的对齐区段
+//! 解释为整数切片（[`Slab`] 的 `Deref`）。
+
+#![allow(unsafe_code)]
+
+use std::fs::File;
+use std::io::Write;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use memmap2::Mmap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// 可直接从映射中按小端读取的定宽无符号整数：任意位模式都是合法值
+pub trait Scalar: Copy + private::Sealed + 'static {
+    /// 按小端写出，供 [`Slab::mapped`] 读回
+    fn write_le(values: &[Self], w: &mut impl Write) -> std::io::Result<()>;
+}
+
+macro_rules! impl_scalar {
+    ($($t:ty),*) => {$(
+        impl Scalar for $t {
+            fn write_le(values: &[Self], w: &mut impl Write) -> std::io::Result<()> {
+                values.iter().try_for_each(|v| w.write_all(&v.to_le_bytes()))
+            }
+        }
+    )*};
+}
+
+impl_scalar!(u16, u32, u64);
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for u16 {}
+    impl Sealed for u32 {}
+    impl Sealed for u64 {}
+}
+
+/// 只读映射整个文件；映射期间文件不应被其他进程截断或改写
+pub fn map_file(path: &Path) -> Result<Arc<Mmap>> {
+    let file = File::open(path)?;
+    // SAFETY: 映射以只读方式打开，之后只通过 `Slab` 的边界与对齐检查访问；
+    // 与所有基于 mmap 的读取一样，要求文件在映射期间不被外部修改
+    let map = unsafe { Mmap::map(&file)? };
+    Ok(Arc::new(map))
+}
+
+/// 整数数组：构建时为自有的 `Vec`，从内存映射加载时为映射中的一段（按需分页，不复制）。
+///
+/// 两种形式都解引用为 `&[T]`，且解引用不分支（热路径上的 rank 查询逐次经过这里）；
+/// 序列化格式与 `Vec<T>` 相同，反序列化总是得到自有数组。
+pub struct Slab<T: Scalar> {
+    /// 指向 `owner` 所持有内存中的首元素
+    ptr: *const T,
+    len: usize,
+    owner: Owner<T>,
+}
+
+/// 持有 [`Slab`] 所指向的内存
+enum Owner<T> {
+    Vec(Vec<T>),
+    Map(Arc<Mmap>),
+}
+
+// SAFETY: `Slab` 只读访问 `owner` 持有的内存，`Vec<T>` 与 `Arc<Mmap>` 均可跨线程发送与共享
+unsafe impl<T: Scalar> Send for Slab<T> {}
+unsafe impl<T: Scalar> Sync for Slab<T> {}
+
+impl<T: Scalar> Slab<T> {
+    /// 映射中从字节偏移 `offset` 起的 `len` 个元素；要求小端平台、偏移按 `T` 对齐且不越界
+    pub fn mapped(map: &Arc<Mmap>, offset: usize, len: usize) -> Result<Self> {
+        if cfg!(target_endian = "big") {
+            return Err(anyhow!("memory-mapped indexes require a little-endian platform"));
+        }
+        let size = std::mem::size_of::<T>();
+        let end = len
+            .checked_mul(size)
+            .and_then(|bytes| bytes.checked_add(offset))
+            .ok_or_else(|| anyhow!("mapped section length overflows"))?;
+        if end > map.len() {
+            return Err(anyhow!(
+                "mapped section at byte {} runs past the end of the file",
+                offset
+            ));
+        }
+        // SAFETY: `offset <= end <= map.len()`，指针仍位于映射之内（或恰在末尾）
+        let ptr = unsafe { map.as_ptr().add(offset) };
+        if (ptr as usize) % std::mem::align_of::<T>() != 0 {
+            return Err(anyhow!("mapped section at byte {} is not aligned", offset));
+        }
+        Ok(Self {
+            ptr: ptr.cast::<T>(),
+            len,
+            owner: Owner::Map(Arc::clone(map)),
+        })
+    }
+
+    /// 是否为内存映射中的视图
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.owner, Owner::Map(_))
+    }
+}
+
+impl<T: Scalar> Deref for Slab<T> {
+    type Target = [T];
+
+    #[inline]
+    fn deref(&self) -> &[T] {
+        // SAFETY: `ptr` 指向 `owner` 持有的 `len` 个元素：自有数组取自 `Vec::as_ptr`，移动 `Vec`
+        // 不会移动其堆内存，且此后不再修改；映射区段已由 `Slab::mapped` 检查边界、对齐与小端平台。
+        // `T` 为定宽整数，任意位模式均合法；切片生命周期不超过 `self`
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<T: Scalar> From<Vec<T>> for Slab<T> {
+    fn from(v: Vec<T>) -> Self {
+        Self {
+            ptr: v.as_ptr(),
+            len: v.len(),
+            owner: Owner::Vec(v),
+        }
+    }
+}
+
+impl<T: Scalar> Default for Slab<T> {
+    fn default() -> Self {
+        Vec::new().into()
+    }
+}
+
+impl<T: Scalar> Clone for Slab<T> {
+    fn clone(&self) -> Self {
+        match &self.owner {
+            Owner::Vec(v) => v.clone().into(),
+            Owner::Map(map) => Self {
+                ptr: self.ptr,
+                len: self.len,
+                owner: Owner::Map(Arc::clone(map)),
+            },
+        }
+    }
+}
+
+impl<T: Scalar + std::fmt::Debug> std::fmt::Debug for Slab<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Scalar + PartialEq> PartialEq for Slab<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: Scalar + Eq> Eq for Slab<T> {}
+
+impl<T: Scalar + PartialEq> PartialEq<Vec<T>> for Slab<T> {
+    fn eq(&self, other: &Vec<T>) -> bool {
+        **self == other[..]
+    }
+}
+
+impl<T: Scalar + Serialize> Serialize for Slab<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        (**self).serialize(serializer)
+    }
+}
+
+impl<'de, T: Scalar + Deserialize<'de>> Deserialize<'de> for Slab<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Self::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_map(name: &str, bytes: &[u8]) -> Arc<Mmap> {
+        let path = std::env::temp_dir().join(format!("bwa_rust_slab_{}_{}", name, std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        let map = map_file(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        map
+    }
+
+    #[test]
+    fn mapped_slab_reads_little_endian_values() {
+        let mut bytes = vec![0xffu8; 8];
+        for v in [1u32, 2, 0xdead_beef] {
+            bytes.extend(v.to_le_bytes());
+        }
+        let map = temp_map("read", &bytes);
+        let slab: Slab<u32> = Slab::mapped(&map, 8, 3).unwrap();
+        assert!(slab.is_mapped());
+        assert_eq!(slab, vec![1, 2, 0xdead_beef]);
+        assert_eq!(slab.clone(), Slab::from(vec![1, 2, 0xdead_beef]));
+        // 序列化格式与 Vec 相同
+        let encoded = bincode::serialize(&slab).unwrap();
+        assert_eq!(encoded, bincode::serialize(&vec![1u32, 2, 0xdead_beef]).unwrap());
+        let decoded: Slab<u32> = bincode::deserialize(&encoded).unwrap();
+        assert!(!decoded.is_mapped());
+        assert_eq!(decoded, slab);
+    }
+
+    #[test]
+    fn mapped_slab_rejects_misaligned_or_out_of_range_sections() {
+        let map = temp_map("bounds", &[0u8; 32]);
+        assert!(Slab::<u64>::mapped(&map, 4, 1).is_err());
+        assert!(Slab::<u32>::mapped(&map, 28, 2).is_err());
+        assert!(Slab::<u16>::mapped(&map, 0, usize::MAX).is_err());
+        assert!(Slab::<u64>::mapped(&map, 24, 1).is_ok());
+    }
+}
@@ -1,4 +1,12 @@
+pub mod alphabet;
+pub mod bifm;
 pub mod builder;
 pub mod bwt;
 pub mod fm;
+pub mod mmap;
+pub mod packed;
+pub mod rank;
 pub mod sa;
+pub mod shard;
+
+pub use bwt::build_bwt_and_sparse_sa;
@@ -0,0 +1,223 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// 以 2 bit 直接存储的编码区间：`1..=4`（DNA 的 A/C/G/T）
+const FIRST_PACKED: u8 = 1;
+const PACKED_CODES: u8 = 4;
+
+/// 2-bit 压缩的字母表编码序列。
+///
+/// - 编码 `1..=4`（DNA 的 A/C/G/T）按每字符 2 bit 存入 `bases`
+/// - 其余编码（哨兵 `$`、`N` 以及自定义字母表中的更大编码）在 `special` 位图中标记，
+///   其取值按连续相同编码的区间存入 `runs`；参考序列中这类位置通常集中在少数长 N 区间
+///   和 contig 分隔符上，区间表很小
+///
+/// 相比每字符 1 字节，DNA 文本约占 3/8 的空间。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackedSeq {
+    len: u64,
+    /// 每个 u64 存 32 个字符的 2 bit 编码（低位在前）
+    bases: Vec<u64>,
+    /// 每个 u64 标记 64 个位置是否为特殊编码
+    special: Vec<u64>,
+    /// 特殊编码区间 `(起点, 长度, 编码)`，按起点升序、互不重叠，相邻区间编码不同
+    runs: Vec<(u32, u32, u8)>,
+}
+
+impl PackedSeq {
+    /// 由逐字节编码构建
+    pub fn from_codes(codes: &[u8]) -> Self {
+        let mut seq = Self {
+            bases: Vec::with_capacity((codes.len() + 31) / 32),
+            special: Vec::with_capacity((codes.len() + 63) / 64),
+            ..Self::default()
+        };
+        codes.iter().for_each(|&c| seq.push(c));
+        seq
+    }
+
+    /// 在末尾追加一个编码；逐 contig 流式构建文本时无需先保存逐字节副本
+    pub fn push(&mut self, code: u8) {
+        let i = self.len();
+        if i % 32 == 0 {
+            self.bases.push(0);
+        }
+        if i % 64 == 0 {
+            self.special.push(0);
+        }
+        self.len += 1;
+        if is_packed(code) {
+            self.bases[i / 32] |= u64::from(code - FIRST_PACKED) << (2 * (i % 32));
+            return;
+        }
+        self.special[i / 64] |= 1u64 << (i % 64);
+        match self.runs.last_mut() {
+            Some((start, len, c)) if *c == code && (*start + *len) as usize == i => *len += 1,
+            _ => self.runs.push((i as u32, 1, code)),
+        }
+    }
+
+    /// 字符数
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 位置 `pos` 处的字母表编码；越界时 panic
+    #[inline]
+    pub fn get(&self, pos: usize) -> u8 {
+        assert!(
+            pos < self.len(),
+            "PackedSeq index {} out of range (len {})",
+            pos,
+            self.len
+        );
+        if self.special[pos / 64] >> (pos % 64) & 1 == 1 {
+            let k = self.runs.partition_point(|&(start, _, _)| start as usize <= pos);
+            return self.runs[k - 1].2;
+        }
+        ((self.bases[pos / 32] >> (2 * (pos % 32))) & 3) as u8 + FIRST_PACKED
+    }
+
+    /// 解压区间 `[start, end)` 为逐字节编码
+    pub fn unpack(&self, start: usize, end: usize) -> Vec<u8> {
+        assert!(
+            start <= end && end <= self.len(),
+            "PackedSeq range {}..{} out of range",
+            start,
+            end
+        );
+        let mut out: Vec<u8> = (start..end)
+            .map(|i| ((self.bases[i / 32] >> (2 * (i % 32))) & 3) as u8 + FIRST_PACKED)
+            .collect();
+        let first = self.runs.partition_point(|&(s, l, _)| ((s + l) as usize) <= start);
+        for &(s, l, code) in &self.runs[first..] {
+            let (s, e) = (s as usize, (s + l) as usize);
+            if s >= end {
+                break;
+            }
+            out[s.max(start) - start..e.min(end) - start].fill(code);
+        }
+        out
+    }
+
+    /// 解压全部字符
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.unpack(0, self.len())
+    }
+
+    /// 按位置顺序遍历编码
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.len()).map(move |i| self.get(i))
+    }
+
+    /// 编码为 `code` 的极大连续区间 `[start, end)`（按起点升序）
+    pub fn runs_of(&self, code: u8) -> Vec<(u32, u32)> {
+        if !is_packed(code) {
+            return self
+                .runs
+                .iter()
+                .filter(|&&(_, _, c)| c == code)
+                .map(|&(s, l, _)| (s, s + l))
+                .collect();
+        }
+        let mut out: Vec<(u32, u32)> = Vec::new();
+        for i in 0..self.len() {
+            if self.get(i) != code {
+                continue;
+            }
+            match out.last_mut() {
+                Some((_, end)) if *end as usize == i => *end += 1,
+                _ => out.push((i as u32, i as u32 + 1)),
+            }
+        }
+        out
+    }
+
+    /// 检查反序列化结果的内部一致性
+    pub fn validate(&self) -> Result<()> {
+        let n = self.len();
+        if self.bases.len() != (n + 31) / 32 || self.special.len() != (n + 63) / 64 {
+            return Err(anyhow!("packed text storage does not match its length"));
+        }
+        let mut prev_end = 0usize;
+        for &(start, len, code) in &self.runs {
+            let (s, e) = (start as usize, start as usize + len as usize);
+            if len == 0 || s < prev_end || e > n || is_packed(code) {
+                return Err(anyhow!("packed text run at {} is invalid", start));
+            }
+            prev_end = e;
+        }
+        let marked: u64 = self.special.iter().map(|w| u64::from(w.count_ones())).sum();
+        let covered: u64 = self.runs.iter().map(|&(_, len, _)| u64::from(len)).sum();
+        if marked != covered {
+            return Err(anyhow!("packed text special-position bitmap does not match its runs"));
+        }
+        Ok(())
+    }
+}
+
+#[inline]
+fn is_packed(code: u8) -> bool {
+    (FIRST_PACKED..FIRST_PACKED + PACKED_CODES).contains(&code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_seq_roundtrip_and_get() {
+        // 跨越多个 u64 字，含哨兵、N 区间与超出 2 bit 范围的编码
+        let mut codes: Vec<u8> = (0..150).map(|i| (i * 7 % 4 + 1) as u8).collect();
+        codes[0] = 0;
+        codes[40..60].fill(5);
+        codes[63] = 0;
+        codes[64] = 0;
+        codes[100] = 9;
+        codes.push(0);
+        let packed = PackedSeq::from_codes(&codes);
+        assert_eq!(packed.len(), codes.len());
+        assert_eq!(packed.to_vec(), codes);
+        for (i, &c) in codes.iter().enumerate() {
+            assert_eq!(packed.get(i), c, "pos {}", i);
+        }
+        assert_eq!(packed.unpack(35, 70), codes[35..70].to_vec());
+        assert_eq!(packed.unpack(45, 45), Vec::<u8>::new());
+        assert_eq!(packed.runs_of(5), vec![(40, 60)]);
+        assert_eq!(packed.runs_of(0), vec![(0, 1), (63, 65), (150, 151)]);
+        assert!(packed.validate().is_ok());
+    }
+
+    #[test]
+    fn packed_seq_is_smaller_than_bytes() {
+        let codes: Vec<u8> = (0..10_000).map(|i| (i % 4 + 1) as u8).collect();
+        let packed = PackedSeq::from_codes(&codes);
+        let bytes = bincode::serialized_size(&packed).unwrap();
+        assert!(bytes * 2 < codes.len() as u64, "{} bytes", bytes);
+        assert_eq!(packed.runs_of(2).len(), 2500);
+    }
+
+    #[test]
+    fn packed_seq_validate_rejects_inconsistent_runs() {
+        let mut packed = PackedSeq::from_codes(&[1, 5, 5, 2, 0]);
+        assert!(packed.validate().is_ok());
+        packed.runs[0].1 = 3;
+        assert!(packed.validate().is_err());
+    }
+
+    #[test]
+    fn push_matches_from_codes() {
+        let codes: Vec<u8> = (0..200u32).map(|i| [1, 2, 5, 5, 3, 0, 4][(i * 13 % 7) as usize]).collect();
+        let mut pushed = PackedSeq::default();
+        codes.iter().for_each(|&c| pushed.push(c));
+        assert_eq!(pushed, PackedSeq::from_codes(&codes));
+        assert_eq!(pushed.iter().collect::<Vec<u8>>(), codes);
+        assert!(pushed.validate().is_ok());
+    }
+}
@@ -0,0 +1,356 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use super::mmap::Slab;
+
+/// 超级块行数上限：细块计数以 `u16` 相对所在超级块起点存储，超级块不能超过 2^16 行
+const SUPERBLOCK_MAX_ROWS: usize = 1 << 16;
+
+/// 位平面压缩的 BWT 与两级 rank 字典。
+///
+/// - BWT 每个符号占 `bits = ⌈log2 sigma⌉` 位（DNA 字母表为 3 位），按位平面存储：
+///   每 64 行一组，第 `k` 个 `u64` 保存这 64 个符号的第 `k` 位
+/// - 粗粒度：每个超级块起点保存各符号的 `u64` 累计计数
+/// - 细粒度：每个细块起点保存相对所在超级块起点的 `u16` 计数
+///
+/// `occ(c, pos)` = 超级块计数 + 细块计数 + 块内若干 64 行字的 popcount；
+/// 每个字通过位平面逐位比较得到“等于 c”的掩码，无需逐字节扫描 BWT。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RankBwt {
+    len: u64,
+    sigma: u8,
+    /// 每符号位数（位平面数）
+    bits: u8,
+    /// 细块行数（64 的倍数）
+    block: u32,
+    /// 每个超级块包含的细块数
+    blocks_per_super: u32,
+    /// 位平面：第 `w` 个 64 行字的第 `k` 位平面为 `planes[w * bits + k]`
+    planes: Slab<u64>,
+    /// 超级块起点的累计计数：`super_counts[sb * sigma + c]`
+    super_counts: Slab<u64>,
+    /// 细块起点相对所在超级块起点的计数：`block_counts[b * sigma + c]`
+    block_counts: Slab<u16>,
+}
+
+/// [`RankBwt`] 的标量参数，与三个数组分开保存时使用（见 [`FMIndex::save_mmap_layout`](super::fm::FMIndex::save_mmap_layout)）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RankShape {
+    len: u64,
+    sigma: u8,
+    bits: u8,
+    block: u32,
+    blocks_per_super: u32,
+}
+
+impl RankBwt {
+    /// 由逐字节 BWT 构建；`block` 为细块采样间隔（行），向上取整到 64 的倍数，
+    /// 上限 2^15。BWT 中的符号须小于 `sigma`。
+    pub fn new(bwt: &[u8], sigma: u8, block: usize) -> Self {
+        Self::from_symbols(bwt.len(), bwt.iter().copied(), sigma, block)
+    }
+
+    /// 由按行顺序产生的 `n` 个 BWT 符号构建，无需逐字节的 BWT 副本（参数同 [`RankBwt::new`]）
+    pub fn from_symbols(n: usize, symbols: impl IntoIterator<Item = u8>, sigma: u8, block: usize) -> Self {
+        assert!(sigma > 0, "sigma must be greater than zero");
+        let sigma_us = sigma as usize;
+        let bits = bits_for(sigma);
+        let block = fine_block_rows(block);
+        let blocks_per_super = SUPERBLOCK_MAX_ROWS / block;
+        let num_blocks = n / block + 1;
+
+        let mut symbols = symbols.into_iter();
+        let mut planes = vec![0u64; (n / 64 + 1) * bits as usize];
+        let mut super_counts = vec![0u64; (num_blocks + blocks_per_super - 1) / blocks_per_super * sigma_us];
+        let mut block_counts = vec![0u16; num_blocks * sigma_us];
+        let mut running = vec![0u64; sigma_us];
+        let mut at_super = vec![0u64; sigma_us];
+        for b in 0..num_blocks {
+            if b % blocks_per_super == 0 {
+                let sb = b / blocks_per_super;
+                super_counts[sb * sigma_us..(sb + 1) * sigma_us].copy_from_slice(&running);
+                at_super.copy_from_slice(&running);
+            }
+            for c in 0..sigma_us {
+                block_counts[b * sigma_us + c] = (running[c] - at_super[c]) as u16;
+            }
+            let start = b * block;
+            let end = (start + block).min(n);
+            for i in start..end {
+                let ch = symbols.next().expect("BWT shorter than its declared length");
+                assert!(ch < sigma, "BWT symbol {} out of range at {}", ch, i);
+                running[ch as usize] += 1;
+                let w = i / 64 * bits as usize;
+                for k in 0..bits as usize {
+                    planes[w + k] |= u64::from(ch >> k & 1) << (i % 64);
+                }
+            }
+        }
+
+        Self {
+            len: n as u64,
+            sigma,
+            bits,
+            block: block as u32,
+            blocks_per_super: blocks_per_super as u32,
+            planes: planes.into(),
+            super_counts: super_counts.into(),
+            block_counts: block_counts.into(),
+        }
+    }
+
+    /// 由标量参数与三个数组重新组装；须再调用 [`RankBwt::validate`] 检查一致性
+    pub fn from_parts(shape: RankShape, planes: Slab<u64>, super_counts: Slab<u64>, block_counts: Slab<u16>) -> Self {
+        let RankShape {
+            len,
+            sigma,
+            bits,
+            block,
+            blocks_per_super,
+        } = shape;
+        Self {
+            len,
+            sigma,
+            bits,
+            block,
+            blocks_per_super,
+            planes,
+            super_counts,
+            block_counts,
+        }
+    }
+
+    /// 标量参数
+    pub fn shape(&self) -> RankShape {
+        RankShape {
+            len: self.len,
+            sigma: self.sigma,
+            bits: self.bits,
+            block: self.block,
+            blocks_per_super: self.blocks_per_super,
+        }
+    }
+
+    /// 位平面压缩的 BWT（布局见类型文档）
+    pub fn planes(&self) -> &[u64] {
+        &self.planes
+    }
+
+    /// 超级块起点的累计计数
+    pub fn super_counts(&self) -> &[u64] {
+        &self.super_counts
+    }
+
+    /// 细块起点相对所在超级块的计数
+    pub fn block_counts(&self) -> &[u16] {
+        &self.block_counts
+    }
+
+    /// BWT 长度
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 字母表大小
+    #[inline]
+    pub fn sigma(&self) -> u8 {
+        self.sigma
+    }
+
+    /// 细块行数（取整后的采样间隔）
+    #[inline]
+    pub fn block(&self) -> usize {
+        self.block as usize
+    }
+
+    /// 第 `w` 个 64 行字中等于 `c` 的位置掩码
+    #[inline]
+    fn eq_mask(&self, w: usize, c: u8) -> u64 {
+        let bits = self.bits as usize;
+        let word = &self.planes[w * bits..(w + 1) * bits];
+        word.iter()
+            .enumerate()
+            .fold(!0u64, |m, (k, &p)| m & if c >> k & 1 == 1 { p } else { !p })
+    }
+
+    /// BWT\[0..pos) 中 `c` 的出现次数
+    #[inline]
+    pub fn occ(&self, c: u8, pos: usize) -> u32 {
+        let sigma = self.sigma as usize;
+        let b = pos / self.block as usize;
+        let sb = b / self.blocks_per_super as usize;
+        let mut count =
+            self.super_counts[sb * sigma + c as usize] + u64::from(self.block_counts[b * sigma + c as usize]);
+        let end_w = pos / 64;
+        for w in b * self.block as usize / 64..end_w {
+            count += u64::from(self.eq_mask(w, c).count_ones());
+        }
+        let r = pos % 64;
+        if r > 0 {
+            count += u64::from((self.eq_mask(end_w, c) & ((1u64 << r) - 1)).count_ones());
+        }
+        count as u32
+    }
+
+    /// 位置 `i` 处的 BWT 符号；越界时 panic
+    #[inline]
+    pub fn get(&self, i: usize) -> u8 {
+        assert!(i < self.len(), "BWT index {} out of range (len {})", i, self.len);
+        let bits = self.bits as usize;
+        let word = &self.planes[i / 64 * bits..(i / 64 + 1) * bits];
+        word.iter()
+            .enumerate()
+            .fold(0u8, |ch, (k, &p)| ch | (((p >> (i % 64)) & 1) as u8) << k)
+    }
+
+    /// 按行顺序遍历 BWT 符号
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.len()).map(move |i| self.get(i))
+    }
+
+    /// 解压为逐字节 BWT
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.iter().collect()
+    }
+
+    /// 长度为 `n`、字母表大小为 `sigma`、采样间隔为 `block` 的结构占用的
+    /// `(位平面字节数, rank 采样字节数)`
+    pub fn byte_sizes(n: usize, sigma: u8, block: usize) -> (u64, u64) {
+        let bits = bits_for(sigma) as u64;
+        let block = fine_block_rows(block);
+        let blocks_per_super = SUPERBLOCK_MAX_ROWS / block;
+        let num_blocks = (n / block + 1) as u64;
+        let num_super = (num_blocks + blocks_per_super as u64 - 1) / blocks_per_super as u64;
+        let sigma = sigma as u64;
+        (
+            8 * bits * (n as u64 / 64 + 1),
+            8 * sigma * num_super + 2 * sigma * num_blocks,
+        )
+    }
+
+    /// 检查反序列化结果的内部一致性
+    pub fn validate(&self) -> Result<()> {
+        let n = self.len();
+        let block = self.block as usize;
+        if self.sigma == 0 || self.bits != bits_for(self.sigma) {
+            return Err(anyhow!("BWT bit width does not match its alphabet size"));
+        }
+        if block == 0 || block % 64 != 0 || self.blocks_per_super as usize != SUPERBLOCK_MAX_ROWS / block {
+            return Err(anyhow!("BWT rank block layout is invalid"));
+        }
+        let (plane_bytes, sample_bytes) = Self::byte_sizes(n, self.sigma, block);
+        let stored_samples = 8 * self.super_counts.len() as u64 + 2 * self.block_counts.len() as u64;
+        if 8 * self.planes.len() as u64 != plane_bytes || stored_samples != sample_bytes {
+            return Err(anyhow!("BWT rank storage does not match its length"));
+        }
+        Ok(())
+    }
+}
+
+/// 表示 `[0, sigma)` 所需的位数（至少 1）
+fn bits_for(sigma: u8) -> u8 {
+    (u8::BITS - (sigma.max(2) - 1).leading_zeros()) as u8
+}
+
+/// 细块行数：向上取整到 64 的倍数，并保证超级块至少容纳两个细块
+fn fine_block_rows(block: usize) -> usize {
+    ((block.max(1) + 63) / 64 * 64).min(SUPERBLOCK_MAX_ROWS / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::Lcg;
+
+    fn random_bwt(n: usize, sigma: u8, seed: u32) -> Vec<u8> {
+        let mut rng = Lcg::new(seed);
+        (0..n).map(|_| rng.below(sigma as u32) as u8).collect()
+    }
+
+    /// 原分块采样实现：块起点计数 + 块内逐字节扫描
+    fn sampled_occ(bwt: &[u8], sigma: u8, block: usize, c: u8, pos: usize) -> u32 {
+        if pos == 0 {
+            return 0;
+        }
+        let sigma = sigma as usize;
+        let bi = (pos - 1) / block;
+        let mut samples = vec![0u32; sigma];
+        for &ch in &bwt[..bi * block] {
+            samples[ch as usize] += 1;
+        }
+        samples[c as usize] + bwt[bi * block..pos].iter().filter(|&&ch| ch == c).count() as u32
+    }
+
+    #[test]
+    fn occ_matches_sampled_scan_on_random_bwts() {
+        for (n, sigma, block) in [
+            (0, 6, 64),
+            (1, 6, 1),
+            (63, 2, 64),
+            (64, 6, 64),
+            (200, 6, 100),
+            (1000, 255, 512),
+        ] {
+            let bwt = random_bwt(n, sigma, n as u32 + 1);
+            let rank = RankBwt::new(&bwt, sigma, block);
+            assert_eq!(rank.len(), n);
+            assert_eq!(rank.to_vec(), bwt);
+            for c in 0..sigma {
+                for pos in 0..=n {
+                    assert_eq!(
+                        rank.occ(c, pos),
+                        sampled_occ(&bwt, sigma, block.max(1), c, pos),
+                        "n={} sigma={} c={} pos={}",
+                        n,
+                        sigma,
+                        c,
+                        pos
+                    );
+                }
+            }
+            assert!(rank.validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn occ_crosses_superblocks() {
+        // 多个超级块，且超级块大小不是细块的整数倍
+        let n = 3 * SUPERBLOCK_MAX_ROWS + 777;
+        let bwt = random_bwt(n, 6, 42);
+        let rank = RankBwt::new(&bwt, 6, 1000);
+        assert_eq!(rank.block(), 1024);
+        let mut counts = [0u32; 6];
+        for (pos, &ch) in bwt.iter().enumerate() {
+            if pos % 4099 == 0 {
+                for c in 0..6u8 {
+                    assert_eq!(rank.occ(c, pos), counts[c as usize], "c={} pos={}", c, pos);
+                }
+            }
+            counts[ch as usize] += 1;
+        }
+        for c in 0..6u8 {
+            assert_eq!(rank.occ(c, n), counts[c as usize]);
+        }
+    }
+
+    #[test]
+    fn byte_sizes_match_storage_and_beat_byte_bwt() {
+        let bwt = random_bwt(100_000, 6, 7);
+        let rank = RankBwt::new(&bwt, 6, 64);
+        let (planes, samples) = RankBwt::byte_sizes(bwt.len(), 6, 64);
+        assert_eq!(planes, 8 * rank.planes.len() as u64);
+        assert!(planes + samples < bwt.len() as u64, "{} + {}", planes, samples);
+
+        let mut broken = rank.clone();
+        let mut counts = broken.block_counts.to_vec();
+        counts.pop();
+        broken.block_counts = counts.into();
+        assert!(broken.validate().is_err());
+    }
+}
@@ -1,25 +1,35 @@
-/// 构建后缀数组（基于倍增法，O(n log²n) 排序）。
-///
-/// # 算法说明
-///
-/// 使用倍增法（doubling algorithm）构建后缀数组：
-/// - 外层循环 log n 轮，每轮将比较长度翻倍
-/// - 每轮排序 O(n log n)，总复杂度 O(n log²n)
-///
-/// # 性能限制
+use super::packed::PackedSeq;
+
+/// 超过该长度的文本使用 SA-IS 构建，较短文本使用倍增法
+pub const SA_IS_THRESHOLD: usize = 1 << 12;
+
+/// 构建后缀数组。
 ///
-/// 对于人类基因组级别（~3Gbp）的参考序列，构建时间可能超过数分钟。
-/// 未来改进方向：
-/// - SA-IS 算法：O(n) 时间，适合大规模数据
-/// - DC3/DC7 算法：O(n) 时间，适合外部排序
-/// - 集成 `libsais` 等成熟库
+/// 长度不小于 [`SA_IS_THRESHOLD`] 的文本使用线性时间的 SA-IS（[`build_sa_is`]），
+/// 较短文本使用倍增法；两者输出完全一致。
 ///
 /// # 输入要求
 ///
 /// 输入为数值化的文本（如 0:$,1:A,2:C,3:G,4:T,5:N）。
-/// 允许文本中包含多个 0 作为不同 contig 的分隔符。
+/// 允许文本中包含多个 0 作为不同 contig 的分隔符：相同字节按其后的字符继续比较，
+/// 文本末尾视为小于任何字符。
 #[must_use]
 pub fn build_sa(text: &[u8]) -> Vec<u32> {
+    if text.len() >= SA_IS_THRESHOLD {
+        build_sa_is(text)
+    } else {
+        build_sa_doubling(text)
+    }
+}
+
+/// 倍增法构建后缀数组（O(n log²n) 排序）。
+///
+/// # 算法说明
+///
+/// - 外层循环 log n 轮，每轮将比较长度翻倍
+/// - 每轮排序 O(n log n)，总复杂度 O(n log²n)
+#[must_use]
+pub fn build_sa_doubling(text: &[u8]) -> Vec<u32> {
     let n = text.len();
     if n == 0 {
         return Vec::new();
@@ -61,9 +71,344 @@ pub fn build_sa(text: &[u8]) -> Vec<u32> {
     sa.into_iter().map(|x| x as u32).collect()
 }
 
+/// 多线程倍增法构建后缀数组，输出与 [`build_sa`] 完全一致。
+///
+/// 每轮先并行计算各后缀的 `(rank[i], rank[i + k])` 排序键，再用 `par_sort_unstable_by_key` 排序；
+/// 新 rank 由“与前一后缀键是否不同”的标记做并行前缀和得到：各块并行计数，块间串行累加，
+/// 再并行回填。总工作量与 [`build_sa_doubling`] 相同，适合核数较多、参考较大的场景。
+#[cfg(feature = "parallel")]
+#[must_use]
+pub fn build_sa_parallel(text: &[u8]) -> Vec<u32> {
+    use rayon::prelude::*;
+
+    /// 前缀和的分块大小
+    const SCAN_CHUNK: usize = 1 << 16;
+
+    let n = text.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    let mut sa: Vec<u32> = (0..n as u32).collect();
+    let mut rank: Vec<u32> = text.iter().map(|&b| u32::from(b)).collect();
+    let mut keys: Vec<(u32, i64)> = vec![(0, 0); n];
+    let mut new_rank: Vec<u32> = vec![0; n];
+
+    let mut k = 1usize;
+    loop {
+        // 末尾之后视为小于任何字符
+        keys.par_iter_mut().enumerate().for_each(|(i, key)| {
+            *key = (rank[i], rank.get(i + k).map_or(-1, |&r| i64::from(r)));
+        });
+        sa.par_sort_unstable_by_key(|&i| keys[i as usize]);
+
+        // 并行前缀和：sorted_rank[j] = 排序后 [1, j] 中键发生变化的次数
+        let mut sorted_rank: Vec<u32> = vec![0; n];
+        let chunk_counts: Vec<u32> = sorted_rank
+            .par_chunks_mut(SCAN_CHUNK)
+            .enumerate()
+            .map(|(c, chunk)| {
+                let mut count = 0u32;
+                for (off, slot) in chunk.iter_mut().enumerate() {
+                    let j = c * SCAN_CHUNK + off;
+                    if j > 0 && keys[sa[j] as usize] != keys[sa[j - 1] as usize] {
+                        count += 1;
+                    }
+                    *slot = count;
+                }
+                count
+            })
+            .collect();
+        let mut base = 0u32;
+        let chunk_bases: Vec<u32> = chunk_counts
+            .iter()
+            .map(|&c| {
+                let b = base;
+                base += c;
+                b
+            })
+            .collect();
+        sorted_rank
+            .par_chunks_mut(SCAN_CHUNK)
+            .zip(&chunk_bases)
+            .for_each(|(chunk, &b)| chunk.iter_mut().for_each(|r| *r += b));
+
+        for (&pos, &r) in sa.iter().zip(&sorted_rank) {
+            new_rank[pos as usize] = r;
+        }
+        std::mem::swap(&mut rank, &mut new_rank);
+        if base as usize == n - 1 || k >= n {
+            break;
+        }
+        k <<= 1;
+    }
+
+    sa
+}
+
+/// SA-IS 诱导排序构建后缀数组（O(n) 时间）。
+///
+/// 与 [`build_sa_doubling`] 的输出逐字节一致：内部把每个符号加 1，并在末尾追加唯一的最小
+/// 终止符 0，使文本中多个 0 分隔符按普通符号参与比较，最后去掉终止符对应的首项。
+#[must_use]
+pub fn build_sa_is(text: &[u8]) -> Vec<u32> {
+    sa_is_from_codes(text.len(), text.iter().copied())
+}
+
+/// 由 2-bit 压缩文本构建后缀数组，与 [`build_sa`] 输出一致。
+///
+/// 长文本的 SA-IS 输入直接由压缩文本逐字符生成，构建期间不存在逐字节的文本副本。
+#[must_use]
+pub fn build_sa_packed(text: &PackedSeq) -> Vec<u32> {
+    if text.len() >= SA_IS_THRESHOLD {
+        sa_is_from_codes(text.len(), text.iter())
+    } else {
+        build_sa_doubling(&text.to_vec())
+    }
+}
+
+/// 对长度为 `n` 的编码序列运行 SA-IS（见 [`build_sa_is`]）
+fn sa_is_from_codes(n: usize, codes: impl Iterator<Item = u8>) -> Vec<u32> {
+    if n == 0 {
+        return Vec::new();
+    }
+    let (s, k) = terminated_symbols(n, codes);
+    let sa = sais(&s, k);
+    sa[1..].iter().map(|&p| p as u32).collect()
+}
+
+/// 每个符号加 1 并追加终止符 0，返回 SA-IS 输入及其字母表大小
+fn terminated_symbols(n: usize, codes: impl Iterator<Item = u8>) -> (Vec<u32>, usize) {
+    let mut s: Vec<u32> = Vec::with_capacity(n + 1);
+    let mut max = 0u8;
+    for b in codes {
+        max = max.max(b);
+        s.push(b as u32 + 1);
+    }
+    s.push(0);
+    (s, max as usize + 2)
+}
+
+/// SA-IS 最后一轮诱导排序时直接得到的 BWT 与采样 SA（见 [`bwt_and_sparse_sa_from_codes`]）
+pub(crate) struct InducedBwt {
+    /// BWT，第 `i` 行为 `text[sa[i] - 1]`（循环）
+    pub bwt: Vec<u8>,
+    /// 每 `rate` 行保留一项的 SA（行 `0, rate, 2 * rate, ...`）
+    pub sparse_sa: Vec<u32>,
+    /// BWT 为分隔符 0 的行及其 SA 值，按行号升序
+    pub sentinel_rows: Vec<(u32, u32)>,
+}
+
+/// 对长度为 `n` 的编码序列运行 SA-IS，在最后一轮诱导排序中逐行写出 BWT 与采样 SA，
+/// 不生成 `u32` 形式的完整后缀数组。`rate <= 1` 时保留全部 SA。
+///
+/// 最后一轮诱导的 S 型扫描从右向左进行，扫描到第 `i` 行时该行的后缀已最终确定，
+/// 因此 BWT 与采样按行号倒序就地填入。
+pub(crate) fn bwt_and_sparse_sa_from_codes(n: usize, codes: impl Iterator<Item = u8>, rate: u32) -> InducedBwt {
+    let rate = rate.max(1) as usize;
+    let mut out = InducedBwt {
+        bwt: vec![0; n],
+        sparse_sa: vec![0; (n + rate - 1) / rate],
+        sentinel_rows: Vec::new(),
+    };
+    if n == 0 {
+        return out;
+    }
+    let (s, k) = terminated_symbols(n, codes);
+    // 工作数组的第 0 行是终止符，文本第 `row` 行对应工作数组第 `row + 1` 行
+    sais_with(&s, k, |i, p| {
+        if i == 0 {
+            return;
+        }
+        let row = i - 1;
+        let prev = s[if p == 0 { n - 1 } else { p - 1 }] - 1;
+        out.bwt[row] = prev as u8;
+        if row % rate == 0 {
+            out.sparse_sa[row / rate] = p as u32;
+        }
+        if prev == 0 {
+            out.sentinel_rows.push((row as u32, p as u32));
+        }
+    });
+    out.sentinel_rows.reverse();
+    out
+}
+
+const EMPTY: usize = usize::MAX;
+
+/// 每个符号桶的起点（`end == false`）或终点后一位（`end == true`）
+fn bucket_bounds(counts: &[usize], end: bool) -> Vec<usize> {
+    let mut bounds = Vec::with_capacity(counts.len());
+    let mut sum = 0usize;
+    for &c in counts {
+        if end {
+            sum += c;
+            bounds.push(sum);
+        } else {
+            bounds.push(sum);
+            sum += c;
+        }
+    }
+    bounds
+}
+
+/// 由已放置的 LMS 后缀诱导排序 L 型与 S 型后缀。
+///
+/// S 型扫描从右向左逐行进行，读到第 `i` 行时该行已最终确定，随即以 `(i, sa[i])` 调用 `visit`。
+fn induce(s: &[u32], is_s: &[bool], counts: &[usize], sa: &mut [usize], mut visit: impl FnMut(usize, usize)) {
+    let n = s.len();
+    let mut heads = bucket_bounds(counts, false);
+    for i in 0..n {
+        let j = sa[i];
+        if j != EMPTY && j > 0 && !is_s[j - 1] {
+            let c = s[j - 1] as usize;
+            sa[heads[c]] = j - 1;
+            heads[c] += 1;
+        }
+    }
+    let mut tails = bucket_bounds(counts, true);
+    for i in (0..n).rev() {
+        let j = sa[i];
+        visit(i, j);
+        if j != EMPTY && j > 0 && is_s[j - 1] {
+            let c = s[j - 1] as usize;
+            tails[c] -= 1;
+            sa[tails[c]] = j - 1;
+        }
+    }
+}
+
+/// SA-IS 主过程：`s` 以唯一最小符号 0 结尾，符号取值 `[0, k)`
+fn sais(s: &[u32], k: usize) -> Vec<usize> {
+    sais_with(s, k, |_, _| {})
+}
+
+/// 同 [`sais`]，最后一轮诱导排序中按行号倒序以 `(行, 后缀)` 调用 `visit`
+fn sais_with(s: &[u32], k: usize, visit: impl FnMut(usize, usize)) -> Vec<usize> {
+    let n = s.len();
+    if n == 1 {
+        return vec![0];
+    }
+
+    // 后缀类型：true 为 S 型
+    let mut is_s = vec![false; n];
+    is_s[n - 1] = true;
+    for i in (0..n - 1).rev() {
+        is_s[i] = s[i] < s[i + 1] || (s[i] == s[i + 1] && is_s[i + 1]);
+    }
+    let is_lms = |i: usize| i > 0 && is_s[i] && !is_s[i - 1];
+
+    let mut counts = vec![0usize; k];
+    for &c in s {
+        counts[c as usize] += 1;
+    }
+
+    // 第一轮：LMS 后缀放入桶尾，诱导排序得到 LMS 子串的顺序
+    let mut sa = vec![EMPTY; n];
+    let mut tails = bucket_bounds(&counts, true);
+    for i in (1..n).rev() {
+        if is_lms(i) {
+            let c = s[i] as usize;
+            tails[c] -= 1;
+            sa[tails[c]] = i;
+        }
+    }
+    induce(s, &is_s, &counts, &mut sa, |_, _| {});
+
+    // 为排好序的 LMS 子串命名
+    let sorted_lms: Vec<usize> = sa.iter().copied().filter(|&p| is_lms(p)).collect();
+    let mut names = vec![EMPTY; n];
+    let mut name = 0usize;
+    let mut prev = EMPTY;
+    for &p in &sorted_lms {
+        if prev != EMPTY && !lms_substrings_equal(s, &is_s, prev, p) {
+            name += 1;
+        }
+        names[p] = name;
+        prev = p;
+    }
+    let name_count = name + 1;
+
+    // 归约串：按文本顺序排列的 LMS 名字
+    let lms_positions: Vec<usize> = (1..n).filter(|&i| is_lms(i)).collect();
+    let reduced: Vec<u32> = lms_positions.iter().map(|&p| names[p] as u32).collect();
+    let reduced_sa = if name_count < reduced.len() {
+        sais(&reduced, name_count)
+    } else {
+        let mut direct = vec![0usize; reduced.len()];
+        for (i, &c) in reduced.iter().enumerate() {
+            direct[c as usize] = i;
+        }
+        direct
+    };
+
+    // 第二轮：按归约 SA 的顺序把 LMS 后缀放入桶尾，再次诱导排序
+    sa.iter_mut().for_each(|x| *x = EMPTY);
+    let mut tails = bucket_bounds(&counts, true);
+    for &r in reduced_sa.iter().rev() {
+        let p = lms_positions[r];
+        let c = s[p] as usize;
+        tails[c] -= 1;
+        sa[tails[c]] = p;
+    }
+    induce(s, &is_s, &counts, &mut sa, visit);
+    sa
+}
+
+/// 比较以 `a`、`b` 起始的两个 LMS 子串（含结尾的 LMS 字符）是否相同
+fn lms_substrings_equal(s: &[u32], is_s: &[bool], a: usize, b: usize) -> bool {
+    let n = s.len();
+    let is_lms = |i: usize| i > 0 && is_s[i] && !is_s[i - 1];
+    let mut i = 0usize;
+    loop {
+        let (x, y) = (a + i, b + i);
+        if x == n || y == n {
+            return false;
+        }
+        if s[x] != s[y] || is_s[x] != is_s[y] {
+            return false;
+        }
+        if i > 0 && (is_lms(x) || is_lms(y)) {
+            return is_lms(x) && is_lms(y);
+        }
+        i += 1;
+    }
+}
+
+/// 由文本与后缀数组计算 LCP 数组（Kasai 算法，O(n)）。
+///
+/// `lcp[i]` 为后缀 `sa[i - 1]` 与 `sa[i]` 的最长公共前缀长度，`lcp[0] = 0`。
+/// 哨兵（编码 0）视为互不相同，公共前缀不会跨越 contig 分隔符。
+#[must_use]
+pub fn build_lcp(text: &[u8], sa: &[u32]) -> Vec<u32> {
+    let n = text.len();
+    assert_eq!(sa.len(), n, "SA length must equal text length");
+    let mut rank = vec![0usize; n];
+    for (i, &p) in sa.iter().enumerate() {
+        rank[p as usize] = i;
+    }
+
+    let mut lcp = vec![0u32; n];
+    let mut h = 0usize;
+    for p in 0..n {
+        if rank[p] == 0 {
+            h = 0;
+            continue;
+        }
+        let q = sa[rank[p] - 1] as usize;
+        while p + h < n && q + h < n && text[p + h] == text[q + h] && text[p + h] != 0 {
+            h += 1;
+        }
+        lcp[rank[p]] = h as u32;
+        // 去掉首字符后，下一个后缀与其前驱的 LCP 至少为 h - 1
+        h = h.saturating_sub(1);
+    }
+    lcp
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testutil::Lcg;
 
     fn naive_sa(text: &[u8]) -> Vec<u32> {
         let n = text.len();
@@ -73,14 +418,8 @@ mod tests {
     }
 
     fn make_text(len: usize) -> Vec<u8> {
-        let mut x: u32 = 1_234_567;
-        let mut v = Vec::with_capacity(len);
-        for _ in 0..len {
-            x = x.wrapping_mul(1_103_515_245).wrapping_add(12_345);
-            let val = (x % 6) as u8;
-            v.push(val);
-        }
-        v
+        let mut rng = Lcg::new(1_234_567);
+        (0..len).map(|_| (rng.next_u32() % 6) as u8).collect()
     }
 
     #[test]
@@ -110,4 +449,119 @@ mod tests {
         let expected = naive_sa(&text);
         assert_eq!(sa, expected);
     }
+
+    fn naive_lcp(text: &[u8], sa: &[u32]) -> Vec<u32> {
+        let mut lcp = vec![0u32; sa.len()];
+        for i in 1..sa.len() {
+            let (a, b) = (&text[sa[i - 1] as usize..], &text[sa[i] as usize..]);
+            lcp[i] = a.iter().zip(b).take_while(|(x, y)| x == y && **x != 0).count() as u32;
+        }
+        lcp
+    }
+
+    #[test]
+    fn lcp_banana() {
+        // banana$ -> b=2 a=1 n=3
+        let text = [2u8, 1, 3, 1, 3, 1, 0];
+        let sa = build_sa(&text);
+        assert_eq!(sa, vec![6, 5, 3, 1, 0, 4, 2]);
+        // $, a$, ana$, anana$, banana$, na$, nana$
+        assert_eq!(build_lcp(&text, &sa), vec![0, 0, 1, 3, 0, 0, 2]);
+    }
+
+    #[test]
+    fn lcp_matches_naive_and_stops_at_separators() {
+        for len in 1..=30 {
+            let text = make_text(len);
+            let sa = build_sa(&text);
+            assert_eq!(build_lcp(&text, &sa), naive_lcp(&text, &sa), "mismatch on len={}", len);
+        }
+        // A C $ A C $：两个 "AC$" 后缀只共享 "AC"
+        let text = [1u8, 2, 0, 1, 2, 0];
+        let sa = build_sa(&text);
+        let lcp = build_lcp(&text, &sa);
+        assert_eq!(lcp.iter().max(), Some(&2));
+        assert!(build_lcp(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn sa_is_matches_doubling_on_random_texts() {
+        let mut rng = Lcg::new(0x2545_f491);
+        let mut next = || rng.next_u32() >> 8;
+        for round in 0..300 {
+            let len = 1 + (next() as usize % 2000);
+            // 不同的字母表大小与分隔符密度，含长重复片段
+            let sigma = 2 + round % 5;
+            let mut text: Vec<u8> = (0..len).map(|_| (next() % sigma as u32) as u8).collect();
+            if round % 3 == 0 && len > 40 {
+                let (a, b) = (next() as usize % (len / 2), len / 2);
+                let span = (len / 2 - a).min(len - b);
+                let copy = text[a..a + span].to_vec();
+                text[b..b + span].copy_from_slice(&copy);
+            }
+            if round % 2 == 0 {
+                text.push(0);
+            }
+            assert_eq!(
+                build_sa_is(&text),
+                build_sa_doubling(&text),
+                "round {} len {}",
+                round,
+                len
+            );
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_sa_matches_build_sa_on_random_texts() {
+        let mut rng = Lcg::new(0x1234_5678);
+        let mut next = || rng.next_u32() >> 8;
+        for round in 0..60 {
+            let len = if round == 0 { 200_000 } else { next() as usize % 5000 };
+            let sigma = 2 + round % 5;
+            let mut text: Vec<u8> = (0..len).map(|_| (next() % sigma as u32) as u8).collect();
+            if round % 3 == 0 && len > 40 {
+                let half = len / 2;
+                let copy = text[..half].to_vec();
+                text[half..2 * half].copy_from_slice(&copy);
+            }
+            if round % 2 == 0 {
+                text.push(0);
+            }
+            assert_eq!(build_sa_parallel(&text), build_sa(&text), "round {} len {}", round, len);
+        }
+    }
+
+    #[test]
+    fn sa_is_handles_degenerate_inputs() {
+        for text in [
+            vec![],
+            vec![0u8],
+            vec![3u8],
+            vec![1u8; 64],
+            vec![0u8; 17],
+            vec![1u8, 2, 0, 1, 2, 0],
+            vec![4u8, 3, 2, 1, 0, 4, 3, 2, 1, 0],
+        ] {
+            assert_eq!(build_sa_is(&text), naive_sa(&text), "{:?}", text);
+        }
+        // 超过阈值时 build_sa 走 SA-IS
+        let text = make_text(SA_IS_THRESHOLD + 3);
+        assert_eq!(build_sa(&text), naive_sa(&text));
+    }
+
+    #[test]
+    fn packed_text_builds_same_sa() {
+        for len in [0, 9, SA_IS_THRESHOLD + 5] {
+            let mut text = make_text(len);
+            text.push(0);
+            assert_eq!(
+                build_sa_packed(&PackedSeq::from_codes(&text)),
+                build_sa(&text),
+                "len {}",
+                len
+            );
+        }
+    }
 }
@@ -0,0 +1,269 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+
+use super::fm::{Contig, FMIndex};
+use super::packed::PackedSeq;
+use super::sa;
+
+/// 分片清单文件的首行标记
+const SHARD_MANIFEST_HEADER: &str = "#bwa-rust shard manifest v1";
+
+/// 分片清单中的一条 contig 记录
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardEntry {
+    /// 所在分片编号（对应 `prefix.<shard>.fm`）
+    pub shard: usize,
+    pub name: String,
+    pub len: u32,
+}
+
+/// 分片索引清单（`prefix.manifest`）：记录每条 contig 所在的分片，按原索引中的 contig 顺序排列。
+///
+/// 文件为制表符分隔文本：首行为版本标记，其后每行 `分片编号\tcontig 名\t长度`。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShardManifest {
+    pub entries: Vec<ShardEntry>,
+}
+
+impl ShardManifest {
+    /// 分片数
+    pub fn n_shards(&self) -> usize {
+        self.entries.iter().map(|e| e.shard + 1).max().unwrap_or(0)
+    }
+
+    /// 名为 `name` 的 contig 所在分片
+    pub fn shard_of(&self, name: &str) -> Option<usize> {
+        self.entries.iter().find(|e| e.name == name).map(|e| e.shard)
+    }
+
+    /// 第 `shard` 个分片包含的 contig（按清单顺序）
+    pub fn contigs_in(&self, shard: usize) -> impl Iterator<Item = &ShardEntry> + '_ {
+        self.entries.iter().filter(move |e| e.shard == shard)
+    }
+
+    /// 序列化为清单文本
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        out.push_str(SHARD_MANIFEST_HEADER);
+        out.push('\n');
+        for e in &self.entries {
+            let _ = writeln!(out, "{}\t{}\t{}", e.shard, e.name, e.len);
+        }
+        out
+    }
+
+    /// 解析清单文本；分片编号须从 0 开始连续、不递减
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text.lines();
+        if lines.next() != Some(SHARD_MANIFEST_HEADER) {
+            bail!("invalid shard manifest: missing '{}' header", SHARD_MANIFEST_HEADER);
+        }
+        let mut entries: Vec<ShardEntry> = Vec::new();
+        for (i, line) in lines.enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let lineno = i + 2;
+            let mut fields = line.split('\t');
+            let (Some(shard), Some(name), Some(len), None) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                bail!("shard manifest line {}: expected 3 tab-separated fields", lineno);
+            };
+            let shard: usize = shard
+                .parse()
+                .map_err(|_| anyhow!("shard manifest line {}: invalid shard id '{}'", lineno, shard))?;
+            let len: u32 = len
+                .parse()
+                .map_err(|_| anyhow!("shard manifest line {}: invalid length '{}'", lineno, len))?;
+            let prev = entries.last().map_or(0, |e| e.shard);
+            if shard < prev || shard > prev + 1 || (entries.is_empty() && shard != 0) {
+                bail!(
+                    "shard manifest line {}: shard ids must start at 0 and be consecutive",
+                    lineno
+                );
+            }
+            entries.push(ShardEntry {
+                shard,
+                name: name.to_string(),
+                len,
+            });
+        }
+        Ok(Self { entries })
+    }
+}
+
+/// 第 `shard` 个分片的索引路径 `prefix.<shard>.fm`
+pub fn shard_path(prefix: impl AsRef<Path>, shard: usize) -> PathBuf {
+    suffixed(prefix.as_ref(), &format!(".{}.fm", shard))
+}
+
+/// 分片清单路径 `prefix.manifest`
+pub fn shard_manifest_path(prefix: impl AsRef<Path>) -> PathBuf {
+    suffixed(prefix.as_ref(), ".manifest")
+}
+
+fn suffixed(prefix: &Path, suffix: &str) -> PathBuf {
+    let mut s = prefix.as_os_str().to_os_string();
+    s.push(suffix);
+    PathBuf::from(s)
+}
+
+/// 读取 `prefix.manifest`
+pub fn read_shard_manifest(prefix: impl AsRef<Path>) -> Result<ShardManifest> {
+    let path = shard_manifest_path(prefix);
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow!("cannot read shard manifest '{}': {}", path.display(), e))?;
+    ShardManifest::parse(&text)
+}
+
+impl FMIndex {
+    /// 按 contig 顺序每 `max_contigs_per_shard` 条分为一组，各组单独建索引写出为
+    /// `prefix.0.fm`、`prefix.1.fm` …，并写出 `prefix.manifest`。
+    ///
+    /// 各分片沿用本索引的字母表、rank 块大小、SA 采样率、构建元数据与无损字节段；
+    /// 分片内 contig 偏移从 0 重新计算。每个分片需重建一次 SA。
+    ///
+    /// 由已加载的完整索引切分：调用时整个索引须在内存中，另需当前分片的文本与 SA 空间。
+    /// 因此只能在能容纳完整索引的机器上生成分片；分片的好处在于之后可经
+    /// [`FMIndex::load_shard`] 按需加载。
+    pub fn save_sharded(&self, prefix: impl AsRef<Path>, max_contigs_per_shard: usize) -> Result<ShardManifest> {
+        if max_contigs_per_shard == 0 {
+            bail!("max contigs per shard must be greater than zero");
+        }
+        let prefix = prefix.as_ref();
+        let mut manifest = ShardManifest::default();
+        for (shard, group) in self.contigs.chunks(max_contigs_per_shard).enumerate() {
+            self.shard_index(group).save_to_file(shard_path(prefix, shard))?;
+            manifest.entries.extend(group.iter().map(|c| ShardEntry {
+                shard,
+                name: c.name.clone(),
+                len: c.len,
+            }));
+        }
+        std::fs::write(shard_manifest_path(prefix), manifest.to_text())?;
+        Ok(manifest)
+    }
+
+    /// 由 `group` 中的 contig 构建独立索引（每条 contig 后接哨兵，与构建器布局一致）
+    fn shard_index(&self, group: &[Contig]) -> FMIndex {
+        let mut text = PackedSeq::default();
+        let mut contigs = Vec::with_capacity(group.len());
+        let mut patches = Vec::new();
+        for c in group {
+            let (old, new) = (c.offset as usize, text.len());
+            for code in self.text.unpack(old, old + c.len as usize) {
+                text.push(code);
+            }
+            text.push(self.alphabet.sentinel);
+            if let Some(raw) = &self.raw_patches {
+                patches.extend(
+                    raw.iter()
+                        .filter(|(start, _)| (old..old + c.len as usize).contains(&(*start as usize)))
+                        .map(|(start, bytes)| ((*start as usize - old + new) as u32, bytes.clone())),
+                );
+            }
+            contigs.push(Contig {
+                name: c.name.clone(),
+                len: c.len,
+                offset: new as u32,
+            });
+        }
+        let sa_arr = sa::build_sa_packed(&text);
+        let mut fm = FMIndex::build_from_packed(text, sa_arr, contigs, self.alphabet.clone(), self.bwt.block());
+        fm.sparsify_sa(self.sa_sample_rate);
+        if self.raw_patches.is_some() {
+            fm.set_raw_patches(patches);
+        }
+        if let Some(meta) = &self.meta {
+            fm.set_meta(meta.clone());
+        }
+        fm
+    }
+
+    /// 加载 [`FMIndex::save_sharded`] 写出的第 `shard` 个分片，并检查其 contig 与清单一致
+    pub fn load_shard(prefix: impl AsRef<Path>, shard: usize) -> Result<FMIndex> {
+        let prefix = prefix.as_ref();
+        let manifest = read_shard_manifest(prefix)?;
+        if shard >= manifest.n_shards() {
+            bail!(
+                "shard {} out of range: manifest lists {} shards",
+                shard,
+                manifest.n_shards()
+            );
+        }
+        let path = shard_path(prefix, shard);
+        let fm =
+            FMIndex::load_from_file(&path).map_err(|e| anyhow!("cannot load shard '{}': {}", path.display(), e))?;
+        let listed: Vec<(&str, u32)> = manifest.contigs_in(shard).map(|e| (e.name.as_str(), e.len)).collect();
+        let actual: Vec<(&str, u32)> = fm.contigs.iter().map(|c| (c.name.as_str(), c.len)).collect();
+        if listed != actual {
+            bail!("shard '{}' does not match its manifest entries", path.display());
+        }
+        Ok(fm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::builder::{build_fm_index, build_fm_index_lossless};
+    use std::io::Cursor;
+
+    const FASTA: &str = ">chr1\nACGTACGGTCAGTTAGCA\n>chr2\nTTGACCANNNNNNNNNNNNGGA\n>chr3\nGATTACAGATTACA\n";
+
+    #[test]
+    fn sharded_index_roundtrip_matches_whole_index() {
+        let fm = build_fm_index(Cursor::new(FASTA), 4).unwrap().fm;
+        let prefix = std::env::temp_dir().join(format!("bwa_rust_test_shards_{}", std::process::id()));
+        let manifest = fm.save_sharded(&prefix, 2).unwrap();
+        assert_eq!(manifest.n_shards(), 2);
+        assert_eq!(manifest.shard_of("chr3"), Some(1));
+        assert_eq!(read_shard_manifest(&prefix).unwrap(), manifest);
+
+        for (shard, names) in [(0, vec!["chr1", "chr2"]), (1, vec!["chr3"])] {
+            let part = FMIndex::load_shard(&prefix, shard).unwrap();
+            let got: Vec<&str> = part.contigs.iter().map(|c| c.name.as_str()).collect();
+            assert_eq!(got, names);
+            for c in &part.contigs {
+                let orig = fm.contigs.iter().find(|o| o.name == c.name).unwrap();
+                let (o, p) = (orig.offset as usize, c.offset as usize);
+                assert_eq!(
+                    part.decode_text(p, p + c.len as usize),
+                    fm.decode_text(o, o + orig.len as usize)
+                );
+            }
+        }
+        let part = FMIndex::load_shard(&prefix, 0).unwrap();
+        assert_eq!(part.count(&[4, 4, 3, 1]), 1); // TTGA 只在 chr2
+        assert!(FMIndex::load_shard(&prefix, 2).is_err());
+        assert!(fm.save_sharded(&prefix, 0).is_err());
+
+        for shard in 0..manifest.n_shards() {
+            std::fs::remove_file(shard_path(&prefix, shard)).ok();
+        }
+        std::fs::remove_file(shard_manifest_path(&prefix)).ok();
+    }
+
+    #[test]
+    fn lossless_patches_follow_their_contig_into_the_shard() {
+        let fm = build_fm_index_lossless(Cursor::new(">a\nACGT\n>b\nacRTNN\n"), 4, 1).unwrap().fm;
+        let b = fm.shard_index(&fm.contigs[1..]);
+        assert_eq!(b.decode_text_lossless(0, 6), b"acRTNN");
+    }
+
+    #[test]
+    fn manifest_parse_rejects_bad_input() {
+        assert!(ShardManifest::parse("0\tchr1\t10\n").is_err());
+        let text = format!("{}\n1\tchr1\t10\n", SHARD_MANIFEST_HEADER);
+        assert!(ShardManifest::parse(&text).is_err());
+        let text = format!("{}\n0\tchr1\tten\n", SHARD_MANIFEST_HEADER);
+        assert!(ShardManifest::parse(&text).is_err());
+        let text = format!("{}\n0\tchr1\t10\n0\tchr2\t5\n1\tchr3\t7\n", SHARD_MANIFEST_HEADER);
+        let m = ShardManifest::parse(&text).unwrap();
+        assert_eq!(ShardManifest::parse(&m.to_text()).unwrap(), m);
+        assert_eq!(m.contigs_in(0).count(), 2);
+    }
+}
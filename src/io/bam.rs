@@ -0,0 +1,397 @@
+use anyhow::{anyhow, bail, Result};
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::align::candidate::cigar_ops_ref_length;
+
+use super::output::OutputFormat;
+use super::sam::{self, parse_cigar_ops, SamRecord, TagValue};
+
+/// Largest uncompressed payload per BGZF block (as in htslib), so that even
+/// incompressible data fits the 64 KiB block limit after deflate.
+const BGZF_BLOCK_DATA: usize = 0xff00;
+
+/// Empty BGZF block marking the end of a BAM file.
+const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00, 0x1b, 0x00, 0x03,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// 4-bit SEQ codes, indexed by code.
+const SEQ_CODES: &[u8; 16] = b"=ACMGRSVTWYHKDBN";
+
+/// Whether `path` should be written as BAM (by extension).
+pub fn is_bam_path(path: impl AsRef<Path>) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("bam"))
+}
+
+/// Blocked gzip (BGZF) writer: data is split into independently deflated
+/// gzip members of at most `BGZF_BLOCK_DATA` bytes, each carrying its
+/// compressed size in a `BC` extra field, followed by the EOF marker block.
+pub struct BgzfWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+    level: Compression,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub fn new(inner: W, level: u32) -> Self {
+        Self {
+            inner,
+            buf: Vec::with_capacity(BGZF_BLOCK_DATA),
+            level: Compression::new(level.min(9)),
+        }
+    }
+
+    fn write_block(&mut self) -> std::io::Result<()> {
+        let mut enc = DeflateEncoder::new(Vec::with_capacity(self.buf.len() / 2), self.level);
+        enc.write_all(&self.buf)?;
+        let cdata = enc.finish()?;
+        let mut crc = Crc::new();
+        crc.update(&self.buf);
+
+        let bsize = (18 + cdata.len() + 8 - 1) as u16;
+        let mut header = [
+            0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, b'B', b'C', 0x02, 0x00, 0x00, 0x00,
+        ];
+        header[16..18].copy_from_slice(&bsize.to_le_bytes());
+        self.inner.write_all(&header)?;
+        self.inner.write_all(&cdata)?;
+        self.inner.write_all(&crc.sum().to_le_bytes())?;
+        self.inner.write_all(&(self.buf.len() as u32).to_le_bytes())?;
+        self.buf.clear();
+        Ok(())
+    }
+
+    /// Write any buffered data and the EOF marker block, then flush.
+    pub fn try_finish(&mut self) -> std::io::Result<()> {
+        if !self.buf.is_empty() {
+            self.write_block()?;
+        }
+        self.inner.write_all(&BGZF_EOF)?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let n = data.len().min(BGZF_BLOCK_DATA - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() == BGZF_BLOCK_DATA {
+            self.write_block()?;
+        }
+        Ok(n)
+    }
+
+    /// Flushes the underlying writer only; partial blocks stay buffered so
+    /// blocks are not needlessly small.
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// BAM output (BGZF-compressed binary SAM).
+///
+/// The header and reference dictionary come from the contigs passed to
+/// [`write_header`](OutputFormat::write_header); records must name one of
+/// them (or `*`). All optional fields are kept, integers in the smallest
+/// BAM integer type that holds them.
+pub struct BamFormat<W: Write> {
+    out: BgzfWriter<W>,
+    ref_ids: HashMap<String, i32>,
+    record: Vec<u8>,
+}
+
+impl BamFormat<BufWriter<File>> {
+    /// Create a BAM file at `path`, deflating blocks at `level` (0-9).
+    pub fn create(path: impl AsRef<Path>, level: u32) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| anyhow!("cannot create '{}': {}", path.display(), e))?;
+        Ok(Self::new(BufWriter::new(file), level))
+    }
+}
+
+impl<W: Write> BamFormat<W> {
+    pub fn new(inner: W, level: u32) -> Self {
+        Self {
+            out: BgzfWriter::new(inner, level),
+            ref_ids: HashMap::new(),
+            record: Vec::new(),
+        }
+    }
+
+    fn ref_id(&self, name: &str) -> Result<i32> {
+        if name == "*" {
+            return Ok(-1);
+        }
+        self.ref_ids
+            .get(name)
+            .copied()
+            .ok_or_else(|| anyhow!("reference '{}' is not in the BAM header", name))
+    }
+}
+
+impl<W: Write> OutputFormat for BamFormat<W> {
+    fn write_header(&mut self, contigs: &[(&str, u32)]) -> Result<()> {
+        self.write_header_with_lines(contigs, &[])
+    }
+
+    fn write_header_with_lines(&mut self, contigs: &[(&str, u32)], lines: &[String]) -> Result<()> {
+        let mut text = Vec::new();
+        sam::write_header_with_lines(&mut text, contigs, lines)?;
+        let mut buf = b"BAM\x01".to_vec();
+        buf.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&text);
+        buf.extend_from_slice(&(contigs.len() as u32).to_le_bytes());
+        for (i, &(name, len)) in contigs.iter().enumerate() {
+            buf.extend_from_slice(&(name.len() as u32 + 1).to_le_bytes());
+            buf.extend_from_slice(name.as_bytes());
+            buf.push(0);
+            buf.extend_from_slice(&len.to_le_bytes());
+            self.ref_ids.insert(name.to_string(), i as i32);
+        }
+        self.out.write_all(&buf)?;
+        Ok(())
+    }
+
+    fn write_record(&mut self, rec: &SamRecord) -> Result<()> {
+        let ref_id = self.ref_id(&rec.rname)?;
+        let next_ref_id = match rec.rnext.as_str() {
+            "=" => ref_id,
+            name => self.ref_id(name)?,
+        };
+        encode_record(rec, ref_id, next_ref_id, &mut self.record)?;
+        self.out.write_all(&(self.record.len() as u32).to_le_bytes())?;
+        self.out.write_all(&self.record)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.out.try_finish()?;
+        Ok(())
+    }
+}
+
+/// Encode `rec` as a BAM alignment record (without the leading `block_size`) into `buf`.
+fn encode_record(rec: &SamRecord, ref_id: i32, next_ref_id: i32, buf: &mut Vec<u8>) -> Result<()> {
+    if rec.qname.is_empty() || rec.qname.len() > 254 {
+        bail!("read name '{}' cannot be stored in BAM", rec.qname);
+    }
+    let ops = if rec.cigar == "*" {
+        Vec::new()
+    } else {
+        parse_cigar_ops(&rec.cigar)
+    };
+    let mut cigar = Vec::with_capacity(ops.len());
+    for &(op, len) in &ops {
+        let code = "MIDNSHP=X"
+            .find(op)
+            .ok_or_else(|| anyhow!("invalid CIGAR operation '{}' in '{}'", op, rec.cigar))?;
+        cigar.push(((len as u32) << 4) | code as u32);
+    }
+    let seq = if rec.seq == "*" { "" } else { rec.seq.as_str() }.as_bytes();
+    let pos = rec.pos as i32 - 1;
+    let ref_span = cigar_ops_ref_length(&ops);
+    let end = if ref_span == 0 { pos + 1 } else { pos + ref_span as i32 };
+
+    buf.clear();
+    buf.extend_from_slice(&ref_id.to_le_bytes());
+    buf.extend_from_slice(&pos.to_le_bytes());
+    buf.push(rec.qname.len() as u8 + 1);
+    buf.push(rec.mapq);
+    let bin = if pos < 0 { 4680 } else { reg2bin(pos, end) };
+    buf.extend_from_slice(&bin.to_le_bytes());
+    buf.extend_from_slice(&(cigar.len() as u16).to_le_bytes());
+    buf.extend_from_slice(&rec.flag.to_le_bytes());
+    buf.extend_from_slice(&(seq.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&next_ref_id.to_le_bytes());
+    buf.extend_from_slice(&(rec.pnext as i32 - 1).to_le_bytes());
+    buf.extend_from_slice(&(rec.tlen as i32).to_le_bytes());
+    buf.extend_from_slice(rec.qname.as_bytes());
+    buf.push(0);
+    for op in cigar {
+        buf.extend_from_slice(&op.to_le_bytes());
+    }
+    for pair in seq.chunks(2) {
+        let code = |b: u8| SEQ_CODES.iter().position(|&c| c == b.to_ascii_uppercase()).unwrap_or(15) as u8;
+        buf.push(code(pair[0]) << 4 | pair.get(1).map_or(0, |&b| code(b)));
+    }
+    if rec.qual == "*" {
+        buf.extend(std::iter::repeat(0xff).take(seq.len()));
+    } else {
+        if rec.qual.len() != seq.len() {
+            bail!("QUAL length {} != SEQ length {}", rec.qual.len(), seq.len());
+        }
+        buf.extend(rec.qual.bytes().map(|q| q.saturating_sub(33)));
+    }
+    for (tag, value) in &rec.tags {
+        buf.extend_from_slice(tag.as_bytes());
+        match value {
+            TagValue::Char(c) => {
+                buf.push(b'A');
+                buf.push(*c as u8);
+            }
+            TagValue::Int(v) => push_int_tag(buf, *v)?,
+            TagValue::Float(f) => {
+                buf.push(b'f');
+                buf.extend_from_slice(&f.to_le_bytes());
+            }
+            TagValue::Str(s) => {
+                buf.push(b'Z');
+                buf.extend_from_slice(s.as_bytes());
+                buf.push(0);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Integer tag in the smallest BAM integer type holding `v`.
+fn push_int_tag(buf: &mut Vec<u8>, v: i64) -> Result<()> {
+    if let Ok(x) = u8::try_from(v) {
+        buf.push(b'C');
+        buf.push(x);
+    } else if let Ok(x) = i8::try_from(v) {
+        buf.push(b'c');
+        buf.extend_from_slice(&x.to_le_bytes());
+    } else if let Ok(x) = u16::try_from(v) {
+        buf.push(b'S');
+        buf.extend_from_slice(&x.to_le_bytes());
+    } else if let Ok(x) = i16::try_from(v) {
+        buf.push(b's');
+        buf.extend_from_slice(&x.to_le_bytes());
+    } else if let Ok(x) = u32::try_from(v) {
+        buf.push(b'I');
+        buf.extend_from_slice(&x.to_le_bytes());
+    } else if let Ok(x) = i32::try_from(v) {
+        buf.push(b'i');
+        buf.extend_from_slice(&x.to_le_bytes());
+    } else {
+        bail!("integer tag value {} does not fit in BAM", v);
+    }
+    Ok(())
+}
+
+/// UCSC binning index bin of the 0-based half-open interval `[beg, end)` (SAM spec §5.3).
+fn reg2bin(beg: i32, end: i32) -> u16 {
+    let end = end - 1;
+    for (shift, offset) in [(14, 4681), (17, 585), (20, 73), (23, 9), (26, 1)] {
+        if beg >> shift == end >> shift {
+            return (offset + (beg >> shift)) as u16;
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+
+    fn u32_at(b: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(b[at..at + 4].try_into().unwrap())
+    }
+
+    fn i32_at(b: &[u8], at: usize) -> i32 {
+        i32::from_le_bytes(b[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn bgzf_blocks_decompress_and_end_with_eof_marker() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let mut w = BgzfWriter::new(Vec::new(), 6);
+        w.write_all(&data).unwrap();
+        w.try_finish().unwrap();
+        let out = w.inner;
+
+        assert!(out.ends_with(&BGZF_EOF));
+        // every block carries its own size in the BC extra field
+        let mut at = 0;
+        let mut blocks = 0;
+        while at < out.len() {
+            assert_eq!(&out[at..at + 4], &[0x1f, 0x8b, 0x08, 0x04]);
+            assert_eq!(&out[at + 12..at + 14], b"BC");
+            at += u16::from_le_bytes([out[at + 16], out[at + 17]]) as usize + 1;
+            blocks += 1;
+        }
+        assert_eq!(at, out.len());
+        assert_eq!(blocks, 200_000 / BGZF_BLOCK_DATA + 2);
+
+        let mut decoded = Vec::new();
+        MultiGzDecoder::new(&out[..]).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn bam_header_and_record_layout() {
+        let mut bam = BamFormat::new(Vec::new(), 6);
+        bam.write_header(&[("chr1", 1000), ("chr2", 500)]).unwrap();
+        let mut rec = SamRecord::mapped("read1", 16, "chr2", 101, 37, "2S5M1I1M", "ACGTNACGT", "IIIII####");
+        rec.push_tag("NM", TagValue::Int(1));
+        rec.push_tag("AS", TagValue::Int(-300));
+        rec.push_tag("XA", TagValue::Str("x".to_string()));
+        bam.write_record(&rec).unwrap();
+        bam.write_record(&SamRecord::unmapped("u", "AC", "*")).unwrap();
+        assert!(bam
+            .write_record(&SamRecord::mapped("x", 0, "chrZ", 1, 0, "1M", "A", "I"))
+            .is_err());
+        bam.finish().unwrap();
+
+        let mut b = Vec::new();
+        MultiGzDecoder::new(&bam.out.inner[..]).read_to_end(&mut b).unwrap();
+        assert_eq!(&b[..4], b"BAM\x01");
+        let l_text = u32_at(&b, 4) as usize;
+        let text = std::str::from_utf8(&b[8..8 + l_text]).unwrap();
+        assert!(text.contains("@SQ\tSN:chr2\tLN:500\n"));
+        let mut at = 8 + l_text;
+        assert_eq!(u32_at(&b, at), 2);
+        at += 4;
+        for (name, len) in [("chr1", 1000), ("chr2", 500)] {
+            let l_name = u32_at(&b, at) as usize;
+            assert_eq!(&b[at + 4..at + 4 + l_name], format!("{}\0", name).as_bytes());
+            assert_eq!(u32_at(&b, at + 4 + l_name), len);
+            at += 8 + l_name;
+        }
+
+        let block_size = u32_at(&b, at) as usize;
+        let r = &b[at + 4..at + 4 + block_size];
+        assert_eq!(i32_at(r, 0), 1); // refID
+        assert_eq!(i32_at(r, 4), 100); // 0-based pos
+        assert_eq!((r[8], r[9]), (6, 37)); // l_read_name, mapq
+        assert_eq!(u16::from_le_bytes([r[10], r[11]]), reg2bin(100, 107));
+        assert_eq!(u16::from_le_bytes([r[12], r[13]]), 4); // n_cigar_op
+        assert_eq!(u16::from_le_bytes([r[14], r[15]]), 16); // flag
+        assert_eq!(u32_at(r, 16), 9); // l_seq
+        assert_eq!((i32_at(r, 20), i32_at(r, 24)), (-1, -1)); // mate
+        assert_eq!(&r[32..38], b"read1\0");
+        let cigar: Vec<u32> = (0..4).map(|i| u32_at(r, 38 + 4 * i)).collect();
+        assert_eq!(cigar, vec![2 << 4 | 4, 5 << 4, 1 << 4 | 1, 1 << 4]);
+        // A=1 C=2 G=4 T=8 N=15, two bases per byte, last nibble padded
+        assert_eq!(&r[54..59], &[0x12, 0x48, 0xf1, 0x24, 0x80]);
+        assert_eq!(&r[59..68], &[40, 40, 40, 40, 40, 2, 2, 2, 2]);
+        assert_eq!(&r[68..], b"NMC\x01ASs\xd4\xfeXAZx\0");
+
+        let at = at + 4 + block_size;
+        let r = &b[at + 4..at + 4 + u32_at(&b, at) as usize];
+        assert_eq!((i32_at(r, 0), i32_at(r, 4)), (-1, -1));
+        assert_eq!(u16::from_le_bytes([r[10], r[11]]), 4680);
+        assert_eq!(&r[r.len() - 2..], &[0xff, 0xff]);
+    }
+
+    #[test]
+    fn reg2bin_matches_spec_examples() {
+        assert_eq!(reg2bin(0, 1), 4681);
+        assert_eq!(reg2bin(0, 1 << 14), 4681);
+        assert_eq!(reg2bin(0, (1 << 14) + 1), 585);
+        assert_eq!(reg2bin(0, 1 << 29), 0);
+        assert!(is_bam_path("out.BAM") && !is_bam_path("out.sam"));
+    }
+}
@@ -0,0 +1,78 @@
+use std::fmt;
+
+/// Errors from the FASTA/FASTQ record readers.
+///
+/// Library callers can match on the variant instead of inspecting message
+/// text; the CLI converts these into `anyhow` errors with `?`.
+#[derive(Debug)]
+pub enum IoError {
+    /// The underlying reader failed
+    Io(std::io::Error),
+    /// A FASTQ record does not start with an `@` header line
+    MissingHeader,
+    /// A record header has no name
+    EmptyName,
+    /// Input ended right after a FASTQ header line
+    UnexpectedEof,
+    /// FASTQ sequence lines are not followed by a `+` separator line
+    MalformedPlusLine,
+    /// A FASTQ header is followed directly by the `+` separator
+    MissingSequence,
+    /// Input ended right after a FASTQ `+` separator line
+    MissingQuality,
+    /// FASTQ sequence and quality have different lengths
+    SeqQualMismatch { seq_len: usize, qual_len: usize },
+    /// A FASTQ sequence contains a non-printable or whitespace byte
+    InvalidSeqByte(u8),
+    /// A FASTQ quality string contains a byte outside `!`..=`~`
+    InvalidQualByte(u8),
+    /// An error from `FastqReader`, with the 1-based number of the last line
+    /// read when it was detected
+    FastqLine { line: u64, error: Box<IoError> },
+}
+
+impl IoError {
+    /// The underlying error with any line context removed
+    pub fn kind(&self) -> &IoError {
+        match self {
+            Self::FastqLine { error, .. } => error.kind(),
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for IoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::MissingHeader => write!(f, "FASTQ header not starting with '@'"),
+            Self::EmptyName => write!(f, "record header has an empty name"),
+            Self::UnexpectedEof => write!(f, "unexpected EOF after header"),
+            Self::MalformedPlusLine => write!(f, "missing '+' line"),
+            Self::MissingSequence => write!(f, "missing sequence line"),
+            Self::MissingQuality => write!(f, "missing quality line"),
+            Self::SeqQualMismatch { seq_len, qual_len } => {
+                write!(f, "seq/qual length mismatch ({} vs {})", seq_len, qual_len)
+            }
+            Self::InvalidSeqByte(b) => write!(f, "invalid character 0x{:02X} in FASTQ sequence", b),
+            Self::InvalidQualByte(b) => write!(f, "invalid character 0x{:02X} in FASTQ quality", b),
+            Self::FastqLine { line, error } => write!(f, "FASTQ parse error at line {}: {}", line, error),
+        }
+    }
+}
+
+impl std::error::Error for IoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::FastqLine { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for IoError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
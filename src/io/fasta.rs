@@ -1,11 +1,36 @@
-use anyhow::{anyhow, Result};
-use std::io::BufRead;
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+pub use super::error::IoError;
 
 #[derive(Debug, Clone)]
 pub struct FastaRecord {
     pub id: String,
     pub desc: Option<String>,
     pub seq: Vec<u8>,
+    /// Soft-masked (lowercase) stretches of the sequence as half-open
+    /// `[start, end)` intervals in ascending order; `None` unless the reader
+    /// was built with [`FastaReader::with_mask`]
+    pub mask: Option<Vec<(u32, u32)>>,
+}
+
+/// Maximal runs of lowercase bytes in `seq` as half-open `[start, end)` intervals.
+pub fn soft_mask_runs(seq: &[u8]) -> Vec<(u32, u32)> {
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+    for (i, b) in seq.iter().enumerate() {
+        if !b.is_ascii_lowercase() {
+            continue;
+        }
+        let i = i as u32;
+        match runs.last_mut() {
+            Some((_, end)) if *end == i => *end += 1,
+            _ => runs.push((i, i + 1)),
+        }
+    }
+    runs
 }
 
 pub struct FastaReader<R: BufRead> {
@@ -13,6 +38,8 @@ pub struct FastaReader<R: BufRead> {
     buf: String,
     done: bool,
     peek_header: Option<String>,
+    keep_case: bool,
+    track_mask: bool,
 }
 
 impl<R: BufRead> FastaReader<R> {
@@ -22,10 +49,34 @@ impl<R: BufRead> FastaReader<R> {
             buf: String::new(),
             done: false,
             peek_header: None,
+            keep_case: false,
+            track_mask: false,
         }
     }
 
-    pub fn next_record(&mut self) -> Result<Option<FastaRecord>> {
+    /// Keep sequence bytes as written instead of uppercasing them
+    /// (used by lossless index builds).
+    pub fn keep_case(mut self) -> Self {
+        self.keep_case = true;
+        self
+    }
+
+    /// Record the lowercase (soft-masked) stretches of each sequence in
+    /// [`FastaRecord::mask`] before uppercasing it.
+    pub fn with_mask(mut self) -> Self {
+        self.track_mask = true;
+        self
+    }
+
+    /// Read the next record; after an error the reader is finished and returns `Ok(None)`.
+    pub fn next_record(&mut self) -> Result<Option<FastaRecord>, IoError> {
+        self.read_record().map_err(|e| {
+            self.done = true;
+            e
+        })
+    }
+
+    fn read_record(&mut self) -> Result<Option<FastaRecord>, IoError> {
         if self.done {
             return Ok(None);
         }
@@ -52,12 +103,13 @@ impl<R: BufRead> FastaReader<R> {
         let mut parts = header.splitn(2, char::is_whitespace);
         let id = parts.next().unwrap_or("").to_string();
         if id.is_empty() {
-            return Err(anyhow!("FASTA header missing sequence name"));
+            return Err(IoError::EmptyName);
         }
         let desc = parts.next().map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
 
         // Read sequence lines
         let mut seq: Vec<u8> = Vec::new();
+        let mut mask: Vec<(u32, u32)> = Vec::new();
         loop {
             self.buf.clear();
             let n = self.reader.read_line(&mut self.buf)?;
@@ -71,14 +123,245 @@ impl<R: BufRead> FastaReader<R> {
                 break;
             }
             for &b in self.buf.as_bytes() {
-                match b {
-                    b'\n' | b'\r' | b' ' | b'\t' => {}
-                    _ => seq.push(b.to_ascii_uppercase()),
+                if matches!(b, b'\n' | b'\r' | b' ' | b'\t') {
+                    continue;
+                }
+                if self.track_mask && b.is_ascii_lowercase() {
+                    let pos = seq.len() as u32;
+                    match mask.last_mut() {
+                        Some((_, end)) if *end == pos => *end += 1,
+                        _ => mask.push((pos, pos + 1)),
+                    }
                 }
+                seq.push(if self.keep_case { b } else { b.to_ascii_uppercase() });
+            }
+        }
+
+        Ok(Some(FastaRecord {
+            id,
+            desc,
+            seq,
+            mask: self.track_mask.then_some(mask),
+        }))
+    }
+}
+
+/// Yields records until EOF; stops after the first error.
+impl<R: BufRead> Iterator for FastaReader<R> {
+    type Item = Result<FastaRecord, IoError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+/// One line of a samtools-compatible `.fai` index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaiEntry {
+    pub name: String,
+    /// Sequence length in bases
+    pub len: u64,
+    /// Byte offset of the first base in the FASTA file
+    pub offset: u64,
+    /// Bases per full sequence line
+    pub line_bases: u64,
+    /// Bytes per full sequence line, including the line terminator
+    pub line_bytes: u64,
+}
+
+/// Path of the `.fai` index for `fasta_path` (`<fasta_path>.fai`).
+pub fn fai_path(fasta_path: impl AsRef<Path>) -> PathBuf {
+    let mut s = fasta_path.as_ref().as_os_str().to_os_string();
+    s.push(".fai");
+    PathBuf::from(s)
+}
+
+/// Scan an uncompressed FASTA stream and compute its `.fai` entries.
+///
+/// Every sequence line except the last of each record must have the same
+/// length (bases and bytes); blank lines are only allowed at the end of a
+/// record. Layouts that break this cannot be indexed and are rejected.
+pub fn build_fai<R: BufRead>(mut reader: R) -> Result<Vec<FaiEntry>> {
+    let mut entries: Vec<FaiEntry> = Vec::new();
+    let mut line = Vec::new();
+    let mut pos = 0u64;
+    let mut lineno = 0usize;
+    // the current record's last line was shorter than a full line (or blank)
+    let mut closed = false;
+    loop {
+        line.clear();
+        let n = reader.read_until(b'\n', &mut line)?;
+        if n == 0 {
+            break;
+        }
+        lineno += 1;
+        pos += n as u64;
+        let bases = line.iter().filter(|b| !matches!(b, b'\n' | b'\r')).count() as u64;
+
+        if line.first() == Some(&b'>') {
+            let header = String::from_utf8_lossy(&line[1..]);
+            let name = header.split_whitespace().next().unwrap_or("").to_string();
+            if name.is_empty() {
+                bail!("FASTA line {}: header has no sequence name", lineno);
+            }
+            entries.push(FaiEntry {
+                name,
+                len: 0,
+                offset: pos,
+                line_bases: 0,
+                line_bytes: 0,
+            });
+            closed = false;
+            continue;
+        }
+        let Some(entry) = entries.last_mut() else {
+            if bases == 0 {
+                continue;
+            }
+            bail!("FASTA line {}: sequence data before the first '>' header", lineno);
+        };
+        if bases == 0 {
+            closed = true;
+            continue;
+        }
+        if closed {
+            bail!(
+                "FASTA line {}: sequence '{}' has lines of different lengths; \
+                 a .fai index needs uniform line lengths",
+                lineno,
+                entry.name
+            );
+        }
+        if entry.line_bases == 0 {
+            entry.line_bases = bases;
+            entry.line_bytes = n as u64;
+        } else if bases > entry.line_bases || (bases == entry.line_bases && n as u64 != entry.line_bytes) {
+            bail!(
+                "FASTA line {}: sequence '{}' has lines of different lengths; \
+                 a .fai index needs uniform line lengths",
+                lineno,
+                entry.name
+            );
+        } else if bases < entry.line_bases || !line.ends_with(b"\n") {
+            closed = true;
+        }
+        entry.len += bases;
+    }
+    Ok(entries)
+}
+
+/// Render `.fai` entries as samtools' tab-separated text.
+pub fn format_fai(entries: &[FaiEntry]) -> String {
+    let mut out = String::new();
+    for e in entries {
+        let _ = writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}",
+            e.name, e.len, e.offset, e.line_bases, e.line_bytes
+        );
+    }
+    out
+}
+
+/// Parse `.fai` text.
+pub fn parse_fai(text: &str) -> Result<Vec<FaiEntry>> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(i, line)| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 5 {
+                bail!(".fai line {}: expected 5 tab-separated fields", i + 1);
             }
+            let num = |k: usize| -> Result<u64> {
+                fields[k]
+                    .parse()
+                    .map_err(|_| anyhow!(".fai line {}: invalid number '{}'", i + 1, fields[k]))
+            };
+            Ok(FaiEntry {
+                name: fields[0].to_string(),
+                len: num(1)?,
+                offset: num(2)?,
+                line_bases: num(3)?,
+                line_bytes: num(4)?,
+            })
+        })
+        .collect()
+}
+
+/// Index the FASTA at `fasta_path` and write `<fasta_path>.fai`; returns the entries.
+pub fn write_fai(fasta_path: impl AsRef<Path>) -> Result<Vec<FaiEntry>> {
+    let path = fasta_path.as_ref();
+    let file = std::fs::File::open(path).map_err(|e| anyhow!("cannot open '{}': {}", path.display(), e))?;
+    let entries = build_fai(BufReader::new(file)).map_err(|e| anyhow!("'{}': {}", path.display(), e))?;
+    let out = fai_path(path);
+    std::fs::write(&out, format_fai(&entries)).map_err(|e| anyhow!("cannot write '{}': {}", out.display(), e))?;
+    Ok(entries)
+}
+
+/// Random access to an uncompressed FASTA file through its `.fai` index.
+pub struct FaidxReader<R: Read + Seek> {
+    reader: R,
+    entries: Vec<FaiEntry>,
+    by_name: HashMap<String, usize>,
+}
+
+impl FaidxReader<BufReader<std::fs::File>> {
+    /// Open `fasta_path` using the existing `<fasta_path>.fai` (see [`write_fai`]).
+    pub fn open(fasta_path: impl AsRef<Path>) -> Result<Self> {
+        let path = fasta_path.as_ref();
+        let fai = fai_path(path);
+        let text =
+            std::fs::read_to_string(&fai).map_err(|e| anyhow!("cannot read FASTA index '{}': {}", fai.display(), e))?;
+        let entries = parse_fai(&text).map_err(|e| anyhow!("'{}': {}", fai.display(), e))?;
+        let file = std::fs::File::open(path).map_err(|e| anyhow!("cannot open '{}': {}", path.display(), e))?;
+        Ok(Self::new(BufReader::new(file), entries))
+    }
+}
+
+impl<R: Read + Seek> FaidxReader<R> {
+    pub fn new(reader: R, entries: Vec<FaiEntry>) -> Self {
+        let by_name = entries.iter().enumerate().map(|(i, e)| (e.name.clone(), i)).collect();
+        Self {
+            reader,
+            entries,
+            by_name,
         }
+    }
 
-        Ok(Some(FastaRecord { id, desc, seq }))
+    pub fn entries(&self) -> &[FaiEntry] {
+        &self.entries
+    }
+
+    /// Bases `[start, end)` (0-based) of sequence `name`, as stored in the file.
+    pub fn fetch(&mut self, name: &str, start: u64, end: u64) -> Result<Vec<u8>> {
+        let entry = self
+            .by_name
+            .get(name)
+            .map(|&i| &self.entries[i])
+            .ok_or_else(|| anyhow!("sequence '{}' not found in FASTA index", name))?;
+        if start > end || end > entry.len {
+            bail!(
+                "region {}:{}-{} is out of range (length {})",
+                name,
+                start,
+                end,
+                entry.len
+            );
+        }
+        if start == end {
+            return Ok(Vec::new());
+        }
+        let byte_of = |i: u64| entry.offset + i / entry.line_bases * entry.line_bytes + i % entry.line_bases;
+        let (from, to) = (byte_of(start), byte_of(end - 1) + 1);
+        let mut raw = vec![0u8; (to - from) as usize];
+        self.reader.seek(SeekFrom::Start(from))?;
+        self.reader.read_exact(&mut raw)?;
+        raw.retain(|b| !matches!(b, b'\n' | b'\r'));
+        if raw.len() as u64 != end - start {
+            bail!("FASTA file does not match its index at {}:{}-{}", name, start, end);
+        }
+        Ok(raw)
     }
 }
 
@@ -106,6 +389,13 @@ mod tests {
         assert!(r.next_record().unwrap().is_none());
     }
 
+    #[test]
+    fn keep_case_preserves_soft_masking() {
+        let data = b">chr1\nACgt\nnRy\n";
+        let mut r = FastaReader::new(Cursor::new(&data[..])).keep_case();
+        assert_eq!(r.next_record().unwrap().unwrap().seq, b"ACgtnRy");
+    }
+
     #[test]
     fn parse_fasta_with_crlf_and_whitespace() {
         let data = b">chr1 desc\r\nAC g t n\r\n acgt\r\n>chr2 \r\n N N N \r\n";
@@ -139,11 +429,89 @@ mod tests {
         assert!(r.next_record().unwrap().is_none());
     }
 
+    #[test]
+    fn iterator_yields_records_then_stops() {
+        let data = b">chr1\nACGT\n>chr2\nGG\n>\nTT\n>chr4\nA\n";
+        let mut r = FastaReader::new(Cursor::new(&data[..]));
+        let ids: Vec<String> = r.by_ref().map_while(Result::ok).map(|rec| rec.id).collect();
+        assert_eq!(ids, ["chr1", "chr2"]);
+        // The empty-name error ended iteration; chr4 is never returned
+        assert!(r.next().is_none());
+        assert!(r.next_record().unwrap().is_none());
+
+        let seqs: Vec<Vec<u8>> = FastaReader::new(Cursor::new(&b">a\nAC\n>b\nGT\n"[..]))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .into_iter()
+            .map(|rec| rec.seq)
+            .collect();
+        assert_eq!(seqs, [b"AC".to_vec(), b"GT".to_vec()]);
+    }
+
     #[test]
     fn parse_fasta_rejects_empty_sequence_name() {
         let data = b">\nACGT\n";
         let cursor = Cursor::new(&data[..]);
         let mut r = FastaReader::new(cursor);
-        assert!(r.next_record().is_err());
+        assert!(matches!(r.next_record(), Err(IoError::EmptyName)));
+    }
+
+    #[test]
+    fn with_mask_records_lowercase_runs() {
+        let data = b">chr1\nACgt\nnRyT\n>chr2\nACGT\n";
+        let mut r = FastaReader::new(Cursor::new(&data[..])).with_mask();
+        let r1 = r.next_record().unwrap().unwrap();
+        assert_eq!(r1.seq, b"ACGTNRYT");
+        assert_eq!(r1.mask, Some(vec![(2, 5), (6, 7)]));
+        assert_eq!(r.next_record().unwrap().unwrap().mask, Some(vec![]));
+
+        let mut r = FastaReader::new(Cursor::new(&data[..])).keep_case().with_mask();
+        let r1 = r.next_record().unwrap().unwrap();
+        assert_eq!(r1.mask, Some(soft_mask_runs(&r1.seq)));
+        assert_eq!(
+            FastaReader::new(Cursor::new(&data[..])).next_record().unwrap().unwrap().mask,
+            None
+        );
+    }
+
+    #[test]
+    fn fai_matches_samtools_layout_and_fetches_across_lines() {
+        let data = b">chr1 desc\nACGTA\nCGTAC\nGT\n>chr2\r\nTTTT\r\nGG\r\n>empty\n>chr3\nacgtn";
+        let entries = build_fai(&data[..]).unwrap();
+        assert_eq!(
+            format_fai(&entries),
+            "chr1\t12\t11\t5\t6\nchr2\t6\t33\t4\t6\nempty\t0\t50\t0\t0\nchr3\t5\t56\t5\t5\n"
+        );
+        assert_eq!(parse_fai(&format_fai(&entries)).unwrap(), entries);
+
+        let mut fai = FaidxReader::new(Cursor::new(&data[..]), entries);
+        assert_eq!(fai.fetch("chr1", 0, 12).unwrap(), b"ACGTACGTACGT");
+        assert_eq!(fai.fetch("chr1", 3, 11).unwrap(), b"TACGTACG");
+        assert_eq!(fai.fetch("chr2", 2, 6).unwrap(), b"TTGG");
+        assert_eq!(fai.fetch("chr3", 1, 4).unwrap(), b"cgt");
+        assert!(fai.fetch("chr1", 4, 4).unwrap().is_empty());
+        assert!(fai.fetch("chr1", 10, 13).is_err());
+        assert!(fai.fetch("chrX", 0, 1).is_err());
+    }
+
+    #[test]
+    fn fai_rejects_ragged_lines() {
+        assert!(build_fai(&b">a\nACGT\nAC\nACGT\n"[..]).is_err());
+        assert!(build_fai(&b">a\nACGT\nACGTA\n"[..]).is_err());
+        assert!(build_fai(&b">a\nACGT\n\nACGT\n"[..]).is_err());
+        // A shorter last line and trailing blank lines are fine
+        assert!(build_fai(&b">a\nACGT\nAC\n\n>b\nA\n"[..]).is_ok());
+    }
+
+    #[test]
+    fn write_fai_and_open_reader() {
+        let path = std::env::temp_dir().join(format!("bwa_rust_test_faidx_{}.fa", std::process::id()));
+        std::fs::write(&path, ">s1\nACGTACGT\nAAC\n").unwrap();
+        write_fai(&path).unwrap();
+        let mut fai = FaidxReader::open(&path).unwrap();
+        assert_eq!(fai.entries()[0].len, 11);
+        assert_eq!(fai.fetch("s1", 6, 10).unwrap(), b"GTAA");
+        std::fs::remove_file(fai_path(&path)).ok();
+        std::fs::remove_file(&path).ok();
     }
 }
@@ -1,6 +1,8 @@
 use anyhow::{anyhow, Result};
 use std::io::BufRead;
 
+pub use super::error::IoError;
+
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct FastqRecord {
@@ -10,15 +12,26 @@ pub struct FastqRecord {
     pub qual: Vec<u8>,
 }
 
+impl FastqRecord {
+    /// Numeric Phred scores of `qual` for the given ASCII offset (33 or 64).
+    pub fn phred_scores(&self, offset: u8) -> Vec<u8> {
+        self.qual.iter().map(|&q| q.saturating_sub(offset)).collect()
+    }
+}
+
 /// A pair of reads from paired-end sequencing.
 #[derive(Debug, Clone)]
 pub struct ReadPair {
     /// Read name (same for both reads, without /1 or /2 suffix)
     pub name: String,
+    /// First read comment (header text after the name)
+    pub desc1: Option<String>,
     /// First read sequence
     pub seq1: Vec<u8>,
     /// First read quality
     pub qual1: Vec<u8>,
+    /// Second read comment (header text after the name)
+    pub desc2: Option<String>,
     /// Second read sequence
     pub seq2: Vec<u8>,
     /// Second read quality
@@ -29,6 +42,8 @@ pub struct FastqReader<R: BufRead> {
     reader: R,
     buf: String,
     done: bool,
+    /// Number of lines read so far (the 1-based number of the last line read)
+    line_no: u64,
 }
 
 impl<R: BufRead> FastqReader<R> {
@@ -37,59 +52,189 @@ impl<R: BufRead> FastqReader<R> {
             reader,
             buf: String::new(),
             done: false,
+            line_no: 0,
+        }
+    }
+
+    /// Number of lines consumed so far
+    pub fn line_no(&self) -> u64 {
+        self.line_no
+    }
+
+    /// Read the next record. Errors are wrapped in [`IoError::FastqLine`] with
+    /// the number of the last line read; use [`IoError::kind`] to match on the cause.
+    /// After an error the reader is finished and returns `Ok(None)`.
+    pub fn next_record(&mut self) -> Result<Option<FastqRecord>, IoError> {
+        self.read_record().map_err(|e| {
+            self.done = true;
+            IoError::FastqLine {
+                line: self.line_no,
+                error: Box::new(e),
+            }
+        })
+    }
+
+    fn read_line(&mut self) -> std::io::Result<usize> {
+        self.buf.clear();
+        let n = self.reader.read_line(&mut self.buf)?;
+        if n > 0 {
+            self.line_no += 1;
         }
+        Ok(n)
     }
 
-    pub fn next_record(&mut self) -> Result<Option<FastqRecord>> {
+    fn read_record(&mut self) -> Result<Option<FastqRecord>, IoError> {
         if self.done {
             return Ok(None);
         }
 
         // header line starting with '@'
-        self.buf.clear();
-        let mut n = self.reader.read_line(&mut self.buf)?;
+        let mut n = self.read_line()?;
         if n == 0 {
             self.done = true;
             return Ok(None);
         }
         if !self.buf.starts_with('@') {
-            return Err(anyhow!("FASTQ header not starting with '@'"));
+            return Err(IoError::MissingHeader);
         }
         let header = self.buf[1..].trim_end().to_string();
         let mut parts = header.splitn(2, char::is_whitespace);
         let id = parts.next().unwrap_or("").to_string();
         let desc = parts.next().map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
 
-        // sequence line
-        self.buf.clear();
-        n = self.reader.read_line(&mut self.buf)?;
-        if n == 0 {
-            return Err(anyhow!("unexpected EOF after header"));
+        // sequence lines (possibly wrapped) up to the '+' separator
+        let mut seq = Vec::new();
+        let mut seq_lines = 0usize;
+        loop {
+            n = self.read_line()?;
+            if n == 0 {
+                return Err(if seq_lines == 0 {
+                    IoError::UnexpectedEof
+                } else {
+                    IoError::MalformedPlusLine
+                });
+            }
+            // The separator may repeat the read id ("+read1"); its content is ignored.
+            if self.buf.starts_with('+') {
+                break;
+            }
+            seq.extend_from_slice(self.buf.trim_end().as_bytes());
+            seq_lines += 1;
         }
-        let seq = self.buf.trim_end().as_bytes().to_vec();
-
-        // plus line
-        self.buf.clear();
-        n = self.reader.read_line(&mut self.buf)?;
-        if n == 0 || !self.buf.starts_with('+') {
-            return Err(anyhow!("missing '+' line"));
+        if seq_lines == 0 {
+            return Err(IoError::MissingSequence);
         }
 
-        // quality line
-        self.buf.clear();
-        n = self.reader.read_line(&mut self.buf)?;
-        if n == 0 {
-            return Err(anyhow!("missing quality line"));
+        // quality lines until their total length reaches the sequence length;
+        // counting bases (not lines) keeps a quality line starting with '@' from
+        // being mistaken for the next header
+        let mut qual = Vec::with_capacity(seq.len());
+        let mut qual_lines = 0usize;
+        while qual_lines == 0 || qual.len() < seq.len() {
+            n = self.read_line()?;
+            if n == 0 {
+                if qual_lines == 0 {
+                    return Err(IoError::MissingQuality);
+                }
+                break;
+            }
+            qual.extend_from_slice(self.buf.trim_end().as_bytes());
+            qual_lines += 1;
         }
-        let qual = self.buf.trim_end().as_bytes().to_vec();
 
-        // If quality length is shorter than seq (line-wrapped seq not supported here), error
         if qual.len() != seq.len() {
-            return Err(anyhow!("seq/qual length mismatch"));
+            return Err(IoError::SeqQualMismatch {
+                seq_len: seq.len(),
+                qual_len: qual.len(),
+            });
+        }
+        if id.is_empty() {
+            return Err(IoError::EmptyName);
+        }
+        if let Some(&b) = seq.iter().find(|&&b| !is_valid_seq_byte(b)) {
+            return Err(IoError::InvalidSeqByte(b));
+        }
+        if let Some(&b) = qual.iter().find(|&&b| !is_valid_qual_byte(b)) {
+            return Err(IoError::InvalidQualByte(b));
         }
 
         Ok(Some(FastqRecord { id, desc, seq, qual }))
     }
+
+    /// Read two consecutive records of an interleaved FASTQ as a mate pair.
+    ///
+    /// The ids must match after stripping a trailing `/1`/`/2`; mate numbers
+    /// given as that suffix or as a Casava-style `1:`/`2:` comment prefix must
+    /// be 1 then 2. Returns `None` at EOF and an error for a dangling read.
+    pub fn next_pair(&mut self) -> Result<Option<(FastqRecord, FastqRecord)>> {
+        let Some(r1) = self.next_record()? else {
+            return Ok(None);
+        };
+        let Some(r2) = self.next_record()? else {
+            return Err(anyhow!("interleaved FASTQ has odd number of reads (last: '{}')", r1.id));
+        };
+        let (name1, name2) = (strip_read_suffix(&r1.id), strip_read_suffix(&r2.id));
+        if name1 != name2 {
+            return Err(anyhow!("interleaved read name mismatch: '{}' vs '{}'", r1.id, r2.id));
+        }
+        match (mate_number(&r1), mate_number(&r2)) {
+            (Some(1) | None, Some(2) | None) => Ok(Some((r1, r2))),
+            (m1, m2) => Err(anyhow!(
+                "interleaved reads '{}' are not mates 1 and 2 (got {} and {})",
+                name1,
+                m1.map_or("-".to_string(), |m| m.to_string()),
+                m2.map_or("-".to_string(), |m| m.to_string()),
+            )),
+        }
+    }
+}
+
+/// Yields records until EOF; stops after the first error.
+impl<R: BufRead> Iterator for FastqReader<R> {
+    type Item = Result<FastqRecord, IoError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+/// Remove a trailing run of `base` (case-insensitive) from a read when the run
+/// is longer than `min_len`, e.g. the poly-G tails of two-colour chemistry dark
+/// cycles. `seq` and `qual` are truncated together; returns the number of bases
+/// removed (0 when the run is too short).
+pub fn trim_homopolymer_tail(seq: &mut Vec<u8>, qual: &mut Vec<u8>, base: u8, min_len: usize) -> usize {
+    let run = seq.iter().rev().take_while(|b| b.eq_ignore_ascii_case(&base)).count();
+    if run <= min_len {
+        return 0;
+    }
+    let keep = seq.len() - run;
+    seq.truncate(keep);
+    qual.truncate(keep);
+    run
+}
+
+/// Mate number declared by a `/1`/`/2` id suffix or a Casava `1:`/`2:` comment.
+fn mate_number(rec: &FastqRecord) -> Option<u8> {
+    let from_id = rec.id.rsplit_once('/').and_then(|(_, n)| match n {
+        "1" => Some(1),
+        "2" => Some(2),
+        _ => None,
+    });
+    from_id.or_else(|| match rec.desc.as_deref()?.split(':').next()? {
+        "1" => Some(1),
+        "2" => Some(2),
+        _ => None,
+    })
+}
+
+/// Bytes accepted in a FASTQ sequence line (SAM SEQ alphabet: letters, `.`, `=`).
+fn is_valid_seq_byte(b: u8) -> bool {
+    b.is_ascii_alphabetic() || b == b'.' || b == b'='
+}
+
+/// Bytes accepted in a FASTQ quality line (printable ASCII `!`..=`~`).
+fn is_valid_qual_byte(b: u8) -> bool {
+    (b'!'..=b'~').contains(&b)
 }
 
 /// Reader for paired-end FASTQ files.
@@ -146,8 +291,10 @@ impl<R1: BufRead, R2: BufRead> PairedFastqReader<R1, R2> {
 
                     Ok(Some(ReadPair {
                         name: name1,
+                        desc1: r1.desc,
                         seq1: r1.seq,
                         qual1: r1.qual,
+                        desc2: r2.desc,
                         seq2: r2.seq,
                         qual2: r2.qual,
                     }))
@@ -161,30 +308,16 @@ impl<R1: BufRead, R2: BufRead> PairedFastqReader<R1, R2> {
             }
         } else {
             // Interleaved mode
-            let rec1 = self.reader1.next_record()?;
-            if rec1.is_none() {
+            let Some((r1, r2)) = self.reader1.next_pair()? else {
                 self.done = true;
                 return Ok(None);
-            }
-            let r1 = rec1.unwrap();
-
-            let rec2 = self.reader1.next_record()?;
-            if rec2.is_none() {
-                return Err(anyhow!("interleaved FASTQ has odd number of reads"));
-            }
-            let r2 = rec2.unwrap();
-
-            let name1 = strip_read_suffix(&r1.id);
-            let name2 = strip_read_suffix(&r2.id);
-
-            if name1 != name2 {
-                return Err(anyhow!("interleaved read name mismatch: '{}' vs '{}'", name1, name2));
-            }
-
+            };
             Ok(Some(ReadPair {
-                name: name1,
+                name: strip_read_suffix(&r1.id),
+                desc1: r1.desc,
                 seq1: r1.seq,
                 qual1: r1.qual,
+                desc2: r2.desc,
                 seq2: r2.seq,
                 qual2: r2.qual,
             }))
@@ -248,28 +381,96 @@ mod tests {
     fn parse_fastq_bad_header() {
         let data = b"ACGT\n+\nIIII\n";
         let mut r = FastqReader::new(Cursor::new(&data[..]));
-        assert!(r.next_record().is_err());
+        assert!(matches!(r.next_record().unwrap_err().kind(), IoError::MissingHeader));
     }
 
     #[test]
     fn parse_fastq_missing_plus() {
         let data = b"@read1\nACGT\nIIII\n";
         let mut r = FastqReader::new(Cursor::new(&data[..]));
-        assert!(r.next_record().is_err());
+        assert!(matches!(
+            r.next_record().unwrap_err().kind(),
+            IoError::MalformedPlusLine
+        ));
     }
 
     #[test]
     fn parse_fastq_seq_qual_length_mismatch() {
         let data = b"@read1\nACGT\n+\nIII\n";
         let mut r = FastqReader::new(Cursor::new(&data[..]));
-        assert!(r.next_record().is_err());
+        let err = r.next_record().unwrap_err();
+        assert!(
+            matches!(
+                err.kind(),
+                IoError::SeqQualMismatch {
+                    seq_len: 4,
+                    qual_len: 3
+                }
+            ),
+            "{}",
+            err
+        );
+        // The CLI still sees a readable message through anyhow
+        assert_eq!(
+            anyhow::Error::from(err).to_string(),
+            "FASTQ parse error at line 4: seq/qual length mismatch (4 vs 3)"
+        );
     }
 
     #[test]
     fn parse_fastq_truncated_after_header() {
         let data = b"@read1\n";
         let mut r = FastqReader::new(Cursor::new(&data[..]));
-        assert!(r.next_record().is_err());
+        assert!(matches!(r.next_record().unwrap_err().kind(), IoError::UnexpectedEof));
+    }
+
+    #[test]
+    fn parse_errors_report_line_number() {
+        // Third record lacks its '+' line; the error surfaces at EOF on line 10
+        let data = b"@r1\nACGT\n+\nIIII\n@r2\nAC\n+\nII\n@r3\nACGT\n";
+        let mut r = FastqReader::new(Cursor::new(&data[..]));
+        assert!(r.next_record().unwrap().is_some());
+        assert!(r.next_record().unwrap().is_some());
+        assert_eq!(r.line_no(), 8);
+        let err = r.next_record().unwrap_err();
+        assert!(matches!(err, IoError::FastqLine { line: 10, .. }), "{}", err);
+        assert!(matches!(err.kind(), IoError::MalformedPlusLine));
+        assert_eq!(err.to_string(), "FASTQ parse error at line 10: missing '+' line");
+        let source = std::error::Error::source(&err).expect("line error has a source");
+        assert_eq!(source.to_string(), "missing '+' line");
+
+        // A stray line where the second header should be
+        let data = b"@r1\nA\n+\nI\nr2\nA\n+\nI\n";
+        let mut r = FastqReader::new(Cursor::new(&data[..]));
+        r.next_record().unwrap();
+        let err = r.next_record().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "FASTQ parse error at line 5: FASTQ header not starting with '@'"
+        );
+    }
+
+    #[test]
+    fn iterator_yields_records_then_stops() {
+        let data = b"@r1\nA\n+\nI\n@r2\nCC\n+\nHH\n";
+        let ids: Vec<String> = FastqReader::new(Cursor::new(&data[..])).map(|r| r.unwrap().id).collect();
+        assert_eq!(ids, ["r1", "r2"]);
+
+        // Polling past EOF keeps returning None
+        let mut r = FastqReader::new(Cursor::new(&data[..]));
+        assert_eq!(r.by_ref().count(), 2);
+        assert!(r.next().is_none());
+        assert!(r.next_record().unwrap().is_none());
+
+        // An error is yielded once, then the iterator is finished
+        let data = b"@r1\nA\n+\nI\n@r2\nCC\n+\nH\n@r3\nG\n+\nI\n";
+        let mut r = FastqReader::new(Cursor::new(&data[..]));
+        assert_eq!(r.next().unwrap().unwrap().id, "r1");
+        assert!(matches!(
+            r.next().unwrap().unwrap_err().kind(),
+            IoError::SeqQualMismatch { .. }
+        ));
+        assert!(r.next().is_none());
     }
 
     #[test]
@@ -305,6 +506,30 @@ mod tests {
         assert_eq!(r1.seq, b"acgt");
     }
 
+    #[test]
+    fn parse_fastq_rejects_tab_in_sequence() {
+        let data = b"@read1\nAC\tGT\n+\nIIIII\n";
+        let mut r = FastqReader::new(Cursor::new(&data[..]));
+        assert!(matches!(
+            r.next_record().unwrap_err().kind(),
+            IoError::InvalidSeqByte(b'\t')
+        ));
+    }
+
+    #[test]
+    fn parse_fastq_rejects_space_in_quality() {
+        let data = b"@read1\nACGT\n+\nII I\n";
+        let mut r = FastqReader::new(Cursor::new(&data[..]));
+        assert!(r.next_record().is_err());
+    }
+
+    #[test]
+    fn parse_fastq_rejects_empty_read_name() {
+        let data = b"@\nACGT\n+\nIIII\n";
+        let mut r = FastqReader::new(Cursor::new(&data[..]));
+        assert!(matches!(r.next_record().unwrap_err().kind(), IoError::EmptyName));
+    }
+
     #[test]
     fn parse_paired_separate_files() {
         let data1 = b"@read1/1\nACGT\n+\nIIII\n@read2/1\nGGCC\n+\nHHHH\n";
@@ -375,4 +600,109 @@ mod tests {
         assert_eq!(strip_read_suffix("read1"), "read1");
         assert_eq!(strip_read_suffix("read/1/extra"), "read/1/extra");
     }
+
+    #[test]
+    fn parse_fastq_wrapped_seq_and_qual() {
+        // Quality continuation lines starting with '@' and '+' lines repeating the id
+        let data = b"@r1 first\nACGTAC\nGTTA\n+r1 first\nIIII@I\n@III\n@r2\nGG\nCC\n+\n@@\n##\n@r3\nACGT\n+\nIIII\n";
+        let mut r = FastqReader::new(Cursor::new(&data[..]));
+
+        let r1 = r.next_record().unwrap().unwrap();
+        assert_eq!(r1.id, "r1");
+        assert_eq!(r1.desc.as_deref(), Some("first"));
+        assert_eq!(r1.seq, b"ACGTACGTTA");
+        assert_eq!(r1.qual, b"IIII@I@III");
+
+        let r2 = r.next_record().unwrap().unwrap();
+        assert_eq!((r2.seq.as_slice(), r2.qual.as_slice()), (&b"GGCC"[..], &b"@@##"[..]));
+
+        let r3 = r.next_record().unwrap().unwrap();
+        assert_eq!((r3.id.as_str(), r3.seq.as_slice()), ("r3", &b"ACGT"[..]));
+        assert!(r.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_fastq_wrapped_qual_overrun_or_truncated() {
+        // Quality wraps past the sequence length
+        let data = b"@r1\nACGT\n+\nII\nIII\n";
+        assert!(FastqReader::new(Cursor::new(&data[..])).next_record().is_err());
+        // EOF before the quality is complete
+        let data = b"@r1\nACGT\nAC\n+\nIIII\n";
+        assert!(FastqReader::new(Cursor::new(&data[..])).next_record().is_err());
+        // No sequence line before '+'
+        let data = b"@r1\n+\n\n";
+        assert!(matches!(
+            FastqReader::new(Cursor::new(&data[..])).next_record().unwrap_err().kind(),
+            IoError::MissingSequence
+        ));
+    }
+
+    #[test]
+    fn parse_fastq_asymmetric_wrapping() {
+        // SEQ wrapped over 3 lines, QUAL over 2 (same total length); quality
+        // lines may begin with '+' or '@' without ending the record
+        let data = b"@r1\nACG\nTAC\nGT\n+\n+III\n@II#\n@r2\nAC\nGT\nTT\n+r2\nIII\nIII\n";
+        let mut r = FastqReader::new(Cursor::new(&data[..]));
+
+        let r1 = r.next_record().unwrap().unwrap();
+        assert_eq!(
+            (r1.seq.as_slice(), r1.qual.as_slice()),
+            (&b"ACGTACGT"[..], &b"+III@II#"[..])
+        );
+        let r2 = r.next_record().unwrap().unwrap();
+        assert_eq!(r2.id, "r2");
+        assert_eq!(
+            (r2.seq.as_slice(), r2.qual.as_slice()),
+            (&b"ACGTTT"[..], &b"IIIIII"[..])
+        );
+        assert!(r.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn fastq_reader_next_pair_checks_mates() {
+        let data = b"@a/1\nAC\n+\nII\n@a/2\nGT\n+\nII\n\
+                     @b 1:N:0:ACGT\nAA\n+\nII\n@b 2:N:0:ACGT\nCC\n+\nII\n";
+        let mut r = FastqReader::new(Cursor::new(&data[..]));
+        let (r1, r2) = r.next_pair().unwrap().unwrap();
+        assert_eq!((r1.id.as_str(), r2.seq.as_slice()), ("a/1", &b"GT"[..]));
+        let (r1, r2) = r.next_pair().unwrap().unwrap();
+        assert_eq!((r1.id.as_str(), r2.id.as_str()), ("b", "b"));
+        assert!(r.next_pair().unwrap().is_none());
+
+        let swapped = b"@c 2:N:0\nAC\n+\nII\n@c 1:N:0\nGT\n+\nII\n";
+        let err = FastqReader::new(Cursor::new(&swapped[..])).next_pair().unwrap_err();
+        assert!(err.to_string().contains("not mates 1 and 2"), "{}", err);
+        let mismatch = b"@c/1\nAC\n+\nII\n@d/2\nGT\n+\nII\n";
+        let err = FastqReader::new(Cursor::new(&mismatch[..])).next_pair().unwrap_err();
+        assert!(err.to_string().contains("'c/1' vs 'd/2'"), "{}", err);
+        let odd = b"@c/1\nAC\n+\nII\n";
+        assert!(FastqReader::new(Cursor::new(&odd[..])).next_pair().is_err());
+    }
+
+    #[test]
+    fn trim_poly_g_tail() {
+        let mut seq = b"ACGTACGTACGTggGGGGGGGGGGGG".to_vec();
+        let mut qual = vec![b'I'; seq.len()];
+        assert_eq!(trim_homopolymer_tail(&mut seq, &mut qual, b'G', 10), 14);
+        assert_eq!(seq, b"ACGTACGTACGT");
+        assert_eq!(qual.len(), 12);
+
+        // A run of exactly min_len is kept
+        let mut seq = b"ACGTAGGGGG".to_vec();
+        let mut qual = vec![b'I'; seq.len()];
+        assert_eq!(trim_homopolymer_tail(&mut seq, &mut qual, b'G', 5), 0);
+        assert_eq!(seq.len(), 10);
+    }
+
+    #[test]
+    fn phred_scores_subtract_offset() {
+        let rec = FastqRecord {
+            id: "r".to_string(),
+            desc: None,
+            seq: b"ACG".to_vec(),
+            qual: b"!Ih".to_vec(),
+        };
+        assert_eq!(rec.phred_scores(33), vec![0, 40, 71]);
+        assert_eq!(rec.phred_scores(64), vec![0, 9, 40]);
+    }
 }
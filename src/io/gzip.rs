@@ -0,0 +1,208 @@
+use anyhow::{anyhow, Result};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Default gzip level: a balanced speed/size trade-off.
+pub const DEFAULT_COMPRESS_LEVEL: u32 = 6;
+
+/// Highest accepted gzip level.
+pub const MAX_COMPRESS_LEVEL: u32 = 9;
+
+/// Returns true when `path` names a gzip file (`.gz` extension).
+pub fn is_gzip_path(path: impl AsRef<Path>) -> bool {
+    path.as_ref().extension().is_some_and(|ext| ext == "gz")
+}
+
+/// gzip stream magic bytes.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Wrap `reader`, transparently decompressing when it starts with the gzip
+/// magic bytes. Decoding is multi-member, so files concatenated from several
+/// gzip streams (e.g. written by parallel compressors) are read to the end.
+pub fn maybe_gzip_reader<R: BufRead + 'static>(mut reader: R) -> std::io::Result<Box<dyn BufRead>> {
+    let is_gzip = reader.fill_buf()?.starts_with(&GZIP_MAGIC);
+    if is_gzip {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Open `path` for buffered reading, decompressing gzip content (detected by
+/// its magic bytes, not the extension).
+pub fn open_maybe_gzip(path: impl AsRef<Path>) -> Result<Box<dyn BufRead>> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| anyhow!("cannot open '{}': {}", path.display(), e))?;
+    Ok(maybe_gzip_reader(BufReader::new(file))?)
+}
+
+/// Validate a user-supplied gzip level (0 = store, 9 = smallest).
+pub fn validate_compress_level(level: u32) -> Result<()> {
+    if level > MAX_COMPRESS_LEVEL {
+        return Err(anyhow!(
+            "compress level must be between 0 and {}, got {}",
+            MAX_COMPRESS_LEVEL,
+            level
+        ));
+    }
+    Ok(())
+}
+
+/// Buffered output sink that transparently gzip-compresses when requested.
+///
+/// Call [`OutputWriter::finish`] to flush buffers and write the gzip trailer;
+/// dropping the writer also finishes it but swallows any error.
+pub enum OutputWriter {
+    Plain(BufWriter<Box<dyn Write>>),
+    Gzip(GzEncoder<BufWriter<Box<dyn Write>>>),
+}
+
+impl OutputWriter {
+    /// Wrap `inner`, compressing at `level` when `compress` is set.
+    pub fn new(inner: Box<dyn Write>, compress: bool, level: u32) -> Self {
+        let buffered = BufWriter::new(inner);
+        if compress {
+            OutputWriter::Gzip(GzEncoder::new(buffered, Compression::new(level)))
+        } else {
+            OutputWriter::Plain(buffered)
+        }
+    }
+
+    /// Create `path`, gzip-compressing at `level` when it ends in `.gz`.
+    pub fn create(path: impl AsRef<Path>, level: u32) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| anyhow!("cannot create '{}': {}", path.display(), e))?;
+        Ok(Self::new(Box::new(file), is_gzip_path(path), level))
+    }
+
+    /// Open `path` for appending, gzip-compressing at `level` when it ends in
+    /// `.gz` (the appended data becomes a new gzip member).
+    pub fn append(path: impl AsRef<Path>, level: u32) -> Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map_err(|e| anyhow!("cannot reopen '{}': {}", path.display(), e))?;
+        Ok(Self::new(Box::new(file), is_gzip_path(path), level))
+    }
+
+    /// Uncompressed standard output.
+    pub fn stdout() -> Self {
+        Self::new(Box::new(std::io::stdout()), false, DEFAULT_COMPRESS_LEVEL)
+    }
+
+    /// Flush all buffered data and, for gzip output, write the trailer.
+    pub fn finish(self) -> Result<()> {
+        match self {
+            OutputWriter::Plain(mut w) => w.flush()?,
+            OutputWriter::Gzip(enc) => enc.finish()?.flush()?,
+        }
+        Ok(())
+    }
+
+    /// Like [`finish`](Self::finish) but through a mutable reference; nothing
+    /// may be written afterwards.
+    pub fn try_finish(&mut self) -> Result<()> {
+        match self {
+            OutputWriter::Plain(w) => w.flush()?,
+            OutputWriter::Gzip(enc) => {
+                enc.try_finish()?;
+                enc.get_mut().flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputWriter::Plain(w) => w.write(buf),
+            OutputWriter::Gzip(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputWriter::Plain(w) => w.flush(),
+            OutputWriter::Gzip(w) => w.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn gunzip(bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        GzDecoder::new(bytes).read_to_end(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn gzip_path_detection() {
+        assert!(is_gzip_path("out.sam.gz"));
+        assert!(is_gzip_path("/tmp/ref.fa.gz"));
+        assert!(!is_gzip_path("out.sam"));
+        assert!(!is_gzip_path("gz"));
+    }
+
+    #[test]
+    fn compress_level_range() {
+        assert!(validate_compress_level(0).is_ok());
+        assert!(validate_compress_level(9).is_ok());
+        assert!(validate_compress_level(10).is_err());
+    }
+
+    #[test]
+    fn levels_round_trip_identically() {
+        let dir = std::env::temp_dir();
+        let payload = b"@HD\tVN:1.6\n".repeat(200);
+        let mut decoded = Vec::new();
+        for level in [0u32, 1, 9] {
+            let path = dir.join(format!("bwa_rust_gzip_level_{}_{}.sam.gz", level, std::process::id()));
+            let mut w = OutputWriter::create(&path, level).unwrap();
+            w.write_all(&payload).unwrap();
+            w.finish().unwrap();
+            let bytes = std::fs::read(&path).unwrap();
+            assert_eq!(&bytes[..2], &[0x1f, 0x8b]);
+            decoded.push(gunzip(&bytes));
+            std::fs::remove_file(&path).ok();
+        }
+        assert!(decoded.iter().all(|d| d == &payload));
+    }
+
+    #[test]
+    fn reads_concatenated_gzip_members() {
+        let member = |text: &[u8]| {
+            let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+            enc.write_all(text).unwrap();
+            enc.finish().unwrap()
+        };
+        let mut bytes = member(b"@r1\nACGT\n+\nIIII\n");
+        bytes.extend(member(b"@r2\nGGCC\n+\nIIII\n"));
+
+        let reader = maybe_gzip_reader(std::io::Cursor::new(bytes)).unwrap();
+        let mut fq = crate::io::fastq::FastqReader::new(reader);
+        let mut ids = Vec::new();
+        while let Some(rec) = fq.next_record().unwrap() {
+            ids.push(rec.id);
+        }
+        assert_eq!(ids, vec!["r1", "r2"]);
+
+        // Plain input passes through untouched
+        let mut plain = String::new();
+        maybe_gzip_reader(std::io::Cursor::new(b">chr1\nACGT\n".to_vec()))
+            .unwrap()
+            .read_to_string(&mut plain)
+            .unwrap();
+        assert_eq!(plain, ">chr1\nACGT\n");
+    }
+}
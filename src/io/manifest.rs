@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+
+/// One reads file listed in a manifest, with its optional read group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// FASTQ path (optionally gzipped)
+    pub path: String,
+    /// `@RG` fields as `(TAG, VALUE)` in manifest order; empty when the file has no read group
+    pub rg_fields: Vec<(String, String)>,
+}
+
+impl ManifestEntry {
+    /// Read group `ID`, used as the `RG:Z` tag of every record from this file.
+    pub fn read_group_id(&self) -> Option<&str> {
+        self.rg_fields
+            .iter()
+            .find(|(tag, _)| tag == "ID")
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// `@RG` header line for this entry, e.g. `@RG\tID:lane1\tSM:s1`.
+    pub fn rg_header_line(&self) -> Option<String> {
+        self.read_group_id()?;
+        Some(format_rg_line(&self.rg_fields))
+    }
+}
+
+/// `@RG` header line for `(TAG, VALUE)` fields.
+pub fn format_rg_line(fields: &[(String, String)]) -> String {
+    let fields: Vec<String> = fields.iter().map(|(t, v)| format!("{}:{}", t, v)).collect();
+    format!("@RG\t{}", fields.join("\t"))
+}
+
+/// A `TAG:VALUE` read-group field with a two-character tag and non-empty value.
+fn parse_rg_field(col: &str) -> Option<(String, String)> {
+    col.split_once(':')
+        .filter(|(tag, value)| tag.len() == 2 && !value.is_empty())
+        .map(|(tag, value)| (tag.to_string(), value.to_string()))
+}
+
+/// Parse a read-group spec as given to `-R`, e.g. `ID:lane1\tSM:sample1\tPL:ILLUMINA`.
+///
+/// Fields are separated by tabs or by a literal `\t`, and a leading `@RG` is
+/// accepted. Returns the `(TAG, VALUE)` fields; an `ID` field is required.
+pub fn parse_read_group(spec: &str) -> Result<Vec<(String, String)>> {
+    let spec = spec.replace("\\t", "\t");
+    let spec = spec.strip_prefix("@RG").unwrap_or(&spec);
+    let mut fields = Vec::new();
+    for col in spec.split('\t').map(str::trim).filter(|c| !c.is_empty()) {
+        let field = parse_rg_field(col).ok_or_else(|| anyhow!("read-group field '{}' is not TAG:VALUE", col))?;
+        fields.push(field);
+    }
+    if !fields.iter().any(|(tag, _)| tag == "ID") {
+        bail!("read group '{}' has no ID field", spec.trim());
+    }
+    Ok(fields)
+}
+
+/// Parse a reads manifest.
+///
+/// Each non-empty line not starting with `#` is tab-separated: the FASTQ path,
+/// then optional `TAG:VALUE` read-group fields (`ID:lane1\tSM:sample1`). When
+/// any field is given, `ID` is required. Several files may share a read group
+/// as long as they list identical fields.
+pub fn parse_manifest(text: &str) -> Result<Vec<ManifestEntry>> {
+    let mut entries = Vec::new();
+    let mut groups: HashMap<String, Vec<(String, String)>> = HashMap::new();
+    for (idx, line) in text.lines().enumerate() {
+        let lineno = idx + 1;
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut cols = line.split('\t');
+        let path = cols.next().unwrap_or("").trim();
+        if path.is_empty() {
+            bail!("manifest line {}: missing reads path", lineno);
+        }
+        let mut rg_fields = Vec::new();
+        for col in cols.map(str::trim).filter(|c| !c.is_empty()) {
+            let field = parse_rg_field(col)
+                .ok_or_else(|| anyhow!("manifest line {}: read-group field '{}' is not TAG:VALUE", lineno, col))?;
+            rg_fields.push(field);
+        }
+        let entry = ManifestEntry {
+            path: path.to_string(),
+            rg_fields,
+        };
+        match entry.read_group_id() {
+            Some(id) => {
+                let known = groups.entry(id.to_string()).or_insert_with(|| entry.rg_fields.clone());
+                if *known != entry.rg_fields {
+                    bail!(
+                        "manifest line {}: read group '{}' redefined with different fields",
+                        lineno,
+                        id
+                    );
+                }
+            }
+            None if !entry.rg_fields.is_empty() => {
+                bail!("manifest line {}: read-group fields without ID", lineno);
+            }
+            None => {}
+        }
+        entries.push(entry);
+    }
+    if entries.is_empty() {
+        bail!("manifest lists no reads files");
+    }
+    Ok(entries)
+}
+
+/// Read and parse a manifest file; relative reads paths are resolved against
+/// the manifest's directory.
+pub fn read_manifest(path: impl AsRef<Path>) -> Result<Vec<ManifestEntry>> {
+    let path = path.as_ref();
+    let text =
+        std::fs::read_to_string(path).map_err(|e| anyhow!("cannot read manifest '{}': {}", path.display(), e))?;
+    let mut entries = parse_manifest(&text)?;
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    for entry in &mut entries {
+        if Path::new(&entry.path).is_relative() {
+            entry.path = base.join(&entry.path).to_string_lossy().into_owned();
+        }
+    }
+    Ok(entries)
+}
+
+/// Distinct `@RG` header lines of `entries`, one per `ID`, in first-appearance order.
+pub fn rg_header_lines(entries: &[ManifestEntry]) -> Result<Vec<String>> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in entries.iter().filter_map(ManifestEntry::rg_header_line) {
+        add_rg_line(&mut lines, line)?;
+    }
+    Ok(lines)
+}
+
+/// Append the `@RG` header `line` to `lines` unless a line with the same `ID`
+/// is already there. The same `ID` with different fields (in any order) is an
+/// error, since records could not say which definition their `RG:Z` refers to.
+pub fn add_rg_line(lines: &mut Vec<String>, line: String) -> Result<()> {
+    let id = rg_line_id(&line).unwrap_or_default().to_string();
+    match lines.iter().find(|l| rg_line_id(l) == Some(id.as_str())) {
+        Some(existing) if rg_line_fields(existing) == rg_line_fields(&line) => Ok(()),
+        Some(existing) => bail!(
+            "read group '{}' defined twice with different fields: '{}' and '{}'",
+            id,
+            existing.replace('\t', "\\t"),
+            line.replace('\t', "\\t")
+        ),
+        None => {
+            lines.push(line);
+            Ok(())
+        }
+    }
+}
+
+fn rg_line_id(line: &str) -> Option<&str> {
+    line.split('\t').find_map(|col| col.strip_prefix("ID:"))
+}
+
+fn rg_line_fields(line: &str) -> Vec<&str> {
+    let mut fields: Vec<&str> = line.split('\t').skip(1).collect();
+    fields.sort_unstable();
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_paths_and_read_groups() {
+        let text = "# batch\nr1.fq\tID:lane1\tSM:s1\n\nr2.fq.gz\nr3.fq\tID:lane1\tSM:s1\n";
+        let entries = parse_manifest(text).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].read_group_id(), Some("lane1"));
+        assert_eq!(entries[1].path, "r2.fq.gz");
+        assert_eq!(entries[1].read_group_id(), None);
+        assert_eq!(
+            rg_header_lines(&entries).unwrap(),
+            vec!["@RG\tID:lane1\tSM:s1".to_string()]
+        );
+    }
+
+    #[test]
+    fn add_rg_line_dedupes_by_id_and_rejects_conflicts() {
+        let mut lines = vec!["@RG\tID:a\tSM:s1\tPL:ILLUMINA".to_string()];
+        add_rg_line(&mut lines, "@RG\tID:a\tPL:ILLUMINA\tSM:s1".to_string()).unwrap();
+        add_rg_line(&mut lines, "@RG\tID:b\tSM:s1".to_string()).unwrap();
+        assert_eq!(lines.len(), 2);
+        let err = add_rg_line(&mut lines, "@RG\tID:a\tSM:s2".to_string()).unwrap_err().to_string();
+        assert!(err.contains("read group 'a' defined twice"), "{}", err);
+        assert_eq!(lines.len(), 2);
+    }
+
+    #[test]
+    fn parse_rejects_bad_read_groups() {
+        assert!(parse_manifest("r1.fq\tSM:s1\n").is_err());
+        assert!(parse_manifest("r1.fq\tlane1\n").is_err());
+        assert!(parse_manifest("r1.fq\tID:a\tSM:x\nr2.fq\tID:a\tSM:y\n").is_err());
+        assert!(parse_manifest("# only comments\n").is_err());
+    }
+
+    #[test]
+    fn parse_read_group_spec() {
+        let fields = parse_read_group("ID:lane1\\tSM:sample1\tPL:ILLUMINA").unwrap();
+        assert_eq!(format_rg_line(&fields), "@RG\tID:lane1\tSM:sample1\tPL:ILLUMINA");
+        assert_eq!(
+            parse_read_group("@RG\\tID:x").unwrap(),
+            vec![("ID".to_string(), "x".to_string())]
+        );
+        assert!(parse_read_group("SM:sample1").is_err());
+        assert!(parse_read_group("ID:x\tbogus").is_err());
+    }
+
+    #[test]
+    fn read_manifest_resolves_relative_paths() {
+        let dir = std::env::temp_dir().join(format!("bwa_rust_manifest_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("list.tsv");
+        std::fs::write(&path, "reads.fq\tID:g1\n/abs/reads.fq\n").unwrap();
+        let entries = read_manifest(&path).unwrap();
+        assert_eq!(entries[0].path, dir.join("reads.fq").to_string_lossy());
+        assert_eq!(entries[1].path, "/abs/reads.fq");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,283 @@
+use anyhow::{anyhow, bail, Result};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::io::{BufRead, Write};
+
+use super::gzip::{open_maybe_gzip, OutputWriter, DEFAULT_COMPRESS_LEVEL};
+
+/// Sort key of a coordinate-sorted SAM record: (`@SQ` index, POS).
+/// Records with RNAME `*` sort after every placed record.
+type SortKey = (u32, u32);
+
+const UNPLACED: u32 = u32::MAX;
+
+/// One coordinate-sorted SAM input being merged.
+struct Shard<R: BufRead> {
+    name: String,
+    reader: R,
+    line: String,
+    lineno: usize,
+    last_key: Option<SortKey>,
+}
+
+impl<R: BufRead> Shard<R> {
+    /// Read the next non-empty line into `self.line`; returns false at EOF.
+    fn advance(&mut self) -> Result<bool> {
+        loop {
+            self.line.clear();
+            if self.reader.read_line(&mut self.line)? == 0 {
+                return Ok(false);
+            }
+            self.lineno += 1;
+            let trimmed = self.line.trim_end_matches(['\n', '\r']).len();
+            self.line.truncate(trimmed);
+            if !self.line.is_empty() {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn context(&self) -> String {
+        format!("{}:{}", self.name, self.lineno)
+    }
+}
+
+/// Parse `(SN, LN)` from an `@SQ` header line.
+fn sq_entry(line: &str) -> Option<(String, String)> {
+    let mut name = None;
+    let mut len = None;
+    for field in line.split('\t').skip(1) {
+        if let Some(v) = field.strip_prefix("SN:") {
+            name = Some(v.to_string());
+        } else if let Some(v) = field.strip_prefix("LN:") {
+            len = Some(v.to_string());
+        }
+    }
+    Some((name?, len?))
+}
+
+fn record_key(line: &str, ref_index: &HashMap<String, u32>) -> Result<SortKey> {
+    let mut fields = line.split('\t');
+    let rname = fields.nth(2).ok_or_else(|| anyhow!("truncated SAM record"))?;
+    let pos = fields.next().ok_or_else(|| anyhow!("truncated SAM record"))?;
+    if rname == "*" {
+        return Ok((UNPLACED, 0));
+    }
+    let idx = *ref_index
+        .get(rname)
+        .ok_or_else(|| anyhow!("RNAME '{}' not present in @SQ", rname))?;
+    let pos: u32 = pos.parse().map_err(|_| anyhow!("invalid POS '{}'", pos))?;
+    Ok((idx, pos))
+}
+
+/// Replace or add `SO:coordinate` on an `@HD` line.
+fn coordinate_hd(line: &str) -> String {
+    let mut fields: Vec<&str> = line.split('\t').filter(|f| !f.starts_with("SO:")).collect();
+    fields.push("SO:coordinate");
+    fields.join("\t")
+}
+
+/// K-way merge of coordinate-sorted SAM streams into `out`.
+///
+/// Every input must carry the same `@SQ` dictionary (names, lengths and
+/// order); a mismatch is an error. The `@HD` line of the first input is kept
+/// with `SO:coordinate`; other header lines are de-duplicated in first-seen
+/// order. Records are ordered by (`@SQ` index, POS) with unplaced records
+/// last; ties keep input order, so the merge is stable. An input that is not
+/// itself sorted is reported as an error.
+pub fn merge_sorted_sam<R: BufRead, W: Write>(inputs: Vec<(String, R)>, out: &mut W) -> Result<()> {
+    if inputs.is_empty() {
+        bail!("no SAM inputs to merge");
+    }
+
+    let mut shards: Vec<Shard<R>> = inputs
+        .into_iter()
+        .map(|(name, reader)| Shard {
+            name,
+            reader,
+            line: String::new(),
+            lineno: 0,
+            last_key: None,
+        })
+        .collect();
+
+    // Headers: read each shard up to its first record.
+    let mut hd: Option<String> = None;
+    let mut reference: Option<Vec<(String, String)>> = None;
+    let mut other_header: Vec<String> = Vec::new();
+    let mut has_record = vec![false; shards.len()];
+    for (i, shard) in shards.iter_mut().enumerate() {
+        let mut sq = Vec::new();
+        while shard.advance()? {
+            if !shard.line.starts_with('@') {
+                has_record[i] = true;
+                break;
+            }
+            if shard.line.starts_with("@HD") {
+                hd.get_or_insert_with(|| coordinate_hd(&shard.line));
+            } else if shard.line.starts_with("@SQ") {
+                sq.push(sq_entry(&shard.line).ok_or_else(|| anyhow!("{}: @SQ without SN/LN", shard.context()))?);
+            } else if !other_header.contains(&shard.line) {
+                other_header.push(shard.line.clone());
+            }
+        }
+        match &reference {
+            None => reference = Some(sq),
+            Some(first) if *first != sq => {
+                bail!("{}: @SQ header does not match the first input", shard.name);
+            }
+            Some(_) => {}
+        }
+    }
+    let reference = reference.unwrap_or_default();
+
+    writeln!(
+        out,
+        "{}",
+        hd.unwrap_or_else(|| "@HD\tVN:1.6\tSO:coordinate".to_string())
+    )?;
+    for (name, len) in &reference {
+        writeln!(out, "@SQ\tSN:{}\tLN:{}", name, len)?;
+    }
+    for line in &other_header {
+        writeln!(out, "{}", line)?;
+    }
+
+    let ref_index: HashMap<String, u32> = reference
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| (name.clone(), i as u32))
+        .collect();
+
+    let mut heap: BinaryHeap<Reverse<(SortKey, usize)>> = BinaryHeap::new();
+    for (i, shard) in shards.iter_mut().enumerate() {
+        if has_record[i] {
+            let key = record_key(&shard.line, &ref_index).map_err(|e| anyhow!("{}: {}", shard.context(), e))?;
+            shard.last_key = Some(key);
+            heap.push(Reverse((key, i)));
+        }
+    }
+
+    while let Some(Reverse((_, i))) = heap.pop() {
+        let shard = &mut shards[i];
+        writeln!(out, "{}", shard.line)?;
+        if shard.advance()? {
+            if shard.line.starts_with('@') {
+                bail!("{}: header line after records", shard.context());
+            }
+            let key = record_key(&shard.line, &ref_index).map_err(|e| anyhow!("{}: {}", shard.context(), e))?;
+            if shard.last_key.is_some_and(|last| key < last) {
+                bail!("{}: input is not coordinate-sorted", shard.context());
+            }
+            shard.last_key = Some(key);
+            heap.push(Reverse((key, i)));
+        }
+    }
+    Ok(())
+}
+
+/// Merge coordinate-sorted SAM files (plain or gzip-compressed)
+/// into `out_path`, or stdout when `None`.
+pub fn merge_sorted_sam_files(paths: &[String], out_path: Option<&str>) -> Result<()> {
+    let mut inputs: Vec<(String, Box<dyn BufRead>)> = Vec::with_capacity(paths.len());
+    for path in paths {
+        inputs.push((path.clone(), open_maybe_gzip(path)?));
+    }
+    let mut out = match out_path {
+        Some(p) => OutputWriter::create(p, DEFAULT_COMPRESS_LEVEL)?,
+        None => OutputWriter::stdout(),
+    };
+    merge_sorted_sam(inputs, &mut out)?;
+    out.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const HEADER: &str = "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:chr1\tLN:100\n@SQ\tSN:chr2\tLN:50\n";
+
+    fn rec(name: &str, rname: &str, pos: u32) -> String {
+        let (flag, cigar) = if rname == "*" { (4, "*") } else { (0, "4M") };
+        format!(
+            "{}\t{}\t{}\t{}\t60\t{}\t*\t0\t0\tACGT\tIIII\n",
+            name, flag, rname, pos, cigar
+        )
+    }
+
+    fn merge(inputs: &[String]) -> Result<String> {
+        let readers = inputs
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (format!("in{}", i), Cursor::new(s.as_bytes())))
+            .collect();
+        let mut out = Vec::new();
+        merge_sorted_sam(readers, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    fn names(sam: &str) -> Vec<&str> {
+        sam.lines()
+            .filter(|l| !l.starts_with('@'))
+            .map(|l| l.split('\t').next().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn merges_two_sorted_inputs_in_coordinate_order() {
+        let a = format!(
+            "{}@PG\tID:bwa-rust\n{}{}{}{}",
+            HEADER,
+            rec("a1", "chr1", 5),
+            rec("a2", "chr1", 40),
+            rec("a3", "chr2", 10),
+            rec("a4", "*", 0)
+        );
+        let b = format!(
+            "{}@PG\tID:bwa-rust\n{}{}{}",
+            HEADER,
+            rec("b1", "chr1", 5),
+            rec("b2", "chr1", 20),
+            rec("b3", "chr2", 3)
+        );
+        let merged = merge(&[a, b]).unwrap();
+        assert_eq!(names(&merged), vec!["a1", "b1", "b2", "a2", "b3", "a3", "a4"]);
+        let header: Vec<&str> = merged.lines().take_while(|l| l.starts_with('@')).collect();
+        assert_eq!(
+            header,
+            vec![
+                "@HD\tVN:1.6\tSO:coordinate",
+                "@SQ\tSN:chr1\tLN:100",
+                "@SQ\tSN:chr2\tLN:50",
+                "@PG\tID:bwa-rust"
+            ]
+        );
+        assert!(crate::io::sam::validate_sam_header(&merged).is_ok());
+    }
+
+    #[test]
+    fn merge_rejects_mismatched_sq_headers() {
+        let a = format!("{}{}", HEADER, rec("a1", "chr1", 5));
+        let b = format!(
+            "@HD\tVN:1.6\n@SQ\tSN:chr1\tLN:99\n@SQ\tSN:chr2\tLN:50\n{}",
+            rec("b1", "chr1", 5)
+        );
+        let err = merge(&[a, b]).unwrap_err().to_string();
+        assert!(err.contains("@SQ"), "{}", err);
+    }
+
+    #[test]
+    fn merge_rejects_unsorted_input() {
+        let a = format!("{}{}{}", HEADER, rec("a1", "chr2", 5), rec("a2", "chr1", 5));
+        let err = merge(&[a]).unwrap_err().to_string();
+        assert!(err.contains("not coordinate-sorted"), "{}", err);
+    }
+
+    #[test]
+    fn merge_handles_header_only_inputs() {
+        let a = HEADER.to_string();
+        let b = format!("{}{}", HEADER, rec("b1", "chr1", 1));
+        assert_eq!(names(&merge(&[a, b]).unwrap()), vec!["b1"]);
+    }
+}
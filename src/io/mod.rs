@@ -1,3 +1,13 @@
+pub mod bam;
+pub mod error;
 pub mod fasta;
 pub mod fastq;
+pub mod gzip;
+pub mod manifest;
+pub mod merge;
+pub mod output;
 pub mod sam;
+pub mod twobit;
+
+pub use error::IoError;
+pub use gzip::open_maybe_gzip;
@@ -0,0 +1,381 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use super::gzip::OutputWriter;
+use super::sam::{self, flags, SamRecord, TagValue};
+
+/// Serializes alignment records into one output format.
+///
+/// The pipeline calls [`write_header`](Self::write_header) once with the
+/// reference contigs, then [`write_record`](Self::write_record) for every
+/// record in output order, then [`finish`](Self::finish). Formats without a
+/// header may use the contig list for lookups (e.g. target lengths in PAF).
+pub trait OutputFormat {
+    /// Start the output; `contigs` are `(name, length)` in index order.
+    fn write_header(&mut self, contigs: &[(&str, u32)]) -> Result<()>;
+
+    /// Like [`write_header`](Self::write_header), with extra header lines such
+    /// as `@RG`. Formats without a text header ignore them.
+    fn write_header_with_lines(&mut self, contigs: &[(&str, u32)], lines: &[String]) -> Result<()> {
+        let _ = lines;
+        self.write_header(contigs)
+    }
+
+    /// Write one alignment record.
+    fn write_record(&mut self, rec: &SamRecord) -> Result<()>;
+
+    /// Flush and close the output.
+    fn finish(&mut self) -> Result<()>;
+}
+
+/// Output format selected with `--output-format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputMode {
+    /// SAM text (header plus one line per record)
+    #[default]
+    Sam,
+    /// Pairwise mApping Format, one line per mapped record
+    Paf,
+    /// BED12, one line per mapped record with alignment blocks split at `N` skips
+    Bed12,
+}
+
+impl OutputMode {
+    /// Formatter for this mode writing to `out`.
+    pub fn formatter(self, out: OutputWriter) -> Box<dyn OutputFormat> {
+        match self {
+            OutputMode::Sam => Box::new(SamFormat::new(out)),
+            OutputMode::Paf => Box::new(PafFormat::new(out)),
+            OutputMode::Bed12 => Box::new(Bed12Format::new(out)),
+        }
+    }
+}
+
+impl FromStr for OutputMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "sam" => Ok(OutputMode::Sam),
+            "paf" => Ok(OutputMode::Paf),
+            "bed12" | "bed" => Ok(OutputMode::Bed12),
+            _ => Err(format!("unknown output format '{}' (expected sam, paf or bed12)", s)),
+        }
+    }
+}
+
+impl fmt::Display for OutputMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OutputMode::Sam => "sam",
+            OutputMode::Paf => "paf",
+            OutputMode::Bed12 => "bed12",
+        })
+    }
+}
+
+/// SAM text output.
+pub struct SamFormat {
+    out: OutputWriter,
+}
+
+impl SamFormat {
+    pub fn new(out: OutputWriter) -> Self {
+        Self { out }
+    }
+}
+
+impl OutputFormat for SamFormat {
+    fn write_header(&mut self, contigs: &[(&str, u32)]) -> Result<()> {
+        sam::write_header(&mut self.out, contigs)
+    }
+
+    fn write_header_with_lines(&mut self, contigs: &[(&str, u32)], lines: &[String]) -> Result<()> {
+        sam::write_header_with_lines(&mut self.out, contigs, lines)
+    }
+
+    fn write_record(&mut self, rec: &SamRecord) -> Result<()> {
+        rec.write(&mut self.out)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.out.try_finish()
+    }
+}
+
+/// PAF output; unmapped records are skipped.
+pub struct PafFormat {
+    out: OutputWriter,
+    contig_lens: HashMap<String, u32>,
+}
+
+impl PafFormat {
+    pub fn new(out: OutputWriter) -> Self {
+        Self {
+            out,
+            contig_lens: HashMap::new(),
+        }
+    }
+}
+
+impl OutputFormat for PafFormat {
+    fn write_header(&mut self, contigs: &[(&str, u32)]) -> Result<()> {
+        self.contig_lens = contigs.iter().map(|&(name, len)| (name.to_string(), len)).collect();
+        Ok(())
+    }
+
+    fn write_record(&mut self, rec: &SamRecord) -> Result<()> {
+        if rec.is_unmapped() {
+            return Ok(());
+        }
+        let target_len = self
+            .contig_lens
+            .get(&rec.rname)
+            .copied()
+            .ok_or_else(|| anyhow!("record '{}' references unknown contig '{}'", rec.qname, rec.rname))?;
+        writeln!(self.out, "{}", paf_line(rec, target_len))?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.out.try_finish()
+    }
+}
+
+/// BED12 output; unmapped records are skipped.
+pub struct Bed12Format {
+    out: OutputWriter,
+}
+
+impl Bed12Format {
+    pub fn new(out: OutputWriter) -> Self {
+        Self { out }
+    }
+}
+
+impl OutputFormat for Bed12Format {
+    fn write_header(&mut self, _contigs: &[(&str, u32)]) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_record(&mut self, rec: &SamRecord) -> Result<()> {
+        if rec.is_unmapped() {
+            return Ok(());
+        }
+        writeln!(self.out, "{}", bed12_line(rec))?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.out.try_finish()
+    }
+}
+
+/// CIGAR-derived extents of a mapped record.
+struct Extents {
+    /// Leading clip (S/H) on the SAM-oriented query
+    clip_left: usize,
+    /// Aligned query bases (M/I/=/X)
+    query_aligned: usize,
+    /// Full query length including clips
+    query_len: usize,
+    /// Reference bases spanned (M/D/N/=/X)
+    ref_span: usize,
+    /// Alignment columns (M/I/D/=/X)
+    columns: usize,
+}
+
+fn extents(cigar: &str) -> Extents {
+    let mut e = Extents {
+        clip_left: 0,
+        query_aligned: 0,
+        query_len: 0,
+        ref_span: 0,
+        columns: 0,
+    };
+    let mut seen_aligned = false;
+    for (op, len) in sam::parse_cigar_ops(cigar) {
+        match op {
+            'S' | 'H' => {
+                if !seen_aligned {
+                    e.clip_left += len;
+                }
+                e.query_len += len;
+            }
+            'M' | '=' | 'X' => {
+                seen_aligned = true;
+                e.query_aligned += len;
+                e.query_len += len;
+                e.ref_span += len;
+                e.columns += len;
+            }
+            'I' => {
+                seen_aligned = true;
+                e.query_aligned += len;
+                e.query_len += len;
+                e.columns += len;
+            }
+            'D' => {
+                seen_aligned = true;
+                e.ref_span += len;
+                e.columns += len;
+            }
+            'N' => {
+                seen_aligned = true;
+                e.ref_span += len;
+            }
+            _ => {}
+        }
+    }
+    e
+}
+
+/// Render a mapped record as a PAF line (without trailing newline).
+///
+/// Query coordinates are on the original read strand as PAF requires;
+/// residue matches are derived as alignment columns minus `NM`. Extra
+/// columns carry `tp:A` (`P` primary, `S` secondary/supplementary), `NM:i`,
+/// `AS:i` when present, and the CIGAR as `cg:Z` without clipping.
+pub fn paf_line(rec: &SamRecord, target_len: u32) -> String {
+    let e = extents(&rec.cigar);
+    let reverse = rec.flag & flags::REVERSE != 0;
+    let (q_start, q_end) = if reverse {
+        let end = e.query_len - e.clip_left;
+        (end - e.query_aligned, end)
+    } else {
+        (e.clip_left, e.clip_left + e.query_aligned)
+    };
+    let t_start = rec.pos.saturating_sub(1) as usize;
+    let nm = match rec.tag("NM") {
+        Some(TagValue::Int(v)) => *v as usize,
+        _ => 0,
+    };
+    let primary = rec.flag & (flags::SECONDARY | flags::SUPPLEMENTARY) == 0;
+    let mut line = format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\ttp:A:{}\tNM:i:{}",
+        rec.qname,
+        e.query_len,
+        q_start,
+        q_end,
+        if reverse { '-' } else { '+' },
+        rec.rname,
+        target_len,
+        t_start,
+        t_start + e.ref_span,
+        e.columns.saturating_sub(nm),
+        e.columns,
+        rec.mapq,
+        if primary { 'P' } else { 'S' },
+        nm,
+    );
+    if let Some(TagValue::Int(score)) = rec.tag("AS") {
+        line.push_str(&format!("\tAS:i:{}", score));
+    }
+    let cg: String = sam::parse_cigar_ops(&rec.cigar)
+        .into_iter()
+        .filter(|&(op, _)| op != 'S' && op != 'H')
+        .map(|(op, len)| format!("{}{}", len, op))
+        .collect();
+    line.push_str(&format!("\tcg:Z:{}", cg));
+    line
+}
+
+/// Render a mapped record as a BED12 line (without trailing newline).
+///
+/// Score is the MAPQ; blocks are split at `N` (reference skip) operations
+/// only, so deletions stay inside a block as in `bedtools bamtobed -bed12`.
+pub fn bed12_line(rec: &SamRecord) -> String {
+    let start = rec.pos.saturating_sub(1) as usize;
+    let mut blocks: Vec<(usize, usize)> = Vec::new();
+    let mut ref_off = 0usize;
+    let mut block_start = 0usize;
+    for (op, len) in sam::parse_cigar_ops(&rec.cigar) {
+        match op {
+            'M' | '=' | 'X' | 'D' => ref_off += len,
+            'N' => {
+                if ref_off > block_start {
+                    blocks.push((block_start, ref_off - block_start));
+                }
+                ref_off += len;
+                block_start = ref_off;
+            }
+            _ => {}
+        }
+    }
+    if ref_off > block_start || blocks.is_empty() {
+        blocks.push((block_start, ref_off - block_start));
+    }
+    let end = start + ref_off;
+    let join = |f: fn(&(usize, usize)) -> usize| -> String { blocks.iter().map(|b| format!("{},", f(b))).collect() };
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t0\t{}\t{}\t{}",
+        rec.rname,
+        start,
+        end,
+        rec.qname,
+        rec.mapq,
+        if rec.flag & flags::REVERSE != 0 { '-' } else { '+' },
+        start,
+        end,
+        blocks.len(),
+        join(|b| b.1),
+        join(|b| b.0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(flag: u16, cigar: &str, seq_len: usize) -> SamRecord {
+        let seq = "A".repeat(seq_len);
+        let mut rec = SamRecord::mapped("r1", flag, "chr1", 101, 60, cigar, &seq, &"I".repeat(seq_len));
+        rec.push_tag("AS", TagValue::Int(80));
+        rec.push_tag("NM", TagValue::Int(3));
+        rec
+    }
+
+    #[test]
+    fn output_mode_parses_names() {
+        assert_eq!("sam".parse::<OutputMode>(), Ok(OutputMode::Sam));
+        assert_eq!("PAF".parse::<OutputMode>(), Ok(OutputMode::Paf));
+        assert_eq!("bed".parse::<OutputMode>(), Ok(OutputMode::Bed12));
+        assert_eq!(
+            OutputMode::Bed12.to_string().parse::<OutputMode>(),
+            Ok(OutputMode::Bed12)
+        );
+        assert!("bam".parse::<OutputMode>().is_err());
+    }
+
+    #[test]
+    fn paf_line_forward_and_reverse() {
+        // 100 bp read: 92 aligned query bases, 90 (forward) / 92 (reverse) reference bases
+        let fwd = paf_line(&record(0, "5S40M2I50M3S", 100), 5000);
+        assert_eq!(
+            fwd,
+            "r1\t100\t5\t97\t+\tchr1\t5000\t100\t190\t89\t92\t60\ttp:A:P\tNM:i:3\tAS:i:80\tcg:Z:40M2I50M"
+        );
+        let rev = paf_line(&record(flags::REVERSE | flags::SECONDARY, "5S40M2D50M5S", 100), 5000);
+        assert_eq!(
+            rev,
+            "r1\t100\t5\t95\t-\tchr1\t5000\t100\t192\t89\t92\t60\ttp:A:S\tNM:i:3\tAS:i:80\tcg:Z:40M2D50M"
+        );
+    }
+
+    #[test]
+    fn bed12_line_splits_blocks_at_ref_skips() {
+        let rec = record(flags::REVERSE, "10M5D10M100N20M", 40);
+        assert_eq!(
+            bed12_line(&rec),
+            "chr1\t100\t245\tr1\t60\t-\t100\t245\t0\t2\t25,20,\t0,125,"
+        );
+        assert_eq!(
+            bed12_line(&record(0, "3S30M", 33)),
+            "chr1\t100\t130\tr1\t60\t+\t100\t130\t0\t1\t30,\t0,"
+        );
+    }
+}
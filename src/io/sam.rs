@@ -1,5 +1,10 @@
-use anyhow::Result;
-use std::io::Write;
+use anyhow::{anyhow, bail, Result};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{BufRead, Write};
+
+use super::gzip::OutputWriter;
+use super::output::OutputFormat;
 
 /// SAM flag constants
 pub mod flags {
@@ -31,16 +36,186 @@ pub mod flags {
 
 /// Write SAM header (@HD, @SQ, @PG) to output
 pub fn write_header<W: Write, S: AsRef<str>>(out: &mut W, contigs: &[(S, u32)]) -> Result<()> {
+    write_header_with_lines(out, contigs, &[])
+}
+
+/// Write SAM header with extra lines (e.g. `@RG`) between `@SQ` and `@PG`
+pub fn write_header_with_lines<W: Write, S: AsRef<str>>(
+    out: &mut W,
+    contigs: &[(S, u32)],
+    lines: &[String],
+) -> Result<()> {
     writeln!(out, "@HD\tVN:1.6\tSO:unsorted")?;
     for (name, len) in contigs {
         writeln!(out, "@SQ\tSN:{}\tLN:{}", name.as_ref(), len)?;
     }
-    writeln!(out, "@PG\tID:bwa-rust\tPN:bwa-rust\tVN:{}", env!("CARGO_PKG_VERSION"))?;
+    for line in lines {
+        writeln!(out, "{}", line)?;
+    }
+    writeln!(
+        out,
+        "@PG\tID:bwa-rust\tPN:bwa-rust\tVN:{}\tCL:{}",
+        env!("CARGO_PKG_VERSION"),
+        command_line()
+    )?;
     Ok(())
 }
 
-/// Format an unmapped SAM record (FLAG=4)
+/// The process command line for the `@PG` `CL` field, with tabs and newlines
+/// replaced so it stays a single header field.
+pub fn command_line() -> String {
+    std::env::args().collect::<Vec<_>>().join(" ").replace(['\t', '\n', '\r'], " ")
+}
+
+/// Value of an optional SAM field (`TAG:TYPE:VALUE`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagValue {
+    /// `A`: printable character
+    Char(char),
+    /// `i`: signed integer
+    Int(i64),
+    /// `f`: single-precision float
+    Float(f32),
+    /// `Z`: printable string
+    Str(String),
+}
+
+impl TagValue {
+    /// SAM type code for this value (`A`, `i`, `f` or `Z`).
+    pub fn type_code(&self) -> char {
+        match self {
+            TagValue::Char(_) => 'A',
+            TagValue::Int(_) => 'i',
+            TagValue::Float(_) => 'f',
+            TagValue::Str(_) => 'Z',
+        }
+    }
+}
+
+impl fmt::Display for TagValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TagValue::Char(c) => write!(f, "{}", c),
+            TagValue::Int(v) => write!(f, "{}", v),
+            TagValue::Float(v) => write!(f, "{}", v),
+            TagValue::Str(v) => f.write_str(v),
+        }
+    }
+}
+
+/// A structured SAM alignment record.
+///
+/// `Display` renders the tab-separated SAM line (without trailing newline);
+/// empty SEQ/QUAL are written as `*`. Records can be inspected or modified
+/// before being written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamRecord {
+    pub qname: String,
+    pub flag: u16,
+    /// Reference name, `*` when unplaced
+    pub rname: String,
+    /// 1-based leftmost position, `0` when unplaced
+    pub pos: u32,
+    pub mapq: u8,
+    /// CIGAR string, `*` when unavailable
+    pub cigar: String,
+    /// Mate reference name (`*`, `=` or a contig name)
+    pub rnext: String,
+    pub pnext: u32,
+    pub tlen: i64,
+    pub seq: String,
+    pub qual: String,
+    /// Optional fields in output order, e.g. `("AS", TagValue::Int(50))`
+    pub tags: Vec<(String, TagValue)>,
+}
+
+impl SamRecord {
+    /// Unmapped record (FLAG=4) with no placement.
+    pub fn unmapped(qname: &str, seq: &str, qual: &str) -> Self {
+        Self {
+            qname: qname.to_string(),
+            flag: flags::UNMAP,
+            rname: "*".to_string(),
+            pos: 0,
+            mapq: 0,
+            cigar: "*".to_string(),
+            rnext: "*".to_string(),
+            pnext: 0,
+            tlen: 0,
+            seq: seq.to_string(),
+            qual: qual.to_string(),
+            tags: Vec::new(),
+        }
+    }
+
+    /// Mapped single-end record without mate information or tags.
+    pub fn mapped(qname: &str, flag: u16, rname: &str, pos: u32, mapq: u8, cigar: &str, seq: &str, qual: &str) -> Self {
+        Self {
+            qname: qname.to_string(),
+            flag,
+            rname: rname.to_string(),
+            pos,
+            mapq,
+            cigar: cigar.to_string(),
+            rnext: "*".to_string(),
+            pnext: 0,
+            tlen: 0,
+            seq: seq.to_string(),
+            qual: qual.to_string(),
+            tags: Vec::new(),
+        }
+    }
+
+    /// Append an optional field.
+    pub fn push_tag(&mut self, tag: &str, value: TagValue) {
+        self.tags.push((tag.to_string(), value));
+    }
+
+    /// Look up an optional field by its two-letter tag.
+    pub fn tag(&self, tag: &str) -> Option<&TagValue> {
+        self.tags.iter().find(|(t, _)| t == tag).map(|(_, v)| v)
+    }
+
+    /// True when FLAG has the unmapped bit set.
+    pub fn is_unmapped(&self) -> bool {
+        self.flag & flags::UNMAP != 0
+    }
+
+    /// Write the SAM line followed by a newline.
+    pub fn write<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        writeln!(out, "{}", self)
+    }
+}
+
+impl fmt::Display for SamRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_star = |s: &str| if s.is_empty() { "*" } else { s }.to_string();
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.qname,
+            self.flag,
+            self.rname,
+            self.pos,
+            self.mapq,
+            self.cigar,
+            self.rnext,
+            self.pnext,
+            self.tlen,
+            or_star(&self.seq),
+            or_star(&self.qual),
+        )?;
+        for (tag, value) in &self.tags {
+            write!(f, "\t{}:{}:{}", tag, value.type_code(), value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Format an unmapped SAM record (FLAG=4); empty SEQ/QUAL are written as `*`
 pub fn format_unmapped(qname: &str, seq: &str, qual: &str) -> String {
+    let seq = if seq.is_empty() { "*" } else { seq };
+    let qual = if qual.is_empty() { "*" } else { qual };
     format!("{}\t4\t*\t0\t0\t*\t*\t0\t0\t{}\t{}", qname, seq, qual,)
 }
 
@@ -124,6 +299,8 @@ pub fn format_record_with_optional_tags(
 ///
 /// The MD:Z tag encodes the reference sequence at mismatch positions for variant calling.
 /// Format: numbers for matches, letters for mismatches, ^ followed by letters for deletions.
+/// Same as [`crate::align::md_tag`] with `ref_start = 0`: the tag always starts
+/// and ends with a number, with `0` between adjacent mismatches/deletions.
 ///
 /// # Arguments
 /// * `reference` - Reference sequence segment (already oriented to match query strand)
@@ -144,83 +321,392 @@ pub fn format_record_with_optional_tags(
 /// assert_eq!(md, "4A3");
 /// ```
 pub fn generate_md_tag(reference: &[u8], query: &[u8], cigar: &str) -> String {
-    let ops = parse_cigar_ops(cigar);
-    let mut md = String::new();
-    let mut ref_pos = 0usize;
-    let mut query_pos = 0usize;
-    let mut match_count = 0usize;
+    crate::align::md_tag(query, reference, cigar, 0)
+}
 
-    for (op, len) in ops {
-        match op {
-            'M' | '=' | 'X' => {
-                // Alignment match: compare query vs reference
-                for _ in 0..len {
-                    if ref_pos >= reference.len() || query_pos >= query.len() {
-                        break;
+/// Placeholder substituted with the contig name in split output templates.
+pub const CONTIG_PLACEHOLDER: &str = "{contig}";
+
+/// File-name label used for unmapped records in split output. A contig
+/// literally named `unmapped` gets the label `%75nmapped` instead (see
+/// [`contig_file_label`]), so the two never share a file.
+pub const UNMAPPED_LABEL: &str = "unmapped";
+
+/// Most split output files held open at once. When a record arrives for
+/// another contig, the least recently written file is closed first and later
+/// reopened in append mode, so references with thousands of contigs stay
+/// within the process file-descriptor limit.
+pub const MAX_OPEN_SPLIT_FILES: usize = 256;
+
+/// File-name label for contig `rname` in split output.
+///
+/// `/`, `\` and `%` are percent-escaped (`%2F`, `%5C`, `%25`), so the label
+/// cannot leave the template's directory and distinct contigs never share a
+/// file; names that would still resolve to `.` or `..` are rejected. A contig
+/// named like [`UNMAPPED_LABEL`] has its first letter escaped (`%75nmapped`).
+pub fn contig_file_label(rname: &str) -> Result<String> {
+    if rname.is_empty() || rname == "." || rname == ".." {
+        bail!("contig name '{}' cannot be used as a split output file name", rname);
+    }
+    if rname == UNMAPPED_LABEL {
+        return Ok("%75nmapped".to_string());
+    }
+    let mut label = String::with_capacity(rname.len());
+    for c in rname.chars() {
+        match c {
+            '/' => label.push_str("%2F"),
+            '\\' => label.push_str("%5C"),
+            '%' => label.push_str("%25"),
+            _ => label.push(c),
+        }
+    }
+    Ok(label)
+}
+
+/// Routes SAM records into one file per reference contig.
+///
+/// The output path is a template containing [`CONTIG_PLACEHOLDER`], e.g.
+/// `out.{contig}.sam` → `out.chr1.sam`; records with RNAME `*` go to
+/// `out.unmapped.sam`. Files are created lazily on the first record for a
+/// contig and each starts with the full SAM header. At most
+/// [`MAX_OPEN_SPLIT_FILES`] files are open at a time.
+pub struct ContigSplitWriter {
+    template: String,
+    header: Vec<u8>,
+    compress_level: u32,
+    max_open: usize,
+    /// Open writers by RNAME, with the record count at their last write
+    files: HashMap<String, (OutputWriter, u64)>,
+    /// RNAMEs whose file has been created (header written), open or not
+    created: HashSet<String>,
+    records: u64,
+}
+
+impl ContigSplitWriter {
+    /// `header` is the pre-rendered SAM header written at the top of every file.
+    pub fn new(template: &str, header: Vec<u8>, compress_level: u32) -> Self {
+        Self {
+            template: template.to_string(),
+            header,
+            compress_level,
+            max_open: MAX_OPEN_SPLIT_FILES,
+            files: HashMap::new(),
+            created: HashSet::new(),
+            records: 0,
+        }
+    }
+
+    /// Keep at most `max_open` files open instead of [`MAX_OPEN_SPLIT_FILES`].
+    pub fn with_max_open(self, max_open: usize) -> Self {
+        Self {
+            max_open: max_open.max(1),
+            ..self
+        }
+    }
+
+    /// Returns true when `path` is a split-output template.
+    pub fn is_template(path: &str) -> bool {
+        path.contains(CONTIG_PLACEHOLDER)
+    }
+
+    /// Output path for records whose RNAME is `rname`.
+    pub fn path_for(&self, rname: &str) -> Result<String> {
+        let label = if rname == "*" {
+            UNMAPPED_LABEL.to_string()
+        } else {
+            contig_file_label(rname)?
+        };
+        Ok(self.template.replace(CONTIG_PLACEHOLDER, &label))
+    }
+
+    /// Write one SAM record line (without trailing newline), routed by its RNAME column.
+    pub fn write_record(&mut self, line: &str) -> Result<()> {
+        let rname = line.split('\t').nth(2).unwrap_or("*");
+        if !self.files.contains_key(rname) {
+            if self.files.len() >= self.max_open {
+                self.close_least_recent()?;
+            }
+            let path = self.path_for(rname)?;
+            let w = if self.created.contains(rname) {
+                OutputWriter::append(path, self.compress_level)?
+            } else {
+                let mut w = OutputWriter::create(path, self.compress_level)?;
+                w.write_all(&self.header)?;
+                self.created.insert(rname.to_string());
+                w
+            };
+            self.files.insert(rname.to_string(), (w, 0));
+        }
+        self.records += 1;
+        let (w, last) = self.files.get_mut(rname).expect("writer inserted above");
+        *last = self.records;
+        writeln!(w, "{}", line)?;
+        Ok(())
+    }
+
+    /// Finish the open file written least recently; it is reopened for appending if needed again.
+    fn close_least_recent(&mut self) -> Result<()> {
+        let Some(rname) = self.files.iter().min_by_key(|(_, (_, last))| *last).map(|(r, _)| r.clone()) else {
+            return Ok(());
+        };
+        if let Some((w, _)) = self.files.remove(&rname) {
+            w.finish()?;
+        }
+        Ok(())
+    }
+
+    /// Flush and close every opened file.
+    pub fn finish(self) -> Result<()> {
+        for (_, (w, _)) in self.files {
+            w.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// Split SAM output: the header is rendered once and copied to every file.
+impl OutputFormat for ContigSplitWriter {
+    fn write_header(&mut self, contigs: &[(&str, u32)]) -> Result<()> {
+        self.write_header_with_lines(contigs, &[])
+    }
+
+    fn write_header_with_lines(&mut self, contigs: &[(&str, u32)], lines: &[String]) -> Result<()> {
+        self.header.clear();
+        write_header_with_lines(&mut self.header, contigs, lines)
+    }
+
+    fn write_record(&mut self, rec: &SamRecord) -> Result<()> {
+        ContigSplitWriter::write_record(self, &rec.to_string())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for (_, (w, _)) in self.files.drain() {
+            w.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// Check emitted SAM text (header and records) for basic well-formedness.
+///
+/// Verifies that `@HD` appears exactly once, `@SQ` names are unique with a
+/// valid `LN`, and that every record has the 11 mandatory fields, an RNAME
+/// listed in `@SQ`, a POS within the contig, a CIGAR whose query length
+/// matches SEQ, and flags consistent with its placement (unmapped unpaired
+/// records use RNAME `*`, mapped records have a CIGAR). Errors name the
+/// offending line (1-based).
+pub fn validate_sam_header(sam: &str) -> Result<()> {
+    let mut validator = SamValidator::default();
+    for line in sam.lines() {
+        validator.line(line)?;
+    }
+    validator.finish()
+}
+
+/// Read a SAM file (plain or gzip-compressed) line by line and run the
+/// [`validate_sam_header`] checks on it; only the `@SQ` table is kept in memory.
+pub fn validate_sam_file(path: &str) -> Result<()> {
+    let mut validator = SamValidator::default();
+    for line in super::gzip::open_maybe_gzip(path)?.lines() {
+        let line = line.map_err(|e| anyhow!("{}: {}", path, e))?;
+        validator.line(&line).map_err(|e| anyhow!("{}: {}", path, e))?;
+    }
+    validator.finish().map_err(|e| anyhow!("{}: {}", path, e))
+}
+
+/// Line-at-a-time state behind [`validate_sam_header`] / [`validate_sam_file`].
+#[derive(Default)]
+struct SamValidator {
+    lineno: usize,
+    hd_count: usize,
+    contigs: HashMap<String, u32>,
+}
+
+impl SamValidator {
+    fn line(&mut self, line: &str) -> Result<()> {
+        self.lineno += 1;
+        let lineno = self.lineno;
+        if line.is_empty() {
+            bail!("line {}: empty line", lineno);
+        }
+        if let Some(rest) = line.strip_prefix('@') {
+            let mut fields = rest.split('\t');
+            match fields.next() {
+                Some("HD") => {
+                    self.hd_count += 1;
+                    if self.hd_count > 1 {
+                        bail!("line {}: duplicate @HD", lineno);
                     }
-                    let ref_base = reference[ref_pos].to_ascii_uppercase();
-                    let query_base = query[query_pos].to_ascii_uppercase();
-
-                    if ref_base == query_base {
-                        match_count += 1;
-                    } else {
-                        // Mismatch: output accumulated matches then the mismatched ref base
-                        if match_count > 0 {
-                            md.push_str(&match_count.to_string());
-                            match_count = 0;
-                        }
-                        md.push(ref_base as char);
+                    if lineno != 1 {
+                        bail!("line {}: @HD must be the first line", lineno);
                     }
-                    ref_pos += 1;
-                    query_pos += 1;
                 }
-            }
-            'I' => {
-                // Insertion: skip query bases, not in MD tag
-                query_pos += len;
-            }
-            'D' | 'N' => {
-                // Deletion/skip: output ^ followed by deleted reference bases
-                if match_count > 0 {
-                    md.push_str(&match_count.to_string());
-                    match_count = 0;
-                }
-                md.push('^');
-                for _ in 0..len {
-                    if ref_pos < reference.len() {
-                        md.push(reference[ref_pos].to_ascii_uppercase() as char);
-                        ref_pos += 1;
+                Some("SQ") => {
+                    let mut name = None;
+                    let mut len = None;
+                    for f in fields {
+                        if let Some(v) = f.strip_prefix("SN:") {
+                            name = Some(v);
+                        } else if let Some(v) = f.strip_prefix("LN:") {
+                            len = v.parse::<u32>().ok().filter(|&l| l > 0);
+                        }
+                    }
+                    let name = name.ok_or_else(|| anyhow!("line {}: @SQ without SN", lineno))?;
+                    let len = len.ok_or_else(|| anyhow!("line {}: @SQ '{}' without a valid LN", lineno, name))?;
+                    if self.contigs.insert(name.to_string(), len).is_some() {
+                        bail!("line {}: duplicate @SQ '{}'", lineno, name);
                     }
                 }
+                _ => {}
             }
-            'S' => {
-                // Soft clip: skip query bases, not in MD tag
-                query_pos += len;
-            }
-            'H' => {
-                // Hard clip: nothing consumed from either sequence
-            }
-            'P' => {
-                // Padding: skip both (rare)
-                ref_pos += len;
-                query_pos += len;
-            }
-            _ => {
-                // Unknown operator: skip
-            }
+            return Ok(());
+        }
+        if self.hd_count == 0 {
+            bail!("line {}: record before @HD", lineno);
+        }
+        validate_record(line, &self.contigs).map_err(|e| anyhow!("line {}: {}", lineno, e))
+    }
+
+    fn finish(self) -> Result<()> {
+        if self.hd_count == 0 {
+            bail!("missing @HD header line");
         }
+        Ok(())
     }
+}
+
+fn validate_record(line: &str, contigs: &HashMap<String, u32>) -> Result<()> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() < 11 {
+        bail!("expected at least 11 fields, found {}", fields.len());
+    }
+    let flag: u16 = fields[1].parse().map_err(|_| anyhow!("invalid FLAG '{}'", fields[1]))?;
+    let rname = fields[2];
+    let pos: u32 = fields[3].parse().map_err(|_| anyhow!("invalid POS '{}'", fields[3]))?;
+    let cigar = fields[5];
+    let seq = fields[9];
+
+    if rname != "*" {
+        let len = contigs
+            .get(rname)
+            .ok_or_else(|| anyhow!("RNAME '{}' not present in @SQ", rname))?;
+        if pos == 0 || pos > *len {
+            bail!("POS {} outside contig '{}' (length {})", pos, rname, len);
+        }
+    } else if pos != 0 {
+        bail!("POS {} with RNAME '*'", pos);
+    }
+
+    if flag & flags::UNMAP != 0 {
+        if flag & flags::PAIRED == 0 && rname != "*" {
+            bail!("unmapped unpaired record has RNAME '{}'", rname);
+        }
+        if cigar != "*" {
+            bail!("unmapped record has CIGAR '{}'", cigar);
+        }
+    } else {
+        if rname == "*" {
+            bail!("mapped record has RNAME '*'");
+        }
+        if cigar == "*" {
+            bail!("mapped record has CIGAR '*'");
+        }
+    }
+
+    if cigar != "*" {
+        let ops = parse_cigar_ops(cigar);
+        if ops.is_empty() {
+            bail!("invalid CIGAR '{}'", cigar);
+        }
+        let qlen: usize = ops
+            .iter()
+            .filter(|(op, _)| matches!(op, 'M' | 'I' | 'S' | '=' | 'X'))
+            .map(|&(_, n)| n)
+            .sum();
+        if seq != "*" && qlen != seq.len() {
+            bail!("CIGAR '{}' query length {} != SEQ length {}", cigar, qlen, seq.len());
+        }
+    }
+
+    if fields[10] != "*" && seq != "*" && fields[10].len() != seq.len() {
+        bail!("QUAL length {} != SEQ length {}", fields[10].len(), seq.len());
+    }
+    Ok(())
+}
 
-    // Output any remaining match count
-    if match_count > 0 {
-        md.push_str(&match_count.to_string());
+/// Pad a CIGAR with soft clips so it spans the whole read.
+///
+/// `query_start..query_end` is the aligned part of the read (in SAM
+/// orientation) and `read_len` its full length. A missing leading
+/// `{query_start}S` or trailing `{read_len - query_end}S` is added, so the
+/// M/I/S/=/X operations sum to the SEQ length. CIGARs that already cover the
+/// read, or already start/end with a clip, are left as they are.
+///
+/// # Example
+/// ```
+/// let cigar = bwa_rust::io::sam::soft_clip_cigar("6M1I3M", 4, 14, 20);
+/// assert_eq!(cigar, "4S6M1I3M6S");
+/// ```
+pub fn soft_clip_cigar(cigar: &str, query_start: usize, query_end: usize, read_len: usize) -> String {
+    let ops = parse_cigar_ops(cigar);
+    let covered: usize = ops
+        .iter()
+        .filter(|(op, _)| matches!(op, 'M' | 'I' | 'S' | '=' | 'X'))
+        .map(|&(_, n)| n)
+        .sum();
+    if ops.is_empty() || covered >= read_len {
+        return cigar.to_string();
+    }
+    let is_clip = |op: Option<&(char, usize)>| matches!(op, Some(('S' | 'H', _)));
+    let mut out = String::with_capacity(cigar.len() + 8);
+    if query_start > 0 && !is_clip(ops.first()) {
+        out.push_str(&format!("{}S", query_start));
     }
+    out.push_str(cigar);
+    let right = read_len.saturating_sub(query_end);
+    if right > 0 && !is_clip(ops.last()) {
+        out.push_str(&format!("{}S", right));
+    }
+    out
+}
 
-    md
+/// Reference blocks covered by aligned bases of a CIGAR.
+///
+/// Returns `(offset, len)` pairs relative to POS for each run of `M`/`=`/`X`
+/// operations; deletions (`D`) and skips (`N`) advance the reference but end
+/// the current block, so they contribute no coverage.
+///
+/// # Example
+/// ```
+/// let blocks = bwa_rust::io::sam::cigar_ref_blocks("2S5M2D3M1I2M");
+/// assert_eq!(blocks, vec![(0, 5), (7, 5)]);
+/// ```
+pub fn cigar_ref_blocks(cigar: &str) -> Vec<(usize, usize)> {
+    let mut blocks: Vec<(usize, usize)> = Vec::new();
+    let mut ref_off = 0usize;
+    let mut open = false;
+    for (op, len) in parse_cigar_ops(cigar) {
+        match op {
+            'M' | '=' | 'X' => {
+                match blocks.last_mut() {
+                    Some(last) if open => last.1 += len,
+                    _ => blocks.push((ref_off, len)),
+                }
+                open = true;
+                ref_off += len;
+            }
+            'D' | 'N' => {
+                open = false;
+                ref_off += len;
+            }
+            _ => {}
+        }
+    }
+    blocks
 }
 
 /// Parse CIGAR string into (operator, length) pairs.
-fn parse_cigar_ops(cigar: &str) -> Vec<(char, usize)> {
+pub(crate) fn parse_cigar_ops(cigar: &str) -> Vec<(char, usize)> {
     let mut result = Vec::new();
     let mut num = 0usize;
 
@@ -252,6 +738,22 @@ mod tests {
         assert!(s.contains("@PG\tID:bwa-rust"));
     }
 
+    #[test]
+    fn header_orders_hd_sq_extra_pg() {
+        let mut buf = Vec::new();
+        let contigs = vec![("chr1", 1000u32)];
+        write_header_with_lines(&mut buf, &contigs, &["@RG\tID:lane1".to_string()]).unwrap();
+        let s = String::from_utf8(buf).unwrap();
+        let kinds: Vec<&str> = s.lines().map(|l| &l[..3]).collect();
+        assert_eq!(kinds, vec!["@HD", "@SQ", "@RG", "@PG"]);
+        let pg = s.lines().last().unwrap();
+        assert!(pg.starts_with(&format!(
+            "@PG\tID:bwa-rust\tPN:bwa-rust\tVN:{}\tCL:",
+            env!("CARGO_PKG_VERSION")
+        )));
+        assert_eq!(pg.split('\t').count(), 5);
+    }
+
     #[test]
     fn unmapped_format() {
         let line = format_unmapped("read1", "ACGT", "IIII");
@@ -291,6 +793,14 @@ mod tests {
         assert_eq!(fields[10], "IIII");
     }
 
+    #[test]
+    fn unmapped_empty_sequence_uses_star() {
+        let line = format_unmapped("r1", "", "");
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert_eq!(fields[9], "*");
+        assert_eq!(fields[10], "*");
+    }
+
     #[test]
     fn record_format_reverse_complement() {
         let line = format_record("read1", 16, "chr1", 50, 30, "20M", "ACGT", "IIII", 40, 10, 1);
@@ -343,7 +853,7 @@ mod tests {
         let ref_seq = b"ACGTACGTACGT";
         let query = b"TCGTTCGTACGT"; // T at pos 0, T at pos 4
         let md = generate_md_tag(ref_seq, query, "12M");
-        assert_eq!(md, "A3A7");
+        assert_eq!(md, "0A3A7");
     }
 
     #[test]
@@ -402,7 +912,7 @@ mod tests {
                                  // First M of last 4M: ref=A, query=T -> mismatch A
                                  // Next 3 M: CGT matches
         let md = generate_md_tag(ref_seq, query, "4M3D4M");
-        assert_eq!(md, "4^TTTA3");
+        assert_eq!(md, "4^TTT0A3");
     }
 
     #[test]
@@ -469,4 +979,216 @@ mod tests {
         let md = generate_md_tag(b"", b"", "");
         assert_eq!(md, "");
     }
+
+    #[test]
+    fn split_writer_paths() {
+        assert!(ContigSplitWriter::is_template("out.{contig}.sam"));
+        assert!(!ContigSplitWriter::is_template("out.sam"));
+        let w = ContigSplitWriter::new("out.{contig}.sam", Vec::new(), 6);
+        assert_eq!(w.path_for("chr1").unwrap(), "out.chr1.sam");
+        assert_eq!(w.path_for("*").unwrap(), "out.unmapped.sam");
+    }
+
+    #[test]
+    fn split_writer_escapes_path_separators_in_contig_names() {
+        let w = ContigSplitWriter::new("dir/{contig}.sam", Vec::new(), 6);
+        assert_eq!(w.path_for("../etc/x").unwrap(), "dir/..%2Fetc%2Fx.sam");
+        assert_eq!(w.path_for("a\\b").unwrap(), "dir/a%5Cb.sam");
+        for bad in ["..", ".", ""] {
+            assert!(w.path_for(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn split_writer_unmapped_file_cannot_collide_with_contig() {
+        let w = ContigSplitWriter::new("out.{contig}.sam", Vec::new(), 6);
+        assert_eq!(w.path_for("*").unwrap(), "out.unmapped.sam");
+        assert_eq!(w.path_for("unmapped").unwrap(), "out.%75nmapped.sam");
+        assert_eq!(w.path_for("%75nmapped").unwrap(), "out.%2575nmapped.sam");
+        // Escaping is injective, so two contigs never share a file either
+        assert_ne!(w.path_for("a/b").unwrap(), w.path_for("a%2Fb").unwrap());
+    }
+
+    #[test]
+    fn split_writer_reopens_files_beyond_the_open_limit() {
+        use std::io::Read;
+
+        let dir = std::env::temp_dir();
+        for ext in ["sam", "sam.gz"] {
+            let template = dir.join(format!("bwa_rust_split_lru_{}.{{contig}}.{}", std::process::id(), ext));
+            let mut w =
+                ContigSplitWriter::new(template.to_str().unwrap(), b"@HD\tVN:1.6\n".to_vec(), 6).with_max_open(2);
+            let rnames = ["chr1", "chr2", "chr3", "chr1", "*", "chr2", "chr1"];
+            for (i, rname) in rnames.iter().enumerate() {
+                w.write_record(&format!("r{}\t0\t{}", i, rname)).unwrap();
+                assert!(w.files.len() <= 2);
+            }
+            w.finish().unwrap();
+
+            for label in ["chr1", "chr2", "chr3", "unmapped"] {
+                let path = template.to_str().unwrap().replace(CONTIG_PLACEHOLDER, label);
+                let mut text = String::new();
+                crate::io::gzip::open_maybe_gzip(&path)
+                    .unwrap()
+                    .read_to_string(&mut text)
+                    .unwrap();
+                std::fs::remove_file(&path).ok();
+                let rname = if label == "unmapped" { "*" } else { label };
+                let expected: String = std::iter::once("@HD\tVN:1.6\n".to_string())
+                    .chain(
+                        rnames
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, r)| **r == rname)
+                            .map(|(i, r)| format!("r{}\t0\t{}\n", i, r)),
+                    )
+                    .collect();
+                assert_eq!(text, expected, "{}", path);
+            }
+        }
+    }
+
+    fn valid_sam() -> String {
+        let mut buf = Vec::new();
+        write_header(&mut buf, &[("chr1", 100u32), ("chr2", 50)]).unwrap();
+        let mut sam = String::from_utf8(buf).unwrap();
+        sam.push_str(&format_record(
+            "r1", 0, "chr1", 10, 60, "2S6M", "ACGTACGT", "IIIIIIII", 12, 0, 0,
+        ));
+        sam.push('\n');
+        sam.push_str(&format_unmapped("r2", "ACGT", "IIII"));
+        sam.push('\n');
+        sam
+    }
+
+    #[test]
+    fn validate_accepts_emitted_sam() {
+        assert!(validate_sam_header(&valid_sam()).is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_missing_or_duplicate_hd() {
+        let sam = valid_sam();
+        let no_hd: String = sam.lines().skip(1).map(|l| format!("{}\n", l)).collect();
+        assert!(validate_sam_header(&no_hd).is_err());
+        let dup_hd = format!("{}@HD\tVN:1.6\n", sam);
+        assert!(validate_sam_header(&dup_hd).is_err());
+    }
+
+    #[test]
+    fn validate_rejects_unknown_rname_and_out_of_range_pos() {
+        let mut sam = valid_sam();
+        sam.push_str(&format_record("r3", 0, "chrX", 1, 60, "4M", "ACGT", "IIII", 8, 0, 0));
+        let err = validate_sam_header(&sam).unwrap_err().to_string();
+        assert!(err.contains("chrX"), "{}", err);
+
+        let mut sam = valid_sam();
+        sam.push_str(&format_record("r3", 0, "chr2", 51, 60, "4M", "ACGT", "IIII", 8, 0, 0));
+        assert!(validate_sam_header(&sam).is_err());
+    }
+
+    #[test]
+    fn validate_rejects_cigar_seq_length_mismatch() {
+        let mut sam = valid_sam();
+        sam.push_str(&format_record("r3", 0, "chr1", 1, 60, "3M1D", "ACGT", "IIII", 8, 0, 0));
+        let err = validate_sam_header(&sam).unwrap_err().to_string();
+        assert!(err.contains("query length"), "{}", err);
+    }
+
+    #[test]
+    fn validate_rejects_inconsistent_unmapped_flags() {
+        let mut sam = valid_sam();
+        sam.push_str("r3\t4\tchr1\t5\t0\t*\t*\t0\t0\tACGT\tIIII\n");
+        assert!(validate_sam_header(&sam).is_err());
+
+        let mut sam = valid_sam();
+        sam.push_str("r3\t0\t*\t0\t0\t*\t*\t0\t0\tACGT\tIIII\n");
+        assert!(validate_sam_header(&sam).is_err());
+
+        // An unmapped mate of a paired read may take its mate's placement
+        let mut sam = valid_sam();
+        sam.push_str("r3\t69\tchr1\t5\t0\t*\t=\t5\t0\tACGT\tIIII\n");
+        assert!(validate_sam_header(&sam).is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_truncated_record() {
+        let mut sam = valid_sam();
+        sam.push_str("r3\t0\tchr1\t5\n");
+        let err = validate_sam_header(&sam).unwrap_err().to_string();
+        assert!(err.starts_with("line 7"), "{}", err);
+    }
+
+    #[test]
+    fn validate_sam_file_streams_plain_and_gzip_files() {
+        let dir = std::env::temp_dir();
+        let plain = dir.join(format!("bwa_rust_validate_stream_{}.sam", std::process::id()));
+        let gz = dir.join(format!("bwa_rust_validate_stream_{}.sam.gz", std::process::id()));
+        let mut sam = valid_sam();
+        std::fs::write(&plain, &sam).unwrap();
+        assert!(validate_sam_file(plain.to_str().unwrap()).is_ok());
+
+        sam.push_str("r3\t0\tchr1\t5\n");
+        let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        enc.write_all(sam.as_bytes()).unwrap();
+        std::fs::write(&gz, enc.finish().unwrap()).unwrap();
+        let err = validate_sam_file(gz.to_str().unwrap()).unwrap_err().to_string();
+        std::fs::remove_file(&plain).ok();
+        std::fs::remove_file(&gz).ok();
+        assert!(err.contains("line 7"), "{}", err);
+        assert!(err.starts_with(gz.to_str().unwrap()), "{}", err);
+    }
+
+    #[test]
+    fn sam_record_display_matches_text_formatters() {
+        let rec = SamRecord::unmapped("r1", "ACGT", "IIII");
+        assert_eq!(rec.to_string(), format_unmapped("r1", "ACGT", "IIII"));
+        assert!(rec.is_unmapped());
+        assert_eq!(
+            SamRecord::unmapped("r1", "", "").to_string(),
+            format_unmapped("r1", "", "")
+        );
+
+        let mut rec = SamRecord::mapped("r2", flags::REVERSE, "chr1", 100, 60, "4M", "ACGT", "IIII");
+        rec.push_tag("AS", TagValue::Int(8));
+        rec.push_tag("XS", TagValue::Int(0));
+        rec.push_tag("NM", TagValue::Int(0));
+        rec.push_tag("MD", TagValue::Str("4".to_string()));
+        assert_eq!(
+            rec.to_string(),
+            format_record_with_optional_tags("r2", 16, "chr1", 100, 60, "4M", "ACGT", "IIII", 8, 0, 0, "4", "")
+        );
+        assert_eq!(rec.tag("NM"), Some(&TagValue::Int(0)));
+        assert_eq!(rec.tag("SA"), None);
+
+        let mut out = Vec::new();
+        rec.write(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{}\n", rec));
+    }
+
+    #[test]
+    fn tag_value_type_codes() {
+        let mut rec = SamRecord::unmapped("r", "A", "I");
+        rec.push_tag("XA", TagValue::Char('x'));
+        rec.push_tag("XF", TagValue::Float(0.5));
+        assert!(rec.to_string().ends_with("\tXA:A:x\tXF:f:0.5"));
+    }
+
+    #[test]
+    fn soft_clip_cigar_pads_unaligned_ends() {
+        assert_eq!(soft_clip_cigar("10M", 3, 13, 20), "3S10M7S");
+        assert_eq!(soft_clip_cigar("10M", 0, 10, 12), "10M2S");
+        assert_eq!(soft_clip_cigar("5M2D5M", 2, 12, 12), "2S5M2D5M");
+        // already full length or already clipped
+        assert_eq!(soft_clip_cigar("3S10M7S", 3, 13, 20), "3S10M7S");
+        assert_eq!(soft_clip_cigar("3S10M", 3, 13, 20), "3S10M7S");
+        assert_eq!(soft_clip_cigar("", 0, 0, 5), "");
+        let padded = soft_clip_cigar("4M1I4M", 5, 14, 30);
+        let qlen: usize = parse_cigar_ops(&padded)
+            .iter()
+            .filter(|(op, _)| matches!(op, 'M' | 'I' | 'S' | '=' | 'X'))
+            .map(|&(_, n)| n)
+            .sum();
+        assert_eq!(qlen, 30);
+    }
 }
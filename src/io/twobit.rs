@@ -0,0 +1,225 @@
+use anyhow::{anyhow, bail, Result};
+use std::path::Path;
+
+use super::fasta::FastaRecord;
+
+/// `.2bit` file signature (stored in the file's byte order)
+const SIGNATURE: u32 = 0x1A41_2743;
+
+/// Packed base codes, two bits per base, most significant bits first
+const BASES: [u8; 4] = [b'T', b'C', b'A', b'G'];
+
+/// Whether `path` names a UCSC `.2bit` file (by extension).
+pub fn is_2bit_path(path: impl AsRef<Path>) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("2bit"))
+}
+
+/// Read every sequence of a `.2bit` file.
+pub fn read_2bit_file(path: impl AsRef<Path>) -> Result<Vec<FastaRecord>> {
+    let path = path.as_ref();
+    let data = std::fs::read(path).map_err(|e| anyhow!("cannot read '{}': {}", path.display(), e))?;
+    parse_2bit(&data).map_err(|e| anyhow!("'{}': {}", path.display(), e))
+}
+
+/// Decode an in-memory `.2bit` file into FASTA-equivalent records.
+///
+/// N blocks are written as `N` and mask blocks as lowercase bases, matching
+/// a soft-masked FASTA of the same reference. Both byte orders and the
+/// 64-bit offset variant (version 1) are accepted.
+pub fn parse_2bit(data: &[u8]) -> Result<Vec<FastaRecord>> {
+    let mut header = Cursor {
+        data,
+        pos: 0,
+        big_endian: false,
+    };
+    match header.u32()? {
+        SIGNATURE => {}
+        s if s.swap_bytes() == SIGNATURE => header.big_endian = true,
+        _ => bail!("not a .2bit file (bad signature)"),
+    }
+    let version = header.u32()?;
+    if version > 1 {
+        bail!("unsupported .2bit version {}", version);
+    }
+    let count = header.u32()? as usize;
+    header.u32()?; // reserved
+
+    let mut index = Vec::with_capacity(count.min(1 << 16));
+    for _ in 0..count {
+        let name_len = header.bytes(1)?[0] as usize;
+        let name = String::from_utf8(header.bytes(name_len)?.to_vec())
+            .map_err(|_| anyhow!(".2bit sequence name is not valid UTF-8"))?;
+        let offset = if version == 1 {
+            header.u64()?
+        } else {
+            u64::from(header.u32()?)
+        };
+        index.push((name, offset));
+    }
+
+    index
+        .into_iter()
+        .map(|(name, offset)| {
+            let pos = usize::try_from(offset).map_err(|_| anyhow!("sequence '{}' offset out of range", name))?;
+            let mut rec = Cursor { pos, ..header };
+            let seq = rec.sequence().map_err(|e| anyhow!("sequence '{}': {}", name, e))?;
+            Ok(FastaRecord {
+                id: name,
+                desc: None,
+                seq,
+                mask: None,
+            })
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy)]
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl<'a> Cursor<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| anyhow!("truncated .2bit data at byte {}", self.pos))?;
+        let out = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(out)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let b: [u8; 4] = self.bytes(4)?.try_into().expect("4 bytes");
+        Ok(if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let b: [u8; 8] = self.bytes(8)?.try_into().expect("8 bytes");
+        Ok(if self.big_endian {
+            u64::from_be_bytes(b)
+        } else {
+            u64::from_le_bytes(b)
+        })
+    }
+
+    /// `count` block starts followed by `count` block sizes
+    fn blocks(&mut self) -> Result<Vec<(usize, usize)>> {
+        let count = self.u32()? as usize;
+        let starts = (0..count).map(|_| self.u32()).collect::<Result<Vec<_>>>()?;
+        let sizes = (0..count).map(|_| self.u32()).collect::<Result<Vec<_>>>()?;
+        Ok(starts.into_iter().zip(sizes).map(|(s, l)| (s as usize, l as usize)).collect())
+    }
+
+    /// One sequence record: size, N blocks, mask blocks, reserved word, packed bases
+    fn sequence(&mut self) -> Result<Vec<u8>> {
+        let len = self.u32()? as usize;
+        let n_blocks = self.blocks()?;
+        let mask_blocks = self.blocks()?;
+        self.u32()?; // reserved
+        let packed = self.bytes((len + 3) / 4)?;
+
+        let mut seq: Vec<u8> = (0..len)
+            .map(|i| BASES[usize::from(packed[i / 4] >> (6 - 2 * (i % 4)) & 3)])
+            .collect();
+        for (blocks, what) in [(&n_blocks, "N"), (&mask_blocks, "mask")] {
+            if let Some(&(s, l)) = blocks.iter().find(|&&(s, l)| s.saturating_add(l) > len) {
+                bail!("{} block {}+{} exceeds sequence length {}", what, s, l, len);
+            }
+        }
+        for &(s, l) in &n_blocks {
+            seq[s..s + l].fill(b'N');
+        }
+        for &(s, l) in &mask_blocks {
+            seq[s..s + l].make_ascii_lowercase();
+        }
+        Ok(seq)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (name, bases, N blocks, mask blocks)
+    type TestSeq<'a> = (&'a str, &'a [u8], &'a [(u32, u32)], &'a [(u32, u32)]);
+
+    /// Hand-build a little-endian version-0 `.2bit` file
+    fn build_2bit(seqs: &[TestSeq]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let word = |out: &mut Vec<u8>, v: u32| out.extend_from_slice(&v.to_le_bytes());
+        word(&mut out, SIGNATURE);
+        word(&mut out, 0);
+        word(&mut out, seqs.len() as u32);
+        word(&mut out, 0);
+
+        let index_len: usize = seqs.iter().map(|(name, ..)| 1 + name.len() + 4).sum();
+        let mut records = Vec::new();
+        for (name, bases, n_blocks, mask_blocks) in seqs {
+            out.push(name.len() as u8);
+            out.extend_from_slice(name.as_bytes());
+            word(&mut out, (16 + index_len + records.len()) as u32);
+
+            word(&mut records, bases.len() as u32);
+            for blocks in [n_blocks, mask_blocks] {
+                word(&mut records, blocks.len() as u32);
+                blocks.iter().for_each(|&(s, _)| word(&mut records, s));
+                blocks.iter().for_each(|&(_, l)| word(&mut records, l));
+            }
+            word(&mut records, 0);
+            for chunk in bases.chunks(4) {
+                let mut byte = 0u8;
+                for (k, &b) in chunk.iter().enumerate() {
+                    let code = BASES.iter().position(|&c| c == b).unwrap_or(0) as u8;
+                    byte |= code << (6 - 2 * k);
+                }
+                records.push(byte);
+            }
+        }
+        out.extend(records);
+        out
+    }
+
+    #[test]
+    fn parse_2bit_applies_n_and_mask_blocks() {
+        let data = build_2bit(&[
+            ("chr1", b"ACGTACGTTTGCA", &[(4, 3)], &[(0, 2), (10, 3)]),
+            ("chrM", b"GATTACA", &[], &[]),
+        ]);
+        let recs = parse_2bit(&data).unwrap();
+        assert_eq!(recs.len(), 2);
+        assert_eq!(recs[0].id, "chr1");
+        // N block overrides the packed bases, mask blocks lowercase them
+        assert_eq!(recs[0].seq, b"acGTNNNTTTgca");
+        assert_eq!(recs[1].id, "chrM");
+        assert_eq!(recs[1].seq, b"GATTACA");
+    }
+
+    #[test]
+    fn parse_2bit_accepts_big_endian_and_rejects_garbage() {
+        let le = build_2bit(&[("c", b"ACGTA", &[], &[])]);
+        // Byte-swap every 32-bit field: header, index offset, record header words
+        let mut be = le.clone();
+        for w in [0, 4, 8, 12, 18, 22, 26, 30, 34] {
+            be[w..w + 4].reverse();
+        }
+        assert_eq!(parse_2bit(&be).unwrap()[0].seq, b"ACGTA");
+
+        assert!(parse_2bit(b"\x00\x01\x02\x03").is_err());
+        assert!(parse_2bit(&le[..le.len() - 1]).is_err());
+        let bad_block = build_2bit(&[("c", b"ACGT", &[(2, 5)], &[])]);
+        assert!(parse_2bit(&bad_block).is_err());
+        assert!(is_2bit_path("hg38.2bit") && is_2bit_path("x/Ref.2BIT"));
+        assert!(!is_2bit_path("ref.fa"));
+    }
+}
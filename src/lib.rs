@@ -70,4 +70,49 @@ pub(crate) mod testutil {
         let bwt_arr = bwt::build_bwt(&text, &sa_arr);
         FMIndex::build(text, bwt_arr, sa_arr, contigs, dna::SIGMA as u8, 4)
     }
+
+    /// 由 `(名称, ASCII 序列)` 列表经 FASTA 解析构建多 contig FM 索引（仅用于测试）
+    pub fn build_test_fm_contigs(contigs: &[(&str, &[u8])]) -> FMIndex {
+        let mut fasta = Vec::new();
+        for (name, seq) in contigs {
+            fasta.push(b'>');
+            fasta.extend_from_slice(name.as_bytes());
+            fasta.push(b'\n');
+            fasta.extend_from_slice(seq);
+            fasta.push(b'\n');
+        }
+        crate::index::builder::build_fm_index(std::io::Cursor::new(fasta), 4)
+            .unwrap()
+            .fm
+    }
+
+    /// 测试用的线性同余伪随机数发生器，同一种子产生同一序列
+    pub struct Lcg(u32);
+
+    impl Lcg {
+        pub fn new(seed: u32) -> Self {
+            Self(seed)
+        }
+
+        /// 推进一步并返回完整的 32 位状态
+        pub fn next_u32(&mut self) -> u32 {
+            self.0 = self.0.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            self.0
+        }
+
+        /// `[0, bound)` 内的随机数（取状态高 16 位）
+        pub fn below(&mut self, bound: u32) -> u32 {
+            (self.next_u32() >> 16) % bound
+        }
+
+        /// 长度为 `len` 的随机 ACGT 序列
+        pub fn dna(&mut self, len: usize) -> Vec<u8> {
+            (0..len).map(|_| b"ACGT"[self.below(4) as usize]).collect()
+        }
+    }
+
+    /// 以 `seed` 生成长度为 `len` 的随机 ACGT 序列
+    pub fn random_dna(seed: u32, len: usize) -> Vec<u8> {
+        Lcg::new(seed).dna(len)
+    }
 }
@@ -7,10 +7,12 @@ use tikv_jemallocator::Jemalloc;
 static GLOBAL: Jemalloc = Jemalloc;
 
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::parser::ValueSource;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
 
 use bwa_rust::align;
 use bwa_rust::index;
+use bwa_rust::io::output::OutputMode;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -34,107 +36,267 @@ enum Commands {
         /// Output prefix for the generated .fm index
         #[arg(short, long, default_value = "ref")]
         output: String,
+        /// Gzip-compress the index at this level (0-9); uncompressed if omitted
+        #[arg(long = "compress-level", value_parser = clap::value_parser!(u32).range(0..=9))]
+        compress_level: Option<u32>,
+        /// Keep one suffix-array entry every N rows (1 = full SA); others are recovered via LF-mapping
+        #[arg(long = "sa-sample-rate", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        sa_sample_rate: u32,
+        /// Only scan the FASTA and print projected index size and build memory; write nothing
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+        /// Also store reference bytes outside ACGTN (IUPAC codes, lowercase) so the exact FASTA sequence can be recovered
+        #[arg(long)]
+        lossless: bool,
     },
     /// Align reads in FASTQ against an existing FM index
     Align {
         /// Path to FM index (.fm)
         #[arg(short = 'i', long = "index")]
         index: String,
-        /// Reads FASTQ file
-        reads: String,
-        /// Output SAM path (stdout if omitted)
+        /// Reads FASTQ file (R1 when a mate file is given)
+        #[arg(required_unless_present = "manifest")]
+        reads: Option<String>,
+        /// Mate FASTQ file (R2) for paired-end alignment
+        reads2: Option<String>,
+        /// TSV listing single-end FASTQ paths, each optionally followed by @RG fields (ID:x, SM:y, ...);
+        /// all files go to one output and their records carry RG:Z tags
+        #[arg(long, conflicts_with_all = ["reads", "reads2"])]
+        manifest: Option<String>,
+        /// Output SAM path (stdout if omitted); a `.bam` path writes BAM, a `{contig}` placeholder one file per contig (unmapped reads: `unmapped`)
         #[arg(short, long)]
         out: Option<String>,
-        #[arg(long = "match", default_value_t = align::AlignOpt::default().match_score)]
-        match_score: i32,
-        #[arg(long = "mismatch", default_value_t = align::AlignOpt::default().mismatch_penalty)]
-        mismatch_penalty: i32,
-        #[arg(long = "gap-open", default_value_t = align::AlignOpt::default().gap_open)]
-        gap_open: i32,
-        #[arg(long = "gap-ext", default_value_t = align::AlignOpt::default().gap_extend)]
-        gap_extend: i32,
-        #[arg(long = "clip-penalty", default_value_t = align::AlignOpt::default().clip_penalty)]
-        clip_penalty: i32,
-        #[arg(long = "band-width", default_value_t = align::AlignOpt::default().band_width)]
-        band_width: usize,
-        #[arg(long = "score-threshold", default_value_t = align::AlignOpt::default().score_threshold)]
-        score_threshold: i32,
-        /// Minimum seed length
-        #[arg(short = 'k', long = "min-seed-len", default_value_t = align::AlignOpt::default().min_seed_len)]
-        min_seed_len: usize,
-        /// Z-drop threshold for alignment extension
-        #[arg(short = 'd', long = "z-drop", default_value_t = align::AlignOpt::default().zdrop)]
-        zdrop: i32,
-        /// Preset configuration (pacbio, ont2d)
-        #[arg(short = 'x', long = "preset")]
-        preset: Option<String>,
-        /// Number of threads
-        #[arg(short = 't', long = "threads", value_parser = parse_threads, default_value_t = align::AlignOpt::default().threads)]
-        threads: usize,
-        /// Maximum occurrences for a MEM seed (skip highly repetitive seeds)
-        #[arg(long = "max-occ", default_value_t = align::AlignOpt::default().max_occ)]
-        max_occ: usize,
-        /// Maximum chains to extract per contig
-        #[arg(long = "max-chains", default_value_t = align::AlignOpt::default().max_chains_per_contig)]
-        max_chains: usize,
-        /// Maximum alignments to output per read
-        #[arg(long = "max-alignments", default_value_t = align::AlignOpt::default().max_alignments_per_read)]
-        max_alignments: usize,
+        #[command(flatten)]
+        args: AlignArgs,
     },
     /// BWA-MEM style alignment: build index from FASTA and align FASTQ in one step
     Mem {
-        /// Reference FASTA file
+        /// Reference FASTA (optionally gzipped) or UCSC .2bit file
         reference: String,
+        /// Reads FASTQ file (R1 when a mate file is given)
+        reads: String,
+        /// Mate FASTQ file (R2) for paired-end alignment
+        reads2: Option<String>,
+        /// Output SAM path (stdout if omitted); a `.bam` path writes BAM, a `{contig}` placeholder one file per contig (unmapped reads: `unmapped`)
+        #[arg(short, long)]
+        out: Option<String>,
+        #[command(flatten)]
+        args: AlignArgs,
+    },
+    /// Align one literal sequence (both strands) and print its SAM line(s)
+    AlignOne {
+        /// Path to FM index (.fm)
+        #[arg(short = 'i', long = "index")]
+        index: String,
+        /// Read sequence; accepts the same bases as a FASTQ read (lowercase, U, IUPAC codes)
+        #[arg(long = "seq")]
+        seq: String,
+        /// Read name used as QNAME
+        #[arg(long = "name", default_value = "read")]
+        name: String,
+        /// Write a complete SAM file (with header) here instead of printing records to stdout
+        #[arg(short, long)]
+        out: Option<String>,
+        #[command(flatten)]
+        args: AlignArgs,
+    },
+    /// Seed stage only: write each read's SMEM suffix-array intervals to a .sai file for `samse`
+    Aln {
+        /// Path to FM index (.fm)
+        #[arg(short = 'i', long = "index")]
+        index: String,
         /// Reads FASTQ file
         reads: String,
+        /// Output .sai path
+        #[arg(short, long)]
+        out: String,
+        #[command(flatten)]
+        args: AlignArgs,
+    },
+    /// Extension stage: produce alignments from a .sai file written by `aln` and its FASTQ
+    Samse {
+        /// Path to FM index (.fm) used by `aln`
+        #[arg(short = 'i', long = "index")]
+        index: String,
+        /// .sai file written by `aln`
+        sai: String,
+        /// Reads FASTQ file given to `aln`
+        reads: String,
+        /// Output SAM path (stdout if omitted); a `.bam` path writes BAM, a `{contig}` placeholder one file per contig (unmapped reads: `unmapped`)
+        #[arg(short, long)]
+        out: Option<String>,
+        #[command(flatten)]
+        args: AlignArgs,
+    },
+    /// Estimate the insert-size distribution from a sample of read pairs
+    EstimateInsert {
+        /// Path to FM index (.fm)
+        #[arg(short = 'i', long = "index")]
+        index: String,
+        /// R1 FASTQ file
+        reads: String,
+        /// R2 FASTQ file
+        reads2: String,
+        /// Number of leading pairs to align
+        #[arg(long = "sample-pairs", default_value_t = 10_000)]
+        sample_pairs: usize,
+        /// Write the report here instead of stdout
+        #[arg(short, long)]
+        out: Option<String>,
+        #[command(flatten)]
+        args: AlignArgs,
+    },
+    /// Merge coordinate-sorted SAM files (e.g. per-shard outputs) into one sorted SAM
+    Merge {
+        /// Coordinate-sorted SAM inputs with identical @SQ headers
+        #[arg(required = true, num_args = 1..)]
+        inputs: Vec<String>,
         /// Output SAM path (stdout if omitted)
         #[arg(short, long)]
         out: Option<String>,
-        /// Match score
-        #[arg(short = 'A', long = "match", default_value_t = align::AlignOpt::default().match_score)]
-        match_score: i32,
-        /// Mismatch penalty
-        #[arg(short = 'B', long = "mismatch", default_value_t = align::AlignOpt::default().mismatch_penalty)]
-        mismatch_penalty: i32,
-        /// Gap open penalty
-        #[arg(short = 'O', long = "gap-open", default_value_t = align::AlignOpt::default().gap_open)]
-        gap_open: i32,
-        /// Gap extension penalty
-        #[arg(short = 'E', long = "gap-ext", default_value_t = align::AlignOpt::default().gap_extend)]
-        gap_extend: i32,
-        /// Soft-clipping penalty used during candidate ranking
-        #[arg(long = "clip-penalty", default_value_t = align::AlignOpt::default().clip_penalty)]
-        clip_penalty: i32,
-        /// Band width for banded SW
-        #[arg(short = 'w', long = "band-width", default_value_t = align::AlignOpt::default().band_width)]
-        band_width: usize,
-        /// Minimum alignment score to output
-        #[arg(short = 'T', long = "score-threshold", default_value_t = align::AlignOpt::default().score_threshold)]
-        score_threshold: i32,
-        /// Minimum seed length
-        #[arg(short = 'k', long = "min-seed-len", default_value_t = align::AlignOpt::default().min_seed_len)]
-        min_seed_len: usize,
-        /// Z-drop threshold for alignment extension
-        #[arg(short = 'd', long = "z-drop", default_value_t = align::AlignOpt::default().zdrop)]
-        zdrop: i32,
-        /// Preset configuration (pacbio, ont2d)
-        #[arg(short = 'x', long = "preset")]
-        preset: Option<String>,
-        /// Number of threads
-        #[arg(short = 't', long = "threads", value_parser = parse_threads, default_value_t = align::AlignOpt::default().threads)]
-        threads: usize,
-        /// Maximum occurrences for a MEM seed (skip highly repetitive seeds)
-        #[arg(long = "max-occ", default_value_t = align::AlignOpt::default().max_occ)]
-        max_occ: usize,
-        /// Maximum chains to extract per contig
-        #[arg(long = "max-chains", default_value_t = align::AlignOpt::default().max_chains_per_contig)]
-        max_chains: usize,
-        /// Maximum alignments to output per read
-        #[arg(long = "max-alignments", default_value_t = align::AlignOpt::default().max_alignments_per_read)]
-        max_alignments: usize,
+    },
+    /// Print an FM index's build metadata and layout
+    Inspect {
+        /// Path to FM index (.fm)
+        index: String,
     },
 }
 
+/// Alignment options shared by the aligning subcommands; `align-one`, `aln` and
+/// `estimate-insert` reject the ones they do not use
+#[derive(Args, Debug)]
+struct AlignArgs {
+    /// Match score
+    #[arg(short = 'A', long = "match", default_value_t = align::AlignOpt::default().match_score)]
+    match_score: i32,
+    /// Mismatch penalty
+    #[arg(short = 'B', long = "mismatch", default_value_t = align::AlignOpt::default().mismatch_penalty)]
+    mismatch_penalty: i32,
+    /// Gap open penalty
+    #[arg(short = 'O', long = "gap-open", default_value_t = align::AlignOpt::default().gap_open)]
+    gap_open: i32,
+    /// Gap extension penalty
+    #[arg(short = 'E', long = "gap-ext", default_value_t = align::AlignOpt::default().gap_extend)]
+    gap_extend: i32,
+    /// Soft-clipping penalty used during candidate ranking
+    #[arg(long = "clip-penalty", default_value_t = align::AlignOpt::default().clip_penalty)]
+    clip_penalty: i32,
+    /// Penalty for aligning a base against an N in the read or reference (0 = neutral)
+    #[arg(long = "n-penalty", default_value_t = align::AlignOpt::default().n_penalty)]
+    n_penalty: i32,
+    /// Score IUPAC codes (R, Y, ...) in reads and lossless-index references as matches to compatible bases
+    #[arg(long = "iupac")]
+    iupac: bool,
+    /// Band width for banded SW
+    #[arg(short = 'w', long = "band-width", default_value_t = align::AlignOpt::default().band_width)]
+    band_width: usize,
+    /// Minimum alignment score to output
+    #[arg(short = 'T', long = "score-threshold", default_value_t = align::AlignOpt::default().score_threshold)]
+    score_threshold: i32,
+    /// Minimum seed length
+    #[arg(short = 'k', long = "min-seed-len", default_value_t = align::AlignOpt::default().min_seed_len)]
+    min_seed_len: usize,
+    /// Z-drop threshold for alignment extension
+    #[arg(short = 'd', long = "z-drop", default_value_t = align::AlignOpt::default().zdrop)]
+    zdrop: i32,
+    /// Preset configuration (pacbio, ont2d)
+    #[arg(short = 'x', long = "preset")]
+    preset: Option<String>,
+    /// Number of threads
+    #[arg(short = 't', long = "threads", value_parser = parse_threads, default_value_t = align::AlignOpt::default().threads)]
+    threads: usize,
+    /// Maximum occurrences for a MEM seed (skip highly repetitive seeds)
+    #[arg(long = "max-occ", visible_alias = "max-seed-hits", default_value_t = align::AlignOpt::default().max_occ)]
+    max_occ: usize,
+    /// Maximum chains to extract per contig
+    #[arg(long = "max-chains", default_value_t = align::AlignOpt::default().max_chains_per_contig)]
+    max_chains: usize,
+    /// Maximum alignments to output per read
+    #[arg(long = "max-alignments", default_value_t = align::AlignOpt::default().max_alignments_per_read)]
+    max_alignments: usize,
+    /// Gzip level (0-9) for output paths ending in .gz
+    #[arg(long = "compress-level", default_value_t = align::AlignOpt::default().compress_level)]
+    compress_level: u32,
+    /// Skip chains scoring below this before extension (fraction of read length if < 1)
+    #[arg(long = "min-chain-score", default_value_t = align::AlignOpt::default().min_chain_score)]
+    min_chain_score: f64,
+    /// Number of reads loaded and aligned per batch
+    #[arg(long = "batch-size", default_value_t = align::AlignOpt::default().batch_size)]
+    batch_size: usize,
+    /// Per-read time budget in milliseconds; slower reads are reported unmapped (0 = unlimited)
+    #[arg(long = "per-read-timeout-ms", default_value_t = align::AlignOpt::default().per_read_timeout_ms)]
+    per_read_timeout_ms: u64,
+    /// Skip the reverse strand when the forward strand has a perfect full-length hit
+    #[arg(long = "strand-shortcut")]
+    strand_shortcut: bool,
+    /// Print CIGAR-op counts and score quantiles of primary alignments to stderr
+    #[arg(long = "score-report")]
+    score_report: bool,
+    /// Append the FASTQ comment of each read to its SAM records as a CO:Z tag
+    #[arg(short = 'C', long = "append-comment")]
+    append_comment: bool,
+    /// Annotate each mapped record with the reference bases it aligned against (ZR:Z tag)
+    #[arg(long = "emit-ref")]
+    emit_ref: bool,
+    /// Attach soft-clipped 5'/3' read bases of mapped records as Z5:Z / Z3:Z tags
+    #[arg(long = "clip-tags")]
+    clip_tags: bool,
+    /// Trim trailing poly-G runs (two-colour chemistry dark cycles) from reads before aligning
+    #[arg(long = "trim-polyg")]
+    trim_polyg: bool,
+    /// Output format: sam, paf or bed12
+    #[arg(long = "output-format", value_name = "FORMAT", default_value_t = align::AlignOpt::default().output_format)]
+    output_format: OutputMode,
+    /// Comma-separated contig names giving the order of @SQ header lines (must list every contig)
+    #[arg(long = "sq-order", value_name = "NAMES", value_delimiter = ',')]
+    sq_order: Option<Vec<String>>,
+    /// Drop reads whose primary alignment has MAPQ below this (0 = keep all)
+    #[arg(long = "min-mapq", default_value_t = align::AlignOpt::default().min_mapq)]
+    min_mapq: u8,
+    /// With --min-mapq, emit low-MAPQ reads as unmapped instead of dropping them
+    #[arg(long = "low-mapq-unmapped")]
+    low_mapq_unmapped: bool,
+    /// Extend only chains on the N contigs with the most seed support per strand (0 = all contigs)
+    #[arg(long = "max-contigs", default_value_t = align::AlignOpt::default().max_contigs_per_read)]
+    max_contigs: usize,
+    /// Write a BEDGRAPH per-base coverage track of the aligned reads to this path
+    #[arg(long = "coverage", value_name = "PATH")]
+    coverage: Option<String>,
+    /// Recompute each mapped record's edit distance and report records whose NM is off by more than TOL (default 2)
+    #[arg(long = "verify", value_name = "TOL", num_args = 0..=1, default_missing_value = "2")]
+    verify: Option<u32>,
+    /// Report secondary alignments in an XA:Z tag on the primary record instead of as 0x100 records
+    #[arg(long = "xa-tag")]
+    xa_tag: bool,
+    /// Input qualities are Phred+64 (Illumina 1.3-1.7); convert them to Phred+33 in the output
+    #[arg(long = "phred64")]
+    phred64: bool,
+    /// Read group header line fields, e.g. 'ID:lane1\tSM:sample1\tPL:ILLUMINA' (ID required); records get RG:Z:<ID>
+    #[arg(short = 'R', long = "read-group", value_name = "RG")]
+    read_group: Option<String>,
+    /// Align reads end to end without soft clipping; reads with no full-length placement are unmapped
+    #[arg(long = "no-clip")]
+    no_clip: bool,
+    /// Do not print progress lines or the end-of-run read summary to stderr
+    #[arg(long = "quiet")]
+    quiet: bool,
+    /// Smallest insert size of a proper pair (paired-end; see `estimate-insert`)
+    #[arg(long = "min-insert", default_value_t = align::AlignOpt::default().min_insert)]
+    min_insert: usize,
+    /// Largest insert size of a proper pair (paired-end; see `estimate-insert`)
+    #[arg(long = "max-insert", default_value_t = align::AlignOpt::default().max_insert)]
+    max_insert: usize,
+    /// Treat parameter warnings (e.g. zero mismatch penalty) as errors
+    #[arg(long = "strict")]
+    strict: bool,
+    /// Re-read the written SAM and check it for well-formedness (requires -o)
+    #[arg(long = "validate", requires = "out")]
+    validate: bool,
+    /// Write run statistics (reads, mapped, MAPQ histogram, per-contig counts, timing) as JSON to this path
+    #[arg(long = "stats-json", value_name = "PATH")]
+    stats_json: Option<String>,
+}
+
 fn parse_threads(s: &str) -> std::result::Result<usize, String> {
     let threads: usize = s.parse().map_err(|_| "threads must be a positive integer".to_string())?;
     if threads == 0 {
@@ -165,39 +327,50 @@ fn apply_preset(opt: &mut align::AlignOpt, preset: &str) {
     }
 }
 
-fn build_align_opt(
-    match_score: i32,
-    mismatch_penalty: i32,
-    gap_open: i32,
-    gap_extend: i32,
-    clip_penalty: i32,
-    band_width: usize,
-    score_threshold: i32,
-    min_seed_len: usize,
-    zdrop: i32,
-    threads: usize,
-    max_occ: usize,
-    max_chains: usize,
-    max_alignments: usize,
-    preset: Option<&str>,
-) -> align::AlignOpt {
+fn build_align_opt(args: &AlignArgs) -> align::AlignOpt {
     let mut opt = align::AlignOpt {
-        match_score,
-        mismatch_penalty,
-        gap_open,
-        gap_extend,
-        clip_penalty,
-        band_width,
-        score_threshold,
-        min_seed_len,
-        threads,
-        zdrop,
-        max_occ,
-        max_chains_per_contig: max_chains,
-        max_alignments_per_read: max_alignments,
+        match_score: args.match_score,
+        mismatch_penalty: args.mismatch_penalty,
+        gap_open: args.gap_open,
+        gap_extend: args.gap_extend,
+        clip_penalty: args.clip_penalty,
+        n_penalty: args.n_penalty,
+        iupac: args.iupac,
+        band_width: args.band_width,
+        score_threshold: args.score_threshold,
+        min_seed_len: args.min_seed_len,
+        threads: args.threads,
+        zdrop: args.zdrop,
+        max_occ: args.max_occ,
+        max_chains_per_contig: args.max_chains,
+        max_alignments_per_read: args.max_alignments,
+        compress_level: args.compress_level,
+        min_chain_score: args.min_chain_score,
+        batch_size: args.batch_size,
+        per_read_timeout_ms: args.per_read_timeout_ms,
+        strand_shortcut: args.strand_shortcut,
+        score_report: args.score_report,
+        append_comment: args.append_comment,
+        emit_ref: args.emit_ref,
+        clip_tags: args.clip_tags,
+        trim_polyg: args.trim_polyg,
+        output_format: args.output_format,
+        sq_order: args.sq_order.clone(),
+        min_mapq: args.min_mapq,
+        low_mapq_as_unmapped: args.low_mapq_unmapped,
+        max_contigs_per_read: args.max_contigs,
+        coverage_path: args.coverage.clone(),
+        verify_tolerance: args.verify,
+        xa_tag: args.xa_tag,
+        phred64: args.phred64,
+        read_group: args.read_group.clone(),
+        no_clip: args.no_clip,
+        quiet: args.quiet,
+        min_insert: args.min_insert,
+        max_insert: args.max_insert,
     };
 
-    if let Some(p) = preset {
+    if let Some(p) = args.preset.as_deref() {
         apply_preset(&mut opt, p);
     }
 
@@ -205,92 +378,281 @@ fn build_align_opt(
         eprintln!("Error: invalid alignment parameters: {}", e);
         std::process::exit(1);
     }
+    if let Err(e) = check_parameter_warnings(&opt, args.strict, &mut std::io::stderr()) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
     opt
 }
 
+/// Whether `subcommand` acts on the shared alignment option with clap argument id `id`;
+/// `align` and `mem` honor all of them
+fn honors_align_arg(subcommand: &str, id: &str) -> bool {
+    // Options that only change how each read is seeded, extended and scored
+    const SCORING: [&str; 20] = [
+        "match_score",
+        "mismatch_penalty",
+        "gap_open",
+        "gap_extend",
+        "clip_penalty",
+        "n_penalty",
+        "iupac",
+        "band_width",
+        "score_threshold",
+        "min_seed_len",
+        "zdrop",
+        "preset",
+        "max_occ",
+        "max_chains",
+        "max_alignments",
+        "min_chain_score",
+        "per_read_timeout_ms",
+        "strand_shortcut",
+        "max_contigs",
+        "no_clip",
+    ];
+    match subcommand {
+        "align-one" => {
+            SCORING.contains(&id)
+                || [
+                    "compress_level",
+                    "emit_ref",
+                    "clip_tags",
+                    "trim_polyg",
+                    "output_format",
+                    "sq_order",
+                    "min_mapq",
+                    "low_mapq_unmapped",
+                    "xa_tag",
+                    "strict",
+                    "validate",
+                    "stats_json",
+                ]
+                .contains(&id)
+        }
+        "aln" => [
+            "min_seed_len",
+            "preset",
+            "threads",
+            "batch_size",
+            "trim_polyg",
+            "strict",
+        ]
+        .contains(&id),
+        "estimate-insert" => SCORING.contains(&id) || ["threads", "trim_polyg", "strict"].contains(&id),
+        // Single-end only: no proper-pair window to apply
+        "samse" => !matches!(id, "min_insert" | "max_insert"),
+        _ => true,
+    }
+}
+
+/// Reject shared alignment options given on the command line that the chosen subcommand would
+/// silently ignore (e.g. `--min-mapq` for `aln`, which writes seeds, not alignments)
+fn check_unused_align_args(matches: &clap::ArgMatches) -> Result<()> {
+    let Some((name, sub)) = matches.subcommand() else {
+        return Ok(());
+    };
+    for arg in AlignArgs::augment_args(clap::Command::new("align-args")).get_arguments() {
+        let id = arg.get_id().as_str();
+        if !honors_align_arg(name, id) && sub.value_source(id) == Some(ValueSource::CommandLine) {
+            anyhow::bail!("--{} has no effect with `{}`", arg.get_long().unwrap_or(id), name);
+        }
+    }
+    Ok(())
+}
+
+/// Print parameter sanity warnings to `err`; with `strict`, any warning is an error.
+fn check_parameter_warnings(opt: &align::AlignOpt, strict: bool, err: &mut dyn std::io::Write) -> Result<()> {
+    let warnings = opt.parameter_warnings();
+    for w in &warnings {
+        writeln!(err, "[bwa-rust] Warning: {}", w)?;
+    }
+    if strict && !warnings.is_empty() {
+        anyhow::bail!("{} parameter warning(s) with --strict", warnings.len());
+    }
+    Ok(())
+}
+
 fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    check_unused_align_args(&matches)?;
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
     match cli.command {
-        Commands::Index { reference, output } => run_index(&reference, &output),
+        Commands::Index {
+            reference,
+            output,
+            compress_level,
+            sa_sample_rate,
+            dry_run,
+            lossless,
+        } => {
+            if dry_run {
+                run_index_dry_run(&reference, &output, sa_sample_rate)
+            } else {
+                run_index(&reference, &output, compress_level, sa_sample_rate, lossless)
+            }
+        }
         Commands::Align {
             index,
             reads,
+            reads2,
+            manifest,
             out,
-            match_score,
-            mismatch_penalty,
-            gap_open,
-            gap_extend,
-            clip_penalty,
-            band_width,
-            score_threshold,
-            min_seed_len,
-            zdrop,
-            preset,
-            threads,
-            max_occ,
-            max_chains,
-            max_alignments,
+            args,
         } => {
-            let opt = build_align_opt(
-                match_score,
-                mismatch_penalty,
-                gap_open,
-                gap_extend,
-                clip_penalty,
-                band_width,
-                score_threshold,
-                min_seed_len,
-                zdrop,
-                threads,
-                max_occ,
-                max_chains,
-                max_alignments,
-                preset.as_deref(),
-            );
-            run_align(&index, &reads, out.as_deref(), opt)
+            check_validate_args(out.as_deref(), &args)?;
+            let opt = build_align_opt(&args);
+            let stats = match (manifest, reads) {
+                (Some(manifest), _) => align::align_manifest(&index, &manifest, out.as_deref(), opt)?,
+                (None, Some(reads)) => run_align(&index, &reads, reads2.as_deref(), out.as_deref(), opt)?,
+                (None, None) => unreachable!("clap requires reads unless --manifest is given"),
+            };
+            maybe_write_stats(&stats, &args)?;
+            maybe_validate(out.as_deref(), &args)
         }
         Commands::Mem {
             reference,
             reads,
+            reads2,
             out,
-            match_score,
-            mismatch_penalty,
-            gap_open,
-            gap_extend,
-            clip_penalty,
-            band_width,
-            score_threshold,
-            min_seed_len,
-            zdrop,
-            preset,
-            threads,
-            max_occ,
-            max_chains,
-            max_alignments,
+            args,
         } => {
-            let opt = build_align_opt(
-                match_score,
-                mismatch_penalty,
-                gap_open,
-                gap_extend,
-                clip_penalty,
-                band_width,
-                score_threshold,
-                min_seed_len,
-                zdrop,
-                threads,
-                max_occ,
-                max_chains,
-                max_alignments,
-                preset.as_deref(),
-            );
-            run_mem(&reference, &reads, out.as_deref(), opt)
+            check_validate_args(out.as_deref(), &args)?;
+            let opt = build_align_opt(&args);
+            let stats = run_mem(&reference, &reads, reads2.as_deref(), out.as_deref(), opt)?;
+            maybe_write_stats(&stats, &args)?;
+            maybe_validate(out.as_deref(), &args)
         }
+        Commands::AlignOne {
+            index,
+            seq,
+            name,
+            out,
+            args,
+        } => {
+            check_validate_args(out.as_deref(), &args)?;
+            let opt = build_align_opt(&args);
+            let stats = run_align_one(&index, &name, &seq, out.as_deref(), opt)?;
+            maybe_write_stats(&stats, &args)?;
+            maybe_validate(out.as_deref(), &args)
+        }
+        Commands::Aln {
+            index,
+            reads,
+            out,
+            args,
+        } => run_aln(&index, &reads, &out, &build_align_opt(&args)),
+        Commands::Samse {
+            index,
+            sai,
+            reads,
+            out,
+            args,
+        } => {
+            check_validate_args(out.as_deref(), &args)?;
+            let opt = build_align_opt(&args);
+            let stats = align::align_sai(&index, &sai, &reads, out.as_deref(), opt)?;
+            maybe_write_stats(&stats, &args)?;
+            maybe_validate(out.as_deref(), &args)
+        }
+        Commands::EstimateInsert {
+            index,
+            reads,
+            reads2,
+            sample_pairs,
+            out,
+            args,
+        } => run_estimate_insert(
+            &index,
+            &reads,
+            &reads2,
+            sample_pairs,
+            out.as_deref(),
+            &build_align_opt(&args),
+        ),
+        Commands::Merge { inputs, out } => bwa_rust::io::merge::merge_sorted_sam_files(&inputs, out.as_deref()),
+        Commands::Inspect { index } => {
+            let fm = index::fm::FMIndex::load_from_file(&index)?;
+            write_index_info(&fm, &mut std::io::stdout().lock())
+        }
+    }
+}
+
+/// `inspect`: index metadata and layout, one `key: value` per line
+fn write_index_info(fm: &index::fm::FMIndex, out: &mut impl std::io::Write) -> Result<()> {
+    let meta = fm.meta().cloned().unwrap_or_default();
+    let or_unknown = |v: Option<String>| v.unwrap_or_else(|| "-".to_string());
+    writeln!(out, "reference: {}", or_unknown(meta.reference_file))?;
+    writeln!(out, "build_args: {}", or_unknown(meta.build_args))?;
+    writeln!(out, "build_timestamp: {}", or_unknown(meta.build_timestamp))?;
+    writeln!(out, "version: {}", fm.version)?;
+    writeln!(out, "sigma: {}", fm.sigma)?;
+    writeln!(out, "block_size: {}", fm.bwt.block())?;
+    writeln!(out, "sequences: {}", fm.contigs.len())?;
+    writeln!(
+        out,
+        "total_len: {}",
+        fm.contigs.iter().map(|c| u64::from(c.len)).sum::<u64>()
+    )?;
+    writeln!(out, "sa_sample_rate: {}", fm.sa_sample_rate.max(1))?;
+    writeln!(out, "lossless: {}", fm.is_lossless())?;
+    Ok(())
+}
+
+/// `--stats-json`: write the run statistics as JSON
+fn maybe_write_stats(stats: &align::AlignStats, args: &AlignArgs) -> Result<()> {
+    if let Some(path) = args.stats_json.as_deref() {
+        stats.write_json(path)?;
+    }
+    Ok(())
+}
+
+/// `--validate`: reject output options that cannot be re-read as SAM, before any
+/// index is loaded or read aligned
+fn check_validate_args(out_path: Option<&str>, args: &AlignArgs) -> Result<()> {
+    if !args.validate {
+        return Ok(());
     }
+    let Some(path) = out_path else {
+        anyhow::bail!("--validate requires -o");
+    };
+    if args.output_format != OutputMode::Sam {
+        anyhow::bail!("--validate only supports SAM output");
+    }
+    if path.contains("{contig}") {
+        anyhow::bail!("--validate does not support split {{contig}} output");
+    }
+    if bwa_rust::io::bam::is_bam_path(path) {
+        anyhow::bail!("--validate does not support BAM output");
+    }
+    Ok(())
+}
+
+/// `--validate`: re-read the written SAM file and check its well-formedness
+fn maybe_validate(out_path: Option<&str>, args: &AlignArgs) -> Result<()> {
+    let (true, Some(path)) = (args.validate, out_path) else {
+        return Ok(());
+    };
+    bwa_rust::io::sam::validate_sam_file(path)?;
+    eprintln!("[bwa-rust] SAM validation passed: {}", path);
+    Ok(())
 }
 
-fn run_index(reference: &str, output: &str) -> Result<()> {
-    let mut result = index::builder::build_fm_from_fasta(reference, 512)?;
+/// Occ sampling interval used by `index`
+const INDEX_BLOCK_SIZE: usize = 512;
+
+fn run_index(
+    reference: &str,
+    output: &str,
+    compress_level: Option<u32>,
+    sa_sample_rate: u32,
+    lossless: bool,
+) -> Result<()> {
+    let mut result = if lossless {
+        index::builder::build_fm_from_fasta_lossless(reference, INDEX_BLOCK_SIZE, sa_sample_rate)?
+    } else {
+        index::builder::build_fm_from_fasta_sampled(reference, INDEX_BLOCK_SIZE, sa_sample_rate)?
+    };
 
     println!("reference: {}", reference);
     println!("sequences: {}", result.n_seqs);
@@ -303,19 +665,159 @@ fn run_index(reference: &str, output: &str) -> Result<()> {
     });
 
     let out_path = format!("{}.fm", output);
-    result
-        .fm
-        .save_to_file(&out_path)
-        .map_err(|e| anyhow::anyhow!("cannot write index to '{}': {}", out_path, e))?;
+    match compress_level {
+        Some(level) => result.fm.save_to_file_compressed(&out_path, level),
+        None => result.fm.save_to_file(&out_path),
+    }
+    .map_err(|e| anyhow::anyhow!("cannot write index to '{}': {}", out_path, e))?;
     println!("FM index saved: {}", out_path);
     Ok(())
 }
 
-fn run_align(index_path: &str, reads_path: &str, out_path: Option<&str>, opt: align::AlignOpt) -> Result<()> {
-    align::align_fastq_with_opt(index_path, reads_path, out_path, opt)
+fn run_index_dry_run(reference: &str, output: &str, sa_sample_rate: u32) -> Result<()> {
+    let est = index::builder::estimate_index_from_fasta(reference, INDEX_BLOCK_SIZE, sa_sample_rate)?;
+    let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+
+    println!("reference: {}", reference);
+    println!("sequences: {}", est.n_seqs);
+    println!("total_len: {}", est.total_len);
+    println!("dry run: {}.fm not written", output);
+    println!("  text:  {:>14} bytes ({:.1} MiB)", est.text_bytes, mib(est.text_bytes));
+    println!("  bwt:   {:>14} bytes ({:.1} MiB)", est.bwt_bytes, mib(est.bwt_bytes));
+    println!("  sa:    {:>14} bytes ({:.1} MiB)", est.sa_bytes, mib(est.sa_bytes));
+    println!(
+        "  occ:   {:>14} bytes ({:.1} MiB, block {})",
+        est.occ_bytes,
+        mib(est.occ_bytes),
+        est.block_size
+    );
+    println!(
+        "estimated index file: {} bytes ({:.1} MiB)",
+        est.disk_bytes,
+        mib(est.disk_bytes)
+    );
+    println!(
+        "estimated peak build memory: {} bytes ({:.1} MiB)",
+        est.peak_build_bytes,
+        mib(est.peak_build_bytes)
+    );
+    Ok(())
+}
+
+fn run_align(
+    index_path: &str,
+    reads_path: &str,
+    mates_path: Option<&str>,
+    out_path: Option<&str>,
+    opt: align::AlignOpt,
+) -> Result<align::AlignStats> {
+    match mates_path {
+        Some(r2) => align::align_fastq_paired(index_path, reads_path, r2, out_path, opt),
+        None => align::align_fastq_with_opt(index_path, reads_path, out_path, opt),
+    }
+}
+
+/// Align a literal sequence as a single synthetic FASTQ record
+fn align_one(
+    fm: &index::fm::FMIndex,
+    name: &str,
+    seq: &str,
+    opt: &align::AlignOpt,
+) -> Result<Vec<bwa_rust::io::sam::SamRecord>> {
+    let seq = seq.trim().as_bytes().to_vec();
+    if seq.is_empty() {
+        anyhow::bail!("--seq must not be empty");
+    }
+    let rec = bwa_rust::io::fastq::FastqRecord {
+        id: name.to_string(),
+        desc: None,
+        qual: vec![b'I'; seq.len()],
+        seq,
+    };
+    Ok(align::align_read_records(fm, &rec, opt))
 }
 
-fn run_mem(reference: &str, reads_path: &str, out_path: Option<&str>, opt: align::AlignOpt) -> Result<()> {
+fn run_align_one(
+    index_path: &str,
+    name: &str,
+    seq: &str,
+    out_path: Option<&str>,
+    opt: align::AlignOpt,
+) -> Result<align::AlignStats> {
+    let fm = index::fm::FMIndex::load_from_file(index_path)?;
+    let started = std::time::Instant::now();
+    let records = align_one(&fm, name, seq, &opt)?;
+    let mut stats = align::AlignStats::new();
+    records.iter().for_each(|r| stats.add(r));
+    if out_path.is_none() && opt.output_format == OutputMode::Sam {
+        // Plain SAM on stdout is printed without a header for quick inspection
+        for record in &records {
+            println!("{}", record);
+        }
+    } else {
+        let mut out = align::open_output(out_path, &opt)?;
+        out.write_header(&align::header_contigs(&fm, &opt)?)?;
+        for record in &records {
+            out.write_record(record)?;
+        }
+        out.finish()?;
+    }
+    stats.elapsed_secs = started.elapsed().as_secs_f64();
+    Ok(stats)
+}
+
+fn run_aln(index_path: &str, reads_path: &str, sai_path: &str, opt: &align::AlignOpt) -> Result<()> {
+    let fm = index::fm::FMIndex::load_from_file(index_path)?;
+    let n_reads = align::write_sai_file(&fm, reads_path, sai_path, opt)?;
+    eprintln!("[bwa-rust aln] {} reads -> {}", n_reads, sai_path);
+    Ok(())
+}
+
+fn run_estimate_insert(
+    index_path: &str,
+    r1_path: &str,
+    r2_path: &str,
+    sample_pairs: usize,
+    out_path: Option<&str>,
+    opt: &align::AlignOpt,
+) -> Result<()> {
+    let fm = index::fm::FMIndex::load_from_file(index_path)?;
+    let Some(est) = align::estimate_insert_size(&fm, r1_path, r2_path, opt, sample_pairs)? else {
+        anyhow::bail!(
+            "no confidently mapped proper pairs (MAPQ >= {}) among the first {} pairs",
+            align::pipeline::ESTIMATE_MIN_MAPQ,
+            sample_pairs
+        );
+    };
+    let window = est.pairing_opt(align::PairingOpt::default());
+    let report = format!(
+        "pairs\t{}\nmean\t{:.2}\nstddev\t{:.2}\nmedian\t{:.1}\nproper_pair_window\t{}-{}\n\
+         align_args\t--min-insert {} --max-insert {}\n",
+        est.pairs,
+        est.mean,
+        est.stddev,
+        est.median,
+        window.min_insert,
+        window.max_insert,
+        window.min_insert,
+        window.max_insert
+    );
+    match out_path {
+        Some(p) => std::fs::write(p, report).map_err(|e| anyhow::anyhow!("cannot write '{}': {}", p, e)),
+        None => {
+            print!("{}", report);
+            Ok(())
+        }
+    }
+}
+
+fn run_mem(
+    reference: &str,
+    reads_path: &str,
+    mates_path: Option<&str>,
+    out_path: Option<&str>,
+    opt: align::AlignOpt,
+) -> Result<align::AlignStats> {
     eprintln!("[bwa-rust mem] Loading reference: {}", reference);
 
     let result = index::builder::build_fm_from_fasta(reference, 512)?;
@@ -328,91 +830,487 @@ fn run_mem(reference: &str, reads_path: &str, out_path: Option<&str>, opt: align
 
     let fm = std::sync::Arc::new(result.fm);
 
-    eprintln!("[bwa-rust mem] Aligning reads from: {}", reads_path);
-    align::align_fastq_with_fm_opt(fm, reads_path, out_path, opt)
+    match mates_path {
+        Some(r2) => {
+            eprintln!("[bwa-rust mem] Aligning read pairs from: {} {}", reads_path, r2);
+            align::align_fastq_paired_with_fm(fm, reads_path, r2, out_path, opt)
+        }
+        None => {
+            eprintln!("[bwa-rust mem] Aligning reads from: {}", reads_path);
+            align::align_fastq_with_fm_opt(fm, reads_path, out_path, opt)
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn assert_defaults(args: &AlignArgs) {
+        let defaults = align::AlignOpt::default();
+
+        assert_eq!(args.match_score, defaults.match_score);
+        assert_eq!(args.mismatch_penalty, defaults.mismatch_penalty);
+        assert_eq!(args.gap_open, defaults.gap_open);
+        assert_eq!(args.gap_extend, defaults.gap_extend);
+        assert_eq!(args.clip_penalty, defaults.clip_penalty);
+        assert_eq!(args.n_penalty, defaults.n_penalty);
+        assert_eq!(args.iupac, defaults.iupac);
+        assert_eq!(args.band_width, defaults.band_width);
+        assert_eq!(args.score_threshold, defaults.score_threshold);
+        assert_eq!(args.min_seed_len, defaults.min_seed_len);
+        assert_eq!(args.zdrop, defaults.zdrop);
+        assert_eq!(args.threads, defaults.threads);
+        assert_eq!(args.max_occ, defaults.max_occ);
+        assert_eq!(args.max_chains, defaults.max_chains_per_contig);
+        assert_eq!(args.max_alignments, defaults.max_alignments_per_read);
+        assert_eq!(args.compress_level, defaults.compress_level);
+        assert_eq!(args.min_chain_score, defaults.min_chain_score);
+        assert_eq!(args.batch_size, defaults.batch_size);
+        assert_eq!(args.per_read_timeout_ms, defaults.per_read_timeout_ms);
+        assert_eq!(args.strand_shortcut, defaults.strand_shortcut);
+        assert_eq!(args.score_report, defaults.score_report);
+        assert_eq!(args.append_comment, defaults.append_comment);
+        assert_eq!(args.emit_ref, defaults.emit_ref);
+        assert_eq!(args.clip_tags, defaults.clip_tags);
+        assert_eq!(args.trim_polyg, defaults.trim_polyg);
+        assert_eq!(args.output_format, defaults.output_format);
+        assert_eq!(args.sq_order, defaults.sq_order);
+        assert_eq!(args.min_mapq, defaults.min_mapq);
+        assert_eq!(args.low_mapq_unmapped, defaults.low_mapq_as_unmapped);
+        assert_eq!(args.max_contigs, defaults.max_contigs_per_read);
+        assert_eq!(args.coverage, defaults.coverage_path);
+        assert_eq!(args.verify, defaults.verify_tolerance);
+        assert_eq!(args.xa_tag, defaults.xa_tag);
+        assert_eq!(args.phred64, defaults.phred64);
+        assert_eq!(args.read_group, defaults.read_group);
+        assert_eq!(args.no_clip, defaults.no_clip);
+        assert_eq!(args.quiet, defaults.quiet);
+        assert_eq!(args.min_insert, defaults.min_insert);
+        assert_eq!(args.max_insert, defaults.max_insert);
+    }
+
     #[test]
     fn mem_defaults_match_align_opt_default() {
         let cli = Cli::try_parse_from(["bwa-rust", "mem", "ref.fa", "reads.fq"]).unwrap();
-        let defaults = align::AlignOpt::default();
+        let Commands::Mem { args, .. } = cli.command else {
+            panic!("expected mem command");
+        };
+        assert_defaults(&args);
+    }
 
-        let Commands::Mem {
-            match_score,
-            mismatch_penalty,
-            gap_open,
-            gap_extend,
-            clip_penalty,
-            band_width,
-            score_threshold,
-            min_seed_len,
-            zdrop,
-            threads,
-            max_occ,
-            max_chains,
-            max_alignments,
+    #[test]
+    fn align_defaults_match_align_opt_default() {
+        let cli = Cli::try_parse_from(["bwa-rust", "align", "-i", "ref.fm", "reads.fq"]).unwrap();
+        let Commands::Align { args, .. } = cli.command else {
+            panic!("expected align command");
+        };
+        assert_defaults(&args);
+    }
+
+    #[test]
+    fn validate_requires_output_path() {
+        assert!(Cli::try_parse_from(["bwa-rust", "mem", "ref.fa", "reads.fq", "--validate"]).is_err());
+        let cli =
+            Cli::try_parse_from(["bwa-rust", "mem", "ref.fa", "reads.fq", "--validate", "-o", "out.sam"]).unwrap();
+        let Commands::Mem { args, .. } = cli.command else {
+            panic!("expected mem command");
+        };
+        assert!(args.validate);
+    }
+
+    #[test]
+    fn validate_rejects_unreadable_outputs_before_loading_index() {
+        let check = |out: &str| {
+            let cli = Cli::try_parse_from(["bwa-rust", "align", "-i", "missing.fm", "r.fq", "--validate", "-o", out])
+                .unwrap();
+            let Commands::Align { out, args, .. } = cli.command else {
+                panic!("expected align command");
+            };
+            check_validate_args(out.as_deref(), &args).map_err(|e| e.to_string())
+        };
+        assert!(check("out.bam").unwrap_err().contains("BAM"));
+        assert!(check("out.{contig}.sam").unwrap_err().contains("{contig}"));
+        assert!(check("out.sam").is_ok());
+    }
+
+    #[test]
+    fn seed_length_and_hit_cap_reach_align_opt() {
+        let cli = Cli::try_parse_from([
+            "bwa-rust",
+            "align",
+            "-i",
+            "ref.fm",
+            "reads.fq",
+            "--min-seed-len",
+            "25",
+            "--max-seed-hits",
+            "64",
+        ])
+        .unwrap();
+        let Commands::Align { args, .. } = cli.command else {
+            panic!("expected align command");
+        };
+        let opt = build_align_opt(&args);
+        assert_eq!((opt.min_seed_len, opt.max_occ), (25, 64));
+    }
+
+    #[test]
+    fn verify_takes_optional_tolerance() {
+        for (extra, expected) in [
+            (vec!["--verify"], Some(2)),
+            (vec!["--verify=0"], Some(0)),
+            (vec![], None),
+        ] {
+            let mut argv = vec!["bwa-rust", "align", "-i", "ref.fm", "reads.fq"];
+            argv.extend(extra);
+            let Commands::Align { args, .. } = Cli::try_parse_from(argv).unwrap().command else {
+                panic!("expected align command");
+            };
+            assert_eq!(build_align_opt(&args).verify_tolerance, expected);
+        }
+    }
+
+    #[test]
+    fn merge_requires_inputs() {
+        assert!(Cli::try_parse_from(["bwa-rust", "merge", "-o", "m.sam"]).is_err());
+        let cli = Cli::try_parse_from(["bwa-rust", "merge", "a.sam", "b.sam", "-o", "m.sam"]).unwrap();
+        let Commands::Merge { inputs, out } = cli.command else {
+            panic!("expected merge command");
+        };
+        assert_eq!(inputs, vec!["a.sam", "b.sam"]);
+        assert_eq!(out.as_deref(), Some("m.sam"));
+    }
+
+    #[test]
+    fn subcommands_reject_shared_options_they_ignore() {
+        let check = |argv: &[&str]| check_unused_align_args(&Cli::command().try_get_matches_from(argv).unwrap());
+
+        let aln = ["bwa-rust", "aln", "-i", "ref.fm", "r.fq", "-o", "r.sai"];
+        assert!(check(&aln).is_ok());
+        assert!(check(&[&aln[..], &["-k", "25", "-t", "2", "--trim-polyg"]].concat()).is_ok());
+        let err = check(&[&aln[..], &["--min-mapq", "20"]].concat()).unwrap_err();
+        assert_eq!(err.to_string(), "--min-mapq has no effect with `aln`");
+        assert!(check(&[&aln[..], &["-A", "2"]].concat()).is_err());
+
+        let one = ["bwa-rust", "align-one", "-i", "ref.fm", "--seq", "ACGT"];
+        assert!(check(&[&one[..], &["--min-mapq", "20", "--xa-tag", "-B", "6"]].concat()).is_ok());
+        assert!(check(&[&one[..], &["--coverage", "c.bedgraph"]].concat()).is_err());
+        assert!(check(&[&one[..], &["-R", "ID:x"]].concat()).is_err());
+
+        let est = ["bwa-rust", "estimate-insert", "-i", "ref.fm", "r1.fq", "r2.fq"];
+        assert!(check(&[&est[..], &["-t", "4", "-k", "25"]].concat()).is_ok());
+        assert!(check(&[&est[..], &["--compress-level", "9"]].concat()).is_err());
+        assert!(check(&[&est[..], &["--max-insert", "900"]].concat()).is_err());
+        let samse = ["bwa-rust", "samse", "-i", "ref.fm", "r.sai", "r.fq"];
+        assert!(check(&[&samse[..], &["--min-mapq", "20"]].concat()).is_ok());
+        assert!(check(&[&samse[..], &["--max-insert", "900"]].concat()).is_err());
+
+        // Subcommands that honor every shared option accept them all
+        assert!(check(&[
+            "bwa-rust",
+            "align",
+            "-i",
+            "ref.fm",
+            "r.fq",
+            "--min-mapq",
+            "20",
+            "--coverage",
+            "c"
+        ])
+        .is_ok());
+    }
+
+    #[test]
+    fn estimate_insert_parses_pair_and_sample_size() {
+        assert!(Cli::try_parse_from(["bwa-rust", "estimate-insert", "-i", "ref.fm", "r1.fq"]).is_err());
+        let cli = Cli::try_parse_from([
+            "bwa-rust",
+            "estimate-insert",
+            "-i",
+            "ref.fm",
+            "r1.fq",
+            "r2.fq",
+            "--sample-pairs",
+            "500",
+        ])
+        .unwrap();
+        let Commands::EstimateInsert {
+            reads,
+            reads2,
+            sample_pairs,
             ..
         } = cli.command
         else {
-            panic!("expected mem command");
+            panic!("expected estimate-insert command");
         };
+        assert_eq!((reads.as_str(), reads2.as_str(), sample_pairs), ("r1.fq", "r2.fq", 500));
+    }
 
-        assert_eq!(match_score, defaults.match_score);
-        assert_eq!(mismatch_penalty, defaults.mismatch_penalty);
-        assert_eq!(gap_open, defaults.gap_open);
-        assert_eq!(gap_extend, defaults.gap_extend);
-        assert_eq!(clip_penalty, defaults.clip_penalty);
-        assert_eq!(band_width, defaults.band_width);
-        assert_eq!(score_threshold, defaults.score_threshold);
-        assert_eq!(min_seed_len, defaults.min_seed_len);
-        assert_eq!(zdrop, defaults.zdrop);
-        assert_eq!(threads, defaults.threads);
-        assert_eq!(max_occ, defaults.max_occ);
-        assert_eq!(max_chains, defaults.max_chains_per_contig);
-        assert_eq!(max_alignments, defaults.max_alignments_per_read);
+    #[test]
+    fn index_compress_level_is_range_checked() {
+        let cli = Cli::try_parse_from(["bwa-rust", "index", "ref.fa", "--compress-level", "0"]).unwrap();
+        let Commands::Index { compress_level, .. } = cli.command else {
+            panic!("expected index command");
+        };
+        assert_eq!(compress_level, Some(0));
+        assert!(Cli::try_parse_from(["bwa-rust", "index", "ref.fa", "--compress-level", "10"]).is_err());
+        assert!(Cli::try_parse_from(["bwa-rust", "index", "ref.fa", "--sa-sample-rate", "0"]).is_err());
     }
 
     #[test]
-    fn align_defaults_match_align_opt_default() {
-        let cli = Cli::try_parse_from(["bwa-rust", "align", "-i", "ref.fm", "reads.fq"]).unwrap();
-        let defaults = align::AlignOpt::default();
+    fn index_dry_run_writes_nothing() {
+        let dir = std::env::temp_dir();
+        let pid = std::process::id();
+        let fa = dir.join(format!("bwa_rust_dry_run_{}.fa", pid));
+        let prefix = dir.join(format!("bwa_rust_dry_run_{}", pid));
+        std::fs::write(&fa, ">chr1\nACGTACGTACGT\n>chr2\nGGCCAATT\n").unwrap();
 
-        let Commands::Align {
-            match_score,
-            mismatch_penalty,
-            gap_open,
-            gap_extend,
-            clip_penalty,
-            band_width,
-            score_threshold,
-            min_seed_len,
-            zdrop,
-            threads,
-            max_occ,
-            max_chains,
-            max_alignments,
+        let cli = Cli::try_parse_from([
+            "bwa-rust",
+            "index",
+            fa.to_str().unwrap(),
+            "-o",
+            prefix.to_str().unwrap(),
+            "--dry-run",
+        ])
+        .unwrap();
+        let Commands::Index {
+            reference,
+            output,
+            sa_sample_rate,
+            dry_run,
             ..
         } = cli.command
         else {
+            panic!("expected index command");
+        };
+        assert!(dry_run);
+        assert_eq!(sa_sample_rate, 1);
+        run_index_dry_run(&reference, &output, sa_sample_rate).unwrap();
+        assert!(!std::path::Path::new(&format!("{}.fm", output)).exists());
+
+        let est = index::builder::estimate_index_from_fasta(&fa, INDEX_BLOCK_SIZE, 1).unwrap();
+        assert_eq!((est.n_seqs, est.total_len, est.text_len), (2, 20, 22));
+        assert!(est.disk_bytes > est.text_bytes + est.bwt_bytes + est.sa_bytes);
+        std::fs::remove_file(&fa).ok();
+    }
+
+    #[test]
+    fn index_lossless_flag_keeps_iupac_bases() {
+        let dir = std::env::temp_dir();
+        let pid = std::process::id();
+        let fa = dir.join(format!("bwa_rust_lossless_cli_{}.fa", pid));
+        let prefix = dir.join(format!("bwa_rust_lossless_cli_{}", pid));
+        std::fs::write(&fa, ">chr1\nACGTRYKMacgt\n").unwrap();
+
+        let cli = Cli::try_parse_from([
+            "bwa-rust",
+            "index",
+            fa.to_str().unwrap(),
+            "-o",
+            prefix.to_str().unwrap(),
+            "--lossless",
+        ])
+        .unwrap();
+        let Commands::Index {
+            reference,
+            output,
+            compress_level,
+            sa_sample_rate,
+            lossless,
+            ..
+        } = cli.command
+        else {
+            panic!("expected index command");
+        };
+        assert!(lossless);
+        run_index(&reference, &output, compress_level, sa_sample_rate, lossless).unwrap();
+        let fm_path = format!("{}.fm", output);
+        let fm = index::fm::FMIndex::load_from_file(&fm_path).unwrap();
+        std::fs::remove_file(&fm_path).ok();
+        std::fs::remove_file(&fa).ok();
+        assert_eq!(fm.decode_text_lossless(0, 12), b"ACGTRYKMacgt".to_vec());
+    }
+
+    #[test]
+    fn inspect_prints_index_metadata() {
+        let fasta = b">chr1\nACGTACGTAC\n>chr2\nGGGCCC\n";
+        let mut fm = index::builder::build_fm_index(std::io::Cursor::new(&fasta[..]), 64).unwrap().fm;
+        fm.set_meta(index::fm::IndexMeta {
+            reference_file: Some("ref.fa".to_string()),
+            build_args: Some("bwa-rust index ref.fa".to_string()),
+            build_timestamp: None,
+        });
+        let mut buf = Vec::new();
+        write_index_info(&fm, &mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        for line in [
+            "reference: ref.fa",
+            "build_args: bwa-rust index ref.fa",
+            "build_timestamp: -",
+            "block_size: 64",
+            "sequences: 2",
+            "total_len: 16",
+            "sa_sample_rate: 1",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing '{}' in\n{}", line, text);
+        }
+
+        let cli = Cli::try_parse_from(["bwa-rust", "inspect", "ref.fm"]).unwrap();
+        assert!(matches!(cli.command, Commands::Inspect { index } if index == "ref.fm"));
+    }
+
+    #[test]
+    fn align_one_maps_literal_reference_substring() {
+        let reference = b"GATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCCTAGCAATGGTACCGTTAGCCTA";
+        let fasta = [b">chr1\n".as_slice(), reference, b"\n"].concat();
+        let fm = index::builder::build_fm_index(std::io::Cursor::new(fasta), 4).unwrap().fm;
+        let opt = align::AlignOpt::default();
+
+        // reference[8..] -> 1-based POS 9
+        let lines = align_one(&fm, "q1", "gcttcaagtccgatgcattgcatcggatcc", &opt).unwrap();
+        let line = lines[0].to_string();
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert_eq!(&fields[..4], &["q1", "0", "chr1", "9"]);
+        assert!(fields.iter().any(|f| f.starts_with("AS:i:")));
+        assert!(fields.iter().any(|f| f.starts_with("XS:i:")));
+        assert!(fields.contains(&"NM:i:0"));
+
+        // Bases outside ACGTN are encoded like FASTQ reads rather than rejected
+        let lines = align_one(&fm, "q1", "gcuucaagtccgatgcRttgcatcggatcc", &opt).unwrap();
+        let line = lines[0].to_string();
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert_eq!(&fields[..4], &["q1", "0", "chr1", "9"]);
+        assert!(align_one(&fm, "q1", "", &opt).is_err());
+
+        let cli = Cli::try_parse_from(["bwa-rust", "align-one", "-i", "ref.fm", "--seq", "ACGT"]).unwrap();
+        let Commands::AlignOne { seq, name, args, .. } = cli.command else {
+            panic!("expected align-one command");
+        };
+        assert_eq!((seq.as_str(), name.as_str()), ("ACGT", "read"));
+        assert_defaults(&args);
+    }
+
+    #[test]
+    fn align_manifest_replaces_reads_arguments() {
+        let cli = Cli::try_parse_from(["bwa-rust", "align", "-i", "ref.fm", "--manifest", "list.tsv"]).unwrap();
+        let Commands::Align { reads, manifest, .. } = cli.command else {
             panic!("expected align command");
         };
+        assert_eq!((reads, manifest.as_deref()), (None, Some("list.tsv")));
 
-        assert_eq!(match_score, defaults.match_score);
-        assert_eq!(mismatch_penalty, defaults.mismatch_penalty);
-        assert_eq!(gap_open, defaults.gap_open);
-        assert_eq!(gap_extend, defaults.gap_extend);
-        assert_eq!(clip_penalty, defaults.clip_penalty);
-        assert_eq!(band_width, defaults.band_width);
-        assert_eq!(score_threshold, defaults.score_threshold);
-        assert_eq!(min_seed_len, defaults.min_seed_len);
-        assert_eq!(zdrop, defaults.zdrop);
-        assert_eq!(threads, defaults.threads);
-        assert_eq!(max_occ, defaults.max_occ);
-        assert_eq!(max_chains, defaults.max_chains_per_contig);
-        assert_eq!(max_alignments, defaults.max_alignments_per_read);
+        assert!(Cli::try_parse_from(["bwa-rust", "align", "-i", "ref.fm"]).is_err());
+        assert!(Cli::try_parse_from(["bwa-rust", "align", "-i", "ref.fm", "r1.fq", "--manifest", "list.tsv"]).is_err());
+    }
+
+    #[test]
+    fn aln_and_samse_take_index_sai_and_reads() {
+        let cli = Cli::try_parse_from(["bwa-rust", "aln", "-i", "ref.fm", "reads.fq", "-o", "reads.sai"]).unwrap();
+        let Commands::Aln { reads, out, .. } = cli.command else {
+            panic!("expected aln command");
+        };
+        assert_eq!((reads.as_str(), out.as_str()), ("reads.fq", "reads.sai"));
+        assert!(Cli::try_parse_from(["bwa-rust", "aln", "-i", "ref.fm", "reads.fq"]).is_err());
+
+        let cli = Cli::try_parse_from(["bwa-rust", "samse", "-i", "ref.fm", "reads.sai", "reads.fq"]).unwrap();
+        let Commands::Samse { sai, reads, out, .. } = cli.command else {
+            panic!("expected samse command");
+        };
+        assert_eq!((sai.as_str(), reads.as_str(), out), ("reads.sai", "reads.fq", None));
+    }
+
+    #[test]
+    fn align_accepts_optional_mate_file() {
+        let cli = Cli::try_parse_from(["bwa-rust", "align", "-i", "ref.fm", "r1.fq", "r2.fq"]).unwrap();
+        let Commands::Align { reads, reads2, .. } = cli.command else {
+            panic!("expected align command");
+        };
+        assert_eq!((reads.as_deref(), reads2.as_deref()), (Some("r1.fq"), Some("r2.fq")));
+
+        let cli = Cli::try_parse_from(["bwa-rust", "mem", "ref.fa", "r1.fq"]).unwrap();
+        let Commands::Mem { reads2, .. } = cli.command else {
+            panic!("expected mem command");
+        };
+        assert_eq!(reads2, None);
+    }
+
+    #[test]
+    fn stats_json_flag_writes_run_summary() {
+        let dir = std::env::temp_dir();
+        let pid = std::process::id();
+        let fa = dir.join(format!("bwa_rust_stats_cli_{}.fa", pid));
+        let fq = dir.join(format!("bwa_rust_stats_cli_{}.fq", pid));
+        let sam = dir.join(format!("bwa_rust_stats_cli_{}.sam", pid));
+        let json = dir.join(format!("bwa_rust_stats_cli_{}.json", pid));
+        let chr1 = "GATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCCTAGCAATGGTACCGTTAGCCTA";
+        std::fs::write(&fa, format!(">chr1\n{}\n", chr1)).unwrap();
+        let read = &chr1[8..38];
+        std::fs::write(
+            &fq,
+            format!(
+                "@r1\n{}\n+\n{}\n@r2\n{}\n+\n{}\n",
+                read,
+                "I".repeat(read.len()),
+                "TTTTTTTTTTTTTTTTTTTTTTTTT",
+                "I".repeat(25)
+            ),
+        )
+        .unwrap();
+
+        let cli = Cli::try_parse_from([
+            "bwa-rust",
+            "mem",
+            fa.to_str().unwrap(),
+            fq.to_str().unwrap(),
+            "-o",
+            sam.to_str().unwrap(),
+            "--stats-json",
+            json.to_str().unwrap(),
+        ])
+        .unwrap();
+        let Commands::Mem {
+            reference,
+            reads,
+            out,
+            args,
+            ..
+        } = cli.command
+        else {
+            panic!("expected mem command");
+        };
+        let stats = run_mem(&reference, &reads, None, out.as_deref(), build_align_opt(&args)).unwrap();
+        maybe_write_stats(&stats, &args).unwrap();
+
+        let parsed: align::AlignStats = serde_json::from_str(&std::fs::read_to_string(&json).unwrap()).unwrap();
+        for path in [&fa, &fq, &sam, &json] {
+            std::fs::remove_file(path).ok();
+        }
+        assert_eq!(parsed, stats);
+        assert_eq!((parsed.reads, parsed.mapped, parsed.unmapped), (2, 1, 1));
+        assert_eq!(parsed.per_contig.get("chr1"), Some(&1));
+        assert_eq!(parsed.mapq_histogram.values().sum::<u64>(), 1);
+    }
+
+    #[test]
+    fn degenerate_parameters_warn_and_fail_with_strict() {
+        let cli = Cli::try_parse_from(["bwa-rust", "mem", "ref.fa", "r.fq", "-B", "0", "-O", "1", "-E", "3"]).unwrap();
+        let Commands::Mem { args, .. } = cli.command else {
+            panic!("expected mem command");
+        };
+        let opt = build_align_opt(&args);
+
+        let mut err = Vec::new();
+        check_parameter_warnings(&opt, false, &mut err).unwrap();
+        let text = String::from_utf8(err).unwrap();
+        assert!(text.contains("mismatches are free"), "{}", text);
+        assert!(text.contains("exceeds gap open penalty"), "{}", text);
+        assert_eq!(text.lines().count(), 2);
+
+        assert!(check_parameter_warnings(&opt, true, &mut Vec::new()).is_err());
+        let defaults = align::AlignOpt::default();
+        let mut err = Vec::new();
+        check_parameter_warnings(&defaults, true, &mut err).unwrap();
+        assert!(err.is_empty());
     }
 }
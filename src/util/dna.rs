@@ -59,6 +59,57 @@ pub fn normalize_seq(seq: &[u8]) -> Vec<u8> {
     out
 }
 
+/// 同 [`normalize_seq`]，但保留 IUPAC 兼并碱基（`R Y S W K M B D H V`）。
+///
+/// 作为可选的扩展字母表，配合 [`iupac_matches`] 使用；默认路径仍为 [`SIGMA`] 编码。
+#[must_use]
+pub fn normalize_seq_iupac(seq: &[u8]) -> Vec<u8> {
+    seq.iter()
+        .map(|&b| match b.to_ascii_uppercase() {
+            b'U' => b'T',
+            up if to_iupac_mask(up) != 0 => up,
+            _ => b'N',
+        })
+        .collect()
+}
+
+/// 将 IUPAC 碱基编码为 `{A, C, G, T}` 上的 4 位掩码（`A=1 C=2 G=4 T=8`）。
+///
+/// 例如 `R`（A/G）→ `0b0101`，`N` → `0b1111`；大小写均支持，`U` 等同 `T`，
+/// 未知字符返回 0。
+#[inline]
+#[must_use]
+pub fn to_iupac_mask(b: u8) -> u8 {
+    match b.to_ascii_uppercase() {
+        b'A' => 0b0001,
+        b'C' => 0b0010,
+        b'G' => 0b0100,
+        b'T' | b'U' => 0b1000,
+        b'M' => 0b0011,
+        b'R' => 0b0101,
+        b'W' => 0b1001,
+        b'S' => 0b0110,
+        b'Y' => 0b1010,
+        b'K' => 0b1100,
+        b'V' => 0b0111,
+        b'H' => 0b1011,
+        b'D' => 0b1101,
+        b'B' => 0b1110,
+        b'N' => 0b1111,
+        _ => 0,
+    }
+}
+
+/// 两个 IUPAC 碱基是否可能代表同一碱基（掩码有交集）。
+///
+/// 与 BWA 一致，`N` 不与任何碱基匹配（包括 `N` 自身）。
+#[inline]
+#[must_use]
+pub fn iupac_matches(code_a: u8, code_b: u8) -> bool {
+    let (a, b) = (to_iupac_mask(code_a), to_iupac_mask(code_b));
+    a != 0b1111 && b != 0b1111 && a & b != 0
+}
+
 /// 返回单个碱基的互补碱基（大小写均支持）。未知字符返回 `N`。
 #[inline]
 #[must_use]
@@ -72,6 +123,25 @@ pub fn complement(base: u8) -> u8 {
     }
 }
 
+/// 同 [`complement`]，但 IUPAC 兼并碱基取其互补码（`R`↔`Y`、`K`↔`M`、`B`↔`V`、`D`↔`H`，
+/// `S`、`W` 不变），配合 [`normalize_seq_iupac`] 使用。
+#[inline]
+#[must_use]
+pub fn complement_iupac(base: u8) -> u8 {
+    match base.to_ascii_uppercase() {
+        b'R' => b'Y',
+        b'Y' => b'R',
+        b'K' => b'M',
+        b'M' => b'K',
+        b'B' => b'V',
+        b'V' => b'B',
+        b'D' => b'H',
+        b'H' => b'D',
+        up @ (b'S' | b'W') => up,
+        _ => complement(base),
+    }
+}
+
 /// 返回序列的反向互补（reverse complement）。长度不变。
 #[must_use]
 pub fn revcomp(seq: &[u8]) -> Vec<u8> {
@@ -82,6 +152,61 @@ pub fn revcomp(seq: &[u8]) -> Vec<u8> {
     out
 }
 
+/// `query` 与 `reference` 的编辑距离（Levenshtein，替换 / 插入 / 缺失各计 1）。
+///
+/// 与 NM 的计法一致，参考上的 `N` 与任何 query 碱基比对都不计为替换。
+/// 时间 O(nm)，空间 O(m)。
+#[must_use]
+pub fn edit_distance(query: &[u8], reference: &[u8]) -> usize {
+    let mut prev: Vec<usize> = (0..=reference.len()).collect();
+    let mut cur = vec![0usize; reference.len() + 1];
+    for (i, &q) in query.iter().enumerate() {
+        cur[0] = i + 1;
+        for (j, &r) in reference.iter().enumerate() {
+            let subst = prev[j] + usize::from(q != r && r != b'N');
+            cur[j + 1] = subst.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[reference.len()]
+}
+
+/// 无损编码：原始字节中无法由 `from_alphabet` 还原的连续片段 `(起点, 原始字节)`，按起点升序
+pub type RawPatches = Vec<(usize, Vec<u8>)>;
+
+/// 将原始碱基序列编码为字母表索引，同时记录无法还原的原始字节段。
+///
+/// 编码结果与 `normalize_seq` + `to_alphabet` 完全相同（FM 搜索仍使用折叠字母表）；
+/// 小写、`U`、IUPAC 简并碱基等被折叠的字节按连续片段保存在补丁中，
+/// 经 [`decode_lossless`] 可逐字节恢复原始输入。
+#[must_use]
+pub fn encode_lossless(seq: &[u8]) -> (Vec<u8>, RawPatches) {
+    let codes: Vec<u8> = normalize_seq(seq).iter().map(|&b| to_alphabet(b)).collect();
+    let mut patches: RawPatches = Vec::new();
+    let mut run_end = usize::MAX;
+    for (i, (&raw, &code)) in seq.iter().zip(&codes).enumerate() {
+        if from_alphabet(code) == raw {
+            continue;
+        }
+        match patches.last_mut() {
+            Some((_, bytes)) if run_end == i => bytes.push(raw),
+            _ => patches.push((i, vec![raw])),
+        }
+        run_end = i + 1;
+    }
+    (codes, patches)
+}
+
+/// [`encode_lossless`] 的逆过程：解码字母表索引并覆盖补丁中的原始字节
+#[must_use]
+pub fn decode_lossless(codes: &[u8], patches: &[(usize, Vec<u8>)]) -> Vec<u8> {
+    let mut out: Vec<u8> = codes.iter().map(|&c| from_alphabet(c)).collect();
+    for (start, bytes) in patches {
+        out[*start..*start + bytes.len()].copy_from_slice(bytes);
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -125,6 +250,13 @@ mod tests {
         assert_eq!(complement(b'U'), b'A');
         assert_eq!(complement(b'N'), b'N');
         assert_eq!(complement(b'x'), b'N');
+        assert_eq!(complement_iupac(b'r'), b'Y');
+        assert_eq!(complement_iupac(b'B'), b'V');
+        assert_eq!(complement_iupac(b'S'), b'S');
+        assert_eq!(complement_iupac(b'A'), b'T');
+        for &b in b"ACGTRYSWKMBDHVN" {
+            assert_eq!(complement_iupac(complement_iupac(b)), b);
+        }
 
         let seq = b"ACGTN";
         let rc = revcomp(seq);
@@ -171,4 +303,45 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn lossless_roundtrip_keeps_iupac_and_case() {
+        let raw = b"ACGTRYKMacgtNNSWBDHVuU-*n";
+        let (codes, patches) = encode_lossless(raw);
+        let collapsed: Vec<u8> = normalize_seq(raw).iter().map(|&b| to_alphabet(b)).collect();
+        assert_eq!(codes, collapsed);
+        assert_eq!(patches[0], (4, b"RYKMacgt".to_vec()));
+        assert_eq!(decode_lossless(&codes, &patches), raw.to_vec());
+
+        let (codes, patches) = encode_lossless(b"ACGTN");
+        assert!(patches.is_empty());
+        assert_eq!(decode_lossless(&codes, &patches), b"ACGTN".to_vec());
+    }
+
+    #[test]
+    fn iupac_masks_and_matching() {
+        assert_eq!(to_iupac_mask(b'A'), 0b0001);
+        assert_eq!(to_iupac_mask(b'r'), 0b0101);
+        assert_eq!(to_iupac_mask(b'N'), 0b1111);
+        assert_eq!(to_iupac_mask(b'X'), 0);
+        assert!(iupac_matches(b'R', b'A'));
+        assert!(iupac_matches(b'G', b'R'));
+        assert!(!iupac_matches(b'R', b'C'));
+        assert!(iupac_matches(b'S', b'K'));
+        assert!(!iupac_matches(b'N', b'A'));
+        assert!(!iupac_matches(b'N', b'N'));
+        assert_eq!(normalize_seq_iupac(b"acgRyuX"), b"ACGRYTN".to_vec());
+    }
+
+    #[test]
+    fn edit_distance_counts_edits() {
+        assert_eq!(edit_distance(b"", b""), 0);
+        assert_eq!(edit_distance(b"ACGT", b""), 4);
+        assert_eq!(edit_distance(b"ACGT", b"ACGT"), 0);
+        assert_eq!(edit_distance(b"ACGT", b"AGGT"), 1);
+        assert_eq!(edit_distance(b"ACGT", b"ACGGT"), 1);
+        assert_eq!(edit_distance(b"ACGTT", b"ACGT"), 1);
+        // 参考 N 不计替换
+        assert_eq!(edit_distance(b"ACGT", b"ANNT"), 0);
+    }
 }
@@ -1 +1,2 @@
 pub mod dna;
+pub mod qual;
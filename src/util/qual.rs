@@ -0,0 +1,52 @@
+/// Phred+33（Sanger / Illumina 1.8+）质量值的 ASCII 偏移
+pub const PHRED33: u8 = 33;
+/// Phred+64（Illumina 1.3–1.7）质量值的 ASCII 偏移
+pub const PHRED64: u8 = 64;
+
+/// Illumina Phred+33 数据（最高 `J` = Q41）中不会出现、Phred+64 数据中常见的最低字节
+const PHRED64_ONLY_MIN: u8 = b'K';
+/// Phred+64 数据中不会出现的字节上限（其最低值为 Solexa 的 `;`，即 Q-5）
+const PHRED33_ONLY_MAX: u8 = b':';
+
+/// 根据一批质量串推测 ASCII 偏移（33 或 64）。
+///
+/// - 出现低于 `;` 的字节 → 只可能是 Phred+33
+/// - 否则出现不低于 `K` 的字节 → Phred+64
+/// - 无法区分（如全为 `@`..`J`）或样本为空时按 Phred+33 处理
+pub fn detect_offset<'a>(quals: impl IntoIterator<Item = &'a [u8]>) -> u8 {
+    let (mut min, mut max) = (u8::MAX, 0u8);
+    for q in quals.into_iter().flatten() {
+        min = min.min(*q);
+        max = max.max(*q);
+    }
+    if min <= PHRED33_ONLY_MAX || max < PHRED64_ONLY_MIN {
+        PHRED33
+    } else {
+        PHRED64
+    }
+}
+
+/// 将以 `offset` 编码的质量串原地改写为 Phred+33；低于 `offset` 的字节（Solexa 负值）截断为 Q0
+pub fn to_phred33(qual: &mut [u8], offset: u8) {
+    for q in qual {
+        *q = q.saturating_sub(offset) + PHRED33;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_offset_and_convert() {
+        assert_eq!(detect_offset([&b"II5#"[..], b"IIII"]), PHRED33);
+        assert_eq!(detect_offset([&b"hhhB"[..], b"ffZ;"]), PHRED64);
+        // 全为高质量 Phred+33（如 `I`）时不应误判
+        assert_eq!(detect_offset([&b"IIIIJ"[..]]), PHRED33);
+        assert_eq!(detect_offset(std::iter::empty::<&[u8]>()), PHRED33);
+
+        let mut q = b"h@;".to_vec();
+        to_phred33(&mut q, PHRED64);
+        assert_eq!(q, b"I!!");
+    }
+}
@@ -0,0 +1,100 @@
+//! 模糊测试回归：FASTQ 解析 + 比对在任意字节输入下不 panic，且输出的 SAM 行字段数正确。
+//!
+//! 种子语料位于 `fuzz/corpus/fastq_align/`，与 `cargo fuzz run fastq_align` 共用。
+
+use std::io::Cursor;
+use std::path::Path;
+
+use bwa_rust::align::{align_read, AlignOpt};
+use bwa_rust::index::builder::build_fm_index;
+use bwa_rust::index::fm::FMIndex;
+use bwa_rust::io::fastq::FastqReader;
+
+const REFERENCE: &[u8] = b">chr1\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCCTAGCAATGGTACCGTTAGCCTA\n\
+>chr2\nTCGGTTAGCCTAGCAATGGTACCGTTCAGGACTTAGCANNNNNNNNNNNNACGTACGTACGT\n";
+
+/// SAM 必需字段数
+const SAM_MANDATORY_FIELDS: usize = 11;
+
+fn fuzz_fm() -> FMIndex {
+    build_fm_index(Cursor::new(REFERENCE), 16).unwrap().fm
+}
+
+/// 单个输入的检查逻辑：解析失败允许返回错误，但不得 panic；解析成功的记录必须产生合法字段数的 SAM 行。
+fn run_one(fm: &FMIndex, data: &[u8]) {
+    let opt = AlignOpt::default();
+    let mut reader = FastqReader::new(Cursor::new(data));
+    while let Ok(Some(rec)) = reader.next_record() {
+        let lines = align_read(fm, &rec, &opt);
+        assert!(!lines.is_empty(), "no SAM output for parsed record {:?}", rec.id);
+        for line in &lines {
+            assert!(!line.contains('\n'), "embedded newline in SAM line: {:?}", line);
+            let fields: Vec<&str> = line.split('\t').collect();
+            assert!(
+                fields.len() >= SAM_MANDATORY_FIELDS,
+                "SAM line has {} fields: {:?}",
+                fields.len(),
+                line
+            );
+            assert!(
+                fields[..SAM_MANDATORY_FIELDS].iter().all(|f| !f.is_empty()),
+                "empty field: {:?}",
+                line
+            );
+            for tag in &fields[SAM_MANDATORY_FIELDS..] {
+                let b = tag.as_bytes();
+                assert!(
+                    b.len() >= 5 && b[2] == b':' && b[4] == b':',
+                    "malformed tag {:?} in {:?}",
+                    tag,
+                    line
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn fuzz_seed_corpus_does_not_panic() {
+    let fm = fuzz_fm();
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus/fastq_align");
+    let mut n = 0;
+    for entry in std::fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        let data = std::fs::read(&path).unwrap();
+        run_one(&fm, &data);
+        n += 1;
+    }
+    assert!(n > 0, "empty seed corpus at {}", dir.display());
+}
+
+#[test]
+fn fuzz_random_mutations_do_not_panic() {
+    let fm = fuzz_fm();
+    let base = b"@r1 desc\nGGCTTCAAGTCCGATGCATTGCATC\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n\
+@r2\nAGCCTAGCAATGGTACCGTTCAGGA\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n";
+    let alphabet = b"@+\n\r\t ACGTNacgtn!I~\xff\xc3\xa9";
+
+    // 确定性 LCG，避免引入随机数依赖
+    let mut state = 0x2545_F491_4F6C_DD1Du64;
+    let mut next = move || {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 33) as usize
+    };
+
+    for _ in 0..2000 {
+        let mut data = base.to_vec();
+        for _ in 0..(next() % 6 + 1) {
+            let pos = next() % (data.len() + 1);
+            match next() % 3 {
+                0 if pos < data.len() => data[pos] = alphabet[next() % alphabet.len()],
+                1 => data.insert(pos, alphabet[next() % alphabet.len()]),
+                _ if pos < data.len() => {
+                    data.remove(pos);
+                }
+                _ => {}
+            }
+        }
+        run_one(&fm, &data);
+    }
+}
@@ -2,7 +2,7 @@
 
 use std::io::Cursor;
 
-use bwa_rust::align::{build_chains, chain_to_alignment, filter_chains, find_smem_seeds, SwParams};
+use bwa_rust::align::{build_chains, chain_to_alignment, filter_chains, find_smem_seeds, SwMode, SwParams};
 use bwa_rust::index::builder::build_fm_index;
 use bwa_rust::index::fm::FMIndex;
 use bwa_rust::index::{bwt, sa};
@@ -82,9 +82,11 @@ fn e2e_seed_chain_align_exact() {
     assert!(!chains.is_empty());
 
     // SW 对齐
-    let ref_seq: Vec<u8> = fm.text[..fm.contigs[0].len as usize]
-        .iter()
-        .map(|&c| dna::from_alphabet(c))
+    let ref_seq: Vec<u8> = fm
+        .text
+        .unpack(0, fm.contigs[0].len as usize)
+        .into_iter()
+        .map(dna::from_alphabet)
         .collect();
     let p = SwParams {
         match_score: 2,
@@ -92,6 +94,11 @@ fn e2e_seed_chain_align_exact() {
         gap_open: 2,
         gap_extend: 1,
         band_width: 16,
+        diagonal_offset: 0,
+        score_matrix: None,
+        mode: SwMode::Local,
+        iupac: false,
+        n_penalty: 0,
     };
     let res = chain_to_alignment(&chains[0], &norm, &ref_seq, p, 100);
     assert!(res.score > 0);
@@ -116,9 +123,11 @@ fn e2e_seed_chain_align_with_mismatch() {
         // 过滤弱链：保留得分 >= 最佳得分 * 0.3 的链
         filter_chains(&mut chains, 0.3);
         if !chains.is_empty() {
-            let ref_seq: Vec<u8> = fm.text[..fm.contigs[0].len as usize]
-                .iter()
-                .map(|&c| dna::from_alphabet(c))
+            let ref_seq: Vec<u8> = fm
+                .text
+                .unpack(0, fm.contigs[0].len as usize)
+                .into_iter()
+                .map(dna::from_alphabet)
                 .collect();
             let p = SwParams {
                 match_score: 2,
@@ -126,6 +135,11 @@ fn e2e_seed_chain_align_with_mismatch() {
                 gap_open: 2,
                 gap_extend: 1,
                 band_width: 16,
+                diagonal_offset: 0,
+                score_matrix: None,
+                mode: SwMode::Local,
+                iupac: false,
+                n_penalty: 0,
             };
             let res = chain_to_alignment(&chains[0], &norm, &ref_seq, p, 100);
             assert!(res.score > 0);
@@ -315,3 +329,352 @@ fn e2e_sa_produces_sorted_suffixes() {
         assert!(s1 <= s2, "SA not sorted at position {}", i);
     }
 }
+
+// ─── gzip 压缩输出 ───────────────────────────────────────────
+
+#[test]
+fn e2e_gzip_sam_output_levels_decompress_identically() {
+    use std::io::Read;
+    use std::sync::Arc;
+
+    let fm = Arc::new(build_fm_from_fasta_str(
+        b">chr1\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCCTAGCAATGGTACCGTTAGCCTA\n",
+    ));
+    let dir = std::env::temp_dir();
+    let pid = std::process::id();
+    let fq_path = dir.join(format!("bwa_rust_gz_levels_{}.fq", pid));
+    std::fs::write(
+        &fq_path,
+        "@r1\nGCTTCAAGTCCGATGCATTGCATCGGATCC\n+\nIIIIIIIIIIIIIIIIIIIIIIIIIIIIII\n",
+    )
+    .unwrap();
+
+    let mut decoded = Vec::new();
+    for level in [1u32, 9] {
+        let out_path = dir.join(format!("bwa_rust_gz_levels_{}_{}.sam.gz", pid, level));
+        let opt = bwa_rust::align::AlignOpt {
+            compress_level: level,
+            ..Default::default()
+        };
+        bwa_rust::align::align_fastq_with_fm_opt(
+            Arc::clone(&fm),
+            fq_path.to_str().unwrap(),
+            Some(out_path.to_str().unwrap()),
+            opt,
+        )
+        .unwrap();
+        let mut text = String::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(&out_path).unwrap())
+            .read_to_string(&mut text)
+            .unwrap();
+        assert!(text.starts_with("@HD"));
+        assert!(text.contains("r1\t0\tchr1\t"));
+        decoded.push(text);
+        std::fs::remove_file(&out_path).ok();
+    }
+    std::fs::remove_file(&fq_path).ok();
+    assert_eq!(decoded[0], decoded[1]);
+}
+
+#[test]
+fn e2e_gzip_reference_and_reads_input() {
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+    use std::sync::Arc;
+
+    let gz = |text: &[u8]| {
+        let mut enc = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        enc.write_all(text).unwrap();
+        enc.finish().unwrap()
+    };
+    let dir = std::env::temp_dir();
+    let pid = std::process::id();
+    let fa_path = dir.join(format!("bwa_rust_gz_input_{}.fa.gz", pid));
+    let fq_path = dir.join(format!("bwa_rust_gz_input_{}.fq.gz", pid));
+    let out_path = dir.join(format!("bwa_rust_gz_input_{}.sam", pid));
+
+    // 参考与 reads 均由两个 gzip 成员拼接而成（bgzip / pigz 风格）
+    let mut fa = gz(b">chr1\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCCTAGCAATGGTACCGTTAGCCTA\n");
+    fa.extend(gz(b">chr2\nTCGGTTAGCCTAGCAATGGTACCGTTCAGGACTTAGCAGGACCTTAAG\n"));
+    std::fs::write(&fa_path, fa).unwrap();
+    let mut fq = gz(b"@r1\nGCTTCAAGTCCGATGCATTGCATCGGATCC\n+\nIIIIIIIIIIIIIIIIIIIIIIIIIIIIII\n");
+    fq.extend(gz(b"@r2\nGGTACCGTTCAGGACTTAGCAGGAC\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n"));
+    std::fs::write(&fq_path, fq).unwrap();
+
+    let result = bwa_rust::index::builder::build_fm_from_fasta(&fa_path, 4).unwrap();
+    assert_eq!(result.n_seqs, 2);
+    bwa_rust::align::align_fastq_with_fm_opt(
+        Arc::new(result.fm),
+        fq_path.to_str().unwrap(),
+        Some(out_path.to_str().unwrap()),
+        bwa_rust::align::AlignOpt::default(),
+    )
+    .unwrap();
+    let sam = std::fs::read_to_string(&out_path).unwrap();
+    assert!(sam.contains("r1\t0\tchr1\t"), "{}", sam);
+    assert!(sam.contains("r2\t0\tchr2\t"), "{}", sam);
+
+    for p in [&fa_path, &fq_path, &out_path] {
+        std::fs::remove_file(p).ok();
+    }
+}
+
+// ─── SAM 合法性自检 ──────────────────────────────────────────
+
+#[test]
+fn e2e_pipeline_output_passes_sam_validation() {
+    use std::sync::Arc;
+
+    let fm = Arc::new(build_fm_from_fasta_str(
+        b">chr1\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCCTAGCAATGGTACCGTTAGCCTA\n>chr2\nTCGGTTAGCCTAGCAATGGTACCGTTCAGGACTTAGCA\n",
+    ));
+    let dir = std::env::temp_dir();
+    let pid = std::process::id();
+    let fq_path = dir.join(format!("bwa_rust_validate_{}.fq", pid));
+    let out_path = dir.join(format!("bwa_rust_validate_{}.sam", pid));
+    // 正向、反向互补、错配、未比对
+    std::fs::write(
+        &fq_path,
+        "@fwd\nGCTTCAAGTCCGATGCATTGCATCGGATCC\n+\nIIIIIIIIIIIIIIIIIIIIIIIIIIIIII\n\
+         @rev\nTCCTGAACGGTACCATTGCTAGGCT\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n\
+         @mm\nGCTTCAAGTCCGTTGCATTGCATCGGATCC\n+\nIIIIIIIIIIIIIIIIIIIIIIIIIIIIII\n\
+         @none\nTTTTTTTTTTTTTTTTTTTTTTTTT\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n",
+    )
+    .unwrap();
+
+    bwa_rust::align::align_fastq_with_fm_opt(
+        fm,
+        fq_path.to_str().unwrap(),
+        Some(out_path.to_str().unwrap()),
+        bwa_rust::align::AlignOpt::default(),
+    )
+    .unwrap();
+    bwa_rust::io::sam::validate_sam_file(out_path.to_str().unwrap()).unwrap();
+
+    std::fs::remove_file(&fq_path).ok();
+    std::fs::remove_file(&out_path).ok();
+}
+
+// ─── BAM 输出 ────────────────────────────────────────────────
+
+#[test]
+fn e2e_bam_output_decodes_with_noodles_to_same_records_as_sam() {
+    use noodles_sam::alignment::io::Write as _;
+    use std::sync::Arc;
+
+    let fm = Arc::new(build_fm_from_fasta_str(
+        b">chr1\nGATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCCTAGCAATGGTACCGTTAGCCTA\n>chr2\nTCGGTTAGCCTAGCAATGGTACCGTTCAGGACTTAGCA\n",
+    ));
+    let dir = std::env::temp_dir();
+    let pid = std::process::id();
+    let fq_path = dir.join(format!("bwa_rust_bam_rt_{}.fq", pid));
+    let sam_path = dir.join(format!("bwa_rust_bam_rt_{}.sam", pid));
+    let bam_path = dir.join(format!("bwa_rust_bam_rt_{}.bam", pid));
+    // 正向、反向互补、错配、软剪切、未比对
+    std::fs::write(
+        &fq_path,
+        "@fwd\nGCTTCAAGTCCGATGCATTGCATCGGATCC\n+\nIIIIIIIIIIIIIIIIIIIIIIIIIIIIII\n\
+         @rev\nTCCTGAACGGTACCATTGCTAGGCT\n+\nIIIIIIIIIIIIII#IIIIIIIIII\n\
+         @mm\nAAAAGCTTCAAGTCCGATGCATTGCATCGGTTCC\n+\n5555IIIIIIIIIIIIIIIIIIIIIIIIIIIIII\n\
+         @clip\nCCCCCGATTACAGGCTTCAAGTCCGATGCATTGC\n+\nIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIII\n\
+         @none\nTTTTTTTTTTTTTTTTTTTTTTTTT\n+\nIIIIIIIIIIIIIIIIIIIIIIIII\n",
+    )
+    .unwrap();
+    let opt = bwa_rust::align::AlignOpt {
+        read_group: Some("ID:g1\tSM:s1".to_string()),
+        clip_tags: true,
+        ..bwa_rust::align::AlignOpt::default()
+    };
+    for out in [&sam_path, &bam_path] {
+        bwa_rust::align::align_fastq_with_fm_opt(
+            Arc::clone(&fm),
+            fq_path.to_str().unwrap(),
+            Some(out.to_str().unwrap()),
+            opt.clone(),
+        )
+        .unwrap();
+    }
+
+    // 用 noodles 独立解码 BAM，再按 SAM 文本输出，应与本 crate 写出的 SAM 记录逐行一致
+    let mut reader = noodles_bam::io::reader::Builder.build_from_path(&bam_path).unwrap();
+    let header = reader.read_header().unwrap();
+    let names: Vec<String> = header.reference_sequences().keys().map(ToString::to_string).collect();
+    assert_eq!(names, ["chr1", "chr2"]);
+    assert!(header.read_groups().contains_key(b"g1".as_slice()));
+    let mut writer = noodles_sam::io::Writer::new(Vec::new());
+    for record in reader.records() {
+        writer.write_alignment_record(&header, &record.unwrap()).unwrap();
+    }
+    let decoded = String::from_utf8(writer.get_ref().clone()).unwrap();
+
+    let sam = std::fs::read_to_string(&sam_path).unwrap();
+    let expected: Vec<&str> = sam.lines().filter(|l| !l.starts_with('@')).collect();
+    assert_eq!(expected.len(), 5);
+    assert_eq!(decoded.lines().collect::<Vec<_>>(), expected);
+
+    for p in [&fq_path, &sam_path, &bam_path] {
+        std::fs::remove_file(p).ok();
+    }
+}
+
+// ─── 双端比对 ────────────────────────────────────────────────
+
+#[test]
+fn e2e_paired_end_sets_mate_fields() {
+    use std::sync::Arc;
+
+    let reference =
+        b"GATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCCTAGCAATGGTACCGTTAGCCTAGGACTTCAGTCAAGGCTATCGATCCGTAGGCATTACGGA";
+    let fm = Arc::new(build_fm_from_seq(reference));
+    let dir = std::env::temp_dir();
+    let pid = std::process::id();
+    let r1_path = dir.join(format!("bwa_rust_paired_{}_1.fq", pid));
+    let r2_path = dir.join(format!("bwa_rust_paired_{}_2.fq", pid));
+    let out_path = dir.join(format!("bwa_rust_paired_{}.sam", pid));
+
+    // pair1: R1 正向 [5, 35)，R2 为 [60, 90) 的反向互补；pair2: R2 无法比对
+    let r1_seq = std::str::from_utf8(&reference[5..35]).unwrap().to_string();
+    let r2_seq = String::from_utf8(dna::revcomp(&reference[60..90])).unwrap();
+    let q30 = "I".repeat(30);
+    std::fs::write(
+        &r1_path,
+        format!("@pair1/1 c1\n{r1_seq}\n+\n{q30}\n@pair2/1\n{r1_seq}\n+\n{q30}\n"),
+    )
+    .unwrap();
+    std::fs::write(
+        &r2_path,
+        format!(
+            "@pair1/2 c2\n{r2_seq}\n+\n{q30}\n@pair2/2\n{}\n+\n{q30}\n",
+            "T".repeat(30)
+        ),
+    )
+    .unwrap();
+
+    bwa_rust::align::align_fastq_paired_with_fm(
+        fm,
+        r1_path.to_str().unwrap(),
+        r2_path.to_str().unwrap(),
+        Some(out_path.to_str().unwrap()),
+        bwa_rust::align::AlignOpt::default(),
+    )
+    .unwrap();
+    let text = std::fs::read_to_string(&out_path).unwrap();
+    let records: Vec<Vec<&str>> = text
+        .lines()
+        .filter(|l| !l.starts_with('@'))
+        .map(|l| l.split('\t').collect())
+        .collect();
+    let fields = |r: &[&str]| {
+        (
+            r[0].to_string(),
+            r[1].parse::<u16>().unwrap(),
+            r[3].to_string(),
+            r[6].to_string(),
+            r[7].to_string(),
+            r[8].to_string(),
+        )
+    };
+
+    assert_eq!(records.len(), 4);
+    assert_eq!(
+        fields(&records[0]),
+        (
+            "pair1".into(),
+            0x1 | 0x2 | 0x20 | 0x40,
+            "6".into(),
+            "=".into(),
+            "61".into(),
+            "85".into()
+        )
+    );
+    assert_eq!(
+        fields(&records[1]),
+        (
+            "pair1".into(),
+            0x1 | 0x2 | 0x10 | 0x80,
+            "61".into(),
+            "=".into(),
+            "6".into(),
+            "-85".into()
+        )
+    );
+    assert_eq!(
+        fields(&records[2]),
+        (
+            "pair2".into(),
+            0x1 | 0x8 | 0x40,
+            "6".into(),
+            "=".into(),
+            "6".into(),
+            "0".into()
+        )
+    );
+    assert_eq!(
+        fields(&records[3]),
+        (
+            "pair2".into(),
+            0x1 | 0x4 | 0x80,
+            "6".into(),
+            "=".into(),
+            "6".into(),
+            "0".into()
+        )
+    );
+    bwa_rust::io::sam::validate_sam_file(out_path.to_str().unwrap()).unwrap();
+
+    for p in [&r1_path, &r2_path, &out_path] {
+        std::fs::remove_file(p).ok();
+    }
+}
+
+#[test]
+fn e2e_insert_window_options_set_proper_pair_flag() {
+    use std::sync::Arc;
+
+    let reference =
+        b"GATTACAGGCTTCAAGTCCGATGCATTGCATCGGATCCTAGCAATGGTACCGTTAGCCTAGGACTTCAGTCAAGGCTATCGATCCGTAGGCATTACGGA";
+    let fm = Arc::new(build_fm_from_seq(reference));
+    let dir = std::env::temp_dir();
+    let pid = std::process::id();
+    let r1_path = dir.join(format!("bwa_rust_insert_{}_1.fq", pid));
+    let r2_path = dir.join(format!("bwa_rust_insert_{}_2.fq", pid));
+    let out_path = dir.join(format!("bwa_rust_insert_{}.sam", pid));
+
+    // 插入片段长 85bp
+    let q30 = "I".repeat(30);
+    let r1_seq = std::str::from_utf8(&reference[5..35]).unwrap().to_string();
+    let r2_seq = String::from_utf8(dna::revcomp(&reference[60..90])).unwrap();
+    std::fs::write(&r1_path, format!("@p/1\n{r1_seq}\n+\n{q30}\n")).unwrap();
+    std::fs::write(&r2_path, format!("@p/2\n{r2_seq}\n+\n{q30}\n")).unwrap();
+
+    let proper_flags = |min_insert: usize, max_insert: usize| -> Vec<bool> {
+        let opt = bwa_rust::align::AlignOpt {
+            min_insert,
+            max_insert,
+            ..bwa_rust::align::AlignOpt::default()
+        };
+        bwa_rust::align::align_fastq_paired_with_fm(
+            Arc::clone(&fm),
+            r1_path.to_str().unwrap(),
+            r2_path.to_str().unwrap(),
+            Some(out_path.to_str().unwrap()),
+            opt,
+        )
+        .unwrap();
+        std::fs::read_to_string(&out_path)
+            .unwrap()
+            .lines()
+            .filter(|l| !l.starts_with('@'))
+            .map(|l| l.split('\t').nth(1).unwrap().parse::<u16>().unwrap() & 0x2 != 0)
+            .collect()
+    };
+
+    assert_eq!(proper_flags(0, 500), [true, true]);
+    assert_eq!(proper_flags(0, 80), [false, false]);
+    assert_eq!(proper_flags(90, 500), [false, false]);
+    assert_eq!(proper_flags(80, 90), [true, true]);
+
+    for p in [&r1_path, &r2_path, &out_path] {
+        std::fs::remove_file(p).ok();
+    }
+}